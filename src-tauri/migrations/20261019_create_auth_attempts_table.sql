@@ -0,0 +1,5 @@
+CREATE TABLE IF NOT EXISTS auth_attempts (
+    wallet_id UUID PRIMARY KEY REFERENCES wallets(id),
+    failed_attempts INTEGER NOT NULL DEFAULT 0,
+    last_failed_at INTEGER
+);
@@ -0,0 +1,20 @@
+ALTER TABLE accounts ADD COLUMN script_type TEXT NOT NULL DEFAULT '';
+ALTER TABLE accounts ADD COLUMN used BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- Same outcome as parsing the address, for the accounts created before the column existed.
+UPDATE accounts SET script_type = CASE
+    WHEN address LIKE 'bc1p%' OR address LIKE 'tb1p%' OR address LIKE 'bcrt1p%' THEN 'p2tr'
+    WHEN (address LIKE 'bc1q%' OR address LIKE 'tb1q%') AND length(address) = 42 THEN 'p2wpkh'
+    WHEN (address LIKE 'bc1q%' OR address LIKE 'tb1q%') AND length(address) = 62 THEN 'p2wsh'
+    WHEN address LIKE 'bcrt1q%' AND length(address) = 44 THEN 'p2wpkh'
+    WHEN address LIKE 'bcrt1q%' AND length(address) = 64 THEN 'p2wsh'
+    WHEN substr(address, 1, 1) IN ('1', 'm', 'n') THEN 'p2pkh'
+    WHEN substr(address, 1, 1) IN ('3', '2') THEN 'p2sh'
+    ELSE ''
+END;
+
+CREATE INDEX IF NOT EXISTS accounts_by_position ON accounts (wallet_id, position, created_at, id);
+CREATE INDEX IF NOT EXISTS accounts_by_creation ON accounts (wallet_id, created_at, id);
+CREATE INDEX IF NOT EXISTS accounts_by_address ON accounts (wallet_id, address, id);
+CREATE INDEX IF NOT EXISTS accounts_by_label ON accounts (wallet_id, label, id);
+CREATE INDEX IF NOT EXISTS accounts_by_kind ON accounts (wallet_id, network, script_type, used);
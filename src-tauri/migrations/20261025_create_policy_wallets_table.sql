@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS policy_wallets (
+    id UUID PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    policy TEXT NOT NULL,
+    -- Compiled descriptor of both chains, the source of truth for addresses.
+    descriptor TEXT NOT NULL,
+    network TEXT NOT NULL,
+    -- JSON array of the keys, their aliases and the wallets holding them.
+    keys TEXT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+);
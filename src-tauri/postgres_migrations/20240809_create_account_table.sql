@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS accounts (
+    id UUID PRIMARY KEY,
+    wallet_id UUID REFERENCES wallets(id),
+    address TEXT NOT NULL UNIQUE,
+    path TEXT NOT NULL UNIQUE,
+    network TEXT NOT NULL,
+    blockchain TEXT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,5 @@
+ALTER TABLE accounts ADD COLUMN label TEXT NOT NULL DEFAULT '';
+ALTER TABLE accounts ADD COLUMN notes TEXT NOT NULL DEFAULT '';
+ALTER TABLE accounts ADD COLUMN colour TEXT NOT NULL DEFAULT '';
+ALTER TABLE accounts ADD COLUMN archived BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE accounts ADD COLUMN position BIGINT NOT NULL DEFAULT 0;
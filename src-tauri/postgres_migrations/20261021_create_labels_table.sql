@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS labels (
+    wallet_id UUID NOT NULL REFERENCES wallets(id),
+    type TEXT NOT NULL,
+    ref TEXT NOT NULL,
+    label TEXT,
+    origin TEXT,
+    spendable BOOLEAN,
+    PRIMARY KEY (wallet_id, type, ref)
+);
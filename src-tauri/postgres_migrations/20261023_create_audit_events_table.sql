@@ -0,0 +1,20 @@
+-- No reference to the wallet, its history has to survive it being removed.
+CREATE TABLE IF NOT EXISTS audit_events (
+    wallet_id UUID NOT NULL,
+    sequence BIGINT NOT NULL,
+    action TEXT NOT NULL,
+    details TEXT NOT NULL,
+    created_at BIGINT NOT NULL,
+    prev_hash TEXT NOT NULL,
+    hash TEXT NOT NULL,
+    PRIMARY KEY (wallet_id, sequence)
+);
+
+CREATE OR REPLACE FUNCTION audit_events_append_only() RETURNS trigger AS $$
+BEGIN
+    RAISE EXCEPTION 'audit_events is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER audit_events_append_only BEFORE UPDATE OR DELETE ON audit_events
+FOR EACH ROW EXECUTE FUNCTION audit_events_append_only();
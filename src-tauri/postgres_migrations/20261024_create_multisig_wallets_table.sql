@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS multisig_wallets (
+    id UUID PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    threshold INTEGER NOT NULL,
+    script_type TEXT NOT NULL,
+    network TEXT NOT NULL,
+    -- JSON array of the cosigners, their keys and the wallets holding them.
+    cosigners TEXT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+);
@@ -1,15 +1,17 @@
 use core::fmt;
+use std::str::FromStr;
 
 use crate::{
     path_builder::PathBuilder,
     utils::{decrypt, encrypt, AESKey},
 };
 use bitcoin::{
-    bip32::{DerivationPath, Xpriv},
+    bip32::{ChildNumber, DerivationPath, Xpriv},
     hex::DisplayHex,
     secp256k1, Address, CompressedPublicKey, Network as BitcoinNetwork, NetworkKind, PrivateKey,
 };
 use hex::decode;
+use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use thiserror::Error;
 
@@ -18,6 +20,8 @@ use super::wallet::WalletModel;
 const BITCOIN: &str = "Bitcoin";
 const TESTNET: &str = "Testnet";
 const MAINNET: &str = "Mainnet";
+const SIGNET: &str = "Signet";
+const REGTEST: &str = "Regtest";
 
 #[derive(Debug, Error)]
 pub enum AccountError {
@@ -27,9 +31,11 @@ pub enum AccountError {
     Path(String),
     #[error("Failed deriving key form path: {0}")]
     Derivation(String),
+    #[error("Invalid metadata: {0}")]
+    Metadata(String),
 }
 
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct AccountModel {
     pub id: String,
     pub wallet_id: String,
@@ -38,6 +44,88 @@ pub struct AccountModel {
     pub network: String,
     pub blockchain: String,
     pub created_at: Option<String>,
+    // Metadata is purely cosmetic, documents written before it existed simply lack it.
+    #[serde(default)]
+    pub label: String,
+    #[serde(default)]
+    pub notes: String,
+    /// `#rrggbb`, empty when none was picked.
+    #[serde(default)]
+    pub colour: String,
+    #[serde(default)]
+    pub archived: bool,
+    /// Accounts are listed by ascending position, then creation.
+    #[serde(default)]
+    pub position: i64,
+    /// Kind of address, see [`script_type`].
+    #[serde(default)]
+    pub script_type: String,
+    /// Whether the address already received funds.
+    #[serde(default)]
+    pub used: bool,
+}
+
+/// `p2pkh`, `p2sh`, `p2wpkh`, `p2wsh` or `p2tr` depending on the address, empty when it
+/// can't be told.
+pub fn script_type(address: &str) -> String {
+    Address::from_str(address)
+        .ok()
+        .and_then(|address| address.assume_checked().address_type())
+        .map(|kind| kind.to_string())
+        .unwrap_or_default()
+}
+
+/// Changes to the metadata of an account, `None` fields are left as they are.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct UpdateAccountInput {
+    pub id: String,
+    pub label: Option<String>,
+    pub notes: Option<String>,
+    pub colour: Option<String>,
+    pub archived: Option<bool>,
+    pub position: Option<i64>,
+    pub used: Option<bool>,
+}
+
+impl UpdateAccountInput {
+    pub fn validate(&self) -> Result<(), AccountError> {
+        if let Some(colour) = &self.colour {
+            let valid = colour.is_empty()
+                || (colour.len() == 7
+                    && colour.starts_with('#')
+                    && colour[1..].chars().all(|c| c.is_ascii_hexdigit()));
+
+            if !valid {
+                return Err(AccountError::Metadata(format!(
+                    "Colour {colour} is not of the #rrggbb form"
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Applies the changes to `account`, for backends which don't do it in their queries.
+    pub fn apply(&self, account: &mut AccountModel) {
+        if let Some(label) = &self.label {
+            account.label = label.clone();
+        }
+        if let Some(notes) = &self.notes {
+            account.notes = notes.clone();
+        }
+        if let Some(colour) = &self.colour {
+            account.colour = colour.clone();
+        }
+        if let Some(archived) = self.archived {
+            account.archived = archived;
+        }
+        if let Some(position) = self.position {
+            account.position = position;
+        }
+        if let Some(used) = self.used {
+            account.used = used;
+        }
+    }
 }
 
 #[derive(Debug, Default, Clone, Copy)]
@@ -70,11 +158,13 @@ impl Blockchain {
     }
 }
 
-#[derive(Debug, Default, Clone, Copy)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Network {
     #[default]
     Mainnet,
     Testnet,
+    Signet,
+    Regtest,
 }
 
 impl fmt::Display for Network {
@@ -82,16 +172,27 @@ impl fmt::Display for Network {
         let output = match self {
             Network::Mainnet => MAINNET,
             Network::Testnet => TESTNET,
+            Network::Signet => SIGNET,
+            Network::Regtest => REGTEST,
         };
         write!(f, "{}", output)
     }
 }
 
 impl Network {
+    pub const ALL: [Network; 4] = [
+        Network::Mainnet,
+        Network::Testnet,
+        Network::Signet,
+        Network::Regtest,
+    ];
+
     pub fn from_string(text: &str) -> Result<Self, &'static str> {
         match text {
             MAINNET => Ok(Network::Mainnet),
             TESTNET => Ok(Network::Testnet),
+            SIGNET => Ok(Network::Signet),
+            REGTEST => Ok(Network::Regtest),
             _ => Err("Error parsing"),
         }
     }
@@ -99,13 +200,132 @@ impl Network {
     pub fn to_bitcoin_network_kind(&self) -> NetworkKind {
         match self {
             Network::Mainnet => NetworkKind::Main,
-            Network::Testnet => NetworkKind::Test,
+            _ => NetworkKind::Test,
         }
     }
+
+    pub fn to_bitcoin_network(&self) -> BitcoinNetwork {
+        match self {
+            Network::Mainnet => BitcoinNetwork::Bitcoin,
+            Network::Testnet => BitcoinNetwork::Testnet,
+            Network::Signet => BitcoinNetwork::Signet,
+            Network::Regtest => BitcoinNetwork::Regtest,
+        }
+    }
+}
+
+/// Kind of single key address an account is derived as, each with the BIP purpose of its
+/// derivation paths.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScriptType {
+    /// BIP-44.
+    P2pkh,
+    /// BIP-49, segwit nested in P2SH.
+    P2shP2wpkh,
+    /// BIP-84.
+    #[default]
+    P2wpkh,
+    /// BIP-86, taproot key path only.
+    P2tr,
+}
+
+impl ScriptType {
+    pub const ALL: [ScriptType; 4] = [
+        ScriptType::P2pkh,
+        ScriptType::P2shP2wpkh,
+        ScriptType::P2wpkh,
+        ScriptType::P2tr,
+    ];
+
+    pub fn purpose(&self) -> u32 {
+        match self {
+            ScriptType::P2pkh => 44,
+            ScriptType::P2shP2wpkh => 49,
+            ScriptType::P2wpkh => 84,
+            ScriptType::P2tr => 86,
+        }
+    }
+
+    /// `purpose' / coin_type' / account' / change / index` for the network.
+    pub fn path(
+        &self,
+        network: Network,
+        account: u32,
+        change: u32,
+        index: u32,
+    ) -> Result<DerivationPath, AccountError> {
+        let coin_type = match network {
+            Network::Mainnet => 0,
+            _ => 1,
+        };
+
+        let children = [
+            ChildNumber::from_hardened_idx(self.purpose()),
+            ChildNumber::from_hardened_idx(coin_type),
+            ChildNumber::from_hardened_idx(account),
+            ChildNumber::from_normal_idx(change),
+            ChildNumber::from_normal_idx(index),
+        ];
+
+        let mut path = vec![];
+        for child in children {
+            match child {
+                Ok(child) => path.push(child),
+                Err(err) => return Err(AccountError::Path(err.to_string())),
+            }
+        }
+
+        Ok(DerivationPath::from(path))
+    }
+
+    /// Address of the key for the network.
+    pub fn address(&self, key: &PrivateKey, network: Network) -> Address {
+        let secp = secp256k1::Secp256k1::new();
+        let c_pk = CompressedPublicKey::from_private_key(&secp, key)
+            .expect("Failed while attempting to create compressed pub key from slice.");
+        let bitcoin_network = network.to_bitcoin_network();
+
+        match self {
+            ScriptType::P2pkh => Address::p2pkh(c_pk, bitcoin_network),
+            ScriptType::P2shP2wpkh => Address::p2shwpkh(&c_pk, bitcoin_network),
+            ScriptType::P2wpkh => Address::p2wpkh(&c_pk, bitcoin_network),
+            ScriptType::P2tr => {
+                let keypair = secp256k1::Keypair::from_secret_key(&secp, &key.inner);
+                let (internal_key, _) = keypair.x_only_public_key();
+                Address::p2tr(&secp, internal_key, None, bitcoin_network)
+            }
+        }
+    }
+}
+
+/// Derives the address at `path` out of a plain seed.
+pub fn derive_address(
+    seed: &[u8],
+    path: &DerivationPath,
+    network: Network,
+    script_type: ScriptType,
+) -> Result<Address, AccountError> {
+    let secp = secp256k1::Secp256k1::new();
+    let xprv = Xpriv::new_master(network.to_bitcoin_network_kind(), seed);
+
+    if let Err(err) = xprv {
+        return Err(AccountError::Path(err.to_string()));
+    }
+
+    let xprv = xprv.unwrap().derive_priv(&secp, path);
+
+    if let Err(err) = xprv {
+        return Err(AccountError::Derivation(err.to_string()));
+    }
+
+    let pk = PrivateKey::new(xprv.unwrap().private_key, network.to_bitcoin_network_kind());
+    Ok(script_type.address(&pk, network))
 }
 
 impl From<StoreAccountInput> for AccountModel {
     fn from(value: StoreAccountInput) -> Self {
+        let script_type = script_type(&value.address);
         Self {
             id: uuid::Uuid::new_v4().to_string(),
             wallet_id: value.wallet_id,
@@ -114,6 +334,8 @@ impl From<StoreAccountInput> for AccountModel {
             created_at: None,
             blockchain: value.blockchain.to_string(),
             network: value.network.to_string(),
+            script_type,
+            ..Default::default()
         }
     }
 }
@@ -125,6 +347,13 @@ impl AccountModel {
             "address": self.address,
             "network": self.network,
             "blockchain": self.blockchain,
+            "label": self.label,
+            "notes": self.notes,
+            "colour": self.colour,
+            "archived": self.archived,
+            "position": self.position,
+            "script_type": self.script_type,
+            "used": self.used,
         })
     }
 }
@@ -140,11 +369,12 @@ pub struct StoreAccountInput {
 
 type AccountInputBuilderResult = Result<StoreAccountInput, AccountError>;
 
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Clone)]
 pub struct AccountInputBuilder {
     pub path: DerivationPath,
     pub blockchain: Blockchain,
     pub network: Network,
+    pub script_type: ScriptType,
     pub encrypted_seed: String,
     pub wallet_id: String,
 }
@@ -187,38 +417,25 @@ impl AccountInputBuilder {
         self.network = network;
     }
 
+    pub fn script_type(&mut self, script_type: ScriptType) {
+        self.script_type = script_type;
+    }
+
     pub fn encrypted_seed(&mut self, encrypted_seed: &str) {
         self.encrypted_seed = encrypted_seed.to_string();
     }
 
     pub fn build(&self, key: AESKey) -> AccountInputBuilderResult {
-        let path = &self.path;
-        let secp = secp256k1::Secp256k1::new();
         let seed = decode(&self.encrypted_seed).unwrap();
         let seed = decrypt(&key, &seed);
         if let Err(err) = seed {
             return Err(AccountError::Building(err.to_string()));
         }
-        let bitcoin_network = self.blockchain.to_bitcoin_network();
-        let xprv = Xpriv::new_master(bitcoin_network, &seed.unwrap());
-
-        if let Err(err) = xprv {
-            return Err(AccountError::Path(err.to_string()));
-        }
-
-        let xprv = xprv.unwrap().derive_priv(&secp, path);
-
-        if let Err(err) = xprv {
-            return Err(AccountError::Derivation(err.to_string()));
-        }
 
-        let xprv = xprv.unwrap();
-
-        let pk = PrivateKey::new(xprv.private_key, self.network.to_bitcoin_network_kind());
-        let c_pk = CompressedPublicKey::from_private_key(&secp, &pk)
-            .expect("Failed while attempting to create compressed pub key from slice.");
         let address = match &self.blockchain {
-            Blockchain::Bitcoin => Address::p2wpkh(&c_pk, bitcoin_network),
+            Blockchain::Bitcoin => {
+                derive_address(&seed.unwrap(), &self.path, self.network, self.script_type)?
+            }
         };
 
         let encrypted_path = &self.path.to_string();
@@ -235,8 +452,39 @@ impl AccountInputBuilder {
             address: address.to_string(),
             blockchain: self.blockchain,
             encrypted_path,
-            network: Network::Mainnet,
+            network: self.network,
             wallet_id: self.wallet_id.clone(),
         })
     }
+
+    /// Builds `count` accounts at consecutive indexes, the first one at the path of the builder.
+    pub fn build_many(
+        &self,
+        key: AESKey,
+        count: u32,
+    ) -> Result<Vec<StoreAccountInput>, AccountError> {
+        let mut builder = self.clone();
+        let mut accounts = vec![];
+
+        for i in 0..count {
+            if i > 0 {
+                builder.path = next_path(&builder.path)?;
+            }
+            accounts.push(builder.build(key)?);
+        }
+
+        Ok(accounts)
+    }
+}
+
+/// Same path with its last index incremented.
+fn next_path(path: &DerivationPath) -> Result<DerivationPath, AccountError> {
+    let mut children = path.as_ref().to_vec();
+    let next = children.pop().map(|child| child.increment());
+    match next {
+        Some(Ok(child)) => children.push(child),
+        Some(Err(err)) => return Err(AccountError::Path(err.to_string())),
+        None => return Err(AccountError::Path("Empty path".to_string())),
+    }
+    Ok(DerivationPath::from(children))
 }
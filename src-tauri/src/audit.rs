@@ -0,0 +1,370 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use thiserror::Error;
+
+use crate::{
+    account::{AccountModel, StoreAccountInput},
+    multisig::MultisigModel,
+    policy::PolicyWalletModel,
+    vault_interface::{VaultInterface, VaultResult},
+    wallet::{StoreWalletInput, WalletModel},
+};
+
+/// `prev_hash` of the first event of a wallet.
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum AuditError {
+    #[error("Audit log of wallet {0} broken at event {1}: {2}")]
+    Broken(String, i64, String),
+}
+
+/// Sensitive operation recorded in the audit log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    AuthSucceeded,
+    AuthFailed,
+    AccountCreated,
+    AccountRemoved,
+    WalletRemoved,
+    /// Recorded in the log of the local cosigner wallet that removed the multisig.
+    MultisigRemoved,
+    /// Recorded in the log of the wallet holding a key of the policy wallet that removed it.
+    PolicyWalletRemoved,
+    Signed,
+    SecretExported,
+    /// The password checked out but re-hashing it with the current KDF parameters failed.
+    KdfUpgradeFailed,
+}
+
+impl fmt::Display for AuditAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let output = match self {
+            AuditAction::AuthSucceeded => "auth_succeeded",
+            AuditAction::AuthFailed => "auth_failed",
+            AuditAction::AccountCreated => "account_created",
+            AuditAction::AccountRemoved => "account_removed",
+            AuditAction::WalletRemoved => "wallet_removed",
+            AuditAction::MultisigRemoved => "multisig_removed",
+            AuditAction::PolicyWalletRemoved => "policy_wallet_removed",
+            AuditAction::Signed => "signed",
+            AuditAction::SecretExported => "secret_exported",
+            AuditAction::KdfUpgradeFailed => "kdf_upgrade_failed",
+        };
+        write!(f, "{}", output)
+    }
+}
+
+impl AuditAction {
+    pub fn from_string(text: &str) -> Option<Self> {
+        match text {
+            "auth_succeeded" => Some(AuditAction::AuthSucceeded),
+            "auth_failed" => Some(AuditAction::AuthFailed),
+            "account_created" => Some(AuditAction::AccountCreated),
+            "account_removed" => Some(AuditAction::AccountRemoved),
+            "wallet_removed" => Some(AuditAction::WalletRemoved),
+            "multisig_removed" => Some(AuditAction::MultisigRemoved),
+            "policy_wallet_removed" => Some(AuditAction::PolicyWalletRemoved),
+            "signed" => Some(AuditAction::Signed),
+            "secret_exported" => Some(AuditAction::SecretExported),
+            "kdf_upgrade_failed" => Some(AuditAction::KdfUpgradeFailed),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct StoreAuditEventInput {
+    pub wallet_id: String,
+    pub action: AuditAction,
+    /// What the action applied to, e.g. the address of an account. Never holds secrets.
+    pub details: Value,
+}
+
+/// Entry of the audit log. Events of a wallet form a chain, each one carrying the hash of the
+/// one before, so editing or dropping an event breaks every hash that follows.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditEvent {
+    pub wallet_id: String,
+    /// Position of the event in the chain of its wallet, starting at 1.
+    pub sequence: i64,
+    pub action: AuditAction,
+    /// JSON text of the details, kept as stored so hashes can be recomputed.
+    pub details: String,
+    /// Unix timestamp (seconds).
+    pub created_at: i64,
+    pub prev_hash: String,
+    pub hash: String,
+}
+
+impl AuditEvent {
+    /// Builds the event following `previous` in the chain of the wallet.
+    pub fn chain(
+        previous: Option<&AuditEvent>,
+        input: StoreAuditEventInput,
+        created_at: i64,
+    ) -> AuditEvent {
+        let mut event = AuditEvent {
+            wallet_id: input.wallet_id,
+            sequence: previous.map_or(1, |previous| previous.sequence + 1),
+            action: input.action,
+            details: input.details.to_string(),
+            created_at,
+            prev_hash: previous.map_or(GENESIS_HASH.to_string(), |previous| previous.hash.clone()),
+            hash: String::new(),
+        };
+        event.hash = event.compute_hash();
+        event
+    }
+
+    /// SHA-256 of every other field, serialized as a JSON array so fields can't bleed into
+    /// each other.
+    pub fn compute_hash(&self) -> String {
+        let content = json!([
+            self.prev_hash,
+            self.sequence,
+            self.wallet_id,
+            self.action.to_string(),
+            self.created_at,
+            self.details,
+        ]);
+
+        hex::encode(Sha256::digest(content.to_string().as_bytes()))
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "wallet_id": self.wallet_id,
+            "sequence": self.sequence,
+            "action": self.action,
+            "details": serde_json::from_str::<Value>(&self.details).unwrap_or(Value::Null),
+            "created_at": self.created_at,
+            "prev_hash": self.prev_hash,
+            "hash": self.hash,
+        })
+    }
+}
+
+/// Checks the events of one wallet, ordered by sequence, form an unbroken chain from its
+/// first event.
+pub fn verify(events: &[AuditEvent]) -> Result<(), AuditError> {
+    let mut previous: Option<&AuditEvent> = None;
+
+    for event in events {
+        let broken = |reason: &str| {
+            Err(AuditError::Broken(
+                event.wallet_id.clone(),
+                event.sequence,
+                reason.to_string(),
+            ))
+        };
+
+        let (sequence, prev_hash) = match previous {
+            Some(previous) => (previous.sequence + 1, previous.hash.as_str()),
+            None => (1, GENESIS_HASH),
+        };
+
+        if previous.is_some_and(|previous| previous.wallet_id != event.wallet_id) {
+            return broken("belongs to another wallet");
+        }
+        if event.sequence != sequence {
+            return broken(&format!("expected event {sequence}"));
+        }
+        if event.prev_hash != prev_hash {
+            return broken("does not follow the previous event");
+        }
+        if event.hash != event.compute_hash() {
+            return broken("content does not match its hash");
+        }
+
+        previous = Some(event);
+    }
+
+    Ok(())
+}
+
+/// Appends an event for the wallet, stamped with the current time.
+pub async fn record<V: VaultInterface + ?Sized>(
+    vault: &V,
+    wallet_id: &str,
+    action: AuditAction,
+    details: Value,
+) -> VaultResult<AuditEvent> {
+    vault
+        .append_audit_event(StoreAuditEventInput {
+            wallet_id: wallet_id.to_string(),
+            action,
+            details,
+        })
+        .await
+}
+
+fn account_event(account: &AccountModel, action: AuditAction) -> StoreAuditEventInput {
+    StoreAuditEventInput {
+        wallet_id: account.wallet_id.clone(),
+        action,
+        details: json!({ "account_id": account.id, "address": account.address }),
+    }
+}
+
+/// Inserts the accounts and records their creation, all of it or nothing.
+pub async fn insert_accounts<V: VaultInterface + ?Sized>(
+    vault: &V,
+    inputs: Vec<StoreAccountInput>,
+) -> VaultResult<Vec<AccountModel>> {
+    let mut tx = vault.begin().await?;
+    let mut accounts = vec![];
+
+    for input in inputs {
+        let account = tx.insert_account(input).await?;
+        tx.append_audit_event(account_event(&account, AuditAction::AccountCreated))
+            .await?;
+        accounts.push(account);
+    }
+
+    tx.commit().await?;
+    Ok(accounts)
+}
+
+/// Removes the account and records it, all of it or nothing.
+pub async fn remove_account<V: VaultInterface + ?Sized>(
+    vault: &V,
+    account: &AccountModel,
+) -> VaultResult<()> {
+    let mut tx = vault.begin().await?;
+    tx.remove_account_by_id(&account.id).await?;
+    tx.append_audit_event(account_event(account, AuditAction::AccountRemoved))
+        .await?;
+    tx.commit().await
+}
+
+/// Inserts the BIP-85 child wallet derived at `path` and records the export in the log of the
+/// parent wallet, all of it or nothing.
+pub async fn insert_child_wallet<V: VaultInterface + ?Sized>(
+    vault: &V,
+    input: StoreWalletInput,
+    parent_id: &str,
+    path: &str,
+) -> VaultResult<WalletModel> {
+    let mut tx = vault.begin().await?;
+    let wallet = tx.insert_wallet(input).await?;
+    tx.append_audit_event(StoreAuditEventInput {
+        wallet_id: parent_id.to_string(),
+        action: AuditAction::SecretExported,
+        details: json!({
+            "application": "bip39",
+            "path": path,
+            "child_wallet_id": wallet.id,
+        }),
+    })
+    .await?;
+    tx.commit().await?;
+    Ok(wallet)
+}
+
+/// Removes the wallet and records it, all of it or nothing. Its audit log is kept.
+pub async fn remove_wallet<V: VaultInterface + ?Sized>(
+    vault: &V,
+    wallet: &WalletModel,
+) -> VaultResult<()> {
+    let mut tx = vault.begin().await?;
+    tx.remove_wallet_by_id(&wallet.id).await?;
+    tx.append_audit_event(StoreAuditEventInput {
+        wallet_id: wallet.id.clone(),
+        action: AuditAction::WalletRemoved,
+        details: json!({ "name": wallet.name }),
+    })
+    .await?;
+    tx.commit().await
+}
+
+/// Removes the multisig and records it in the log of the cosigner wallet, all of it or nothing.
+pub async fn remove_multisig<V: VaultInterface + ?Sized>(
+    vault: &V,
+    multisig: &MultisigModel,
+    wallet_id: &str,
+) -> VaultResult<()> {
+    let mut tx = vault.begin().await?;
+    tx.remove_multisig_by_id(&multisig.id).await?;
+    tx.append_audit_event(StoreAuditEventInput {
+        wallet_id: wallet_id.to_string(),
+        action: AuditAction::MultisigRemoved,
+        details: json!({ "multisig_id": multisig.id, "name": multisig.name }),
+    })
+    .await?;
+    tx.commit().await
+}
+
+/// Removes the policy wallet and records it in the log of the wallet holding one of its keys,
+/// all of it or nothing.
+pub async fn remove_policy_wallet<V: VaultInterface + ?Sized>(
+    vault: &V,
+    policy: &PolicyWalletModel,
+    wallet_id: &str,
+) -> VaultResult<()> {
+    let mut tx = vault.begin().await?;
+    tx.remove_policy_wallet_by_id(&policy.id).await?;
+    tx.append_audit_event(StoreAuditEventInput {
+        wallet_id: wallet_id.to_string(),
+        action: AuditAction::PolicyWalletRemoved,
+        details: json!({ "policy_wallet_id": policy.id, "name": policy.name }),
+    })
+    .await?;
+    tx.commit().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chain(count: usize) -> Vec<AuditEvent> {
+        let mut events: Vec<AuditEvent> = vec![];
+
+        for i in 0..count {
+            let input = StoreAuditEventInput {
+                wallet_id: "wallet".to_string(),
+                action: AuditAction::AccountCreated,
+                details: json!({ "address": format!("address-{i}") }),
+            };
+            events.push(AuditEvent::chain(
+                events.last(),
+                input,
+                1_700_000_000 + i as i64,
+            ));
+        }
+
+        events
+    }
+
+    #[test]
+    fn links_events_together() {
+        let events = chain(3);
+
+        assert_eq!(events[0].sequence, 1);
+        assert_eq!(events[0].prev_hash, GENESIS_HASH);
+        assert_eq!(events[2].prev_hash, events[1].hash);
+        assert_eq!(verify(&events), Ok(()));
+    }
+
+    #[test]
+    fn detects_tampering() {
+        let mut events = chain(3);
+        events[1].details = json!({ "address": "elsewhere" }).to_string();
+        assert!(matches!(verify(&events), Err(AuditError::Broken(_, 2, _))));
+
+        // Recomputing the hash of the edited event doesn't help, the next one points to the old one.
+        events[1].hash = events[1].compute_hash();
+        assert!(matches!(verify(&events), Err(AuditError::Broken(_, 3, _))));
+
+        let mut events = chain(3);
+        events.remove(1);
+        assert!(matches!(verify(&events), Err(AuditError::Broken(_, 3, _))));
+
+        let mut events = chain(3);
+        events[0].action = AuditAction::AuthFailed;
+        assert!(verify(&events).is_err());
+    }
+}
@@ -0,0 +1,90 @@
+//! Prints the address book of a fixture as JSON, straight from a mnemonic and without any vault,
+//! e.g. to check a test against the addresses `seed_fixture` creates:
+//!
+//! ```sh
+//! cargo run --bin address-book -- --network regtest --script-type p2wpkh --count 10
+//! ```
+
+use dev_wallet::{
+    account::{Network, ScriptType},
+    fixture::{self, FixtureSpec},
+};
+use std::{env, process};
+
+const USAGE: &str = "Usage: address-book [--mnemonic <words>] [--passphrase <text>] \
+                     [--network <mainnet|testnet|signet|regtest>]... \
+                     [--script-type <p2pkh|p2sh_p2wpkh|p2wpkh|p2tr>]... \
+                     [--count <n>] [--account <n>]";
+
+fn fail(message: &str) -> ! {
+    eprintln!("{message}\n{USAGE}");
+    process::exit(2);
+}
+
+fn parse_network(text: &str) -> Network {
+    Network::ALL
+        .into_iter()
+        .find(|network| network.to_string().eq_ignore_ascii_case(text))
+        .unwrap_or_else(|| fail(&format!("Unknown network {text}")))
+}
+
+fn parse_script_type(text: &str) -> ScriptType {
+    serde_json::from_value(serde_json::json!(text))
+        .unwrap_or_else(|_| fail(&format!("Unknown script type {text}")))
+}
+
+fn parse_number(text: &str) -> u32 {
+    text.parse()
+        .unwrap_or_else(|_| fail(&format!("Not a number: {text}")))
+}
+
+fn main() {
+    let mut mnemonic = env::var("DEV_MNEMONIC").ok();
+    let mut passphrase = String::new();
+    let mut spec = FixtureSpec::default();
+    let mut networks = vec![];
+    let mut script_types = vec![];
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "-h" || arg == "--help" {
+            println!("{USAGE}");
+            return;
+        }
+
+        let value = args
+            .next()
+            .unwrap_or_else(|| fail(&format!("Missing value of {arg}")));
+
+        match arg.as_str() {
+            "--mnemonic" => mnemonic = Some(value),
+            "--passphrase" => passphrase = value,
+            "--network" => networks.push(parse_network(&value)),
+            "--script-type" => script_types.push(parse_script_type(&value)),
+            "--count" => spec.count = parse_number(&value),
+            "--account" => spec.account = parse_number(&value),
+            _ => fail(&format!("Unknown option {arg}")),
+        }
+    }
+
+    if !networks.is_empty() {
+        spec.networks = networks;
+    }
+    if !script_types.is_empty() {
+        spec.script_types = script_types;
+    }
+
+    let seed = fixture::dev_mnemonic(mnemonic.as_deref()).map(|m| m.to_seed(&passphrase));
+    let entries = seed.and_then(|seed| spec.derive(&seed));
+
+    match entries {
+        Ok(entries) => println!(
+            "{}",
+            serde_json::to_string_pretty(&fixture::address_book(&entries)).unwrap()
+        ),
+        Err(err) => {
+            eprintln!("{err}");
+            process::exit(1);
+        }
+    }
+}
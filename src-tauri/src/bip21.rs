@@ -0,0 +1,447 @@
+use bitcoin::{address::NetworkUnchecked, Address, Amount, Denomination};
+use serde_json::{json, Value};
+use std::{collections::HashSet, fmt, str::FromStr};
+use thiserror::Error;
+
+use crate::account::{self, Network};
+
+/// Scheme of BIP-21 URIs, matched case-insensitively when parsing.
+pub const SCHEME: &str = "bitcoin";
+
+// Amounts are in BTC, so anything past the satoshi is a typo or a unit mix-up.
+const MAX_DECIMALS: usize = 8;
+
+#[derive(Error, Debug)]
+pub enum Bip21Error {
+    #[error("Not a bitcoin: URI")]
+    Scheme,
+    #[error("Invalid address: {0}")]
+    Address(String),
+    #[error("Address of another network, expected {0}")]
+    WrongNetwork(Network),
+    #[error("Invalid amount {0}: {1}")]
+    Amount(String, &'static str),
+    #[error("Invalid {0}: {1}")]
+    Parameter(&'static str, String),
+    #[error("Parameter {0} is given more than once")]
+    Duplicate(String),
+    #[error("Unsupported required parameter {0}")]
+    Required(String),
+}
+
+pub type Bip21Result<T> = Result<T, Bip21Error>;
+
+/// Payment a `bitcoin:` URI asks for, built for one of our addresses or parsed from someone
+/// else's, in which case it pre-fills the transaction to send.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PaymentRequest {
+    pub address: String,
+    pub network: Network,
+    pub amount: Option<Amount>,
+    pub label: Option<String>,
+    pub message: Option<String>,
+    /// BOLT11 invoice or BOLT12 offer the payer may use instead of the address.
+    pub lightning: Option<String>,
+    /// BIP-78 payjoin endpoint.
+    pub pj: Option<String>,
+}
+
+impl PaymentRequest {
+    /// Request paying `address`, which has to belong to `network`.
+    pub fn new(address: &str, network: Network) -> Bip21Result<Self> {
+        let address = parse_address(address, network)?;
+
+        Ok(PaymentRequest {
+            address: address.to_string(),
+            network,
+            amount: None,
+            label: None,
+            message: None,
+            lightning: None,
+            pj: None,
+        })
+    }
+
+    /// Checks the parts a URI doesn't constrain by itself, so a request is only ever built or
+    /// parsed valid.
+    pub fn validate(&self) -> Bip21Result<()> {
+        parse_address(&self.address, self.network)?;
+
+        if self.amount == Some(Amount::ZERO) {
+            return Err(Bip21Error::Amount("0".to_string(), "must be positive"));
+        }
+
+        if let Some(lightning) = &self.lightning {
+            check_lightning(lightning, self.network)?;
+        }
+
+        if let Some(pj) = &self.pj {
+            check_payjoin(pj)?;
+        }
+
+        Ok(())
+    }
+
+    /// Parses `uri`, rejecting addresses of any network but `network`, amounts finer than a
+    /// satoshi and `req-` parameters, which BIP-21 forbids ignoring.
+    pub fn parse(uri: &str, network: Network) -> Bip21Result<Self> {
+        let uri = uri.trim();
+        let rest = match uri.split_once(':') {
+            Some((scheme, rest)) if scheme.eq_ignore_ascii_case(SCHEME) => rest,
+            _ => return Err(Bip21Error::Scheme),
+        };
+        let (address, query) = rest.split_once('?').unwrap_or((rest, ""));
+        let mut request = PaymentRequest::new(&decode(address, "address")?, network)?;
+        let mut seen = HashSet::new();
+
+        for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            let key = decode(key, "parameter")?.to_lowercase();
+
+            if !seen.insert(key.clone()) {
+                return Err(Bip21Error::Duplicate(key));
+            }
+
+            let value = decode(value, "parameter")?;
+
+            match key.as_str() {
+                "amount" => request.amount = Some(parse_amount(&value)?),
+                "label" => request.label = Some(value),
+                "message" => request.message = Some(value),
+                "lightning" => request.lightning = Some(value),
+                "pj" => request.pj = Some(value),
+                _ if key.starts_with("req-") => return Err(Bip21Error::Required(key)),
+                _ => {}
+            }
+        }
+
+        request.validate()?;
+        Ok(request)
+    }
+
+    /// The request as the frontend pre-fills a transaction with, amounts in satoshis.
+    pub fn to_json(&self) -> Value {
+        json!({
+            "uri": self.to_string(),
+            "address": self.address,
+            "network": self.network,
+            "script_type": account::script_type(&self.address),
+            "amount": self.amount.map(Amount::to_sat),
+            "label": self.label,
+            "message": self.message,
+            "lightning": self.lightning,
+            "pj": self.pj,
+        })
+    }
+}
+
+impl fmt::Display for PaymentRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", SCHEME, self.address)?;
+
+        let amount = self.amount.map(format_amount);
+        let params = [
+            ("amount", amount.as_ref()),
+            ("label", self.label.as_ref()),
+            ("message", self.message.as_ref()),
+            ("lightning", self.lightning.as_ref()),
+            ("pj", self.pj.as_ref()),
+        ];
+        let mut separator = '?';
+
+        for (key, value) in params {
+            if let Some(value) = value {
+                write!(f, "{}{}={}", separator, key, encode(value))?;
+                separator = '&';
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn parse_address(address: &str, network: Network) -> Bip21Result<Address> {
+    let address = Address::<NetworkUnchecked>::from_str(address);
+
+    if let Err(err) = address {
+        return Err(Bip21Error::Address(err.to_string()));
+    }
+
+    match address
+        .unwrap()
+        .require_network(network.to_bitcoin_network())
+    {
+        Ok(address) => Ok(address),
+        Err(_) => Err(Bip21Error::WrongNetwork(network)),
+    }
+}
+
+/// Amount in BTC, as plain decimal digits: no sign, exponent or thousands separator.
+pub fn parse_amount(text: &str) -> Bip21Result<Amount> {
+    let invalid = |reason| Err(Bip21Error::Amount(text.to_string(), reason));
+    let (whole, fraction) = text.split_once('.').unwrap_or((text, ""));
+
+    if whole.is_empty() && fraction.is_empty() {
+        return invalid("no digits");
+    }
+
+    if !whole
+        .chars()
+        .chain(fraction.chars())
+        .all(|c| c.is_ascii_digit())
+    {
+        return invalid("only digits and a decimal point are allowed");
+    }
+
+    if fraction.len() > MAX_DECIMALS {
+        return invalid("more than 8 decimals");
+    }
+
+    match Amount::from_str_in(text, Denomination::Bitcoin) {
+        Ok(amount) if amount == Amount::ZERO => invalid("must be positive"),
+        Ok(amount) if amount <= Amount::MAX_MONEY => Ok(amount),
+        _ => invalid("more than 21 million bitcoin"),
+    }
+}
+
+/// `amount` in BTC without trailing zeros, `0.001` rather than `0.00100000`.
+pub fn format_amount(amount: Amount) -> String {
+    let text = amount.to_string_in(Denomination::Bitcoin);
+
+    match text.contains('.') {
+        true => text.trim_end_matches('0').trim_end_matches('.').to_string(),
+        false => text,
+    }
+}
+
+fn check_lightning(lightning: &str, network: Network) -> Bip21Result<()> {
+    let lower = lightning.to_lowercase();
+
+    // Offers don't name their chain in the prefix.
+    if lower.starts_with("lno1") {
+        return Ok(());
+    }
+
+    // Invoice prefixes, the longer ones first since `lnbc` starts `lnbcrt` too.
+    let invoice_network = [
+        ("lnbcrt", Network::Regtest),
+        ("lntbs", Network::Signet),
+        ("lntb", Network::Testnet),
+        ("lnbc", Network::Mainnet),
+    ]
+    .into_iter()
+    .find(|(prefix, _)| lower.starts_with(prefix));
+
+    match invoice_network {
+        Some((_, invoice_network)) if invoice_network == network => Ok(()),
+        Some(_) => Err(Bip21Error::Parameter(
+            "lightning",
+            format!("invoice of another network, expected {}", network),
+        )),
+        None => Err(Bip21Error::Parameter(
+            "lightning",
+            "not a BOLT11 invoice or BOLT12 offer".to_string(),
+        )),
+    }
+}
+
+fn check_payjoin(pj: &str) -> Bip21Result<()> {
+    let lower = pj.to_lowercase();
+    let (scheme, rest) = lower.split_once("://").unwrap_or(("", ""));
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or("");
+    let host = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host);
+    let host = host.split(':').next().unwrap_or("");
+
+    match scheme {
+        _ if host.is_empty() => Err(Bip21Error::Parameter("pj", "URL has no host".to_string())),
+        "https" => Ok(()),
+        // BIP-78 only allows plain HTTP to onion services, which encrypt on their own.
+        "http" if host.ends_with(".onion") => Ok(()),
+        _ => Err(Bip21Error::Parameter(
+            "pj",
+            "must be an https or onion URL".to_string(),
+        )),
+    }
+}
+
+/// Percent-encodes everything but the unreserved characters of RFC 3986.
+fn encode(text: &str) -> String {
+    let mut encoded = String::with_capacity(text.len());
+
+    for byte in text.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+
+    encoded
+}
+
+// `+` is left alone, BIP-21 follows RFC 3986 rather than HTML forms.
+fn decode(text: &str, what: &'static str) -> Bip21Result<String> {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] != b'%' {
+            decoded.push(bytes[i]);
+            i += 1;
+            continue;
+        }
+
+        let byte = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+
+        match byte {
+            Some(byte) => decoded.push(byte),
+            None => {
+                return Err(Bip21Error::Parameter(
+                    what,
+                    format!("bad percent encoding in {}", text),
+                ))
+            }
+        }
+
+        i += 3;
+    }
+
+    match String::from_utf8(decoded) {
+        Ok(decoded) => Ok(decoded),
+        Err(_) => Err(Bip21Error::Parameter(what, format!("{} isn't UTF-8", text))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The examples of BIP-21 use an address with a bad checksum, so they're run against
+    // another P2PKH one. The segwit addresses are from BIP-173.
+    const LEGACY: &str = "1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2";
+    const SEGWIT: &str = "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4";
+    const REGTEST: &str = "bcrt1qw508d6qejxtdg4y5r3zarvary0c5xw7kygt080";
+
+    fn parse(uri: &str) -> Bip21Result<PaymentRequest> {
+        PaymentRequest::parse(uri, Network::Mainnet)
+    }
+
+    #[test]
+    fn parses_bip21_examples() {
+        let request = parse(&format!("bitcoin:{}?label=Luke-Jr", LEGACY)).unwrap();
+        assert_eq!(request.address, LEGACY);
+        assert_eq!(request.label.as_deref(), Some("Luke-Jr"));
+        assert_eq!(request.amount, None);
+
+        let request = parse(&format!("bitcoin:{}?amount=20.3&label=Luke-Jr", LEGACY)).unwrap();
+        assert_eq!(request.amount, Some(Amount::from_sat(2_030_000_000)));
+
+        let request = parse(&format!(
+            "BITCOIN:{}?amount=50&label=Luke-Jr&message=Donation%20for%20project%20xyz",
+            LEGACY
+        ))
+        .unwrap();
+        assert_eq!(request.amount, Some(Amount::from_int_btc(50)));
+        assert_eq!(request.message.as_deref(), Some("Donation for project xyz"));
+
+        let ignored = format!(
+            "bitcoin:{}?somethingyoudontunderstand=50&somethingelseyoudontget=999",
+            LEGACY
+        );
+        assert!(parse(&ignored).is_ok());
+
+        let required = format!(
+            "bitcoin:{}?req-somethingyoudontunderstand=50&req-somethingelseyoudontget=999",
+            LEGACY
+        );
+        assert!(matches!(parse(&required), Err(Bip21Error::Required(_))));
+    }
+
+    #[test]
+    fn generates_and_round_trips() {
+        let mut request = PaymentRequest::new(SEGWIT, Network::Mainnet).unwrap();
+        assert_eq!(request.to_string(), format!("bitcoin:{}", SEGWIT));
+
+        request.amount = Some(Amount::from_sat(100_000));
+        request.label = Some("Café & bar".to_string());
+        request.message = Some("50/50 split".to_string());
+        request.lightning = Some("lnbc1500n1pdummy".to_string());
+        request.pj = Some("https://example.com/pj?v=1".to_string());
+        request.validate().unwrap();
+
+        let uri = request.to_string();
+        assert_eq!(
+            uri,
+            format!(
+                "bitcoin:{}?amount=0.001&label=Caf%C3%A9%20%26%20bar&message=50%2F50%20split\
+                 &lightning=lnbc1500n1pdummy&pj=https%3A%2F%2Fexample.com%2Fpj%3Fv%3D1",
+                SEGWIT
+            )
+        );
+        assert_eq!(parse(&uri).unwrap(), request);
+    }
+
+    #[test]
+    fn checks_amount_precision() {
+        assert_eq!(parse_amount("1").unwrap(), Amount::from_int_btc(1));
+        assert_eq!(parse_amount(".5").unwrap(), Amount::from_sat(50_000_000));
+        assert_eq!(parse_amount("0.00000001").unwrap(), Amount::ONE_SAT);
+
+        for amount in [
+            "0.000000001",
+            "1e-3",
+            "1,5",
+            "-1",
+            "+1",
+            "",
+            ".",
+            "0",
+            "21000001",
+        ] {
+            assert!(parse_amount(amount).is_err(), "{}", amount);
+        }
+
+        assert_eq!(format_amount(Amount::from_int_btc(2)), "2");
+        assert_eq!(format_amount(Amount::from_sat(2_030_000_000)), "20.3");
+        assert_eq!(format_amount(Amount::ONE_SAT), "0.00000001");
+    }
+
+    #[test]
+    fn rejects_other_networks() {
+        let uri = format!("bitcoin:{}", REGTEST);
+        assert!(matches!(parse(&uri), Err(Bip21Error::WrongNetwork(_))));
+        assert!(PaymentRequest::parse(&uri, Network::Regtest).is_ok());
+
+        let invoice = format!("bitcoin:{}?lightning=lntb1pdummy", SEGWIT);
+        assert!(matches!(
+            parse(&invoice),
+            Err(Bip21Error::Parameter("lightning", _))
+        ));
+    }
+
+    #[test]
+    fn rejects_malformed_uris() {
+        let uris = [
+            format!("litecoin:{}", LEGACY),
+            format!("bitcoin:{}?amount=1&amount=2", LEGACY),
+            format!("bitcoin:{}?label=%E2%28", LEGACY),
+            format!("bitcoin:{}?label=%2", LEGACY),
+            format!("bitcoin:{}?pj=http://example.com", LEGACY),
+            "bitcoin:notanaddress".to_string(),
+        ];
+
+        for uri in uris {
+            assert!(parse(&uri).is_err(), "{}", uri);
+        }
+
+        let onion = format!("bitcoin:{}?pj=http://payjoin.onion/pj", LEGACY);
+        assert!(parse(&onion).is_ok());
+    }
+}
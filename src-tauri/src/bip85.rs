@@ -0,0 +1,393 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use bip39::{Language, Mnemonic};
+use bitcoin::{
+    bip32::{ChainCode, ChildNumber, DerivationPath, Fingerprint, Xpriv},
+    hashes::{hmac, sha512, Hash, HashEngine},
+    secp256k1, NetworkKind, PrivateKey,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use thiserror::Error;
+use zeroize::Zeroizing;
+
+use crate::mnemonic::{entropy_length, language_name, parse_language};
+
+/// Purpose of every BIP-85 path, "BIPE" in ASCII.
+pub const PURPOSE: u32 = 83696968;
+
+const HMAC_KEY: &[u8] = b"bip-entropy-from-k";
+
+// Alphabet of RFC 1924, which BIP-85 passwords are encoded with.
+const BASE85: &[u8; 85] =
+    b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz!#$%&()*+-;<=>?@^_`{|}~";
+
+#[derive(Error, Debug)]
+pub enum Bip85Error {
+    #[error("Invalid {0}: {1}")]
+    Parameter(&'static str, String),
+    #[error("Failed deriving child entropy: {0}")]
+    Derivation(String),
+}
+
+pub type Bip85Result<T> = Result<T, Bip85Error>;
+
+/// What the child entropy is turned into, each application having a path of its own.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "application", rename_all = "snake_case")]
+pub enum Application {
+    /// Mnemonic of 12, 15, 18, 21 or 24 words, in any language `mnemonic` supports.
+    Bip39 {
+        words: usize,
+        #[serde(default = "english")]
+        language: String,
+        index: u32,
+    },
+    /// Private key of a single key wallet, as a compressed mainnet WIF.
+    Wif { index: u32 },
+    /// Master key of a BIP-32 wallet.
+    Xprv { index: u32 },
+    /// 16 to 64 bytes, hex encoded.
+    Hex { bytes: u32, index: u32 },
+    /// Base64 password of 20 to 86 characters.
+    PasswordBase64 { length: u32, index: u32 },
+    /// Base85 password of 10 to 80 characters.
+    PasswordBase85 { length: u32, index: u32 },
+}
+
+fn english() -> String {
+    language_name(Language::English).to_string()
+}
+
+/// Code BIP-85 gives each language.
+fn language_code(name: &str) -> Bip85Result<u32> {
+    let code = match name {
+        "english" => 0,
+        "japanese" => 1,
+        "korean" => 2,
+        "spanish" => 3,
+        "simplified_chinese" => 4,
+        "traditional_chinese" => 5,
+        "french" => 6,
+        "italian" => 7,
+        "czech" => 8,
+        _ => return Err(Bip85Error::Parameter("language", name.to_string())),
+    };
+    Ok(code)
+}
+
+fn in_range(name: &'static str, value: u32, min: u32, max: u32) -> Bip85Result<u32> {
+    if value < min || value > max {
+        return Err(Bip85Error::Parameter(
+            name,
+            format!("{value}, expected {min} to {max}"),
+        ));
+    }
+    Ok(value)
+}
+
+impl Application {
+    /// Indexes after the purpose, e.g. `39'/0'/12'/0'` for the first English mnemonic of
+    /// 12 words.
+    fn indexes(&self) -> Bip85Result<Vec<u32>> {
+        let indexes = match self {
+            Application::Bip39 {
+                words,
+                language,
+                index,
+            } => {
+                if let Err(err) = entropy_length(*words) {
+                    return Err(Bip85Error::Parameter("word count", err.to_string()));
+                }
+                vec![39, language_code(language)?, *words as u32, *index]
+            }
+            Application::Wif { index } => vec![2, *index],
+            Application::Xprv { index } => vec![32, *index],
+            Application::Hex { bytes, index } => {
+                vec![128169, in_range("byte count", *bytes, 16, 64)?, *index]
+            }
+            Application::PasswordBase64 { length, index } => {
+                vec![
+                    707764,
+                    in_range("password length", *length, 20, 86)?,
+                    *index,
+                ]
+            }
+            Application::PasswordBase85 { length, index } => {
+                vec![
+                    707785,
+                    in_range("password length", *length, 10, 80)?,
+                    *index,
+                ]
+            }
+        };
+        Ok(indexes)
+    }
+
+    pub fn path(&self) -> Bip85Result<DerivationPath> {
+        let mut path = vec![];
+
+        for index in [vec![PURPOSE], self.indexes()?].concat() {
+            match ChildNumber::from_hardened_idx(index) {
+                Ok(child) => path.push(child),
+                Err(err) => return Err(Bip85Error::Parameter("index", err.to_string())),
+            }
+        }
+
+        Ok(DerivationPath::from(path))
+    }
+
+    /// Name recorded in the audit log when the secret is exported.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Application::Bip39 { .. } => "bip39",
+            Application::Wif { .. } => "wif",
+            Application::Xprv { .. } => "xprv",
+            Application::Hex { .. } => "hex",
+            Application::PasswordBase64 { .. } => "password_base64",
+            Application::PasswordBase85 { .. } => "password_base85",
+        }
+    }
+}
+
+/// BIP-32 master key of the seed, the root of every BIP-85 path.
+pub fn master_key(seed: &[u8]) -> Bip85Result<Xpriv> {
+    Xpriv::new_master(NetworkKind::Main, seed)
+        .map_err(|err| Bip85Error::Derivation(err.to_string()))
+}
+
+/// The 64 bytes of entropy BIP-85 derives at `path` from `master`.
+pub fn derive_entropy(master: &Xpriv, path: &DerivationPath) -> Bip85Result<Zeroizing<[u8; 64]>> {
+    let secp = secp256k1::Secp256k1::new();
+    let child = master.derive_priv(&secp, path);
+
+    if let Err(err) = child {
+        return Err(Bip85Error::Derivation(err.to_string()));
+    }
+
+    let mut engine = hmac::HmacEngine::<sha512::Hash>::new(HMAC_KEY);
+    engine.input(&child.unwrap().private_key.secret_bytes());
+    let hmac = hmac::Hmac::<sha512::Hash>::from_engine(engine);
+
+    Ok(Zeroizing::new(hmac.to_byte_array()))
+}
+
+/// Child mnemonic of the BIP39 application.
+pub fn derive_mnemonic(
+    seed: &[u8],
+    words: usize,
+    language: &str,
+    index: u32,
+) -> Bip85Result<Mnemonic> {
+    let application = Application::Bip39 {
+        words,
+        language: language.to_string(),
+        index,
+    };
+    let entropy = derive_entropy(&master_key(seed)?, &application.path()?)?;
+
+    mnemonic_from_entropy(&*entropy, words, language)
+}
+
+fn mnemonic_from_entropy(entropy: &[u8], words: usize, language: &str) -> Bip85Result<Mnemonic> {
+    let length = entropy_length(words)
+        .map_err(|err| Bip85Error::Parameter("word count", err.to_string()))?;
+    let language = parse_language(language)
+        .map_err(|err| Bip85Error::Parameter("language", err.to_string()))?;
+
+    Mnemonic::from_entropy_in(language, &entropy[..length])
+        .map_err(|err| Bip85Error::Derivation(err.to_string()))
+}
+
+fn base85(data: &[u8]) -> String {
+    let mut output = String::new();
+
+    for chunk in data.chunks(4) {
+        let mut word = [0u8; 4];
+        word[..chunk.len()].copy_from_slice(chunk);
+        let mut value = u32::from_be_bytes(word);
+
+        let mut digits = [0u8; 5];
+        for digit in digits.iter_mut().rev() {
+            *digit = BASE85[(value % 85) as usize];
+            value /= 85;
+        }
+        // A partial chunk only needs one more digit than it has bytes.
+        output.extend(digits[..chunk.len() + 1].iter().map(|c| *c as char));
+    }
+
+    output
+}
+
+/// Secret of the application, formatted the way BIP-85 specifies.
+pub fn derive(master: &Xpriv, application: &Application) -> Bip85Result<Zeroizing<String>> {
+    let entropy = derive_entropy(master, &application.path()?)?;
+
+    let secret = match application {
+        Application::Bip39 {
+            words, language, ..
+        } => mnemonic_from_entropy(&*entropy, *words, language)?.to_string(),
+        Application::Wif { .. } => {
+            let key = secp256k1::SecretKey::from_slice(&entropy[..32]);
+            if let Err(err) = key {
+                return Err(Bip85Error::Derivation(err.to_string()));
+            }
+            PrivateKey::new(key.unwrap(), NetworkKind::Main).to_wif()
+        }
+        Application::Xprv { .. } => {
+            let key = secp256k1::SecretKey::from_slice(&entropy[32..]);
+            if let Err(err) = key {
+                return Err(Bip85Error::Derivation(err.to_string()));
+            }
+            let chain_code: [u8; 32] = entropy[..32].try_into().unwrap();
+            Xpriv {
+                network: NetworkKind::Main,
+                depth: 0,
+                parent_fingerprint: Fingerprint::default(),
+                child_number: ChildNumber::from_normal_idx(0).unwrap(),
+                private_key: key.unwrap(),
+                chain_code: ChainCode::from(chain_code),
+            }
+            .to_string()
+        }
+        Application::Hex { bytes, .. } => hex::encode(&entropy[..*bytes as usize]),
+        Application::PasswordBase64 { length, .. } => {
+            STANDARD.encode(&entropy[..])[..*length as usize].to_string()
+        }
+        Application::PasswordBase85 { length, .. } => {
+            base85(&*entropy)[..*length as usize].to_string()
+        }
+    };
+
+    Ok(Zeroizing::new(secret))
+}
+
+/// What the frontend gets back from an export, the path allowing the secret to be derived
+/// again with any other BIP-85 tool.
+pub fn to_json(application: &Application, secret: &str) -> Bip85Result<Value> {
+    Ok(json!({
+        "application": application.name(),
+        "path": format!("m/{}", application.path()?),
+        "secret": secret,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    // Master key of every BIP-85 test vector.
+    const MASTER: &str = "xprv9s21ZrQH143K2LBWUUQRFXhucrQqBpKdRRxNVq2zBqsx8HVqFk2uYo8kmbaLLHRdqtQpUm98uKfu3vca1LqdGhUtyoFnCNkfmXRyPXLjbKb";
+
+    fn secret(application: Application) -> String {
+        let master = Xpriv::from_str(MASTER).unwrap();
+        derive(&master, &application).unwrap().to_string()
+    }
+
+    #[test]
+    fn matches_entropy_vectors() {
+        let master = Xpriv::from_str(MASTER).unwrap();
+        let entropy = |path| {
+            let path = DerivationPath::from_str(path).unwrap();
+            hex::encode(*derive_entropy(&master, &path).unwrap())
+        };
+
+        assert_eq!(
+            entropy("m/83696968'/0'/0'"),
+            "efecfbccffea313214232d29e71563d941229afb4338c21f9517c41aaa0d16f0\
+             0b83d2a09ef747e7a64e8e2bd5a14869e693da66ce94ac2da570ab7ee48618f7"
+        );
+        assert_eq!(
+            entropy("m/83696968'/0'/1'"),
+            "70c6e3e8ebee8dc4c0dbba66076819bb8c09672527c4277ca8729532ad711872\
+             218f826919f6b67218adde99018a6df9095ab2b58d803b5b93ec9802085a690e"
+        );
+    }
+
+    #[test]
+    fn matches_application_vectors() {
+        let bip39 = |words| Application::Bip39 {
+            words,
+            language: english(),
+            index: 0,
+        };
+
+        assert_eq!(
+            secret(bip39(12)),
+            "girl mad pet galaxy egg matter matrix prison refuse sense ordinary nose"
+        );
+        assert_eq!(
+            secret(bip39(18)),
+            "near account window bike charge season chef number sketch tomorrow excuse sniff \
+             circle vital hockey outdoor supply token"
+        );
+        assert_eq!(
+            secret(bip39(24)),
+            "puppy ocean match cereal symbol another shed magic wrap hammer bulb intact gadget \
+             divorce twin tonight reason outdoor destroy simple truth cigar social volcano"
+        );
+        assert_eq!(
+            secret(Application::Wif { index: 0 }),
+            "Kzyv4uF39d4Jrw2W7UryTHwZr1zQVNk4dAFyqE6BuMrMh1Za7uhp"
+        );
+        assert_eq!(
+            secret(Application::Xprv { index: 0 }),
+            "xprv9s21ZrQH143K2srSbCSg4m4kLvPMzcWydgmKEnMmoZUurYuBuYG46c6P71UGXMzmriLzCCBvKQWBUv3vPB3m1SATMhp3uEjXHJ42jFg7myX"
+        );
+        assert_eq!(
+            secret(Application::Hex {
+                bytes: 64,
+                index: 0
+            }),
+            "492db4698cf3b73a5a24998aa3e9d7fa96275d85724a91e71aa2d645442f8785\
+             55d078fd1f1f67e368976f04137b1f7a0d19232136ca50c44614af72b5582a5c"
+        );
+        assert_eq!(
+            secret(Application::PasswordBase64 {
+                length: 21,
+                index: 0
+            }),
+            "dKLoepugzdVJvdL56ogNV"
+        );
+        assert_eq!(
+            secret(Application::PasswordBase85 {
+                length: 12,
+                index: 0
+            }),
+            "_s`{TW89)i4`"
+        );
+    }
+
+    #[test]
+    fn rejects_out_of_range_parameters() {
+        let invalid = [
+            Application::Bip39 {
+                words: 13,
+                language: english(),
+                index: 0,
+            },
+            Application::Bip39 {
+                words: 12,
+                language: "portuguese".to_string(),
+                index: 0,
+            },
+            Application::Hex {
+                bytes: 65,
+                index: 0,
+            },
+            Application::PasswordBase64 {
+                length: 19,
+                index: 0,
+            },
+            Application::PasswordBase85 {
+                length: 81,
+                index: 0,
+            },
+            Application::Wif { index: 1 << 31 },
+        ];
+
+        for application in invalid {
+            assert!(matches!(application.path(), Err(Bip85Error::Parameter(..))));
+        }
+    }
+}
@@ -1,14 +1,620 @@
 use dotenv::dotenv;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+use crate::{
+    json_vault::JsonFileVault,
+    kdf::{KdfParams, KdfProfile},
+    keystore::KeyStoreBackend,
+    memory::MemoryVault,
+    postgres::PostgresVault,
+    sqlite::SqliteVault,
+    vault_interface::{VaultError, VaultInterface, VaultResult},
+};
+
+/// Where wallets and accounts are stored.
+#[derive(Debug, Clone)]
+pub enum VaultBackend {
+    /// SQLite database behind the given connection url.
+    Sqlite(String),
+    /// Single encrypted JSON document, keyed from the vault password.
+    JsonFile(PathBuf),
+    /// PostgreSQL database behind the given connection url, shared between machines.
+    Postgres(String),
+    /// Nothing survives a restart.
+    Memory,
+}
+
+/// Vault just opened, along with the backup of the database taken before migrating it.
+pub struct OpenedVault {
+    pub vault: Box<dyn VaultInterface>,
+    pub backup: Option<PathBuf>,
+}
+
+impl VaultBackend {
+    pub fn kind(&self) -> VaultKind {
+        match self {
+            VaultBackend::Sqlite(_) => VaultKind::Sqlite,
+            VaultBackend::JsonFile(_) => VaultKind::Json,
+            VaultBackend::Postgres(_) => VaultKind::Postgres,
+            VaultBackend::Memory => VaultKind::Memory,
+        }
+    }
+
+    /// Opens (and migrates, when relevant) the vault, `password` being the master password
+    /// of the whole vault if it is encrypted.
+    pub async fn open(&self, password: Option<&str>) -> VaultResult<OpenedVault> {
+        match self {
+            VaultBackend::Sqlite(url) => {
+                let vault = match password {
+                    Some(password) => SqliteVault::new_encrypted(Some(url), password).await?,
+                    None => SqliteVault::connect(Some(url)).await?,
+                };
+                let backup = vault.migrate().await?;
+                Ok(OpenedVault {
+                    vault: Box::new(vault),
+                    backup,
+                })
+            }
+            VaultBackend::JsonFile(path) => {
+                if password.is_none() {
+                    return Err(VaultError::Encryption(
+                        "The json vault requires a vault password".to_string(),
+                    ));
+                }
+                let vault = JsonFileVault::open(path, password.unwrap()).await?;
+                Ok(OpenedVault {
+                    vault: Box::new(vault),
+                    backup: None,
+                })
+            }
+            VaultBackend::Postgres(url) => {
+                let vault = PostgresVault::new(url).await?;
+                vault.migrate().await?;
+                Ok(OpenedVault {
+                    vault: Box::new(vault),
+                    backup: None,
+                })
+            }
+            VaultBackend::Memory => Ok(OpenedVault {
+                vault: Box::new(MemoryVault::new()),
+                backup: None,
+            }),
+        }
+    }
+}
+
+/// Name of the config file, kept in the data directory.
+pub const CONFIG_FILE: &str = "config.toml";
+/// Profile used when neither `PROFILE` nor the config file pick one.
+pub const DEFAULT_PROFILE: &str = "default";
+/// SQLite file of a profile, relative to its directory.
+pub const DATABASE_FILE: &str = "database.db";
+/// Where the database was kept before profiles, relative to the working directory.
+pub const LEGACY_DATABASE: &str = "database.db";
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("Failed reading {0}: {1}")]
+    Read(String, String),
+    #[error("Invalid config file {0}: {1}")]
+    Parse(String, String),
+    #[error("Invalid profile {0}: {1}")]
+    Invalid(String, String),
+    #[error("Profile not found {0}")]
+    UnknownProfile(String),
+}
+
+pub type ConfigResult<T> = Result<T, ConfigError>;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VaultKind {
+    #[default]
+    Sqlite,
+    Json,
+    Postgres,
+    Memory,
+}
+
+/// Vault of a profile, as written in the config file.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct VaultSettings {
+    pub backend: VaultKind,
+    /// File of a sqlite or json vault, relative to the directory of the profile.
+    pub path: Option<PathBuf>,
+    /// Connection url of a postgres vault, or of a sqlite one kept anywhere else.
+    pub url: Option<String>,
+}
+
+impl VaultSettings {
+    fn resolve(&self, profile: &str, dir: &Path) -> ConfigResult<VaultBackend> {
+        let file = |default: &str| dir.join(self.path.as_deref().unwrap_or(Path::new(default)));
+
+        match self.backend {
+            VaultKind::Sqlite => Ok(VaultBackend::Sqlite(match &self.url {
+                Some(url) => url.clone(),
+                None => sqlite_url(&file(DATABASE_FILE)),
+            })),
+            VaultKind::Json => Ok(VaultBackend::JsonFile(file("vault.json"))),
+            VaultKind::Postgres => match &self.url {
+                Some(url) => Ok(VaultBackend::Postgres(url.clone())),
+                None => Err(ConfigError::Invalid(
+                    profile.to_string(),
+                    "a postgres vault needs a url".to_string(),
+                )),
+            },
+            VaultKind::Memory => Ok(VaultBackend::Memory),
+        }
+    }
+}
+
+fn sqlite_url(path: &Path) -> String {
+    format!("sqlite://{}", path.display())
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChainNetwork {
+    #[default]
+    Bitcoin,
+    Testnet,
+    Signet,
+    Regtest,
+}
+
+impl ChainNetwork {
+    pub fn to_bitcoin_network(&self) -> bitcoin::Network {
+        match self {
+            ChainNetwork::Bitcoin => bitcoin::Network::Bitcoin,
+            ChainNetwork::Testnet => bitcoin::Network::Testnet,
+            ChainNetwork::Signet => bitcoin::Network::Signet,
+            ChainNetwork::Regtest => bitcoin::Network::Regtest,
+        }
+    }
+}
+
+/// Where chain data comes from and where transactions are broadcast.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChainKind {
+    #[default]
+    None,
+    Esplora,
+    Electrum,
+    Bitcoind,
+}
+
+/// Chain of a profile, as written in the config file.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChainSettings {
+    pub network: ChainNetwork,
+    pub backend: ChainKind,
+    pub url: Option<String>,
+    /// Cookie file of bitcoind, used when no user is given.
+    pub cookie: Option<PathBuf>,
+    pub user: Option<String>,
+    pub password: Option<String>,
+}
+
+impl ChainSettings {
+    fn validate(&self, profile: &str) -> ConfigResult<()> {
+        if self.backend != ChainKind::None && self.url.is_none() {
+            return Err(ConfigError::Invalid(
+                profile.to_string(),
+                "the chain backend needs a url".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProfileSettings {
+    pub vault: VaultSettings,
+    pub chain: ChainSettings,
+}
+
+/// Content of the config file, e.g.
+///
+/// ```toml
+/// active_profile = "regtest"
+///
+/// [profiles.regtest]
+/// vault = { backend = "sqlite" }
+/// chain = { network = "regtest", backend = "bitcoind", url = "http://127.0.0.1:18443" }
+///
+/// [profiles.signet-team]
+/// vault = { backend = "postgres", url = "postgres://wallets@db.internal/signet" }
+/// chain = { network = "signet", backend = "esplora", url = "https://mempool.space/signet/api" }
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConfigFile {
+    pub active_profile: Option<String>,
+    /// Allows creating wallets from a known mnemonic, see [`crate::fixture`].
+    pub dev_mode: bool,
+    pub profiles: BTreeMap<String, ProfileSettings>,
+}
+
+impl ConfigFile {
+    /// Reads the file, a missing one being the same as an empty one.
+    pub fn read(path: &Path) -> ConfigResult<ConfigFile> {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(ConfigFile::default()),
+            Err(err) => {
+                return Err(ConfigError::Read(
+                    path.display().to_string(),
+                    err.to_string(),
+                ))
+            }
+        };
+
+        ConfigFile::parse(&content)
+            .map_err(|err| ConfigError::Parse(path.display().to_string(), err))
+    }
+
+    pub fn parse(content: &str) -> Result<ConfigFile, String> {
+        toml::from_str(content).map_err(|err| err.to_string())
+    }
+
+    /// Names of the profiles, the default one always being available.
+    pub fn profile_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.profiles.keys().cloned().collect();
+
+        if !self.profiles.contains_key(DEFAULT_PROFILE) {
+            names.insert(0, DEFAULT_PROFILE.to_string());
+        }
+
+        names
+    }
+
+    /// Resolves the profile, its files being kept in `<data_dir>/profiles/<name>`.
+    pub fn profile(&self, name: &str, data_dir: &Path) -> ConfigResult<Profile> {
+        let settings = match self.profiles.get(name) {
+            Some(settings) => settings.clone(),
+            None if name == DEFAULT_PROFILE => ProfileSettings::default(),
+            None => return Err(ConfigError::UnknownProfile(name.to_string())),
+        };
+
+        // The name ends up in a path.
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(ConfigError::Invalid(
+                name.to_string(),
+                "names may only hold letters, digits, - and _".to_string(),
+            ));
+        }
+
+        let dir = data_dir.join("profiles").join(name);
+        settings.chain.validate(name)?;
+
+        Ok(Profile {
+            name: name.to_string(),
+            vault: settings.vault.resolve(name, &dir)?,
+            chain: settings.chain,
+            dir,
+        })
+    }
+}
+
+/// A profile resolved against the data directory.
+#[derive(Debug, Clone)]
+pub struct Profile {
+    pub name: String,
+    /// Where files of the profile are kept.
+    pub dir: PathBuf,
+    pub vault: VaultBackend,
+    pub chain: ChainSettings,
+}
+
+impl Profile {
+    /// Opens the vault of the profile, creating its directory first.
+    pub async fn open(&self, password: Option<&str>) -> VaultResult<OpenedVault> {
+        if let Err(err) = fs::create_dir_all(&self.dir) {
+            return Err(VaultError::Inserting(format!(
+                "Failed creating {}: {}",
+                self.dir.display(),
+                err
+            )));
+        }
+
+        self.adopt_legacy_database(Path::new(LEGACY_DATABASE))?;
+        self.vault.open(password).await
+    }
+
+    /// Moves `legacy`, the database of a version of the app without profiles, into the
+    /// default profile when that one has no database of its own yet, so upgrading doesn't
+    /// leave the wallets behind. `true` when it was moved.
+    pub fn adopt_legacy_database(&self, legacy: &Path) -> VaultResult<bool> {
+        let database = self.dir.join(DATABASE_FILE);
+        let default_vault =
+            matches!(&self.vault, VaultBackend::Sqlite(url) if *url == sqlite_url(&database));
+
+        if self.name != DEFAULT_PROFILE || !default_vault || database.exists() || !legacy.is_file()
+        {
+            return Ok(false);
+        }
+
+        // The write-ahead log of the database holds the latest writes.
+        for suffix in ["", "-wal", "-shm"] {
+            let from = PathBuf::from(format!("{}{suffix}", legacy.display()));
+            let to = PathBuf::from(format!("{}{suffix}", database.display()));
+
+            if !from.exists() {
+                continue;
+            }
+
+            // Renaming fails across file systems, copying doesn't.
+            let moved = fs::rename(&from, &to)
+                .or_else(|_| fs::copy(&from, &to).and_then(|_| fs::remove_file(&from)));
+
+            if let Err(err) = moved {
+                return Err(VaultError::Inserting(format!(
+                    "Failed moving {} to {}: {}",
+                    from.display(),
+                    to.display(),
+                    err
+                )));
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Leaves credentials of the chain backend out.
+    pub fn to_json(&self) -> Value {
+        json!({
+            "name": self.name,
+            "vault": self.vault.kind(),
+            "network": self.chain.network,
+            "chain": self.chain.backend,
+            "chain_url": self.chain.url,
+        })
+    }
+}
 
 pub struct Config {
+    pub data_dir: PathBuf,
+    pub file: ConfigFile,
+    /// Name of the profile in use, picked at startup by `PROFILE` or by `active_profile` in
+    /// the config file.
+    pub active_profile: String,
+    /// Read from `DATABASE_URL`, empty when unset.
     pub database_url: String,
+    /// Vault set through the environment, replacing the one of the profile picked at startup.
+    pub vault_override: Option<VaultBackend>,
+    startup_profile: String,
+    /// KDF parameters new wallets are created with, wallets hashed with weaker ones are
+    /// upgraded on their next login.
+    pub kdf: KdfParams,
+    /// Where keys of wallets unlocked with "remember on this machine" are kept.
+    pub keystore: KeyStoreBackend,
+    /// Master password of the whole vault, read from `VAULT_PASSWORD`. When set a SQLite vault
+    /// is opened with SQLCipher and an existing plaintext database gets encrypted, the json
+    /// vault always requires it.
+    pub vault_password: Option<String>,
+    /// Set by `dev_mode` in the config file or by `DEV_MODE=1`.
+    pub dev_mode: bool,
 }
 
 impl Config {
-    pub fn from_env() -> Config {
+    /// `DATA_DIR` when set, otherwise `default` (resolved through Tauri's path API), the
+    /// working directory as a last resort.
+    pub fn data_dir(default: Option<PathBuf>) -> PathBuf {
         dotenv().ok();
+
+        env::var("DATA_DIR")
+            .ok()
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or(default)
+            .unwrap_or(PathBuf::from("."))
+    }
+
+    /// Reads `config.toml` from the data directory, then applies the environment on top.
+    pub fn load(data_dir: PathBuf) -> ConfigResult<Config> {
+        let file = ConfigFile::read(&data_dir.join(CONFIG_FILE))?;
+        Ok(Config::new(data_dir, file))
+    }
+
+    pub fn new(data_dir: PathBuf, file: ConfigFile) -> Config {
+        dotenv().ok();
+
+        let startup_profile = env::var("PROFILE")
+            .ok()
+            .filter(|name| !name.is_empty())
+            .or(file.active_profile.clone())
+            .unwrap_or(DEFAULT_PROFILE.to_string());
+        let database_url = env::var("DATABASE_URL").unwrap_or_default();
+        let dev_mode =
+            file.dev_mode || matches!(env::var("DEV_MODE").as_deref(), Ok("1") | Ok("true"));
+
         Config {
-            database_url: "".to_string(),
+            vault_override: Config::vault_from_env(&database_url, &data_dir),
+            keystore: Config::keystore_from_env(&data_dir),
+            data_dir,
+            file,
+            active_profile: startup_profile.clone(),
+            database_url,
+            startup_profile,
+            kdf: Config::kdf_from_env(),
+            vault_password: env::var("VAULT_PASSWORD")
+                .ok()
+                .filter(|pass| !pass.is_empty()),
+            dev_mode,
         }
     }
+
+    pub fn profile(&self) -> ConfigResult<Profile> {
+        self.profile_named(&self.active_profile)
+    }
+
+    /// Resolves any profile of the config file, environment overrides only apply to the one
+    /// picked at startup.
+    pub fn profile_named(&self, name: &str) -> ConfigResult<Profile> {
+        let mut profile = self.file.profile(name, &self.data_dir)?;
+
+        if name == self.startup_profile {
+            if let Some(vault) = &self.vault_override {
+                profile.vault = vault.clone();
+            }
+        }
+
+        Ok(profile)
+    }
+
+    /// `VAULT_BACKEND` picks `json` or `memory`, the json document being kept at `VAULT_PATH`
+    /// (`vault.json` in the data directory by default). Otherwise the database behind
+    /// `database_url` is used, Postgres or SQLite depending on its scheme. `None` when neither
+    /// is set, leaving the vault of the profile.
+    fn vault_from_env(database_url: &str, data_dir: &Path) -> Option<VaultBackend> {
+        match env::var("VAULT_BACKEND").as_deref() {
+            Ok("json") => Some(VaultBackend::JsonFile(
+                data_dir.join(env::var("VAULT_PATH").unwrap_or("vault.json".to_string())),
+            )),
+            Ok("memory") => Some(VaultBackend::Memory),
+            _ if database_url.is_empty() => None,
+            _ => Some(Config::database_backend(database_url)),
+        }
+    }
+
+    fn database_backend(database_url: &str) -> VaultBackend {
+        if database_url.starts_with("postgres://") || database_url.starts_with("postgresql://") {
+            return VaultBackend::Postgres(database_url.to_string());
+        }
+
+        VaultBackend::Sqlite(database_url.to_string())
+    }
+
+    /// `KEYSTORE` picks `secret-service` or `file`, the latter keeps the keys in
+    /// `KEYSTORE_DIR` (`keys` in the data directory by default).
+    fn keystore_from_env(data_dir: &Path) -> KeyStoreBackend {
+        let dir = env::var("KEYSTORE_DIR").unwrap_or("keys".to_string());
+
+        match env::var("KEYSTORE").as_deref() {
+            Ok("file") => KeyStoreBackend::File(data_dir.join(dir)),
+            Ok("secret-service") => KeyStoreBackend::SecretService,
+            _ => match KeyStoreBackend::default() {
+                KeyStoreBackend::File(dir) => KeyStoreBackend::File(data_dir.join(dir)),
+                backend => backend,
+            },
+        }
+    }
+
+    /// Starts from `KDF_PROFILE` (`default` or `fast`) and applies the `KDF_M_COST`,
+    /// `KDF_T_COST` and `KDF_P_COST` overrides on top, invalid values are ignored.
+    fn kdf_from_env() -> KdfParams {
+        let profile = env::var("KDF_PROFILE")
+            .ok()
+            .and_then(|profile| KdfProfile::from_string(&profile).ok())
+            .unwrap_or_default();
+
+        let params = profile.params();
+        let cost = |name: &str, default: u32| {
+            env::var(name)
+                .ok()
+                .and_then(|value| value.parse::<u32>().ok())
+                .unwrap_or(default)
+        };
+
+        KdfParams::new(
+            cost("KDF_M_COST", params.m_cost),
+            cost("KDF_T_COST", params.t_cost),
+            cost("KDF_P_COST", params.p_cost),
+        )
+        .unwrap_or(params)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONTENT: &str = r#"
+active_profile = "regtest"
+
+[profiles.regtest]
+vault = { backend = "sqlite" }
+chain = { network = "regtest", backend = "bitcoind", url = "http://127.0.0.1:18443", cookie = "/tmp/.cookie" }
+
+[profiles.signet-team.vault]
+backend = "postgres"
+url = "postgres://wallets@db.internal/signet"
+
+[profiles.signet-team.chain]
+network = "signet"
+backend = "esplora"
+url = "https://mempool.space/signet/api"
+"#;
+
+    #[test]
+    fn resolves_profiles_against_the_data_dir() {
+        let file = ConfigFile::parse(CONTENT).unwrap();
+        let data_dir = Path::new("/data");
+
+        assert_eq!(file.active_profile.as_deref(), Some("regtest"));
+        assert_eq!(
+            file.profile_names(),
+            vec!["default", "regtest", "signet-team"]
+        );
+
+        let regtest = file.profile("regtest", data_dir).unwrap();
+        assert_eq!(regtest.dir, Path::new("/data/profiles/regtest"));
+        assert!(matches!(
+            regtest.vault,
+            VaultBackend::Sqlite(ref url) if url == "sqlite:///data/profiles/regtest/database.db"
+        ));
+        assert_eq!(regtest.chain.network, ChainNetwork::Regtest);
+        assert_eq!(regtest.chain.backend, ChainKind::Bitcoind);
+
+        let signet = file.profile("signet-team", data_dir).unwrap();
+        assert!(matches!(signet.vault, VaultBackend::Postgres(_)));
+        assert_eq!(
+            signet.chain.network.to_bitcoin_network(),
+            bitcoin::Network::Signet
+        );
+
+        // Always there, even when the file doesn't mention it.
+        let default = file.profile(DEFAULT_PROFILE, data_dir).unwrap();
+        assert_eq!(default.chain, ChainSettings::default());
+
+        assert!(matches!(
+            file.profile("mainnet", data_dir),
+            Err(ConfigError::UnknownProfile(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_invalid_profiles() {
+        assert!(ConfigFile::parse("[profiles.x]\nvault = { backend = \"mongo\" }").is_err());
+        assert!(ConfigFile::parse("[profiles.x]\nunknown = 1").is_err());
+
+        let file = ConfigFile::parse("[profiles.x]\nvault = { backend = \"postgres\" }\n").unwrap();
+        assert!(matches!(
+            file.profile("x", Path::new(".")),
+            Err(ConfigError::Invalid(_, _))
+        ));
+
+        let file = ConfigFile::parse("[profiles.y]\nchain = { backend = \"esplora\" }\n").unwrap();
+        assert!(file.profile("y", Path::new(".")).is_err());
+
+        let file = ConfigFile::parse("[profiles.\"../escape\"]\n").unwrap();
+        assert!(file.profile("../escape", Path::new(".")).is_err());
+    }
 }
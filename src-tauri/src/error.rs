@@ -0,0 +1,343 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::fmt;
+use thiserror::Error;
+
+use crate::{
+    account::AccountError, bip21::Bip21Error, bip85::Bip85Error, config::ConfigError,
+    fixture::FixtureError, keystore::KeyStoreError, labels::LabelError, mnemonic::MnemonicError,
+    multisig::MultisigError, policy::PolicyError, psbt::PsbtError, session::SessionError,
+    silent_payments::SilentPaymentError, taproot::TaprootError, utils::AESError,
+    vault_interface::VaultError, wallet::AuthError,
+};
+
+/// What went wrong, stable for the frontend and scripts to branch on, unlike messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// The password, or a remembered key, doesn't unlock the wallet.
+    WrongPassword,
+    Throttled,
+    LockedOut,
+    WalletNotFound,
+    AccountNotFound,
+    /// The mnemonic token was already used, expired, or never existed.
+    DraftNotFound,
+    NotFound,
+    /// The session is unknown, expired or belongs to another wallet.
+    Unauthorized,
+    InvalidInput,
+    InvalidPath,
+    DerivationFailed,
+    EncryptionFailed,
+    DecryptionFailed,
+    /// The database could be reached but the query failed.
+    Database,
+    /// Data stored in the vault can't be read back.
+    Corrupted,
+    MigrationFailed,
+    SchemaTooNew,
+    /// The vault is encrypted with another key than the one given.
+    VaultLocked,
+    VaultUnavailable,
+    KeyStore,
+    InvalidConfig,
+    UnknownProfile,
+    /// The command is only available in developer mode.
+    DevModeDisabled,
+    /// An input of the PSBT lacks signatures to be finalized.
+    ThresholdNotMet,
+    /// No path of the policy can be satisfied with the keys and timelocks at hand.
+    NoSpendPath,
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Same text as the serialized code.
+        let code = serde_json::to_value(self).map_err(|_| fmt::Error)?;
+        write!(f, "{}", code.as_str().unwrap_or_default())
+    }
+}
+
+/// Error returned by every Tauri command. `message` is meant for humans, `details` holds
+/// whatever values the code calls for, e.g. how long to wait when throttled.
+#[derive(Error, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[error("{message}")]
+pub struct CommandError {
+    pub code: ErrorCode,
+    pub message: String,
+    pub details: Value,
+}
+
+pub type CommandResult<T> = Result<T, CommandError>;
+
+impl CommandError {
+    pub fn new(code: ErrorCode, message: impl ToString) -> Self {
+        CommandError {
+            code,
+            message: message.to_string(),
+            details: Value::Null,
+        }
+    }
+
+    pub fn with_details(mut self, details: Value) -> Self {
+        self.details = details;
+        self
+    }
+
+    pub fn invalid_input(message: impl ToString) -> Self {
+        CommandError::new(ErrorCode::InvalidInput, message)
+    }
+
+    /// Error of a wallet lookup, telling a missing wallet apart from other failures.
+    pub fn wallet(err: VaultError) -> Self {
+        CommandError::from(err).not_found_as(ErrorCode::WalletNotFound)
+    }
+
+    /// Error of an account lookup, telling a missing account apart from other failures.
+    pub fn account(err: VaultError) -> Self {
+        CommandError::from(err).not_found_as(ErrorCode::AccountNotFound)
+    }
+
+    fn not_found_as(mut self, code: ErrorCode) -> Self {
+        if self.code == ErrorCode::NotFound {
+            self.code = code;
+        }
+        self
+    }
+}
+
+impl From<VaultError> for CommandError {
+    fn from(value: VaultError) -> Self {
+        let (code, details) = match &value {
+            VaultError::NotFound(id) => (ErrorCode::NotFound, json!({"id": id})),
+            VaultError::Parser(_) => (ErrorCode::Corrupted, Value::Null),
+            VaultError::Listing(_)
+            | VaultError::Inserting(_)
+            | VaultError::Removing(_)
+            | VaultError::Updating(_)
+            | VaultError::Transaction(_)
+            | VaultError::Database(_) => (ErrorCode::Database, Value::Null),
+            VaultError::Migrating(_) => (ErrorCode::MigrationFailed, Value::Null),
+            VaultError::Encryption(_) => (ErrorCode::VaultLocked, Value::Null),
+            VaultError::SchemaTooNew(found, supported) => (
+                ErrorCode::SchemaTooNew,
+                json!({"found": found, "supported": supported}),
+            ),
+        };
+
+        CommandError::new(code, value).with_details(details)
+    }
+}
+
+impl From<AuthError> for CommandError {
+    fn from(value: AuthError) -> Self {
+        let (code, details) = match &value {
+            AuthError::Failed(_) => (ErrorCode::WrongPassword, Value::Null),
+            AuthError::Parser(_) => (ErrorCode::Corrupted, Value::Null),
+            AuthError::Throttled(retry_in) => (ErrorCode::Throttled, json!({"retry_in": retry_in})),
+            AuthError::LockedOut(attempts, retry_in) => (
+                ErrorCode::LockedOut,
+                json!({"attempts": attempts, "retry_in": retry_in}),
+            ),
+            AuthError::Attempts(_) | AuthError::Audit(_) => (ErrorCode::Database, Value::Null),
+        };
+
+        CommandError::new(code, value).with_details(details)
+    }
+}
+
+impl From<AccountError> for CommandError {
+    fn from(value: AccountError) -> Self {
+        let code = match &value {
+            AccountError::Building(_) | AccountError::Metadata(_) => ErrorCode::InvalidInput,
+            AccountError::Path(_) => ErrorCode::InvalidPath,
+            AccountError::Derivation(_) => ErrorCode::DerivationFailed,
+        };
+
+        CommandError::new(code, value)
+    }
+}
+
+impl From<AESError> for CommandError {
+    fn from(value: AESError) -> Self {
+        let code = match &value {
+            AESError::Encrypt(_) => ErrorCode::EncryptionFailed,
+            AESError::Decrypt(_) => ErrorCode::DecryptionFailed,
+        };
+
+        CommandError::new(code, value)
+    }
+}
+
+impl From<SessionError> for CommandError {
+    fn from(value: SessionError) -> Self {
+        CommandError::new(ErrorCode::Unauthorized, value)
+    }
+}
+
+impl From<KeyStoreError> for CommandError {
+    fn from(value: KeyStoreError) -> Self {
+        CommandError::new(ErrorCode::KeyStore, value)
+    }
+}
+
+impl From<LabelError> for CommandError {
+    fn from(value: LabelError) -> Self {
+        match value {
+            LabelError::Record(line, _) => CommandError::new(ErrorCode::InvalidInput, &value)
+                .with_details(json!({"line": line})),
+            LabelError::Vault(err) => CommandError::from(err),
+        }
+    }
+}
+
+impl From<MnemonicError> for CommandError {
+    fn from(value: MnemonicError) -> Self {
+        let code = match &value {
+            MnemonicError::DraftNotFound(_) => ErrorCode::DraftNotFound,
+            _ => ErrorCode::InvalidInput,
+        };
+
+        CommandError::new(code, value)
+    }
+}
+
+impl From<Bip21Error> for CommandError {
+    fn from(value: Bip21Error) -> Self {
+        match value {
+            Bip21Error::WrongNetwork(network) => {
+                CommandError::invalid_input(&value).with_details(json!({ "expected": network }))
+            }
+            _ => CommandError::invalid_input(value),
+        }
+    }
+}
+
+impl From<Bip85Error> for CommandError {
+    fn from(value: Bip85Error) -> Self {
+        let code = match &value {
+            Bip85Error::Parameter(..) => ErrorCode::InvalidInput,
+            Bip85Error::Derivation(_) => ErrorCode::DerivationFailed,
+        };
+
+        CommandError::new(code, value)
+    }
+}
+
+impl From<PsbtError> for CommandError {
+    fn from(value: PsbtError) -> Self {
+        match value {
+            PsbtError::ThresholdNotMet(input, signatures, threshold) => {
+                CommandError::new(ErrorCode::ThresholdNotMet, &value).with_details(json!({
+                    "input": input,
+                    "signatures": signatures,
+                    "threshold": threshold,
+                }))
+            }
+            _ => CommandError::invalid_input(value),
+        }
+    }
+}
+
+impl From<MultisigError> for CommandError {
+    fn from(value: MultisigError) -> Self {
+        match value {
+            MultisigError::Psbt(err) => CommandError::from(err),
+            MultisigError::NotCosigner(_) => CommandError::new(ErrorCode::Unauthorized, value),
+            MultisigError::Derivation(_) => CommandError::new(ErrorCode::DerivationFailed, value),
+            _ => CommandError::invalid_input(value),
+        }
+    }
+}
+
+impl From<PolicyError> for CommandError {
+    fn from(value: PolicyError) -> Self {
+        match value {
+            PolicyError::Key(err) => CommandError::from(err),
+            PolicyError::Psbt(err) => CommandError::from(err),
+            PolicyError::Taproot(err) => CommandError::from(err),
+            PolicyError::NotCosigner(_) => CommandError::new(ErrorCode::Unauthorized, value),
+            PolicyError::NoSpendPath => CommandError::new(ErrorCode::NoSpendPath, value),
+            PolicyError::Derivation(_) => CommandError::new(ErrorCode::DerivationFailed, value),
+            _ => CommandError::invalid_input(value),
+        }
+    }
+}
+
+impl From<TaprootError> for CommandError {
+    fn from(value: TaprootError) -> Self {
+        CommandError::invalid_input(value)
+    }
+}
+
+impl From<SilentPaymentError> for CommandError {
+    fn from(value: SilentPaymentError) -> Self {
+        match value {
+            SilentPaymentError::Derivation(_) => {
+                CommandError::new(ErrorCode::DerivationFailed, value)
+            }
+            SilentPaymentError::MissingInputKey(input) => {
+                CommandError::invalid_input(&value).with_details(json!({ "input": input }))
+            }
+            _ => CommandError::invalid_input(value),
+        }
+    }
+}
+
+impl From<FixtureError> for CommandError {
+    fn from(value: FixtureError) -> Self {
+        match value {
+            FixtureError::Disabled => CommandError::new(ErrorCode::DevModeDisabled, value),
+            FixtureError::Account(err) => CommandError::from(err),
+            _ => CommandError::invalid_input(value),
+        }
+    }
+}
+
+impl From<ConfigError> for CommandError {
+    fn from(value: ConfigError) -> Self {
+        let code = match &value {
+            ConfigError::UnknownProfile(_) => ErrorCode::UnknownProfile,
+            _ => ErrorCode::InvalidConfig,
+        };
+
+        CommandError::new(code, value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serializes_code_message_and_details() {
+        let err = CommandError::from(AuthError::LockedOut(10, 60));
+
+        assert_eq!(
+            serde_json::to_value(&err).unwrap(),
+            json!({
+                "code": "locked_out",
+                "message": "Wallet locked out after 10 failed attempts, retry in 60 seconds",
+                "details": {"attempts": 10, "retry_in": 60},
+            })
+        );
+        assert_eq!(ErrorCode::WalletNotFound.to_string(), "wallet_not_found");
+    }
+
+    #[test]
+    fn tells_failures_apart() {
+        let wrong_password = CommandError::from(AuthError::Failed("password".to_string()));
+        let missing_wallet = CommandError::wallet(VaultError::NotFound("id".to_string()));
+        let database = CommandError::wallet(VaultError::Database("disk I/O error".to_string()));
+
+        assert_eq!(wrong_password.code, ErrorCode::WrongPassword);
+        assert_eq!(missing_wallet.code, ErrorCode::WalletNotFound);
+        assert_eq!(missing_wallet.details, json!({"id": "id"}));
+        assert_eq!(database.code, ErrorCode::Database);
+        assert_eq!(
+            CommandError::account(VaultError::NotFound("id".to_string())).code,
+            ErrorCode::AccountNotFound
+        );
+    }
+}
@@ -0,0 +1,243 @@
+use bip39::Mnemonic;
+use bitcoin::bip32::DerivationPath;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use thiserror::Error;
+
+use crate::{
+    account::{
+        derive_address, AccountError, AccountInputBuilder, Network, ScriptType, StoreAccountInput,
+    },
+    kdf::KdfParams,
+    query::MAX_PAGE_SIZE,
+    utils::AESKey,
+    wallet::WalletInputBuilder,
+};
+
+/// Mnemonic of the default Hardhat and Anvil accounts, public and well known, so fixtures
+/// built from it are the same on every machine. Never send real funds to its addresses.
+pub const DEV_MNEMONIC: &str = "test test test test test test test test test test test junk";
+
+#[derive(Error, Debug)]
+pub enum FixtureError {
+    #[error("Invalid mnemonic: {0}")]
+    Mnemonic(String),
+    #[error("Can only derive 1 to {MAX_PAGE_SIZE} accounts per script type and network, got {0}")]
+    Count(u32),
+    #[error("Developer mode is disabled")]
+    Disabled,
+    #[error(transparent)]
+    Account(#[from] AccountError),
+}
+
+pub type FixtureResult<T> = Result<T, FixtureError>;
+
+/// [`DEV_MNEMONIC`], or the one given by the user.
+pub fn dev_mnemonic(mnemonic: Option<&str>) -> FixtureResult<Mnemonic> {
+    Mnemonic::parse(mnemonic.unwrap_or(DEV_MNEMONIC))
+        .map_err(|err| FixtureError::Mnemonic(err.to_string()))
+}
+
+/// Wallet restored from a known mnemonic. Unlike other wallets the seed is derived with an
+/// empty passphrase, so its addresses match those any BIP-39 tool derives from the mnemonic,
+/// whatever the password.
+pub fn dev_wallet_input(
+    name: &str,
+    password: &str,
+    mnemonic: Option<&str>,
+    kdf: KdfParams,
+) -> FixtureResult<WalletInputBuilder> {
+    let mut wallet = WalletInputBuilder::from(dev_mnemonic(mnemonic)?);
+    wallet.name(name);
+    wallet.password(password);
+    wallet.passphrase("");
+    wallet.kdf(kdf);
+    Ok(wallet)
+}
+
+/// Accounts a fixture is made of, every field being optional.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FixtureSpec {
+    /// All of them by default.
+    pub script_types: Vec<ScriptType>,
+    /// Regtest by default.
+    pub networks: Vec<Network>,
+    /// Accounts per script type and network.
+    pub count: u32,
+    /// BIP-44 account the addresses are derived under.
+    pub account: u32,
+}
+
+impl Default for FixtureSpec {
+    fn default() -> Self {
+        Self {
+            script_types: ScriptType::ALL.to_vec(),
+            networks: vec![Network::Regtest],
+            count: 5,
+            account: 0,
+        }
+    }
+}
+
+/// One account of a fixture.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FixtureEntry {
+    pub network: Network,
+    pub script_type: ScriptType,
+    pub path: String,
+    pub address: String,
+}
+
+impl FixtureSpec {
+    /// Receive paths of every account, network by network then script type by script type.
+    pub fn paths(&self) -> FixtureResult<Vec<(Network, ScriptType, DerivationPath)>> {
+        if self.count == 0 || self.count > MAX_PAGE_SIZE {
+            return Err(FixtureError::Count(self.count));
+        }
+
+        let mut paths = vec![];
+        for network in &self.networks {
+            for script_type in &self.script_types {
+                for index in 0..self.count {
+                    let path = script_type.path(*network, self.account, 0, index)?;
+                    paths.push((*network, *script_type, path));
+                }
+            }
+        }
+
+        Ok(paths)
+    }
+
+    /// Derives the fixture out of a plain seed, touching no vault.
+    pub fn derive(&self, seed: &[u8]) -> FixtureResult<Vec<FixtureEntry>> {
+        let mut entries = vec![];
+
+        for (network, script_type, path) in self.paths()? {
+            let address = derive_address(seed, &path, network, script_type)?;
+            entries.push(FixtureEntry {
+                network,
+                script_type,
+                path: format!("m/{path}"),
+                address: address.to_string(),
+            });
+        }
+
+        Ok(entries)
+    }
+
+    /// Builds the accounts of the fixture for the wallet behind `builder`, along with its
+    /// entries. Testnet, signet and regtest share legacy and nested segwit addresses, those
+    /// accounts are only built once.
+    pub fn build(
+        &self,
+        builder: &AccountInputBuilder,
+        key: AESKey,
+    ) -> FixtureResult<(Vec<FixtureEntry>, Vec<StoreAccountInput>)> {
+        let mut builder = builder.clone();
+        let mut entries = vec![];
+        let mut accounts: Vec<StoreAccountInput> = vec![];
+
+        for (network, script_type, path) in self.paths()? {
+            builder.network(network);
+            builder.script_type(script_type);
+            builder.path(path.clone());
+            let account = builder.build(key)?;
+
+            entries.push(FixtureEntry {
+                network,
+                script_type,
+                path: format!("m/{path}"),
+                address: account.address.clone(),
+            });
+
+            if !accounts
+                .iter()
+                .any(|other| other.address == account.address)
+            {
+                accounts.push(account);
+            }
+        }
+
+        Ok((entries, accounts))
+    }
+}
+
+/// JSON dump of the fixture, the format the `address-book` binary prints too.
+pub fn address_book(entries: &[FixtureEntry]) -> Value {
+    json!({ "accounts": entries })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ABANDON: &str = "abandon abandon abandon abandon abandon abandon abandon abandon \
+                           abandon abandon abandon about";
+
+    fn first(spec: &FixtureSpec, mnemonic: &str) -> Vec<String> {
+        let seed = dev_mnemonic(Some(mnemonic)).unwrap().to_seed("");
+        spec.derive(&seed)
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.address)
+            .collect()
+    }
+
+    #[test]
+    fn matches_bip_vectors() {
+        let spec = FixtureSpec {
+            networks: vec![Network::Mainnet],
+            count: 1,
+            ..Default::default()
+        };
+
+        // First receive address of BIP-44, 49, 84 and 86 for the "abandon … about" mnemonic.
+        assert_eq!(
+            first(&spec, ABANDON),
+            vec![
+                "1LqBGSKuX5yYUonjxT5qGfpUsXKYYWeabA",
+                "37VucYSaXLCAsxYyAPfbSi9eh4iEcbShgf",
+                "bc1qcr8te4kr609gcawutmrza0j4xv80jy8z306fyu",
+                "bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr",
+            ]
+        );
+
+        let spec = FixtureSpec {
+            networks: vec![Network::Testnet],
+            script_types: vec![ScriptType::P2shP2wpkh],
+            count: 1,
+            ..Default::default()
+        };
+        assert_eq!(
+            first(&spec, ABANDON),
+            vec!["2Mww8dCYPUpKHofjgcXcBCEGmniw9CoaiD2"]
+        );
+    }
+
+    #[test]
+    fn derives_the_same_fixture_every_time() {
+        let spec = FixtureSpec {
+            networks: vec![Network::Regtest, Network::Signet],
+            ..Default::default()
+        };
+        let seed = dev_mnemonic(None).unwrap().to_seed("");
+        let entries = spec.derive(&seed).unwrap();
+
+        assert_eq!(entries.len(), 2 * 4 * 5);
+        assert_eq!(entries, spec.derive(&seed).unwrap());
+        assert_eq!(entries[0].path, "m/44'/1'/0'/0/0");
+        assert!(entries[10].address.starts_with("bcrt1q"));
+        assert!(entries[35].address.starts_with("tb1p"));
+
+        assert!(matches!(
+            FixtureSpec {
+                count: 0,
+                ..Default::default()
+            }
+            .paths(),
+            Err(FixtureError::Count(0))
+        ));
+        assert!(dev_mnemonic(Some("test junk")).is_err());
+    }
+}
@@ -0,0 +1,594 @@
+use async_trait::async_trait;
+use fs4::fs_std::FileExt;
+use rand::RngCore;
+use rand_core::OsRng;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tokio::sync::{Mutex, MutexGuard};
+use zeroize::Zeroizing;
+
+use super::{
+    account::{AccountModel, StoreAccountInput, UpdateAccountInput},
+    audit::{AuditEvent, StoreAuditEventInput},
+    kdf::KdfParams,
+    labels::Label,
+    memory::MemoryState,
+    multisig::{MultisigModel, StoreMultisigInput},
+    policy::{PolicyWalletModel, StorePolicyWalletInput},
+    query::{AccountPage, AccountQuery},
+    throttle::AuthAttempts,
+    utils::{blocking, decrypt, encrypt, AESKey},
+    vault_interface::{VaultError, VaultInterface, VaultResult, VaultTransaction},
+    wallet::{RekeyWalletInput, StoreWalletInput, UpdateWalletInput, WalletModel},
+};
+
+/// Version of the document layout written by this build, files written by a newer one are
+/// refused rather than risking to drop fields we don't know about.
+pub const SCHEMA_VERSION: u32 = 1;
+
+const FORMAT: &str = "dev-wallet-vault";
+
+#[derive(Serialize, Deserialize)]
+struct KdfEnvelope {
+    salt: String,
+    m_cost: u32,
+    t_cost: u32,
+    p_cost: u32,
+}
+
+/// What actually lands on disk, only `data` (the serialized [`MemoryState`]) is encrypted.
+#[derive(Serialize, Deserialize)]
+struct Envelope {
+    format: String,
+    version: u32,
+    kdf: KdfEnvelope,
+    data: String,
+}
+
+/// A vault kept as a single encrypted JSON document, small enough to be checked into a
+/// fixtures repository.
+///
+/// Every operation re-reads the file under a lock shared with other processes, and
+/// writes go to a temporary file renamed over the document so it is never left half written.
+pub struct JsonFileVault {
+    document: Arc<Document>,
+    // Serializes the read-modify-write cycles of this process, the file lock covers the others.
+    write_lock: Mutex<()>,
+}
+
+/// Where the document lives and how it is encrypted. The file lock and IO are blocking, so
+/// they run on the blocking thread pool with a handle of their own on this.
+struct Document {
+    path: PathBuf,
+    key: Zeroizing<AESKey>,
+    kdf: KdfParams,
+    salt: Vec<u8>,
+}
+
+impl JsonFileVault {
+    /// Opens the document at `path`, creating an empty one keyed from `password` when missing.
+    pub async fn open(path: impl AsRef<Path>, password: &str) -> VaultResult<Self> {
+        JsonFileVault::open_with_kdf(path, password, KdfParams::default()).await
+    }
+
+    /// Same as [`JsonFileVault::open`], `kdf` is only used when the document gets created.
+    pub async fn open_with_kdf(
+        path: impl AsRef<Path>,
+        password: &str,
+        kdf: KdfParams,
+    ) -> VaultResult<Self> {
+        let path = path.as_ref().to_path_buf();
+        let password = Zeroizing::new(password.to_string());
+        // Deriving the key and waiting for other processes both take a while.
+        let document = blocking(move || Document::open(path, &password, kdf)).await?;
+
+        Ok(Self {
+            document: Arc::new(document),
+            write_lock: Mutex::new(()),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.document.path
+    }
+
+    /// Locks the file then reads the document, the lock lasting as long as the returned file.
+    async fn lock_and_read(&self, exclusive: bool) -> VaultResult<(fs::File, MemoryState)> {
+        let document = self.document.clone();
+
+        blocking(move || {
+            let lock = document.lock_file(exclusive)?;
+            let state = document.read()?;
+            Ok((lock, state))
+        })
+        .await
+    }
+
+    async fn write_state(&self, state: MemoryState) -> VaultResult<()> {
+        let document = self.document.clone();
+        blocking(move || document.write(&state)).await
+    }
+
+    async fn read_state(&self) -> VaultResult<MemoryState> {
+        let (_lock, state) = self.lock_and_read(false).await?;
+        Ok(state)
+    }
+
+    /// Runs `update` against the latest document and persists the result, nothing is written
+    /// when `update` fails.
+    async fn update_state<T>(
+        &self,
+        update: impl FnOnce(&mut MemoryState) -> VaultResult<T> + Send,
+    ) -> VaultResult<T> {
+        let _guard = self.write_lock.lock().await;
+        let (_lock, mut state) = self.lock_and_read(true).await?;
+
+        let result = update(&mut state)?;
+        self.write_state(state).await?;
+
+        Ok(result)
+    }
+}
+
+impl Document {
+    fn open(path: PathBuf, password: &str, kdf: KdfParams) -> VaultResult<Self> {
+        if !path.exists() {
+            if let Some(parent) = path
+                .parent()
+                .filter(|parent| !parent.as_os_str().is_empty())
+            {
+                if let Err(err) = fs::create_dir_all(parent) {
+                    return Err(VaultError::Inserting(err.to_string()));
+                }
+            }
+
+            let mut salt = vec![0u8; 16];
+            OsRng.fill_bytes(&mut salt);
+
+            let key = kdf.derive_key(password, &salt);
+            if let Err(err) = key {
+                return Err(VaultError::Encryption(err.to_string()));
+            }
+
+            let document = Self {
+                path: path.clone(),
+                key: Zeroizing::new(key.unwrap()),
+                kdf,
+                salt,
+            };
+
+            let _lock = document.lock_file(true)?;
+            // Another process may have created it in the meantime, in which case it is opened below.
+            if !document.path.exists() {
+                document.write(&MemoryState::default())?;
+                return Ok(document);
+            }
+        }
+
+        let envelope = Document::read_envelope(&path)?;
+
+        let salt = hex::decode(&envelope.kdf.salt);
+        if let Err(err) = salt {
+            return Err(VaultError::Parser(err.to_string()));
+        }
+        let salt = salt.unwrap();
+
+        let kdf = KdfParams::new(
+            envelope.kdf.m_cost,
+            envelope.kdf.t_cost,
+            envelope.kdf.p_cost,
+        );
+        if let Err(err) = kdf {
+            return Err(VaultError::Parser(err.to_string()));
+        }
+        let kdf = kdf.unwrap();
+
+        let key = kdf.derive_key(password, &salt);
+        if let Err(err) = key {
+            return Err(VaultError::Encryption(err.to_string()));
+        }
+
+        let document = Self {
+            path,
+            key: Zeroizing::new(key.unwrap()),
+            kdf,
+            salt,
+        };
+
+        // Makes sure the password is the right one before handing out the vault.
+        document.decrypt_envelope(&envelope)?;
+
+        Ok(document)
+    }
+
+    fn lock_file(&self, exclusive: bool) -> VaultResult<fs::File> {
+        let mut lock_path = self.path.clone();
+        lock_path.as_mut_os_string().push(".lock");
+
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&lock_path);
+
+        if let Err(err) = file {
+            return Err(VaultError::Listing(err.to_string()));
+        }
+
+        let file = file.unwrap();
+        let res = if exclusive {
+            FileExt::lock_exclusive(&file)
+        } else {
+            FileExt::lock_shared(&file)
+        };
+
+        if let Err(err) = res {
+            return Err(VaultError::Listing(err.to_string()));
+        }
+
+        // The lock is released once the file gets dropped.
+        Ok(file)
+    }
+
+    fn read_envelope(path: &Path) -> VaultResult<Envelope> {
+        let content = fs::read(path);
+
+        if let Err(err) = content {
+            return Err(VaultError::Listing(err.to_string()));
+        }
+
+        let envelope = serde_json::from_slice::<Envelope>(&content.unwrap());
+
+        if let Err(err) = envelope {
+            return Err(VaultError::Parser(err.to_string()));
+        }
+
+        let envelope = envelope.unwrap();
+
+        if envelope.format != FORMAT {
+            return Err(VaultError::Parser(format!(
+                "Unknown document format {}",
+                envelope.format
+            )));
+        }
+
+        if envelope.version > SCHEMA_VERSION {
+            return Err(VaultError::Parser(format!(
+                "Document version {} is newer than the supported {}",
+                envelope.version, SCHEMA_VERSION
+            )));
+        }
+
+        Ok(envelope)
+    }
+
+    fn decrypt_envelope(&self, envelope: &Envelope) -> VaultResult<MemoryState> {
+        let data = hex::decode(&envelope.data);
+
+        if let Err(err) = data {
+            return Err(VaultError::Parser(err.to_string()));
+        }
+
+        let data = decrypt(&self.key, &data.unwrap());
+
+        if let Err(err) = data {
+            return Err(VaultError::Encryption(err.to_string()));
+        }
+
+        let data = Zeroizing::new(data.unwrap());
+        let state = serde_json::from_slice::<MemoryState>(&data);
+
+        if let Err(err) = state {
+            return Err(VaultError::Parser(err.to_string()));
+        }
+
+        Ok(state.unwrap())
+    }
+
+    fn read(&self) -> VaultResult<MemoryState> {
+        let envelope = Document::read_envelope(&self.path)?;
+        self.decrypt_envelope(&envelope)
+    }
+
+    fn write(&self, state: &MemoryState) -> VaultResult<()> {
+        let data = serde_json::to_vec(state);
+
+        if let Err(err) = data {
+            return Err(VaultError::Parser(err.to_string()));
+        }
+
+        let data = Zeroizing::new(data.unwrap());
+        let encrypted = encrypt(&self.key, &data);
+
+        if let Err(err) = encrypted {
+            return Err(VaultError::Encryption(err.to_string()));
+        }
+
+        let envelope = Envelope {
+            format: FORMAT.to_string(),
+            version: SCHEMA_VERSION,
+            kdf: KdfEnvelope {
+                salt: hex::encode(&self.salt),
+                m_cost: self.kdf.m_cost,
+                t_cost: self.kdf.t_cost,
+                p_cost: self.kdf.p_cost,
+            },
+            data: hex::encode(encrypted.unwrap()),
+        };
+
+        let content = serde_json::to_vec_pretty(&envelope);
+
+        if let Err(err) = content {
+            return Err(VaultError::Parser(err.to_string()));
+        }
+
+        let mut tmp_path = self.path.clone();
+        tmp_path.as_mut_os_string().push(".tmp");
+
+        let res = fs::File::create(&tmp_path).and_then(|mut file| {
+            file.write_all(&content.unwrap())?;
+            file.sync_all()
+        });
+
+        if let Err(err) = res {
+            return Err(VaultError::Inserting(err.to_string()));
+        }
+
+        if let Err(err) = fs::rename(&tmp_path, &self.path) {
+            return Err(VaultError::Inserting(err.to_string()));
+        }
+
+        Ok(())
+    }
+}
+
+/// Works on a copy of the document, written back on commit. Holds the file lock all along,
+/// so other processes wait for it to end.
+pub struct JsonFileTransaction<'a> {
+    vault: &'a JsonFileVault,
+    _guard: MutexGuard<'a, ()>,
+    _lock: fs::File,
+    state: MemoryState,
+}
+
+#[async_trait]
+impl VaultTransaction for JsonFileTransaction<'_> {
+    async fn insert_wallet(&mut self, input: StoreWalletInput) -> VaultResult<WalletModel> {
+        self.state.insert_wallet(input)
+    }
+
+    async fn insert_account(&mut self, input: StoreAccountInput) -> VaultResult<AccountModel> {
+        self.state.insert_account(input)
+    }
+
+    async fn update_account(&mut self, input: UpdateAccountInput) -> VaultResult<AccountModel> {
+        self.state.update_account(input)
+    }
+
+    async fn remove_account_by_id(&mut self, id: &str) -> VaultResult<()> {
+        self.state.remove_account_by_id(id);
+        Ok(())
+    }
+
+    async fn remove_wallet_by_id(&mut self, id: &str) -> VaultResult<()> {
+        self.state.remove_wallet_by_id(id);
+        Ok(())
+    }
+
+    async fn set_labels(&mut self, wallet_id: &str, labels: Vec<Label>) -> VaultResult<()> {
+        self.state.set_labels(wallet_id, labels)
+    }
+
+    async fn remove_policy_wallet_by_id(&mut self, id: &str) -> VaultResult<()> {
+        self.state.remove_policy_wallet_by_id(id);
+        Ok(())
+    }
+
+    async fn remove_multisig_by_id(&mut self, id: &str) -> VaultResult<()> {
+        self.state.remove_multisig_by_id(id);
+        Ok(())
+    }
+
+    async fn append_audit_event(&mut self, input: StoreAuditEventInput) -> VaultResult<AuditEvent> {
+        Ok(self.state.append_audit_event(input))
+    }
+
+    async fn commit(self: Box<Self>) -> VaultResult<()> {
+        self.vault.write_state(self.state).await
+    }
+
+    async fn rollback(self: Box<Self>) -> VaultResult<()> {
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl VaultInterface for JsonFileVault {
+    async fn begin(&self) -> VaultResult<Box<dyn VaultTransaction + '_>> {
+        let guard = self.write_lock.lock().await;
+        let (lock, state) = self.lock_and_read(true).await?;
+
+        Ok(Box::new(JsonFileTransaction {
+            vault: self,
+            _guard: guard,
+            _lock: lock,
+            state,
+        }))
+    }
+
+    async fn get_wallet_by_id(&self, id: &str) -> VaultResult<WalletModel> {
+        let state = self.read_state().await?;
+        let wallet = state.wallets.into_iter().find(|wallet| wallet.id == id);
+
+        if wallet.is_none() {
+            return Err(VaultError::NotFound(id.to_string()));
+        }
+
+        Ok(wallet.unwrap())
+    }
+
+    async fn get_wallet_by_name(&self, name: &str) -> VaultResult<WalletModel> {
+        let state = self.read_state().await?;
+        let wallet = state.wallets.into_iter().find(|wallet| wallet.name == name);
+
+        if wallet.is_none() {
+            return Err(VaultError::NotFound(name.to_string()));
+        }
+
+        Ok(wallet.unwrap())
+    }
+
+    async fn get_account_by_id(&self, id: &str) -> VaultResult<AccountModel> {
+        let state = self.read_state().await?;
+        let account = state.accounts.into_iter().find(|account| account.id == id);
+
+        if account.is_none() {
+            return Err(VaultError::NotFound(id.to_string()));
+        }
+
+        Ok(account.unwrap())
+    }
+
+    async fn get_all_accounts(&self, wallet_id: &str) -> VaultResult<Vec<AccountModel>> {
+        Ok(self.read_state().await?.accounts_of(wallet_id))
+    }
+
+    async fn query_accounts(
+        &self,
+        wallet_id: &str,
+        query: &AccountQuery,
+    ) -> VaultResult<AccountPage> {
+        query.apply(self.read_state().await?.accounts_of(wallet_id))
+    }
+
+    async fn get_all_wallets(&self) -> VaultResult<Vec<WalletModel>> {
+        Ok(self.read_state().await?.wallets)
+    }
+
+    async fn remove_account_by_id(&self, id: &str) -> VaultResult<()> {
+        self.update_state(|state| {
+            state.remove_account_by_id(id);
+            Ok(())
+        })
+        .await
+    }
+
+    async fn remove_wallet_by_id(&self, id: &str) -> VaultResult<()> {
+        self.update_state(|state| {
+            state.remove_wallet_by_id(id);
+            Ok(())
+        })
+        .await
+    }
+
+    async fn insert_wallet(&self, input: StoreWalletInput) -> VaultResult<WalletModel> {
+        self.update_state(|state| state.insert_wallet(input)).await
+    }
+
+    async fn insert_account(&self, input: StoreAccountInput) -> VaultResult<AccountModel> {
+        self.update_state(|state| state.insert_account(input)).await
+    }
+
+    async fn update_wallet(&self, input: UpdateWalletInput) -> VaultResult<WalletModel> {
+        self.update_state(|state| state.update_wallet(input)).await
+    }
+
+    async fn update_account(&self, input: UpdateAccountInput) -> VaultResult<AccountModel> {
+        self.update_state(|state| state.update_account(input)).await
+    }
+
+    async fn get_labels(&self, wallet_id: &str) -> VaultResult<Vec<Label>> {
+        Ok(self.read_state().await?.labels_of(wallet_id))
+    }
+
+    async fn set_labels(&self, wallet_id: &str, labels: Vec<Label>) -> VaultResult<()> {
+        self.update_state(|state| state.set_labels(wallet_id, labels))
+            .await
+    }
+
+    async fn insert_multisig(&self, input: StoreMultisigInput) -> VaultResult<MultisigModel> {
+        self.update_state(|state| state.insert_multisig(input))
+            .await
+    }
+
+    async fn get_multisig_by_id(&self, id: &str) -> VaultResult<MultisigModel> {
+        self.read_state().await?.get_multisig_by_id(id)
+    }
+
+    async fn get_all_multisigs(&self) -> VaultResult<Vec<MultisigModel>> {
+        Ok(self.read_state().await?.multisigs)
+    }
+
+    async fn remove_multisig_by_id(&self, id: &str) -> VaultResult<()> {
+        self.update_state(|state| {
+            state.remove_multisig_by_id(id);
+            Ok(())
+        })
+        .await
+    }
+
+    async fn insert_policy_wallet(
+        &self,
+        input: StorePolicyWalletInput,
+    ) -> VaultResult<PolicyWalletModel> {
+        self.update_state(|state| state.insert_policy_wallet(input))
+            .await
+    }
+
+    async fn get_policy_wallet_by_id(&self, id: &str) -> VaultResult<PolicyWalletModel> {
+        self.read_state().await?.get_policy_wallet_by_id(id)
+    }
+
+    async fn get_all_policy_wallets(&self) -> VaultResult<Vec<PolicyWalletModel>> {
+        Ok(self.read_state().await?.policy_wallets)
+    }
+
+    async fn remove_policy_wallet_by_id(&self, id: &str) -> VaultResult<()> {
+        self.update_state(|state| {
+            state.remove_policy_wallet_by_id(id);
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_audit_events(&self, wallet_id: &str) -> VaultResult<Vec<AuditEvent>> {
+        Ok(self.read_state().await?.audit_events_of(wallet_id))
+    }
+
+    async fn get_auth_attempts(&self, wallet_id: &str) -> VaultResult<AuthAttempts> {
+        let state = self.read_state().await?;
+        let attempts = state
+            .auth_attempts
+            .into_iter()
+            .find(|attempts| attempts.wallet_id == wallet_id);
+
+        Ok(attempts.unwrap_or(AuthAttempts {
+            wallet_id: wallet_id.to_string(),
+            ..Default::default()
+        }))
+    }
+
+    async fn record_failed_auth(&self, wallet_id: &str) -> VaultResult<AuthAttempts> {
+        self.update_state(|state| state.record_failed_auth(wallet_id))
+            .await
+    }
+
+    async fn reset_auth_attempts(&self, wallet_id: &str) -> VaultResult<()> {
+        self.update_state(|state| {
+            state
+                .auth_attempts
+                .retain(|attempts| attempts.wallet_id != wallet_id);
+            Ok(())
+        })
+        .await
+    }
+
+    async fn rekey_wallet(&self, input: RekeyWalletInput) -> VaultResult<()> {
+        self.update_state(|state| state.rekey_wallet(input)).await
+    }
+}
@@ -0,0 +1,296 @@
+use argon2::{
+    password_hash::{PasswordHash, PasswordHasher, SaltString},
+    Algorithm, Argon2, Params, Version,
+};
+use bitcoin::hex::{Case, DisplayHex};
+use hex::decode;
+use rand_core::OsRng;
+use thiserror::Error;
+use zeroize::Zeroizing;
+
+use crate::{
+    utils::{blocking, decrypt, encrypt, AESKey},
+    vault_interface::VaultInterface,
+    wallet::{RekeyWalletInput, WalletModel},
+};
+
+/// Upper bounds accepted when reading parameters back from the vault, so a tampered
+/// PHC string can't make authentication allocate gigabytes or spin for minutes.
+pub const MAX_M_COST: u32 = 4 * 1024 * 1024; // 4 GiB
+pub const MAX_T_COST: u32 = 64;
+pub const MAX_P_COST: u32 = 64;
+
+#[derive(Error, Debug)]
+pub enum KdfError {
+    #[error("Invalid KDF parameters: {0}")]
+    Params(String),
+    #[error("Failed hashing the password: {0}")]
+    Hashing(String),
+    #[error("Failed upgrading the wallet: {0}")]
+    Upgrade(String),
+}
+
+pub type KdfResult<T> = Result<T, KdfError>;
+
+/// Argon2id cost parameters, `m_cost` is expressed in KiB.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KdfParams {
+    pub m_cost: u32,
+    pub t_cost: u32,
+    pub p_cost: u32,
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        Self {
+            m_cost: Params::DEFAULT_M_COST,
+            t_cost: Params::DEFAULT_T_COST,
+            p_cost: Params::DEFAULT_P_COST,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum KdfProfile {
+    #[default]
+    Default,
+    /// Cheapest parameters argon2 accepts, only meant for automated tests.
+    Fast,
+}
+
+impl KdfProfile {
+    pub fn from_string(text: &str) -> Result<Self, &'static str> {
+        match text {
+            "default" => Ok(KdfProfile::Default),
+            "fast" => Ok(KdfProfile::Fast),
+            _ => Err("Error parsing"),
+        }
+    }
+
+    pub fn params(&self) -> KdfParams {
+        match self {
+            KdfProfile::Default => KdfParams::default(),
+            KdfProfile::Fast => KdfParams::fast(),
+        }
+    }
+}
+
+impl KdfParams {
+    pub fn new(m_cost: u32, t_cost: u32, p_cost: u32) -> KdfResult<Self> {
+        let params = Self {
+            m_cost,
+            t_cost,
+            p_cost,
+        };
+        params.validate()?;
+        Ok(params)
+    }
+
+    pub fn fast() -> Self {
+        Self {
+            m_cost: Params::MIN_M_COST,
+            t_cost: Params::MIN_T_COST,
+            p_cost: Params::MIN_P_COST,
+        }
+    }
+
+    /// Reads the parameters recorded in a PHC string, refusing anything but
+    /// argon2id and costs outside of the accepted bounds.
+    pub fn from_hash(hash: &PasswordHash) -> KdfResult<Self> {
+        if hash.algorithm != Algorithm::Argon2id.ident() {
+            return Err(KdfError::Params(format!(
+                "unsupported algorithm {}",
+                hash.algorithm
+            )));
+        }
+
+        let params = Params::try_from(hash);
+
+        if let Err(err) = params {
+            return Err(KdfError::Params(err.to_string()));
+        }
+
+        let params = params.unwrap();
+
+        if params.output_len().unwrap_or(Params::DEFAULT_OUTPUT_LEN) < 32 {
+            return Err(KdfError::Params("output shorter than 32 bytes".to_string()));
+        }
+
+        KdfParams::new(params.m_cost(), params.t_cost(), params.p_cost())
+    }
+
+    pub fn validate(&self) -> KdfResult<()> {
+        if !(Params::MIN_M_COST..=MAX_M_COST).contains(&self.m_cost) {
+            return Err(KdfError::Params(format!(
+                "m_cost {} out of range",
+                self.m_cost
+            )));
+        }
+        if !(Params::MIN_T_COST..=MAX_T_COST).contains(&self.t_cost) {
+            return Err(KdfError::Params(format!(
+                "t_cost {} out of range",
+                self.t_cost
+            )));
+        }
+        if !(Params::MIN_P_COST..=MAX_P_COST).contains(&self.p_cost) {
+            return Err(KdfError::Params(format!(
+                "p_cost {} out of range",
+                self.p_cost
+            )));
+        }
+        if self.m_cost < 8 * self.p_cost {
+            return Err(KdfError::Params(format!(
+                "m_cost must be at least 8 * p_cost ({})",
+                8 * self.p_cost
+            )));
+        }
+        Ok(())
+    }
+
+    /// Whether any of the costs is lower than the ones of `other`.
+    pub fn is_weaker_than(&self, other: &KdfParams) -> bool {
+        self.m_cost < other.m_cost || self.t_cost < other.t_cost || self.p_cost < other.p_cost
+    }
+
+    pub fn hasher(&self) -> KdfResult<Argon2<'static>> {
+        let params = Params::new(self.m_cost, self.t_cost, self.p_cost, Some(32));
+
+        if let Err(err) = params {
+            return Err(KdfError::Params(err.to_string()));
+        }
+
+        Ok(Argon2::new(
+            Algorithm::Argon2id,
+            Version::V0x13,
+            params.unwrap(),
+        ))
+    }
+
+    /// Hashes the password, returning the PHC string and the AES key taken from its output.
+    pub fn hash_password(&self, password: &str) -> KdfResult<(String, AESKey)> {
+        let salt = SaltString::generate(&mut OsRng);
+        let hash = self.hasher()?.hash_password(password.as_bytes(), &salt);
+
+        if let Err(err) = hash {
+            return Err(KdfError::Hashing(err.to_string()));
+        }
+
+        let hash = hash.unwrap();
+        let mut key = [0u8; 32];
+        key.copy_from_slice(&hash.hash.unwrap().as_bytes()[..32]);
+        Ok((hash.to_string(), key))
+    }
+
+    /// Derives a raw key from the password and a salt kept alongside the encrypted data.
+    pub fn derive_key(&self, password: &str, salt: &[u8]) -> KdfResult<AESKey> {
+        let mut key = [0u8; 32];
+        let res = self
+            .hasher()?
+            .hash_password_into(password.as_bytes(), salt, &mut key);
+
+        if let Err(err) = res {
+            return Err(KdfError::Hashing(err.to_string()));
+        }
+
+        Ok(key)
+    }
+}
+
+/// Re-hashes the password of a wallet with `target` parameters and re-encrypts the seed and
+/// every account path with the new key, stored in a single vault operation. That operation
+/// fails when an account was inserted after the paths were read, rather than leaving it under
+/// the old key, so callers should keep account inserts of the wallet from running meanwhile.
+///
+/// `key` is the key the wallet was just authenticated with, the new key is returned.
+pub async fn upgrade_wallet<V: VaultInterface + ?Sized>(
+    vault: &V,
+    wallet: &WalletModel,
+    password: &str,
+    key: AESKey,
+    target: &KdfParams,
+) -> KdfResult<AESKey> {
+    let (password_hash, new_key) = {
+        let target = *target;
+        let password = Zeroizing::new(password.to_string());
+        blocking(move || target.hash_password(&password)).await?
+    };
+
+    let reencrypt = |data: &str| -> KdfResult<String> {
+        let data = decode(data);
+        if let Err(err) = data {
+            return Err(KdfError::Upgrade(err.to_string()));
+        }
+        let plain = decrypt(&key, &data.unwrap());
+        if let Err(err) = plain {
+            return Err(KdfError::Upgrade(err.to_string()));
+        }
+        let encrypted = encrypt(&new_key, &plain.unwrap());
+        if let Err(err) = encrypted {
+            return Err(KdfError::Upgrade(err.to_string()));
+        }
+        Ok(encrypted.unwrap().to_hex_string(Case::Lower))
+    };
+
+    let accounts = vault.get_all_accounts(&wallet.id).await;
+
+    if let Err(err) = accounts {
+        return Err(KdfError::Upgrade(err.to_string()));
+    }
+
+    let mut account_paths = vec![];
+    for account in accounts.unwrap().iter() {
+        account_paths.push((account.id.clone(), reencrypt(&account.path)?));
+    }
+
+    let input = RekeyWalletInput {
+        wallet_id: wallet.id.clone(),
+        encrypted_pass: password_hash,
+        encrypted_seed: reencrypt(&wallet.seed)?,
+        account_paths,
+    };
+
+    if let Err(err) = vault.rekey_wallet(input).await {
+        return Err(KdfError::Upgrade(err.to_string()));
+    }
+
+    Ok(new_key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_params_in_phc_string() {
+        let params = KdfParams::new(64, 3, 2).unwrap();
+        let (hash, _) = params.hash_password("password").unwrap();
+
+        assert!(hash.starts_with("$argon2id$v=19$m=64,t=3,p=2$"));
+
+        let parsed = PasswordHash::new(&hash).unwrap();
+        assert_eq!(KdfParams::from_hash(&parsed).unwrap(), params);
+    }
+
+    #[test]
+    fn rejects_params_out_of_bounds() {
+        assert!(KdfParams::new(MAX_M_COST + 1, 1, 1).is_err());
+        assert!(KdfParams::new(64, 0, 1).is_err());
+        assert!(KdfParams::new(8, 1, 2).is_err());
+
+        let hash = "$argon2id$v=19$m=65536,t=1000,p=1$c2FsdHNhbHQ$MTIzNDU2Nzg5MDEyMzQ1Njc4OTAxMjM0NTY3ODkwMTI";
+        let parsed = PasswordHash::new(hash).unwrap();
+        assert!(KdfParams::from_hash(&parsed).is_err());
+
+        let hash =
+            "$argon2i$v=19$m=64,t=1,p=1$c2FsdHNhbHQ$MTIzNDU2Nzg5MDEyMzQ1Njc4OTAxMjM0NTY3ODkwMTI";
+        let parsed = PasswordHash::new(hash).unwrap();
+        assert!(KdfParams::from_hash(&parsed).is_err());
+    }
+
+    #[test]
+    fn can_compare_strength() {
+        assert!(KdfParams::fast().is_weaker_than(&KdfParams::default()));
+        assert!(!KdfParams::default().is_weaker_than(&KdfParams::fast()));
+        assert!(!KdfParams::default().is_weaker_than(&KdfParams::default()));
+    }
+}
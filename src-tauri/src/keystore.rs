@@ -0,0 +1,325 @@
+use async_trait::async_trait;
+use std::{fs, io::Write, path::PathBuf};
+use thiserror::Error;
+use zeroize::Zeroizing;
+
+use crate::utils::AESKey;
+
+#[derive(Error, Debug)]
+pub enum KeyStoreError {
+    #[error("Key store unavailable: {0}")]
+    Unavailable(String),
+    #[error("Failed storing key: {0}")]
+    Storing(String),
+    #[error("Failed loading key: {0}")]
+    Loading(String),
+    #[error("Failed removing key: {0}")]
+    Removing(String),
+}
+
+pub type KeyStoreResult<T> = Result<T, KeyStoreError>;
+
+/// Somewhere to remember the unwrapped key of a wallet on this machine, so the wallet can
+/// be unlocked without typing its password.
+#[async_trait]
+pub trait KeyStore: Send + Sync {
+    async fn store(&self, wallet_id: &str, key: &AESKey) -> KeyStoreResult<()>;
+
+    /// Returns `None` when no key is remembered for the wallet.
+    async fn load(&self, wallet_id: &str) -> KeyStoreResult<Option<AESKey>>;
+
+    async fn remove(&self, wallet_id: &str) -> KeyStoreResult<()>;
+}
+
+/// Where remembered keys are kept.
+#[derive(Debug, Clone)]
+pub enum KeyStoreBackend {
+    SecretService,
+    File(PathBuf),
+}
+
+impl Default for KeyStoreBackend {
+    fn default() -> Self {
+        if cfg!(target_os = "linux") {
+            KeyStoreBackend::SecretService
+        } else {
+            KeyStoreBackend::File(PathBuf::from("keys"))
+        }
+    }
+}
+
+impl KeyStoreBackend {
+    pub fn open(&self) -> Box<dyn KeyStore> {
+        match self {
+            #[cfg(target_os = "linux")]
+            KeyStoreBackend::SecretService => Box::new(SecretServiceStore::new()),
+            #[cfg(not(target_os = "linux"))]
+            KeyStoreBackend::SecretService => Box::new(FileKeyStore::new("keys")),
+            KeyStoreBackend::File(dir) => Box::new(FileKeyStore::new(dir)),
+        }
+    }
+}
+
+fn parse_key(data: &[u8]) -> KeyStoreResult<AESKey> {
+    let data = Zeroizing::new(data.to_vec());
+    let decoded = hex::decode(data.trim_ascii());
+
+    if let Err(err) = decoded {
+        return Err(KeyStoreError::Loading(err.to_string()));
+    }
+
+    let decoded = Zeroizing::new(decoded.unwrap());
+
+    if decoded.len() != 32 {
+        return Err(KeyStoreError::Loading("Invalid key length".to_string()));
+    }
+
+    let mut key = [0u8; 32];
+    key.copy_from_slice(&decoded);
+    Ok(key)
+}
+
+/// Keeps every key as a hex file named after the wallet id inside `dir`.
+///
+/// The files are only readable by the current user, but the keys are stored in clear, so
+/// this is meant as a fallback for machines without a secret service and for tests.
+pub struct FileKeyStore {
+    dir: PathBuf,
+}
+
+impl FileKeyStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    fn key_path(&self, wallet_id: &str) -> KeyStoreResult<PathBuf> {
+        // Wallet ids are uuids, anything else could escape the directory.
+        if uuid::Uuid::parse_str(wallet_id).is_err() {
+            return Err(KeyStoreError::Unavailable(format!(
+                "Invalid wallet id {wallet_id}"
+            )));
+        }
+        Ok(self.dir.join(format!("{wallet_id}.key")))
+    }
+}
+
+#[async_trait]
+impl KeyStore for FileKeyStore {
+    async fn store(&self, wallet_id: &str, key: &AESKey) -> KeyStoreResult<()> {
+        let path = self.key_path(wallet_id)?;
+
+        if let Err(err) = fs::create_dir_all(&self.dir) {
+            return Err(KeyStoreError::Storing(err.to_string()));
+        }
+
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+
+        let file = options.open(&path);
+
+        if let Err(err) = file {
+            return Err(KeyStoreError::Storing(err.to_string()));
+        }
+
+        let encoded = Zeroizing::new(hex::encode(key));
+        if let Err(err) = file.unwrap().write_all(encoded.as_bytes()) {
+            return Err(KeyStoreError::Storing(err.to_string()));
+        }
+
+        Ok(())
+    }
+
+    async fn load(&self, wallet_id: &str) -> KeyStoreResult<Option<AESKey>> {
+        let path = self.key_path(wallet_id)?;
+
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let data = fs::read(&path);
+
+        if let Err(err) = data {
+            return Err(KeyStoreError::Loading(err.to_string()));
+        }
+
+        parse_key(&data.unwrap()).map(Some)
+    }
+
+    async fn remove(&self, wallet_id: &str) -> KeyStoreResult<()> {
+        let path = self.key_path(wallet_id)?;
+
+        if !path.exists() {
+            return Ok(());
+        }
+
+        if let Err(err) = fs::remove_file(&path) {
+            return Err(KeyStoreError::Removing(err.to_string()));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub use secret_service_store::SecretServiceStore;
+
+#[cfg(target_os = "linux")]
+mod secret_service_store {
+    use std::collections::HashMap;
+
+    use async_trait::async_trait;
+    use secret_service::{EncryptionType, SecretService};
+
+    use super::{parse_key, KeyStore, KeyStoreError, KeyStoreResult};
+    use crate::utils::AESKey;
+
+    const APPLICATION: &str = "dev-wallet";
+
+    /// Keeps the keys in the default collection of the Linux Secret Service
+    /// (GNOME Keyring, KWallet, KeePassXC...).
+    #[derive(Default)]
+    pub struct SecretServiceStore;
+
+    impl SecretServiceStore {
+        pub fn new() -> Self {
+            Self
+        }
+
+        async fn connect<'a>() -> KeyStoreResult<SecretService<'a>> {
+            let service = SecretService::connect(EncryptionType::Dh).await;
+
+            if let Err(err) = service {
+                return Err(KeyStoreError::Unavailable(err.to_string()));
+            }
+
+            Ok(service.unwrap())
+        }
+
+        fn attributes(wallet_id: &str) -> HashMap<&str, &str> {
+            HashMap::from([("application", APPLICATION), ("wallet_id", wallet_id)])
+        }
+    }
+
+    #[async_trait]
+    impl KeyStore for SecretServiceStore {
+        async fn store(&self, wallet_id: &str, key: &AESKey) -> KeyStoreResult<()> {
+            let service = SecretServiceStore::connect().await?;
+            let collection = service.get_default_collection().await;
+
+            if let Err(err) = collection {
+                return Err(KeyStoreError::Unavailable(err.to_string()));
+            }
+
+            let collection = collection.unwrap();
+
+            if let Err(err) = collection.ensure_unlocked().await {
+                return Err(KeyStoreError::Unavailable(err.to_string()));
+            }
+
+            let encoded = zeroize::Zeroizing::new(hex::encode(key));
+            let res = collection
+                .create_item(
+                    &format!("Dev Wallet key ({wallet_id})"),
+                    SecretServiceStore::attributes(wallet_id),
+                    encoded.as_bytes(),
+                    true,
+                    "text/plain",
+                )
+                .await;
+
+            if let Err(err) = res {
+                return Err(KeyStoreError::Storing(err.to_string()));
+            }
+
+            Ok(())
+        }
+
+        async fn load(&self, wallet_id: &str) -> KeyStoreResult<Option<AESKey>> {
+            let service = SecretServiceStore::connect().await?;
+            let items = service
+                .search_items(SecretServiceStore::attributes(wallet_id))
+                .await;
+
+            if let Err(err) = items {
+                return Err(KeyStoreError::Loading(err.to_string()));
+            }
+
+            let items = items.unwrap();
+            let item = items.unlocked.first().or(items.locked.first());
+
+            if item.is_none() {
+                return Ok(None);
+            }
+
+            let item = item.unwrap();
+
+            if let Err(err) = item.ensure_unlocked().await {
+                return Err(KeyStoreError::Unavailable(err.to_string()));
+            }
+
+            let secret = item.get_secret().await;
+
+            if let Err(err) = secret {
+                return Err(KeyStoreError::Loading(err.to_string()));
+            }
+
+            parse_key(&zeroize::Zeroizing::new(secret.unwrap())).map(Some)
+        }
+
+        async fn remove(&self, wallet_id: &str) -> KeyStoreResult<()> {
+            let service = SecretServiceStore::connect().await?;
+            let items = service
+                .search_items(SecretServiceStore::attributes(wallet_id))
+                .await;
+
+            if let Err(err) = items {
+                return Err(KeyStoreError::Removing(err.to_string()));
+            }
+
+            let items = items.unwrap();
+
+            for item in items.unlocked.iter().chain(items.locked.iter()) {
+                if let Err(err) = item.delete().await {
+                    return Err(KeyStoreError::Removing(err.to_string()));
+                }
+            }
+
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn can_store_load_and_remove_key_from_file() {
+        let dir = std::env::temp_dir().join(format!("dev-wallet-keys-{}", uuid::Uuid::new_v4()));
+        let store = FileKeyStore::new(&dir);
+        let wallet_id = uuid::Uuid::new_v4().to_string();
+        let key = [9u8; 32];
+
+        assert!(store.load(&wallet_id).await.unwrap().is_none());
+
+        store.store(&wallet_id, &key).await.unwrap();
+        assert_eq!(store.load(&wallet_id).await.unwrap(), Some(key));
+
+        store.remove(&wallet_id).await.unwrap();
+        assert!(store.load(&wallet_id).await.unwrap().is_none());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn rejects_wallet_ids_escaping_the_directory() {
+        let store = FileKeyStore::new(std::env::temp_dir());
+
+        assert!(store.store("../key", &[1u8; 32]).await.is_err());
+    }
+}
@@ -0,0 +1,305 @@
+use bitcoin::{bip32::Xpub, OutPoint, PublicKey, Txid};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{collections::HashMap, fmt, str::FromStr};
+use thiserror::Error;
+
+use crate::{
+    account::UpdateAccountInput,
+    vault_interface::{VaultError, VaultInterface},
+};
+
+#[derive(Error, Debug)]
+pub enum LabelError {
+    #[error("Invalid record on line {0}: {1}")]
+    Record(usize, String),
+    #[error("Vault failure: {0}")]
+    Vault(#[from] VaultError),
+}
+
+pub type LabelResult<T> = Result<T, LabelError>;
+
+/// What a BIP-329 record labels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LabelType {
+    Tx,
+    Addr,
+    Pubkey,
+    Input,
+    Output,
+    Xpub,
+}
+
+impl fmt::Display for LabelType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let output = match self {
+            LabelType::Tx => "tx",
+            LabelType::Addr => "addr",
+            LabelType::Pubkey => "pubkey",
+            LabelType::Input => "input",
+            LabelType::Output => "output",
+            LabelType::Xpub => "xpub",
+        };
+        write!(f, "{}", output)
+    }
+}
+
+impl LabelType {
+    /// `None` for types this version of BIP-329 does not define.
+    pub fn from_string(text: &str) -> Option<Self> {
+        match text {
+            "tx" => Some(LabelType::Tx),
+            "addr" => Some(LabelType::Addr),
+            "pubkey" => Some(LabelType::Pubkey),
+            "input" => Some(LabelType::Input),
+            "output" => Some(LabelType::Output),
+            "xpub" => Some(LabelType::Xpub),
+            _ => None,
+        }
+    }
+
+    /// Checks `reference` has the shape the type requires, e.g. `txid:vout` for outputs.
+    pub fn validate_ref(&self, reference: &str) -> Result<(), String> {
+        let res = match self {
+            LabelType::Tx => Txid::from_str(reference)
+                .map(|_| ())
+                .map_err(|e| e.to_string()),
+            LabelType::Input | LabelType::Output => OutPoint::from_str(reference)
+                .map(|_| ())
+                .map_err(|e| e.to_string()),
+            LabelType::Pubkey => PublicKey::from_str(reference)
+                .map(|_| ())
+                .map_err(|e| e.to_string()),
+            LabelType::Xpub => Xpub::from_str(reference)
+                .map(|_| ())
+                .map_err(|e| e.to_string()),
+            LabelType::Addr if reference.is_empty() => Err("Empty address".to_string()),
+            LabelType::Addr => Ok(()),
+        };
+
+        res.map_err(|err| format!("Invalid {self} reference {reference}: {err}"))
+    }
+}
+
+/// One line of a BIP-329 export.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Label {
+    #[serde(rename = "type")]
+    pub kind: LabelType,
+    #[serde(rename = "ref")]
+    pub reference: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub origin: Option<String>,
+    /// Only meaningful for outputs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spendable: Option<bool>,
+}
+
+/// Outcome of [`import`], records of unknown types are skipped as BIP-329 asks.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct ImportReport {
+    /// Address labels applied to accounts of the wallet.
+    pub accounts: usize,
+    /// Other labels stored alongside the wallet.
+    pub labels: usize,
+    pub unknown: usize,
+}
+
+/// Parses a JSONL document, returning the known records and how many unknown ones were skipped.
+///
+/// Blank lines are ignored, anything else which is not a valid record fails the whole parse
+/// so a corrupt file is never half imported.
+pub fn parse(content: &str) -> LabelResult<(Vec<Label>, usize)> {
+    let mut labels = vec![];
+    let mut unknown = 0;
+
+    for (index, line) in content.lines().enumerate() {
+        let line_number = index + 1;
+        let line = line.trim();
+
+        if line.is_empty() {
+            continue;
+        }
+
+        let value = serde_json::from_str::<Value>(line);
+        if let Err(err) = value {
+            return Err(LabelError::Record(line_number, err.to_string()));
+        }
+        let value = value.unwrap();
+
+        let kind = value.get("type").and_then(|kind| kind.as_str());
+        if kind.is_none() {
+            return Err(LabelError::Record(line_number, "Missing type".to_string()));
+        }
+
+        if LabelType::from_string(kind.unwrap()).is_none() {
+            unknown += 1;
+            continue;
+        }
+
+        let label = serde_json::from_value::<Label>(value);
+        if let Err(err) = label {
+            return Err(LabelError::Record(line_number, err.to_string()));
+        }
+        let label = label.unwrap();
+
+        if let Err(err) = label.kind.validate_ref(&label.reference) {
+            return Err(LabelError::Record(line_number, err));
+        }
+
+        labels.push(label);
+    }
+
+    Ok((labels, unknown))
+}
+
+/// Serializes records as JSONL, one per line.
+pub fn serialize(labels: &[Label]) -> String {
+    labels
+        .iter()
+        .filter_map(|label| serde_json::to_string(label).ok())
+        .map(|line| line + "\n")
+        .collect()
+}
+
+/// Exports the labels of the wallet: its labelled accounts as `addr` records followed by
+/// every other stored label.
+pub async fn export<V: VaultInterface + ?Sized>(vault: &V, wallet_id: &str) -> LabelResult<String> {
+    let accounts = vault.get_all_accounts(wallet_id).await?;
+    let mut labels: Vec<Label> = accounts
+        .into_iter()
+        .filter(|account| !account.label.is_empty())
+        .map(|account| Label {
+            kind: LabelType::Addr,
+            reference: account.address,
+            label: Some(account.label),
+            origin: None,
+            spendable: None,
+        })
+        .collect();
+
+    labels.extend(vault.get_labels(wallet_id).await?);
+
+    Ok(serialize(&labels))
+}
+
+/// Imports a JSONL document into the wallet, all of it or nothing. Address labels matching
+/// one of its accounts become the account label, everything else is stored as is, replacing
+/// records with the same type and reference.
+pub async fn import<V: VaultInterface + ?Sized>(
+    vault: &V,
+    wallet_id: &str,
+    content: &str,
+) -> LabelResult<ImportReport> {
+    // Also makes sure the wallet exists before touching anything.
+    vault.get_wallet_by_id(wallet_id).await?;
+
+    let (labels, unknown) = parse(content)?;
+
+    let accounts: HashMap<String, String> = vault
+        .get_all_accounts(wallet_id)
+        .await?
+        .into_iter()
+        .map(|account| (account.address, account.id))
+        .collect();
+
+    let mut report = ImportReport {
+        unknown,
+        ..Default::default()
+    };
+    let mut stored = vec![];
+    let mut tx = vault.begin().await?;
+
+    for label in labels {
+        let account_id = match label.kind {
+            LabelType::Addr => accounts.get(&label.reference),
+            _ => None,
+        };
+
+        match account_id {
+            // A record without a label has nothing to give the account, which keeps its own.
+            Some(id) => {
+                if let Some(text) = label.label {
+                    tx.update_account(UpdateAccountInput {
+                        id: id.clone(),
+                        label: Some(text),
+                        ..Default::default()
+                    })
+                    .await?;
+                    report.accounts += 1;
+                }
+            }
+            None => stored.push(label),
+        }
+    }
+
+    report.labels = stored.len();
+    tx.set_labels(wallet_id, stored).await?;
+    tx.commit().await?;
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TXID: &str = "f91d0a8a78462bc59398f2c5d7a84fcff491c26ba54c4833478b202796c8aafd";
+
+    #[test]
+    fn parses_every_record_type() {
+        let content = format!(
+            r#"{{"type":"tx","ref":"{TXID}","label":"Transaction","origin":"wpkh([d34db33f/84'/0'/0'])"}}
+{{"type":"addr","ref":"bc1q34aq5drpuwy3wgl9lhup9892qp6svr8ldzyy7c","label":"Address"}}
+{{"type":"pubkey","ref":"0283409659355b6d1cc3c32decd5d561abaac86c37a353b52895a5e6c196d6f448","label":"Public Key"}}
+{{"type":"input","ref":"{TXID}:0","label":"Input"}}
+{{"type":"output","ref":"{TXID}:1","label":"Output","spendable":false}}
+{{"type":"xpub","ref":"xpub661MyMwAqRbcFtXgS5sYJABqqG9YLmC4Q1Rdap9gSE8NqtwybGhePY2gZ29ESFjqJoCu1Rupje8YtGqsefD265TMg7usUDFdp6W1EGMcet8","label":"Extended Public Key"}}
+"#
+        );
+
+        let (labels, unknown) = parse(&content).unwrap();
+        assert_eq!(unknown, 0);
+        assert_eq!(labels.len(), 6);
+        assert_eq!(labels[4].spendable, Some(false));
+        assert_eq!(
+            labels[0].origin.as_deref(),
+            Some("wpkh([d34db33f/84'/0'/0'])")
+        );
+
+        // Serializing gives back the same records.
+        let (reparsed, _) = parse(&serialize(&labels)).unwrap();
+        assert_eq!(reparsed, labels);
+    }
+
+    #[test]
+    fn skips_unknown_types() {
+        let content = format!(
+            "{{\"type\":\"tx\",\"ref\":\"{TXID}\",\"label\":\"a\"}}\n\n{{\"type\":\"lnurl\",\"ref\":\"x\",\"label\":\"b\"}}\n"
+        );
+
+        let (labels, unknown) = parse(&content).unwrap();
+        assert_eq!(labels.len(), 1);
+        assert_eq!(unknown, 1);
+    }
+
+    #[test]
+    fn rejects_invalid_records() {
+        assert!(matches!(
+            parse("{\"type\":\"tx\",\"ref\":\"nope\"}"),
+            Err(LabelError::Record(1, _))
+        ));
+        assert!(matches!(
+            parse(&format!("\n{{\"type\":\"output\",\"ref\":\"{TXID}\"}}")),
+            Err(LabelError::Record(2, _))
+        ));
+        assert!(matches!(parse("not json"), Err(LabelError::Record(1, _))));
+        assert!(matches!(
+            parse("{\"ref\":\"x\"}"),
+            Err(LabelError::Record(1, _))
+        ));
+    }
+}
@@ -1,7 +1,28 @@
 pub mod account;
+pub mod audit;
+pub mod bip21;
+pub mod bip85;
 pub mod config;
+pub mod error;
+pub mod fixture;
+pub mod json_vault;
+pub mod kdf;
+pub mod keystore;
+pub mod labels;
+pub mod memory;
+pub mod mnemonic;
+pub mod multisig;
 pub mod path_builder;
+pub mod policy;
+pub mod postgres;
+pub mod psbt;
+pub mod query;
+pub mod schema;
+pub mod session;
+pub mod silent_payments;
 pub mod sqlite;
+pub mod taproot;
+pub mod throttle;
 pub mod utils;
 pub mod vault_interface;
 pub mod wallet;
@@ -1,67 +1,354 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use bitcoin::bip32::DerivationPath;
+use bitcoin::{
+    bip32::{DerivationPath, Xpriv},
+    consensus::encode::serialize_hex,
+    TapLeafHash,
+};
 use dev_wallet::{
-    account::AccountInputBuilder, sqlite::SqliteVault, vault_interface::VaultInterface,
-    wallet::WalletInputBuilder,
+    account::{AccountInputBuilder, Network, UpdateAccountInput},
+    audit::{self, AuditAction},
+    bip21::{self, PaymentRequest},
+    bip85::{self, Application},
+    config::{Config, ConfigFile, OpenedVault},
+    error::{CommandError, CommandResult, ErrorCode},
+    fixture::{self, FixtureError, FixtureSpec},
+    kdf::{self, KdfParams},
+    keystore::KeyStore,
+    labels,
+    mnemonic::{self, MnemonicDrafts, MnemonicOptions},
+    multisig::{Cosigner, CosignerInput, MultisigScriptType, StoreMultisigInput},
+    policy::{self, PolicyKey, PolicyKeyInput, SpendConditions, StorePolicyWalletInput},
+    psbt,
+    query::{AccountQuery, MAX_PAGE_SIZE},
+    session::SessionManager,
+    silent_payments::{self, CandidateTransaction, SilentPaymentKeys, SilentPaymentRecipient},
+    taproot,
+    throttle::{self, ThrottlePolicy},
+    utils::{blocking, KeyedLocks},
+    vault_interface::VaultInterface,
+    wallet::{UpdateWalletInput, WalletInputBuilder},
 };
 use serde_json::{json, Value};
-use std::{str::FromStr, sync::Arc};
+use std::{collections::HashSet, path::PathBuf, str::FromStr, sync::Arc, time::Duration};
 use tauri::{Manager, State};
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, RwLock};
+use zeroize::Zeroizing;
 
 // Learn more about Tauri commands at https://tauri.app/v1/guides/features/command
 
+// How often idle sessions are swept, so their keys don't outlive the timeout for long.
+const SESSION_SWEEP_INTERVAL: Duration = Duration::from_secs(15);
+
 struct AppState {
-    wallet: Arc<Mutex<WalletInputBuilder>>,
-    vault: Arc<Mutex<SqliteVault>>,
+    drafts: Arc<MnemonicDrafts>,
+    // Empty when the vault failed opening, `startup_error` tells why. Only written when
+    // switching profiles, commands hold their own handle on the vault rather than the lock.
+    vault: RwLock<Option<Arc<dyn VaultInterface>>>,
+    startup_error: Mutex<Option<CommandError>>,
+    // Copy of the database taken before migrating the vault in use, if it had to be.
+    backup: Mutex<Option<PathBuf>>,
+    config: Mutex<Config>,
+    sessions: Arc<SessionManager>,
+    throttle: ThrottlePolicy,
+    kdf: KdfParams,
+    keystore: Box<dyn KeyStore>,
+    // Held by logins, which may upgrade the KDF and so re-key the wallet, and by commands
+    // inserting accounts, so no account gets encrypted with a key about to be replaced.
+    wallet_locks: KeyedLocks,
+}
+
+impl AppState {
+    async fn vault(&self) -> CommandResult<Arc<dyn VaultInterface>> {
+        let vault = self.vault.read().await.clone();
+
+        if vault.is_none() {
+            return Err(match &*self.startup_error.lock().await {
+                Some(err) => err.clone(),
+                None => {
+                    CommandError::new(ErrorCode::VaultUnavailable, "The vault is not available")
+                }
+            });
+        }
+
+        Ok(vault.unwrap())
+    }
+}
+
+/// Whether the app started properly, the error explaining why it didn't otherwise.
+#[tauri::command]
+async fn startup_status(state: State<'_, AppState>) -> CommandResult<Value> {
+    let startup_error = state.startup_error.lock().await;
+    Ok(json!({
+        "ok": startup_error.is_none(),
+        "error": *startup_error,
+        "backup": *state.backup.lock().await,
+    }))
+}
+
+#[tauri::command]
+async fn list_profiles(state: State<'_, AppState>) -> CommandResult<Value> {
+    let config = state.config.lock().await;
+    let profiles: Vec<Value> = config
+        .file
+        .profile_names()
+        .iter()
+        .map(|name| match config.profile_named(name) {
+            Ok(profile) => profile.to_json(),
+            Err(err) => json!({"name": name, "error": err.to_string()}),
+        })
+        .collect();
+
+    Ok(json!({
+        "active": config.active_profile,
+        "profiles": profiles,
+    }))
 }
 
+/// Swaps the vault for the one of another profile, every session being locked since they
+/// belong to wallets of the previous one.
 #[tauri::command]
-async fn generate_mnemonic(state: State<'_, AppState>) -> Result<String, String> {
-    let mut wallet = state.wallet.lock().await;
+async fn switch_profile(name: String, state: State<'_, AppState>) -> CommandResult<Value> {
+    let mut config = state.config.lock().await;
+    let profile = config.profile_named(&name);
+
+    if let Err(err) = profile {
+        return Err(err.into());
+    }
+
+    let profile = profile.unwrap();
+
+    // The current vault stays in use when the new one can't be opened.
+    let vault = profile.open(config.vault_password.as_deref()).await;
+
+    if let Err(err) = vault {
+        return Err(err.into());
+    }
+
+    let opened = vault.unwrap();
+    *state.vault.write().await = Some(Arc::from(opened.vault));
+    *state.startup_error.lock().await = None;
+    *state.backup.lock().await = opened.backup.clone();
+    config.active_profile = name;
+    state.sessions.lock_all().await;
+
+    let mut result = profile.to_json();
+    result["backup"] = json!(opened.backup);
+    Ok(result)
+}
+
+/// Generates a mnemonic for a wallet to be created, along with the token `create_wallet`
+/// takes to use it.
+#[tauri::command]
+async fn generate_mnemonic(
+    options: Option<MnemonicOptions>,
+    state: State<'_, AppState>,
+) -> CommandResult<Value> {
+    let options = options.unwrap_or_default();
+    let mnemonic = mnemonic::generate(&options);
+
+    if let Err(err) = mnemonic {
+        return Err(err.into());
+    }
+
+    let mnemonic = mnemonic.unwrap();
+    let token = state.drafts.insert(mnemonic.clone()).await;
+
+    Ok(mnemonic::draft_to_json(
+        &token,
+        &mnemonic,
+        options.user_entropy.as_ref(),
+    ))
+}
 
-    wallet.regenerate_mnemonic();
-    Ok(wallet.mnemonic_as_string())
+/// Word counts and languages `generate_mnemonic` accepts.
+#[tauri::command]
+async fn mnemonic_options() -> CommandResult<Value> {
+    Ok(json!({
+        "words": mnemonic::WORD_COUNTS,
+        "default_words": mnemonic::DEFAULT_WORD_COUNT,
+        "languages": mnemonic::languages(),
+    }))
 }
 
 #[tauri::command]
 async fn authenticate(
     name: String,
     password: String,
+    remember: Option<bool>,
     state: State<'_, AppState>,
-) -> Result<Value, String> {
-    let vault = state.vault.lock().await;
+) -> CommandResult<Value> {
+    let vault = state.vault().await?;
+    let wallet = vault.get_wallet_by_name(&name).await;
+    if let Err(err) = wallet {
+        return Err(CommandError::wallet(err));
+    }
+    let wallet_id = wallet.unwrap().id;
+    let _guard = state.wallet_locks.lock(&wallet_id).await;
+    // Read again under the lock, a concurrent login may have just upgraded the KDF.
+    let wallet = vault.get_wallet_by_id(&wallet_id).await;
+
+    if let Err(err) = wallet {
+        return Err(CommandError::wallet(err));
+    }
+
+    let wallet = wallet.unwrap();
+    let key = throttle::authenticate(&*vault, &state.throttle, &wallet, &password).await;
+
+    if let Err(err) = key {
+        return Err(err.into());
+    }
+
+    let mut key = key.unwrap();
+    let mut remember = remember.unwrap_or(false);
+    let mut upgrade_error = None;
+
+    if wallet.needs_rehash(&state.kdf) {
+        match kdf::upgrade_wallet(&*vault, &wallet, &password, key, &state.kdf).await {
+            Ok(new_key) => {
+                // Sessions opened before the upgrade hold a key that no longer decrypts anything.
+                state.sessions.lock_wallet(&wallet.id).await;
+                key = new_key;
+                // Same goes for a remembered key, which has to be replaced.
+                remember = remember || matches!(state.keystore.load(&wallet.id).await, Ok(Some(_)));
+            }
+            Err(err) => {
+                // Not fatal, the wallet still opens under the old parameters and the upgrade
+                // is tried again on the next login.
+                let details = json!({ "error": err.to_string() });
+                let recorded =
+                    audit::record(&*vault, &wallet.id, AuditAction::KdfUpgradeFailed, details)
+                        .await;
+
+                if let Err(err) = recorded {
+                    return Err(err.into());
+                }
+
+                upgrade_error = Some(err.to_string());
+            }
+        }
+    }
+
+    if remember {
+        if let Err(err) = state.keystore.store(&wallet.id, &key).await {
+            return Err(err.into());
+        }
+    }
+
+    let session_id = state.sessions.open(&wallet.id, key).await;
+
+    let mut result = wallet.to_json();
+    result["session_id"] = json!(session_id);
+    result["kdf_upgrade_error"] = json!(upgrade_error);
+    Ok(result)
+}
+
+#[tauri::command]
+async fn authenticate_remembered(name: String, state: State<'_, AppState>) -> CommandResult<Value> {
+    let vault = state.vault().await?;
     let wallet = vault.get_wallet_by_name(&name).await;
     if let Err(err) = wallet {
-        return Err(err.to_string());
+        return Err(CommandError::wallet(err));
     }
     let wallet = wallet.unwrap();
-    let key = wallet.authenticate(&password);
+
+    let key = state.keystore.load(&wallet.id).await;
+    if let Err(err) = key {
+        return Err(err.into());
+    }
+
+    let key = key.unwrap();
+    if key.is_none() {
+        return Err(CommandError::new(
+            ErrorCode::NotFound,
+            format!("No key remembered for wallet {}", wallet.name),
+        ));
+    }
+
+    let key = wallet.authenticate_with_key(key.unwrap());
+    let action = match key {
+        Ok(_) => AuditAction::AuthSucceeded,
+        Err(_) => AuditAction::AuthFailed,
+    };
+    let recorded =
+        audit::record(&*vault, &wallet.id, action, json!({"method": "remembered"})).await;
+
+    if let Err(err) = recorded {
+        return Err(err.into());
+    }
 
     if let Err(err) = key {
-        return Err(err.to_string());
+        // The remembered key is stale, the password has to be typed again.
+        let _ = state.keystore.remove(&wallet.id).await;
+        return Err(err.into());
+    }
+
+    let session_id = state.sessions.open(&wallet.id, key.unwrap()).await;
+
+    let mut result = wallet.to_json();
+    result["session_id"] = json!(session_id);
+    Ok(result)
+}
+
+#[tauri::command]
+async fn forget(
+    wallet_id: String,
+    session_id: String,
+    state: State<'_, AppState>,
+) -> CommandResult<Value> {
+    let auth_res = state.sessions.key(&session_id, &wallet_id).await;
+
+    if let Err(err) = auth_res {
+        return Err(err.into());
+    }
+
+    let res = state.keystore.remove(&wallet_id).await;
+    if let Err(err) = res {
+        return Err(err.into());
     }
+    Ok(json!({"success": true}))
+}
 
-    Ok(wallet.to_json())
+#[tauri::command]
+async fn lock(session_id: String, state: State<'_, AppState>) -> CommandResult<Value> {
+    let locked = state.sessions.lock(&session_id).await;
+    Ok(json!({"success": locked}))
 }
 
 #[tauri::command]
 async fn create_wallet(
     name: String,
     password: String,
+    draft: String,
     state: State<'_, AppState>,
-) -> Result<Value, String> {
-    let mut wallet = state.wallet.lock().await;
-    let vault = state.vault.lock().await;
+) -> CommandResult<Value> {
+    let vault = state.vault().await?;
+    let mnemonic = state.drafts.take(&draft).await;
+
+    if let Err(err) = mnemonic {
+        return Err(err.into());
+    }
+
+    let mnemonic = mnemonic.unwrap();
+    let mut wallet = WalletInputBuilder::from(mnemonic.clone());
     wallet.name(&name);
     wallet.password(&password);
-    let wallet = wallet.build().unwrap();
-    let result = vault.insert_wallet(wallet).await;
+    wallet.kdf(state.kdf);
+    let wallet = blocking(move || wallet.build()).await;
+
+    let result = match wallet {
+        Ok(wallet) => vault
+            .insert_wallet(wallet)
+            .await
+            .map_err(CommandError::from),
+        Err(err) => Err(err.into()),
+    };
 
     if let Err(err) = result {
-        return Err(err.to_string());
+        // Lets the user pick another name without writing down another mnemonic.
+        state.drafts.put_back(&draft, mnemonic).await;
+        return Err(err);
     }
 
     Ok(result.unwrap().to_json())
@@ -71,147 +358,1657 @@ async fn create_wallet(
 async fn create_account(
     path: String,
     wallet_id: String,
-    password: String,
+    session_id: String,
     state: State<'_, AppState>,
-) -> Result<Value, String> {
-    let vault = state.vault.lock().await;
+) -> CommandResult<Value> {
+    let _guard = state.wallet_locks.lock(&wallet_id).await;
+    let key = state.sessions.key(&session_id, &wallet_id).await;
+
+    if let Err(err) = key {
+        return Err(err.into());
+    }
+
+    let vault = state.vault().await?;
     let wallet = vault.get_wallet_by_id(&wallet_id).await;
 
     if let Err(err) = wallet {
-        return Err(err.to_string());
+        return Err(CommandError::wallet(err));
     }
 
     let wallet = wallet.unwrap();
-    let key = wallet.authenticate(&password);
-
-    if let Err(err) = key {
-        return Err(err.to_string());
-    }
 
     let path = DerivationPath::from_str(&path);
 
     if let Err(err) = path {
-        return Err(err.to_string());
+        return Err(CommandError::new(ErrorCode::InvalidPath, err));
     }
 
     let mut account = AccountInputBuilder::from(wallet);
     account.path(path.unwrap());
 
-    let account = account.build(key.unwrap());
+    let key = key.unwrap();
+    let account = blocking(move || account.build(*key)).await;
 
     if let Err(err) = account {
-        return Err(err.to_string());
+        return Err(err.into());
     }
 
-    let account = vault.insert_account(account.unwrap()).await;
+    let accounts = audit::insert_accounts(&*vault, vec![account.unwrap()]).await;
 
-    if let Err(err) = account {
-        return Err(err.to_string());
+    if let Err(err) = accounts {
+        return Err(err.into());
     }
 
-    Ok(account.unwrap().to_json())
+    Ok(accounts.unwrap()[0].to_json())
 }
 
+/// Creates a wallet from the well-known development mnemonic, or from `mnemonic` when given,
+/// so the same addresses come out on every run. Refused unless in developer mode.
 #[tauri::command]
-async fn remove_wallet(
-    id: String,
+async fn create_dev_wallet(
+    name: String,
     password: String,
+    mnemonic: Option<String>,
     state: State<'_, AppState>,
-) -> Result<Value, String> {
-    let vault = state.vault.lock().await;
-    let wallet = vault.get_wallet_by_id(&id).await;
+) -> CommandResult<Value> {
+    if !state.config.lock().await.dev_mode {
+        return Err(FixtureError::Disabled.into());
+    }
+
+    let vault = state.vault().await?;
+    let wallet = fixture::dev_wallet_input(&name, &password, mnemonic.as_deref(), state.kdf);
+
     if let Err(err) = wallet {
-        return Err(err.to_string());
+        return Err(err.into());
     }
-    let auth_res = wallet.unwrap().authenticate(&password);
 
-    if let Err(err) = auth_res {
-        return Err(err.to_string());
+    let wallet = wallet.unwrap();
+    let wallet = blocking(move || wallet.build()).await;
+
+    if let Err(err) = wallet {
+        return Err(err.into());
     }
 
-    let res = vault.remove_wallet_by_id(&id).await;
-    if let Err(err) = res {
-        return Err(err.to_string());
+    let wallet = vault.insert_wallet(wallet.unwrap()).await;
+
+    if let Err(err) = wallet {
+        return Err(err.into());
     }
-    Ok(json!({"success": true}))
+
+    Ok(wallet.unwrap().to_json())
 }
 
+/// Derives `spec.count` accounts for each script type and network of `spec` and returns the
+/// resulting address book. Accounts the wallet already has are kept, so seeding twice is
+/// harmless.
 #[tauri::command]
-async fn remove_account(
-    id: String,
+async fn seed_fixture(
     wallet_id: String,
-    password: String,
+    session_id: String,
+    spec: Option<FixtureSpec>,
     state: State<'_, AppState>,
-) -> Result<Value, String> {
-    let vault = state.vault.lock().await;
+) -> CommandResult<Value> {
+    let _guard = state.wallet_locks.lock(&wallet_id).await;
+    let key = state.sessions.key(&session_id, &wallet_id).await;
+
+    if let Err(err) = key {
+        return Err(err.into());
+    }
+
+    let vault = state.vault().await?;
     let wallet = vault.get_wallet_by_id(&wallet_id).await;
+
     if let Err(err) = wallet {
-        return Err(err.to_string());
+        return Err(CommandError::wallet(err));
     }
-    let auth_res = wallet.unwrap().authenticate(&password);
 
-    if let Err(err) = auth_res {
-        return Err(err.to_string());
+    let spec = spec.unwrap_or_default();
+    let builder = AccountInputBuilder::from(wallet.unwrap());
+    let key = key.unwrap();
+    let accounts = blocking(move || spec.build(&builder, *key)).await;
+
+    if let Err(err) = accounts {
+        return Err(err.into());
     }
 
-    let res = vault.remove_account_by_id(&id).await;
-    if let Err(err) = res {
-        return Err(err.to_string());
+    let existing = vault.get_all_accounts(&wallet_id).await;
+
+    if let Err(err) = existing {
+        return Err(err.into());
     }
-    Ok(json!({"success": true}))
+
+    let existing: HashSet<String> = existing
+        .unwrap()
+        .into_iter()
+        .map(|account| account.address)
+        .collect();
+    let (entries, accounts) = accounts.unwrap();
+    let accounts: Vec<_> = accounts
+        .into_iter()
+        .filter(|account| !existing.contains(&account.address))
+        .collect();
+    let created = accounts.len();
+
+    if let Err(err) = audit::insert_accounts(&*vault, accounts).await {
+        return Err(err.into());
+    }
+
+    let mut book = fixture::address_book(&entries);
+    book["wallet_id"] = json!(wallet_id);
+    book["created"] = json!(created);
+    Ok(book)
 }
 
+/// Derives a BIP-85 child secret from the seed of the wallet. The secret leaves the vault, so
+/// the export is recorded, without the secret itself.
 #[tauri::command]
-async fn list_accounts(wallet_id: String, state: State<'_, AppState>) -> Result<Value, String> {
-    let vault = state.vault.lock().await;
-    let accounts = vault.get_all_accounts(&wallet_id).await;
-    if let Err(err) = accounts {
-        return Err(err.to_string());
+async fn derive_child_secret(
+    wallet_id: String,
+    session_id: String,
+    application: Application,
+    state: State<'_, AppState>,
+) -> CommandResult<Value> {
+    let key = state.sessions.key(&session_id, &wallet_id).await;
+
+    if let Err(err) = key {
+        return Err(err.into());
     }
 
-    Ok(accounts
+    let vault = state.vault().await?;
+    let wallet = vault.get_wallet_by_id(&wallet_id).await;
+
+    if let Err(err) = wallet {
+        return Err(CommandError::wallet(err));
+    }
+
+    let seed = wallet.unwrap().decrypt_seed(&key.unwrap());
+
+    if let Err(err) = seed {
+        return Err(err.into());
+    }
+
+    let seed = seed.unwrap();
+    let secret = bip85::master_key(&seed).and_then(|master| bip85::derive(&master, &application));
+
+    if let Err(err) = secret {
+        return Err(err.into());
+    }
+
+    let secret = secret.unwrap();
+    let export = bip85::to_json(&application, &secret);
+
+    if let Err(err) = export {
+        return Err(err.into());
+    }
+
+    let export = export.unwrap();
+    let details = json!({ "application": export["application"], "path": export["path"] });
+
+    if let Err(err) = audit::record(&*vault, &wallet_id, AuditAction::SecretExported, details).await
+    {
+        return Err(err.into());
+    }
+
+    Ok(export)
+}
+
+/// Creates a wallet from the BIP-85 child mnemonic at `index` of the wallet, so one master
+/// wallet can restore every child wallet spawned from it.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn create_child_wallet(
+    wallet_id: String,
+    session_id: String,
+    index: u32,
+    words: Option<usize>,
+    language: Option<String>,
+    name: String,
+    password: String,
+    state: State<'_, AppState>,
+) -> CommandResult<Value> {
+    let key = state.sessions.key(&session_id, &wallet_id).await;
+
+    if let Err(err) = key {
+        return Err(err.into());
+    }
+
+    let vault = state.vault().await?;
+    let parent = vault.get_wallet_by_id(&wallet_id).await;
+
+    if let Err(err) = parent {
+        return Err(CommandError::wallet(err));
+    }
+
+    let seed = parent.unwrap().decrypt_seed(&key.unwrap());
+
+    if let Err(err) = seed {
+        return Err(err.into());
+    }
+
+    let words = words.unwrap_or(mnemonic::DEFAULT_WORD_COUNT);
+    let language = language.unwrap_or("english".to_string());
+    let path = Application::Bip39 {
+        words,
+        language: language.clone(),
+        index,
+    }
+    .path();
+
+    if let Err(err) = path {
+        return Err(err.into());
+    }
+
+    let mnemonic = bip85::derive_mnemonic(&seed.unwrap(), words, &language, index);
+
+    if let Err(err) = mnemonic {
+        return Err(err.into());
+    }
+
+    let mut wallet = WalletInputBuilder::from(mnemonic.unwrap());
+    wallet.name(&name);
+    wallet.password(&password);
+    wallet.kdf(state.kdf);
+    let wallet = blocking(move || wallet.build()).await;
+
+    if let Err(err) = wallet {
+        return Err(err.into());
+    }
+
+    let path = format!("m/{}", path.unwrap());
+    let wallet = audit::insert_child_wallet(&*vault, wallet.unwrap(), &wallet_id, &path).await;
+
+    if let Err(err) = wallet {
+        return Err(err.into());
+    }
+
+    Ok(wallet.unwrap().to_json())
+}
+
+/// Key of a multisig or policy wallet, out of a wallet unlocked by the session given or an
+/// imported key.
+async fn resolve_cosigner(
+    state: &AppState,
+    vault: &dyn VaultInterface,
+    input: CosignerInput,
+    script_type: MultisigScriptType,
+    network: Network,
+    account: u32,
+) -> CommandResult<Cosigner> {
+    let cosigner = match input {
+        CosignerInput::Wallet {
+            wallet_id,
+            session_id,
+        } => {
+            let key = state.sessions.key(&session_id, &wallet_id).await;
+
+            if let Err(err) = key {
+                return Err(err.into());
+            }
+
+            let wallet = vault.get_wallet_by_id(&wallet_id).await;
+
+            if let Err(err) = wallet {
+                return Err(CommandError::wallet(err));
+            }
+
+            Cosigner::local(
+                &wallet.unwrap(),
+                &key.unwrap(),
+                script_type,
+                network,
+                account,
+            )
+        }
+        CosignerInput::Key { key } => Cosigner::imported(&key),
+    };
+
+    cosigner.map_err(CommandError::from)
+}
+
+/// Creates an M-of-N multisig out of wallets of the vault, each sharing its key at the BIP-48
+/// path of `account`, and of imported keys.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn create_multisig(
+    name: String,
+    threshold: usize,
+    script_type: Option<MultisigScriptType>,
+    network: Network,
+    cosigners: Vec<CosignerInput>,
+    account: Option<u32>,
+    state: State<'_, AppState>,
+) -> CommandResult<Value> {
+    let vault = state.vault().await?;
+    let script_type = script_type.unwrap_or_default();
+    let mut keys = vec![];
+
+    for cosigner in cosigners {
+        let cosigner = resolve_cosigner(
+            &state,
+            &*vault,
+            cosigner,
+            script_type,
+            network,
+            account.unwrap_or(0),
+        )
+        .await?;
+        keys.push(cosigner);
+    }
+
+    let input = StoreMultisigInput {
+        name,
+        threshold,
+        script_type,
+        network,
+        cosigners: keys,
+    };
+
+    if let Err(err) = input.validate() {
+        return Err(err.into());
+    }
+
+    let multisig = vault.insert_multisig(input).await;
+
+    if let Err(err) = multisig {
+        return Err(err.into());
+    }
+
+    Ok(multisig.unwrap().to_json())
+}
+
+#[tauri::command]
+async fn list_multisigs(state: State<'_, AppState>) -> CommandResult<Value> {
+    let vault = state.vault().await?;
+    let multisigs = vault.get_all_multisigs().await;
+
+    if let Err(err) = multisigs {
+        return Err(err.into());
+    }
+
+    Ok(multisigs
         .unwrap()
         .iter()
         .map(|item| item.to_json())
         .collect())
 }
 
+/// Addresses of the receive (`change` 0, the default) or change chain of the multisig.
 #[tauri::command]
-async fn list_wallets(state: State<'_, AppState>) -> Result<Value, String> {
-    let vault = state.vault.lock().await;
-    let wallets = vault.get_all_wallets().await;
-    if let Err(err) = wallets {
-        return Err(err.to_string());
+async fn multisig_addresses(
+    id: String,
+    change: Option<u32>,
+    start: Option<u32>,
+    count: Option<u32>,
+    state: State<'_, AppState>,
+) -> CommandResult<Value> {
+    let change = change.unwrap_or(0);
+    let start = start.unwrap_or(0);
+    let count = count.unwrap_or(10);
+
+    if change > 1 || count == 0 || count > MAX_PAGE_SIZE {
+        return Err(CommandError::invalid_input(format!(
+            "Expected change 0 or 1 and 1 to {MAX_PAGE_SIZE} addresses"
+        )));
     }
 
-    Ok(wallets.unwrap().iter().map(|item| item.to_json()).collect())
+    let vault = state.vault().await?;
+    let multisig = vault.get_multisig_by_id(&id).await;
+
+    if let Err(err) = multisig {
+        return Err(err.into());
+    }
+
+    let multisig = multisig.unwrap();
+    let mut addresses = vec![];
+
+    for index in start..start.saturating_add(count) {
+        let address = multisig.address(change, index);
+
+        if let Err(err) = address {
+            return Err(err.into());
+        }
+
+        addresses.push(json!({
+            "index": index,
+            "address": address.unwrap().to_string(),
+        }));
+    }
+
+    Ok(json!(addresses))
 }
 
-#[async_std::main]
-async fn main() {
-    let vault = SqliteVault::new(Some("sqlite://database.db")).await;
-    vault.migrate().await.unwrap();
+/// Adds the signatures of a cosigner wallet to the PSBT, which stays partially signed until the
+/// other cosigners signed too.
+#[tauri::command]
+async fn sign_multisig_psbt(
+    id: String,
+    psbt: String,
+    wallet_id: String,
+    session_id: String,
+    state: State<'_, AppState>,
+) -> CommandResult<Value> {
+    let key = state.sessions.key(&session_id, &wallet_id).await;
+
+    if let Err(err) = key {
+        return Err(err.into());
+    }
+
+    let vault = state.vault().await?;
+    let multisig = vault.get_multisig_by_id(&id).await;
+
+    if let Err(err) = multisig {
+        return Err(err.into());
+    }
+
+    let wallet = vault.get_wallet_by_id(&wallet_id).await;
+
+    if let Err(err) = wallet {
+        return Err(CommandError::wallet(err));
+    }
+
+    let seed = wallet.unwrap().decrypt_seed(&key.unwrap());
+
+    if let Err(err) = seed {
+        return Err(err.into());
+    }
+
+    let mut psbt = match psbt::decode(&psbt) {
+        Ok(psbt) => psbt,
+        Err(err) => return Err(err.into()),
+    };
+
+    let multisig = multisig.unwrap();
+    let seed = seed.unwrap();
+    let signer = wallet_id.clone();
+    let signed = blocking(move || {
+        multisig
+            .sign_psbt(&mut psbt, &signer, &seed)
+            .map(|signatures| (psbt, signatures))
+    })
+    .await;
+
+    if let Err(err) = signed {
+        return Err(err.into());
+    }
+
+    let (psbt, signatures) = signed.unwrap();
+    let txid = psbt.unsigned_tx.compute_txid().to_string();
+    let details = json!({ "multisig_id": id, "txid": txid, "signatures": signatures });
+
+    if let Err(err) = audit::record(&*vault, &wallet_id, AuditAction::Signed, details).await {
+        return Err(err.into());
+    }
+
+    Ok(json!({
+        "psbt": psbt::encode(&psbt),
+        "txid": txid,
+        "signatures": signatures,
+    }))
+}
+
+/// Merges PSBTs of the same transaction signed by different cosigners.
+#[tauri::command]
+async fn combine_psbts(psbts: Vec<String>) -> CommandResult<Value> {
+    let mut decoded = vec![];
+
+    for psbt in psbts {
+        match psbt::decode(&psbt) {
+            Ok(psbt) => decoded.push(psbt),
+            Err(err) => return Err(err.into()),
+        }
+    }
+
+    let combined = psbt::combine(decoded);
+
+    if let Err(err) = combined {
+        return Err(err.into());
+    }
+
+    Ok(json!({ "psbt": psbt::encode(&combined.unwrap()) }))
+}
+
+/// Finalizes a PSBT signed by at least `threshold` cosigners and returns the raw transaction.
+#[tauri::command]
+async fn finalize_multisig_psbt(
+    id: String,
+    psbt: String,
+    state: State<'_, AppState>,
+) -> CommandResult<Value> {
+    let vault = state.vault().await?;
+    let multisig = vault.get_multisig_by_id(&id).await;
+
+    if let Err(err) = multisig {
+        return Err(err.into());
+    }
+
+    let mut psbt = match psbt::decode(&psbt) {
+        Ok(psbt) => psbt,
+        Err(err) => return Err(err.into()),
+    };
+
+    let multisig = multisig.unwrap();
+    let tx = blocking(move || multisig.finalize_psbt(&mut psbt)).await;
+
+    if let Err(err) = tx {
+        return Err(err.into());
+    }
+
+    let tx = tx.unwrap();
+    Ok(json!({
+        "tx": serialize_hex(&tx),
+        "txid": tx.compute_txid().to_string(),
+    }))
+}
+
+/// Removes the multisig, its cosigner wallets stay in the vault. Takes a session of one of its
+/// local cosigner wallets, whose audit log records the removal.
+#[tauri::command]
+async fn remove_multisig(
+    id: String,
+    wallet_id: String,
+    session_id: String,
+    state: State<'_, AppState>,
+) -> CommandResult<Value> {
+    let key = state.sessions.key(&session_id, &wallet_id).await;
+
+    if let Err(err) = key {
+        return Err(err.into());
+    }
+
+    let vault = state.vault().await?;
+    let multisig = vault.get_multisig_by_id(&id).await;
+
+    if let Err(err) = multisig {
+        return Err(err.into());
+    }
+
+    let multisig = multisig.unwrap();
+
+    if let Err(err) = multisig.cosigner_of(&wallet_id) {
+        return Err(err.into());
+    }
+
+    let res = audit::remove_multisig(&*vault, &multisig, &wallet_id).await;
+
+    if let Err(err) = res {
+        return Err(err.into());
+    }
+
+    Ok(json!({"success": true}))
+}
+
+async fn resolve_policy_keys(
+    state: &AppState,
+    vault: &dyn VaultInterface,
+    keys: Vec<PolicyKeyInput>,
+    script_type: MultisigScriptType,
+    network: Network,
+) -> CommandResult<Vec<PolicyKey>> {
+    let mut policy_keys = vec![];
+
+    for key in keys {
+        let cosigner = resolve_cosigner(
+            state,
+            vault,
+            key.cosigner,
+            script_type,
+            network,
+            key.account,
+        )
+        .await?;
+
+        policy_keys.push(PolicyKey {
+            alias: key.alias,
+            cosigner,
+        });
+    }
+
+    Ok(policy_keys)
+}
+
+/// Creates a wallet spending under a miniscript policy, e.g.
+/// `or(thresh(2,pk(a),pk(b),pk(c)),and(pk(d),older(1000)))`, each alias being a key of `keys`.
+#[tauri::command]
+async fn create_policy_wallet(
+    name: String,
+    policy: String,
+    script_type: Option<MultisigScriptType>,
+    network: Network,
+    keys: Vec<PolicyKeyInput>,
+    state: State<'_, AppState>,
+) -> CommandResult<Value> {
+    let vault = state.vault().await?;
+    let script_type = script_type.unwrap_or_default();
+    let policy_keys = resolve_policy_keys(&state, &*vault, keys, script_type, network).await?;
+
+    // Compiling explores every way to satisfy the policy, which grows fast with its size.
+    let input = blocking(move || {
+        StorePolicyWalletInput::compile(&name, &policy, script_type, network, policy_keys)
+    })
+    .await;
+
+    if let Err(err) = input {
+        return Err(err.into());
+    }
+
+    let wallet = vault.insert_policy_wallet(input.unwrap()).await;
+
+    if let Err(err) = wallet {
+        return Err(err.into());
+    }
+
+    Ok(wallet.unwrap().to_json())
+}
+
+#[tauri::command]
+async fn list_policy_wallets(state: State<'_, AppState>) -> CommandResult<Value> {
+    let vault = state.vault().await?;
+    let wallets = vault.get_all_policy_wallets().await;
+
+    if let Err(err) = wallets {
+        return Err(err.into());
+    }
+
+    Ok(wallets.unwrap().iter().map(|item| item.to_json()).collect())
+}
+
+/// Creates a policy wallet out of a descriptor template written with the aliases of `keys`,
+/// e.g. `tr(unspendable,{pk(a),and_v(v:pk(b),older(144))})` for a script tree with no key path.
+#[tauri::command]
+async fn create_descriptor_wallet(
+    name: String,
+    descriptor: String,
+    network: Network,
+    keys: Vec<PolicyKeyInput>,
+    state: State<'_, AppState>,
+) -> CommandResult<Value> {
+    let script_type = policy::template_script_type(&descriptor);
+
+    if let Err(err) = script_type {
+        return Err(err.into());
+    }
+
+    let vault = state.vault().await?;
+    let policy_keys =
+        resolve_policy_keys(&state, &*vault, keys, script_type.unwrap(), network).await?;
+    let input = StorePolicyWalletInput::from_template(&name, &descriptor, network, policy_keys);
+
+    if let Err(err) = input {
+        return Err(err.into());
+    }
+
+    let wallet = vault.insert_policy_wallet(input.unwrap()).await;
+
+    if let Err(err) = wallet {
+        return Err(err.into());
+    }
+
+    Ok(wallet.unwrap().to_json())
+}
+
+/// Addresses of the receive (`change` 0, the default) or change chain of the policy wallet.
+#[tauri::command]
+async fn policy_wallet_addresses(
+    id: String,
+    change: Option<u32>,
+    start: Option<u32>,
+    count: Option<u32>,
+    state: State<'_, AppState>,
+) -> CommandResult<Value> {
+    let change = change.unwrap_or(0);
+    let start = start.unwrap_or(0);
+    let count = count.unwrap_or(10);
+
+    if change > 1 || count == 0 || count > MAX_PAGE_SIZE {
+        return Err(CommandError::invalid_input(format!(
+            "Expected change 0 or 1 and 1 to {MAX_PAGE_SIZE} addresses"
+        )));
+    }
+
+    let vault = state.vault().await?;
+    let wallet = vault.get_policy_wallet_by_id(&id).await;
+
+    if let Err(err) = wallet {
+        return Err(err.into());
+    }
+
+    let wallet = wallet.unwrap();
+    let mut addresses = vec![];
+
+    for index in start..start.saturating_add(count) {
+        let address = wallet.address(change, index);
+
+        if let Err(err) = address {
+            return Err(err.into());
+        }
+
+        addresses.push(json!({
+            "index": index,
+            "address": address.unwrap().to_string(),
+        }));
+    }
+
+    Ok(json!(addresses))
+}
+
+/// Cheapest spend path of the policy wallet given the keys able to sign, the chain tip and
+/// when the coin confirmed, along with the lock time and sequence the transaction needs.
+#[tauri::command]
+async fn plan_policy_spend(
+    id: String,
+    conditions: SpendConditions,
+    state: State<'_, AppState>,
+) -> CommandResult<Value> {
+    let vault = state.vault().await?;
+    let wallet = vault.get_policy_wallet_by_id(&id).await;
+
+    if let Err(err) = wallet {
+        return Err(err.into());
+    }
+
+    let wallet = wallet.unwrap();
+    let plan = blocking(move || wallet.plan(&conditions)).await;
+
+    if let Err(err) = plan {
+        return Err(err.into());
+    }
+
+    Ok(json!(plan.unwrap()))
+}
+
+/// Leaves of a taproot policy wallet at an address, with the control blocks spending through
+/// them takes.
+#[tauri::command]
+async fn policy_wallet_leaves(
+    id: String,
+    change: Option<u32>,
+    index: Option<u32>,
+    state: State<'_, AppState>,
+) -> CommandResult<Value> {
+    let vault = state.vault().await?;
+    let wallet = vault.get_policy_wallet_by_id(&id).await;
+
+    if let Err(err) = wallet {
+        return Err(err.into());
+    }
+
+    let tree = wallet
+        .unwrap()
+        .tap_tree(change.unwrap_or(0), index.unwrap_or(0));
+
+    if let Err(err) = tree {
+        return Err(err.into());
+    }
+
+    Ok(json!(tree.unwrap()))
+}
+
+/// Adds the signatures of every key of the policy the wallet holds to the PSBT, committing to
+/// `sighash_type`, e.g. `SIGHASH_SINGLE|SIGHASH_ANYONECANPAY`, when given.
+#[tauri::command]
+async fn sign_policy_psbt(
+    id: String,
+    psbt: String,
+    wallet_id: String,
+    session_id: String,
+    sighash_type: Option<String>,
+    state: State<'_, AppState>,
+) -> CommandResult<Value> {
+    let sighash_type = match sighash_type.as_deref().map(taproot::parse_sighash_type) {
+        Some(Err(err)) => return Err(err.into()),
+        Some(Ok(sighash_type)) => Some(sighash_type),
+        None => None,
+    };
+
+    let key = state.sessions.key(&session_id, &wallet_id).await;
+
+    if let Err(err) = key {
+        return Err(err.into());
+    }
+
+    let vault = state.vault().await?;
+    let policy_wallet = vault.get_policy_wallet_by_id(&id).await;
+
+    if let Err(err) = policy_wallet {
+        return Err(err.into());
+    }
+
+    let wallet = vault.get_wallet_by_id(&wallet_id).await;
+
+    if let Err(err) = wallet {
+        return Err(CommandError::wallet(err));
+    }
+
+    let seed = wallet.unwrap().decrypt_seed(&key.unwrap());
+
+    if let Err(err) = seed {
+        return Err(err.into());
+    }
+
+    let mut psbt = match psbt::decode(&psbt) {
+        Ok(psbt) => psbt,
+        Err(err) => return Err(err.into()),
+    };
+
+    let policy_wallet = policy_wallet.unwrap();
+    let seed = seed.unwrap();
+    let signer = wallet_id.clone();
+    let signed = blocking(move || {
+        policy_wallet
+            .sign_psbt(&mut psbt, &signer, &seed, sighash_type)
+            .map(|signatures| (psbt, signatures))
+    })
+    .await;
+
+    if let Err(err) = signed {
+        return Err(err.into());
+    }
+
+    let (psbt, signatures) = signed.unwrap();
+    let txid = psbt.unsigned_tx.compute_txid().to_string();
+    let details = json!({ "policy_wallet_id": id, "txid": txid, "signatures": signatures });
+
+    if let Err(err) = audit::record(&*vault, &wallet_id, AuditAction::Signed, details).await {
+        return Err(err.into());
+    }
+
+    Ok(json!({
+        "psbt": psbt::encode(&psbt),
+        "txid": txid,
+        "signatures": signatures,
+    }))
+}
+
+/// Finalizes a PSBT satisfying a path of the policy and returns the raw transaction.
+#[tauri::command]
+async fn finalize_policy_psbt(
+    id: String,
+    psbt: String,
+    state: State<'_, AppState>,
+) -> CommandResult<Value> {
+    let vault = state.vault().await?;
+    let wallet = vault.get_policy_wallet_by_id(&id).await;
+
+    if let Err(err) = wallet {
+        return Err(err.into());
+    }
+
+    let mut psbt = match psbt::decode(&psbt) {
+        Ok(psbt) => psbt,
+        Err(err) => return Err(err.into()),
+    };
+
+    let wallet = wallet.unwrap();
+    let tx = blocking(move || wallet.finalize_psbt(&mut psbt)).await;
+
+    if let Err(err) = tx {
+        return Err(err.into());
+    }
+
+    let tx = tx.unwrap();
+    Ok(json!({
+        "tx": serialize_hex(&tx),
+        "txid": tx.compute_txid().to_string(),
+    }))
+}
+
+/// BIP-341 signature hash of a taproot input of the PSBT, through the key path or the leaf of
+/// `leaf_hash`, for signing elsewhere.
+#[tauri::command]
+fn taproot_sighash(
+    psbt: String,
+    input: usize,
+    leaf_hash: Option<String>,
+    sighash_type: Option<String>,
+) -> CommandResult<Value> {
+    let psbt = psbt::decode(&psbt);
+
+    if let Err(err) = psbt {
+        return Err(err.into());
+    }
+
+    let psbt = psbt.unwrap();
+    let leaf = match leaf_hash.as_deref().map(TapLeafHash::from_str) {
+        Some(Err(err)) => return Err(CommandError::invalid_input(err)),
+        Some(Ok(leaf)) => Some(leaf),
+        None => None,
+    };
+
+    let sighash_type = taproot::parse_sighash_type(sighash_type.as_deref().unwrap_or("DEFAULT"));
+
+    if let Err(err) = sighash_type {
+        return Err(err.into());
+    }
+
+    let sighash_type = sighash_type.unwrap();
+    let sighash = taproot::psbt_sighash(&psbt, input, leaf, sighash_type);
+
+    if let Err(err) = sighash {
+        return Err(err.into());
+    }
+
+    Ok(json!({
+        "sighash": sighash.unwrap().to_string(),
+        "sighash_type": sighash_type.to_string(),
+    }))
+}
+
+/// Removes the policy wallet, the wallets holding its keys stay in the vault. Takes a session of
+/// one of those wallets, whose audit log records the removal.
+#[tauri::command]
+async fn remove_policy_wallet(
+    id: String,
+    wallet_id: String,
+    session_id: String,
+    state: State<'_, AppState>,
+) -> CommandResult<Value> {
+    let key = state.sessions.key(&session_id, &wallet_id).await;
+
+    if let Err(err) = key {
+        return Err(err.into());
+    }
+
+    let vault = state.vault().await?;
+    let policy = vault.get_policy_wallet_by_id(&id).await;
+
+    if let Err(err) = policy {
+        return Err(err.into());
+    }
+
+    let policy = policy.unwrap();
+
+    if let Err(err) = policy.check_cosigner(&wallet_id) {
+        return Err(err.into());
+    }
+
+    let res = audit::remove_policy_wallet(&*vault, &policy, &wallet_id).await;
+
+    if let Err(err) = res {
+        return Err(err.into());
+    }
+
+    Ok(json!({"success": true}))
+}
+
+/// Seed of a wallet unlocked by the session.
+async fn unlock_seed(
+    state: &AppState,
+    vault: &dyn VaultInterface,
+    wallet_id: &str,
+    session_id: &str,
+) -> CommandResult<Zeroizing<Vec<u8>>> {
+    let key = state.sessions.key(session_id, wallet_id).await;
+
+    if let Err(err) = key {
+        return Err(err.into());
+    }
+
+    let wallet = vault.get_wallet_by_id(wallet_id).await;
+
+    if let Err(err) = wallet {
+        return Err(CommandError::wallet(err));
+    }
+
+    wallet
+        .unwrap()
+        .decrypt_seed(&key.unwrap())
+        .map_err(CommandError::from)
+}
+
+/// BIP-352 silent payment address of an account of the wallet, labeled when `label` is
+/// given so payments through it can be told apart.
+#[tauri::command]
+async fn silent_payment_address(
+    wallet_id: String,
+    session_id: String,
+    network: Network,
+    account: Option<u32>,
+    label: Option<u32>,
+    state: State<'_, AppState>,
+) -> CommandResult<Value> {
+    let vault = state.vault().await?;
+    let seed = unlock_seed(&state, &*vault, &wallet_id, &session_id).await?;
+    let account = account.unwrap_or(0);
+    let address =
+        SilentPaymentKeys::derive(&seed, network, account).and_then(|keys| keys.address(label));
+
+    if let Err(err) = address {
+        return Err(err.into());
+    }
+
+    let address = address.unwrap();
+    Ok(json!({
+        "address": address.to_string(),
+        "network": network,
+        "account": account,
+        "label": label,
+        "scan_key": address.scan.to_string(),
+        "spend_key": address.spend.to_string(),
+    }))
+}
+
+/// Outputs paying the account among candidate transactions a chain backend supplies, each
+/// with the outputs its inputs spend. Payments to `labels` are found too, along with change.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn scan_silent_payments(
+    wallet_id: String,
+    session_id: String,
+    network: Network,
+    account: Option<u32>,
+    labels: Option<Vec<u32>>,
+    transactions: Vec<CandidateTransaction>,
+    state: State<'_, AppState>,
+) -> CommandResult<Value> {
+    let vault = state.vault().await?;
+    let seed = unlock_seed(&state, &*vault, &wallet_id, &session_id).await?;
+    let keys = SilentPaymentKeys::derive(&seed, network, account.unwrap_or(0));
+
+    if let Err(err) = keys {
+        return Err(err.into());
+    }
+
+    let keys = keys.unwrap();
+    let labels = labels.unwrap_or_default();
+    // Every candidate costs a few elliptic curve multiplications.
+    let found = blocking(move || {
+        let mut found = vec![];
+
+        for candidate in transactions.iter() {
+            let (tx, prevouts) = candidate.parse()?;
+            let txid = tx.compute_txid().to_string();
+
+            for output in keys.scan(&tx, &prevouts, &labels)? {
+                let mut output = json!(output);
+                output["txid"] = json!(txid);
+                found.push(output);
+            }
+        }
+
+        Ok::<_, silent_payments::SilentPaymentError>(found)
+    })
+    .await;
+
+    if let Err(err) = found {
+        return Err(err.into());
+    }
+
+    Ok(json!(found.unwrap()))
+}
+
+/// Appends outputs paying `sp1…` addresses to the PSBT, whose inputs can't change anymore.
+/// The wallet has to hold the key of every input BIP-352 reads a key from.
+#[tauri::command]
+async fn add_silent_payment_outputs(
+    wallet_id: String,
+    session_id: String,
+    network: Network,
+    psbt: String,
+    recipients: Vec<SilentPaymentRecipient>,
+    state: State<'_, AppState>,
+) -> CommandResult<Value> {
+    let vault = state.vault().await?;
+    let seed = unlock_seed(&state, &*vault, &wallet_id, &session_id).await?;
+    let master = Xpriv::new_master(network.to_bitcoin_network_kind(), &seed);
+
+    if let Err(err) = master {
+        return Err(CommandError::new(ErrorCode::DerivationFailed, err));
+    }
+
+    let mut psbt = match psbt::decode(&psbt) {
+        Ok(psbt) => psbt,
+        Err(err) => return Err(err.into()),
+    };
+
+    let master = master.unwrap();
+    // Every input key and recipient costs a few elliptic curve multiplications.
+    let sent = blocking(move || {
+        let outputs = silent_payments::add_outputs(&mut psbt, &master, network, &recipients)?;
+        Ok::<_, silent_payments::SilentPaymentError>((psbt, outputs))
+    })
+    .await;
+
+    if let Err(err) = sent {
+        return Err(err.into());
+    }
+
+    let (psbt, outputs) = sent.unwrap();
+    Ok(json!({
+        "psbt": psbt::encode(&psbt),
+        "outputs": outputs,
+    }))
+}
+
+/// Builds the `bitcoin:` URI asking to pay the account's address, `amount` being in BTC.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn payment_uri(
+    id: String,
+    wallet_id: String,
+    amount: Option<String>,
+    label: Option<String>,
+    message: Option<String>,
+    lightning: Option<String>,
+    pj: Option<String>,
+    state: State<'_, AppState>,
+) -> CommandResult<Value> {
+    let vault = state.vault().await?;
+    let account = vault.get_account_by_id(&id).await;
+
+    if let Err(err) = account {
+        return Err(CommandError::account(err));
+    }
+
+    let account = account.unwrap();
+
+    if account.wallet_id != wallet_id {
+        return Err(CommandError::new(
+            ErrorCode::AccountNotFound,
+            format!("Account {} not found in wallet {}", id, wallet_id),
+        ));
+    }
+
+    let network = Network::from_string(&account.network);
+
+    if let Err(err) = network {
+        return Err(CommandError::invalid_input(format!(
+            "{}: {}",
+            err, account.network
+        )));
+    }
+
+    let request = PaymentRequest::new(&account.address, network.unwrap());
+
+    if let Err(err) = request {
+        return Err(err.into());
+    }
+
+    let mut request = request.unwrap();
+
+    if let Some(amount) = amount {
+        let amount = bip21::parse_amount(&amount);
+
+        if let Err(err) = amount {
+            return Err(err.into());
+        }
+
+        request.amount = Some(amount.unwrap());
+    }
+
+    request.label = label;
+    request.message = message;
+    request.lightning = lightning;
+    request.pj = pj;
+
+    if let Err(err) = request.validate() {
+        return Err(err.into());
+    }
+
+    Ok(request.to_json())
+}
+
+/// Reads a scanned or pasted `bitcoin:` URI into the transaction it asks for, refusing
+/// addresses of any network but `network`.
+#[tauri::command]
+fn parse_payment_uri(uri: String, network: Network) -> CommandResult<Value> {
+    let request = PaymentRequest::parse(&uri, network);
+
+    if let Err(err) = request {
+        return Err(err.into());
+    }
+
+    Ok(request.unwrap().to_json())
+}
+
+/// Derives `count` accounts at consecutive indexes starting from `path`, all of them being
+/// stored or none.
+#[tauri::command]
+async fn create_accounts(
+    path: String,
+    count: u32,
+    wallet_id: String,
+    session_id: String,
+    state: State<'_, AppState>,
+) -> CommandResult<Vec<Value>> {
+    if count == 0 || count > MAX_PAGE_SIZE {
+        return Err(CommandError::invalid_input(format!(
+            "Can only create 1 to {MAX_PAGE_SIZE} accounts at once"
+        )));
+    }
+
+    let _guard = state.wallet_locks.lock(&wallet_id).await;
+    let key = state.sessions.key(&session_id, &wallet_id).await;
+
+    if let Err(err) = key {
+        return Err(err.into());
+    }
+
+    let vault = state.vault().await?;
+    let wallet = vault.get_wallet_by_id(&wallet_id).await;
+
+    if let Err(err) = wallet {
+        return Err(CommandError::wallet(err));
+    }
+
+    let path = DerivationPath::from_str(&path);
+
+    if let Err(err) = path {
+        return Err(CommandError::new(ErrorCode::InvalidPath, err));
+    }
+
+    let mut builder = AccountInputBuilder::from(wallet.unwrap());
+    builder.path(path.unwrap());
+
+    let key = key.unwrap();
+    let accounts = blocking(move || builder.build_many(*key, count)).await;
+
+    if let Err(err) = accounts {
+        return Err(err.into());
+    }
+
+    let accounts = audit::insert_accounts(&*vault, accounts.unwrap()).await;
+
+    if let Err(err) = accounts {
+        return Err(err.into());
+    }
+
+    Ok(accounts
+        .unwrap()
+        .iter()
+        .map(|account| account.to_json())
+        .collect())
+}
+
+#[tauri::command]
+async fn remove_wallet(
+    id: String,
+    session_id: String,
+    password: String,
+    state: State<'_, AppState>,
+) -> CommandResult<Value> {
+    let auth_res = state.sessions.key(&session_id, &id).await;
+
+    if let Err(err) = auth_res {
+        return Err(err.into());
+    }
+
+    let vault = state.vault().await?;
+    let wallet = vault.get_wallet_by_id(&id).await;
+    if let Err(err) = wallet {
+        return Err(CommandError::wallet(err));
+    }
+
+    let wallet = wallet.unwrap();
+
+    // A session may come from a remembered key, destroying the wallet needs the real password.
+    let auth_res = throttle::authenticate(&*vault, &state.throttle, &wallet, &password).await;
+
+    if let Err(err) = auth_res {
+        return Err(err.into());
+    }
+
+    // Forgotten first, so a failure leaves the wallet in place rather than a key nothing uses.
+    let res = state.keystore.remove(&id).await;
+    if let Err(err) = res {
+        return Err(err.into());
+    }
+
+    let res = audit::remove_wallet(&*vault, &wallet).await;
+    if let Err(err) = res {
+        return Err(err.into());
+    }
+    state.sessions.lock_wallet(&id).await;
+
+    Ok(json!({"success": true}))
+}
+
+#[tauri::command]
+async fn remove_account(
+    id: String,
+    wallet_id: String,
+    session_id: String,
+    state: State<'_, AppState>,
+) -> CommandResult<Value> {
+    let auth_res = state.sessions.key(&session_id, &wallet_id).await;
+
+    if let Err(err) = auth_res {
+        return Err(err.into());
+    }
+
+    let vault = state.vault().await?;
+    let account = vault.get_account_by_id(&id).await;
+
+    if let Err(err) = account {
+        return Err(CommandError::account(err));
+    }
+
+    let account = account.unwrap();
+
+    // The session only vouches for its own wallet.
+    if account.wallet_id != wallet_id {
+        return Err(CommandError::new(
+            ErrorCode::AccountNotFound,
+            format!("Account {} not found in wallet {}", id, wallet_id),
+        ));
+    }
+
+    let res = audit::remove_account(&*vault, &account).await;
+    if let Err(err) = res {
+        return Err(err.into());
+    }
+    Ok(json!({"success": true}))
+}
+
+#[tauri::command]
+async fn rename_wallet(
+    id: String,
+    session_id: String,
+    name: String,
+    state: State<'_, AppState>,
+) -> CommandResult<Value> {
+    let auth_res = state.sessions.key(&session_id, &id).await;
+
+    if let Err(err) = auth_res {
+        return Err(err.into());
+    }
+
+    if name.trim().is_empty() {
+        return Err(CommandError::invalid_input("Wallet name can't be empty"));
+    }
+
+    let vault = state.vault().await?;
+    let wallet = vault
+        .update_wallet(UpdateWalletInput {
+            id,
+            name: Some(name),
+        })
+        .await;
+
+    if let Err(err) = wallet {
+        return Err(CommandError::wallet(err));
+    }
+
+    Ok(wallet.unwrap().to_json())
+}
+
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn update_account(
+    id: String,
+    wallet_id: String,
+    session_id: String,
+    label: Option<String>,
+    notes: Option<String>,
+    colour: Option<String>,
+    archived: Option<bool>,
+    position: Option<i64>,
+    used: Option<bool>,
+    state: State<'_, AppState>,
+) -> CommandResult<Value> {
+    let auth_res = state.sessions.key(&session_id, &wallet_id).await;
+
+    if let Err(err) = auth_res {
+        return Err(err.into());
+    }
+
+    let input = UpdateAccountInput {
+        id,
+        label,
+        notes,
+        colour,
+        archived,
+        position,
+        used,
+    };
+
+    if let Err(err) = input.validate() {
+        return Err(err.into());
+    }
+
+    let vault = state.vault().await?;
+    let account = vault.get_account_by_id(&input.id).await;
+
+    if let Err(err) = account {
+        return Err(CommandError::account(err));
+    }
+
+    // The session only vouches for its own wallet.
+    if account.unwrap().wallet_id != wallet_id {
+        return Err(CommandError::new(
+            ErrorCode::AccountNotFound,
+            format!("Account {} not found in wallet {}", input.id, wallet_id),
+        ));
+    }
+
+    let account = vault.update_account(input).await;
+
+    if let Err(err) = account {
+        return Err(CommandError::account(err));
+    }
+
+    Ok(account.unwrap().to_json())
+}
+
+#[tauri::command]
+async fn export_labels(
+    wallet_id: String,
+    session_id: String,
+    state: State<'_, AppState>,
+) -> CommandResult<String> {
+    let auth_res = state.sessions.key(&session_id, &wallet_id).await;
+
+    if let Err(err) = auth_res {
+        return Err(err.into());
+    }
+
+    let vault = state.vault().await?;
+    let content = labels::export(&*vault, &wallet_id).await;
+
+    if let Err(err) = content {
+        return Err(err.into());
+    }
+
+    Ok(content.unwrap())
+}
+
+#[tauri::command]
+async fn import_labels(
+    wallet_id: String,
+    session_id: String,
+    content: String,
+    state: State<'_, AppState>,
+) -> CommandResult<Value> {
+    let auth_res = state.sessions.key(&session_id, &wallet_id).await;
+
+    if let Err(err) = auth_res {
+        return Err(err.into());
+    }
+
+    let vault = state.vault().await?;
+    let report = labels::import(&*vault, &wallet_id, &content).await;
+
+    if let Err(err) = report {
+        return Err(err.into());
+    }
+
+    Ok(json!(report.unwrap()))
+}
+
+/// Audit log of the wallet, along with whether its hash chain is intact.
+#[tauri::command]
+async fn audit_log(
+    wallet_id: String,
+    session_id: String,
+    state: State<'_, AppState>,
+) -> CommandResult<Value> {
+    let auth_res = state.sessions.key(&session_id, &wallet_id).await;
+
+    if let Err(err) = auth_res {
+        return Err(err.into());
+    }
+
+    let vault = state.vault().await?;
+    let events = vault.get_audit_events(&wallet_id).await;
+
+    if let Err(err) = events {
+        return Err(err.into());
+    }
+
+    let events = events.unwrap();
+    let verified = audit::verify(&events);
+
+    Ok(json!({
+        "events": events.iter().map(|event| event.to_json()).collect::<Vec<Value>>(),
+        "verified": verified.is_ok(),
+        "error": verified.err().map(|err| err.to_string()),
+    }))
+}
+
+#[tauri::command]
+async fn list_accounts(
+    wallet_id: String,
+    query: Option<AccountQuery>,
+    state: State<'_, AppState>,
+) -> CommandResult<Value> {
+    let vault = state.vault().await?;
+    let page = vault
+        .query_accounts(&wallet_id, &query.unwrap_or_default())
+        .await;
+    if let Err(err) = page {
+        return Err(err.into());
+    }
+
+    Ok(page.unwrap().to_json())
+}
+
+#[tauri::command]
+async fn list_wallets(state: State<'_, AppState>) -> CommandResult<Value> {
+    let vault = state.vault().await?;
+    let wallets = vault.get_all_wallets().await;
+    if let Err(err) = wallets {
+        return Err(err.into());
+    }
+
+    Ok(wallets.unwrap().iter().map(|item| item.to_json()).collect())
+}
+
+/// Opens the vault of the active profile.
+async fn open_vault(config: &Config) -> Result<OpenedVault, CommandError> {
+    let profile = config.profile();
+
+    if let Err(err) = profile {
+        return Err(err.into());
+    }
+
+    let vault = profile
+        .unwrap()
+        .open(config.vault_password.as_deref())
+        .await;
+
+    vault.map_err(CommandError::from)
+}
+
+#[async_std::main]
+async fn main() {
+    let context = tauri::generate_context!();
+    let data_dir = Config::data_dir(tauri::api::path::app_data_dir(context.config()));
+
+    let (config, vault) = match Config::load(data_dir.clone()) {
+        Ok(config) => {
+            let vault = open_vault(&config).await;
+            (config, vault)
+        }
+        // Starts anyway so the frontend can tell what is wrong with the file.
+        Err(err) => (
+            Config::new(data_dir, ConfigFile::default()),
+            Err(CommandError::from(err)),
+        ),
+    };
+
+    // Reported to the frontend through `startup_status`.
+    let startup_error = vault.as_ref().err().cloned();
+    let (vault, backup) = match vault {
+        Ok(opened) => (Some(Arc::from(opened.vault)), opened.backup),
+        Err(_) => (None, None),
+    };
+
+    let sessions = Arc::new(SessionManager::default());
+    let drafts = Arc::new(MnemonicDrafts::default());
 
     let app_state = AppState {
-        wallet: Arc::new(Mutex::new(WalletInputBuilder::new())),
-        vault: Arc::new(Mutex::new(vault)),
+        drafts: drafts.clone(),
+        vault: RwLock::new(vault),
+        startup_error: Mutex::new(startup_error),
+        backup: Mutex::new(backup),
+        sessions: sessions.clone(),
+        throttle: ThrottlePolicy::default(),
+        kdf: config.kdf,
+        keystore: config.keystore.open(),
+        wallet_locks: KeyedLocks::default(),
+        config: Mutex::new(config),
     };
 
     tauri::Builder::default()
         .setup(|app| {
             app.manage(app_state);
+            tauri::async_runtime::spawn(async move {
+                loop {
+                    tokio::time::sleep(SESSION_SWEEP_INTERVAL).await;
+                    sessions.purge_expired().await;
+                    drafts.purge_expired().await;
+                }
+            });
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
+            startup_status,
+            list_profiles,
+            switch_profile,
             generate_mnemonic,
+            mnemonic_options,
             create_wallet,
             authenticate,
+            authenticate_remembered,
+            forget,
+            lock,
             create_account,
+            create_accounts,
+            create_dev_wallet,
+            seed_fixture,
+            derive_child_secret,
+            create_child_wallet,
+            create_multisig,
+            list_multisigs,
+            multisig_addresses,
+            sign_multisig_psbt,
+            combine_psbts,
+            finalize_multisig_psbt,
+            remove_multisig,
+            create_policy_wallet,
+            create_descriptor_wallet,
+            list_policy_wallets,
+            policy_wallet_addresses,
+            policy_wallet_leaves,
+            plan_policy_spend,
+            sign_policy_psbt,
+            finalize_policy_psbt,
+            taproot_sighash,
+            remove_policy_wallet,
+            silent_payment_address,
+            scan_silent_payments,
+            add_silent_payment_outputs,
+            payment_uri,
+            parse_payment_uri,
             remove_wallet,
             remove_account,
+            rename_wallet,
+            update_account,
+            export_labels,
+            import_labels,
+            audit_log,
             list_accounts,
             list_wallets
         ])
-        .run(tauri::generate_context!())
+        .run(context)
         .expect("error while running tauri application");
 }
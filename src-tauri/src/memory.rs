@@ -0,0 +1,593 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{RwLock, RwLockWriteGuard};
+
+use super::{
+    account::{AccountModel, StoreAccountInput, UpdateAccountInput},
+    audit::{AuditEvent, StoreAuditEventInput},
+    labels::Label,
+    multisig::{MultisigModel, StoreMultisigInput},
+    policy::{PolicyWalletModel, StorePolicyWalletInput},
+    query::{AccountPage, AccountQuery},
+    throttle::{unix_now, AuthAttempts},
+    vault_interface::{VaultError, VaultInterface, VaultResult, VaultTransaction},
+    wallet::{RekeyWalletInput, StoreWalletInput, UpdateWalletInput, WalletModel},
+};
+
+/// Formats the current time the same way SQLite's `CURRENT_TIMESTAMP` does.
+pub fn current_timestamp() -> String {
+    chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WalletLabel {
+    pub wallet_id: String,
+    #[serde(flatten)]
+    pub label: Label,
+}
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct MemoryState {
+    pub wallets: Vec<WalletModel>,
+    pub accounts: Vec<AccountModel>,
+    pub auth_attempts: Vec<AuthAttempts>,
+    #[serde(default)]
+    pub labels: Vec<WalletLabel>,
+    #[serde(default)]
+    pub audit_events: Vec<AuditEvent>,
+    #[serde(default)]
+    pub multisigs: Vec<MultisigModel>,
+    #[serde(default)]
+    pub policy_wallets: Vec<PolicyWalletModel>,
+}
+
+impl MemoryState {
+    fn wallet_exists(&self, id: &str) -> bool {
+        self.wallets.iter().any(|wallet| wallet.id == id)
+    }
+
+    pub fn insert_wallet(&mut self, input: StoreWalletInput) -> VaultResult<WalletModel> {
+        if self.wallets.iter().any(|wallet| wallet.name == input.name) {
+            return Err(VaultError::Inserting(format!(
+                "Wallet name {} already taken",
+                input.name
+            )));
+        }
+
+        let wallet = WalletModel::from(input);
+        self.wallets.push(wallet.clone());
+        Ok(wallet)
+    }
+
+    pub fn insert_account(&mut self, input: StoreAccountInput) -> VaultResult<AccountModel> {
+        if !self.wallet_exists(&input.wallet_id) {
+            return Err(VaultError::Inserting(format!(
+                "Wallet {} does not exist",
+                input.wallet_id
+            )));
+        }
+
+        if self
+            .accounts
+            .iter()
+            .any(|account| account.address == input.address || account.path == input.encrypted_path)
+        {
+            return Err(VaultError::Inserting(format!(
+                "Account {} already exists",
+                input.address
+            )));
+        }
+
+        let account = AccountModel::from(input);
+        self.accounts.push(AccountModel {
+            created_at: Some(current_timestamp()),
+            ..account.clone()
+        });
+        Ok(account)
+    }
+
+    /// Accounts of the wallet, ordered by position then creation.
+    pub fn accounts_of(&self, wallet_id: &str) -> Vec<AccountModel> {
+        let mut accounts: Vec<AccountModel> = self
+            .accounts
+            .iter()
+            .filter(|account| account.wallet_id == wallet_id)
+            .cloned()
+            .collect();
+        // The sort is stable, so equal positions keep their insertion order.
+        accounts.sort_by_key(|account| account.position);
+        accounts
+    }
+
+    pub fn update_wallet(&mut self, input: UpdateWalletInput) -> VaultResult<WalletModel> {
+        if let Some(name) = &input.name {
+            if self
+                .wallets
+                .iter()
+                .any(|wallet| &wallet.name == name && wallet.id != input.id)
+            {
+                return Err(VaultError::Updating(format!(
+                    "Wallet name {name} already taken"
+                )));
+            }
+        }
+
+        let wallet = self.wallets.iter_mut().find(|wallet| wallet.id == input.id);
+
+        if wallet.is_none() {
+            return Err(VaultError::NotFound(input.id));
+        }
+
+        let wallet = wallet.unwrap();
+        if let Some(name) = input.name {
+            wallet.name = name;
+        }
+
+        Ok(wallet.clone())
+    }
+
+    pub fn update_account(&mut self, input: UpdateAccountInput) -> VaultResult<AccountModel> {
+        let account = self
+            .accounts
+            .iter_mut()
+            .find(|account| account.id == input.id);
+
+        if account.is_none() {
+            return Err(VaultError::NotFound(input.id));
+        }
+
+        let account = account.unwrap();
+        input.apply(account);
+
+        Ok(account.clone())
+    }
+
+    pub fn labels_of(&self, wallet_id: &str) -> Vec<Label> {
+        let mut labels: Vec<Label> = self
+            .labels
+            .iter()
+            .filter(|stored| stored.wallet_id == wallet_id)
+            .map(|stored| stored.label.clone())
+            .collect();
+        labels.sort_by(|a, b| {
+            (a.kind.to_string(), &a.reference).cmp(&(b.kind.to_string(), &b.reference))
+        });
+        labels
+    }
+
+    pub fn set_labels(&mut self, wallet_id: &str, labels: Vec<Label>) -> VaultResult<()> {
+        if !self.wallet_exists(wallet_id) {
+            return Err(VaultError::Inserting(format!(
+                "Wallet {wallet_id} does not exist"
+            )));
+        }
+
+        for label in labels {
+            let existing = self.labels.iter_mut().find(|stored| {
+                stored.wallet_id == wallet_id
+                    && stored.label.kind == label.kind
+                    && stored.label.reference == label.reference
+            });
+
+            match existing {
+                Some(stored) => stored.label = label,
+                None => self.labels.push(WalletLabel {
+                    wallet_id: wallet_id.to_string(),
+                    label,
+                }),
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn insert_multisig(&mut self, input: StoreMultisigInput) -> VaultResult<MultisigModel> {
+        if self
+            .multisigs
+            .iter()
+            .any(|multisig| multisig.name == input.name)
+        {
+            return Err(VaultError::Inserting(format!(
+                "Multisig name {} already taken",
+                input.name
+            )));
+        }
+
+        let mut multisig = MultisigModel::from(input);
+        multisig.created_at = Some(current_timestamp());
+        self.multisigs.push(multisig.clone());
+
+        Ok(multisig)
+    }
+
+    pub fn get_multisig_by_id(&self, id: &str) -> VaultResult<MultisigModel> {
+        let multisig = self.multisigs.iter().find(|multisig| multisig.id == id);
+
+        if multisig.is_none() {
+            return Err(VaultError::NotFound(id.to_string()));
+        }
+
+        Ok(multisig.unwrap().clone())
+    }
+
+    pub fn remove_multisig_by_id(&mut self, id: &str) {
+        self.multisigs.retain(|multisig| multisig.id != id);
+    }
+
+    pub fn insert_policy_wallet(
+        &mut self,
+        input: StorePolicyWalletInput,
+    ) -> VaultResult<PolicyWalletModel> {
+        if self
+            .policy_wallets
+            .iter()
+            .any(|wallet| wallet.name == input.name)
+        {
+            return Err(VaultError::Inserting(format!(
+                "Policy wallet name {} already taken",
+                input.name
+            )));
+        }
+
+        let mut wallet = PolicyWalletModel::from(input);
+        wallet.created_at = Some(current_timestamp());
+        self.policy_wallets.push(wallet.clone());
+
+        Ok(wallet)
+    }
+
+    pub fn get_policy_wallet_by_id(&self, id: &str) -> VaultResult<PolicyWalletModel> {
+        let wallet = self.policy_wallets.iter().find(|wallet| wallet.id == id);
+
+        if wallet.is_none() {
+            return Err(VaultError::NotFound(id.to_string()));
+        }
+
+        Ok(wallet.unwrap().clone())
+    }
+
+    pub fn remove_policy_wallet_by_id(&mut self, id: &str) {
+        self.policy_wallets.retain(|wallet| wallet.id != id);
+    }
+
+    pub fn remove_account_by_id(&mut self, id: &str) {
+        self.accounts.retain(|account| account.id != id);
+    }
+
+    pub fn remove_wallet_by_id(&mut self, id: &str) {
+        self.accounts.retain(|account| account.wallet_id != id);
+        self.auth_attempts
+            .retain(|attempts| attempts.wallet_id != id);
+        self.labels.retain(|stored| stored.wallet_id != id);
+        self.wallets.retain(|wallet| wallet.id != id);
+    }
+
+    pub fn append_audit_event(&mut self, input: StoreAuditEventInput) -> AuditEvent {
+        let last = self
+            .audit_events
+            .iter()
+            .filter(|event| event.wallet_id == input.wallet_id)
+            .max_by_key(|event| event.sequence);
+
+        let event = AuditEvent::chain(last, input, unix_now());
+        self.audit_events.push(event.clone());
+        event
+    }
+
+    pub fn audit_events_of(&self, wallet_id: &str) -> Vec<AuditEvent> {
+        let mut events: Vec<AuditEvent> = self
+            .audit_events
+            .iter()
+            .filter(|event| event.wallet_id == wallet_id)
+            .cloned()
+            .collect();
+        events.sort_by_key(|event| event.sequence);
+        events
+    }
+
+    pub fn record_failed_auth(&mut self, wallet_id: &str) -> VaultResult<AuthAttempts> {
+        if !self.wallet_exists(wallet_id) {
+            return Err(VaultError::Updating(format!(
+                "Wallet {wallet_id} does not exist"
+            )));
+        }
+
+        let now = unix_now();
+        let attempts = self
+            .auth_attempts
+            .iter_mut()
+            .find(|attempts| attempts.wallet_id == wallet_id);
+
+        match attempts {
+            Some(attempts) => {
+                attempts.failed_attempts += 1;
+                attempts.last_failed_at = Some(now);
+                Ok(attempts.clone())
+            }
+            None => {
+                let attempts = AuthAttempts {
+                    wallet_id: wallet_id.to_string(),
+                    failed_attempts: 1,
+                    last_failed_at: Some(now),
+                };
+                self.auth_attempts.push(attempts.clone());
+                Ok(attempts)
+            }
+        }
+    }
+
+    pub fn rekey_wallet(&mut self, input: RekeyWalletInput) -> VaultResult<()> {
+        let wallet = self
+            .wallets
+            .iter_mut()
+            .find(|wallet| wallet.id == input.wallet_id);
+
+        if wallet.is_none() {
+            return Err(VaultError::NotFound(input.wallet_id));
+        }
+
+        input.check_accounts(
+            self.accounts
+                .iter()
+                .filter(|account| account.wallet_id == input.wallet_id)
+                .map(|account| account.id.as_str()),
+        )?;
+
+        let wallet = wallet.unwrap();
+        wallet.password = input.encrypted_pass;
+        wallet.seed = input.encrypted_seed;
+
+        for (id, path) in input.account_paths {
+            let account = self
+                .accounts
+                .iter_mut()
+                .find(|account| account.id == id && account.wallet_id == input.wallet_id);
+
+            if let Some(account) = account {
+                account.path = path;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A vault living only in memory, with the same semantics as [`crate::sqlite::SqliteVault`].
+///
+/// Handy for tests, and for ephemeral sessions which must not leave anything on disk.
+#[derive(Default)]
+pub struct MemoryVault(RwLock<MemoryState>);
+
+impl MemoryVault {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+/// Works on a copy of the state, swapped in on commit. Holds the write lock all along, so
+/// no other write can slip in between.
+pub struct MemoryTransaction<'a> {
+    guard: RwLockWriteGuard<'a, MemoryState>,
+    state: MemoryState,
+}
+
+impl<'a> MemoryTransaction<'a> {
+    pub fn new(guard: RwLockWriteGuard<'a, MemoryState>) -> Self {
+        let state = guard.clone();
+        Self { guard, state }
+    }
+}
+
+#[async_trait]
+impl VaultTransaction for MemoryTransaction<'_> {
+    async fn insert_wallet(&mut self, input: StoreWalletInput) -> VaultResult<WalletModel> {
+        self.state.insert_wallet(input)
+    }
+
+    async fn insert_account(&mut self, input: StoreAccountInput) -> VaultResult<AccountModel> {
+        self.state.insert_account(input)
+    }
+
+    async fn update_account(&mut self, input: UpdateAccountInput) -> VaultResult<AccountModel> {
+        self.state.update_account(input)
+    }
+
+    async fn remove_account_by_id(&mut self, id: &str) -> VaultResult<()> {
+        self.state.remove_account_by_id(id);
+        Ok(())
+    }
+
+    async fn remove_wallet_by_id(&mut self, id: &str) -> VaultResult<()> {
+        self.state.remove_wallet_by_id(id);
+        Ok(())
+    }
+
+    async fn set_labels(&mut self, wallet_id: &str, labels: Vec<Label>) -> VaultResult<()> {
+        self.state.set_labels(wallet_id, labels)
+    }
+
+    async fn remove_policy_wallet_by_id(&mut self, id: &str) -> VaultResult<()> {
+        self.state.remove_policy_wallet_by_id(id);
+        Ok(())
+    }
+
+    async fn remove_multisig_by_id(&mut self, id: &str) -> VaultResult<()> {
+        self.state.remove_multisig_by_id(id);
+        Ok(())
+    }
+
+    async fn append_audit_event(&mut self, input: StoreAuditEventInput) -> VaultResult<AuditEvent> {
+        Ok(self.state.append_audit_event(input))
+    }
+
+    async fn commit(self: Box<Self>) -> VaultResult<()> {
+        let MemoryTransaction { mut guard, state } = *self;
+        *guard = state;
+        Ok(())
+    }
+
+    async fn rollback(self: Box<Self>) -> VaultResult<()> {
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl VaultInterface for MemoryVault {
+    async fn begin(&self) -> VaultResult<Box<dyn VaultTransaction + '_>> {
+        Ok(Box::new(MemoryTransaction::new(self.0.write().await)))
+    }
+
+    async fn get_wallet_by_id(&self, id: &str) -> VaultResult<WalletModel> {
+        let state = self.0.read().await;
+        let wallet = state.wallets.iter().find(|wallet| wallet.id == id);
+
+        if wallet.is_none() {
+            return Err(VaultError::NotFound(id.to_string()));
+        }
+
+        Ok(wallet.unwrap().clone())
+    }
+
+    async fn get_wallet_by_name(&self, name: &str) -> VaultResult<WalletModel> {
+        let state = self.0.read().await;
+        let wallet = state.wallets.iter().find(|wallet| wallet.name == name);
+
+        if wallet.is_none() {
+            return Err(VaultError::NotFound(name.to_string()));
+        }
+
+        Ok(wallet.unwrap().clone())
+    }
+
+    async fn get_account_by_id(&self, id: &str) -> VaultResult<AccountModel> {
+        let state = self.0.read().await;
+        let account = state.accounts.iter().find(|account| account.id == id);
+
+        if account.is_none() {
+            return Err(VaultError::NotFound(id.to_string()));
+        }
+
+        Ok(account.unwrap().clone())
+    }
+
+    async fn get_all_accounts(&self, wallet_id: &str) -> VaultResult<Vec<AccountModel>> {
+        Ok(self.0.read().await.accounts_of(wallet_id))
+    }
+
+    async fn query_accounts(
+        &self,
+        wallet_id: &str,
+        query: &AccountQuery,
+    ) -> VaultResult<AccountPage> {
+        query.apply(self.0.read().await.accounts_of(wallet_id))
+    }
+
+    async fn get_all_wallets(&self) -> VaultResult<Vec<WalletModel>> {
+        Ok(self.0.read().await.wallets.clone())
+    }
+
+    async fn remove_account_by_id(&self, id: &str) -> VaultResult<()> {
+        self.0.write().await.remove_account_by_id(id);
+        Ok(())
+    }
+
+    async fn remove_wallet_by_id(&self, id: &str) -> VaultResult<()> {
+        self.0.write().await.remove_wallet_by_id(id);
+        Ok(())
+    }
+
+    async fn insert_wallet(&self, input: StoreWalletInput) -> VaultResult<WalletModel> {
+        self.0.write().await.insert_wallet(input)
+    }
+
+    async fn insert_account(&self, input: StoreAccountInput) -> VaultResult<AccountModel> {
+        self.0.write().await.insert_account(input)
+    }
+
+    async fn update_wallet(&self, input: UpdateWalletInput) -> VaultResult<WalletModel> {
+        self.0.write().await.update_wallet(input)
+    }
+
+    async fn update_account(&self, input: UpdateAccountInput) -> VaultResult<AccountModel> {
+        self.0.write().await.update_account(input)
+    }
+
+    async fn get_labels(&self, wallet_id: &str) -> VaultResult<Vec<Label>> {
+        Ok(self.0.read().await.labels_of(wallet_id))
+    }
+
+    async fn set_labels(&self, wallet_id: &str, labels: Vec<Label>) -> VaultResult<()> {
+        self.0.write().await.set_labels(wallet_id, labels)
+    }
+
+    async fn insert_multisig(&self, input: StoreMultisigInput) -> VaultResult<MultisigModel> {
+        self.0.write().await.insert_multisig(input)
+    }
+
+    async fn get_multisig_by_id(&self, id: &str) -> VaultResult<MultisigModel> {
+        self.0.read().await.get_multisig_by_id(id)
+    }
+
+    async fn get_all_multisigs(&self) -> VaultResult<Vec<MultisigModel>> {
+        Ok(self.0.read().await.multisigs.clone())
+    }
+
+    async fn remove_multisig_by_id(&self, id: &str) -> VaultResult<()> {
+        self.0.write().await.remove_multisig_by_id(id);
+        Ok(())
+    }
+
+    async fn insert_policy_wallet(
+        &self,
+        input: StorePolicyWalletInput,
+    ) -> VaultResult<PolicyWalletModel> {
+        self.0.write().await.insert_policy_wallet(input)
+    }
+
+    async fn get_policy_wallet_by_id(&self, id: &str) -> VaultResult<PolicyWalletModel> {
+        self.0.read().await.get_policy_wallet_by_id(id)
+    }
+
+    async fn get_all_policy_wallets(&self) -> VaultResult<Vec<PolicyWalletModel>> {
+        Ok(self.0.read().await.policy_wallets.clone())
+    }
+
+    async fn remove_policy_wallet_by_id(&self, id: &str) -> VaultResult<()> {
+        self.0.write().await.remove_policy_wallet_by_id(id);
+        Ok(())
+    }
+
+    async fn get_audit_events(&self, wallet_id: &str) -> VaultResult<Vec<AuditEvent>> {
+        Ok(self.0.read().await.audit_events_of(wallet_id))
+    }
+
+    async fn get_auth_attempts(&self, wallet_id: &str) -> VaultResult<AuthAttempts> {
+        let state = self.0.read().await;
+        let attempts = state
+            .auth_attempts
+            .iter()
+            .find(|attempts| attempts.wallet_id == wallet_id);
+
+        Ok(attempts.cloned().unwrap_or(AuthAttempts {
+            wallet_id: wallet_id.to_string(),
+            ..Default::default()
+        }))
+    }
+
+    async fn record_failed_auth(&self, wallet_id: &str) -> VaultResult<AuthAttempts> {
+        self.0.write().await.record_failed_auth(wallet_id)
+    }
+
+    async fn reset_auth_attempts(&self, wallet_id: &str) -> VaultResult<()> {
+        self.0
+            .write()
+            .await
+            .auth_attempts
+            .retain(|attempts| attempts.wallet_id != wallet_id);
+        Ok(())
+    }
+
+    async fn rekey_wallet(&self, input: RekeyWalletInput) -> VaultResult<()> {
+        self.0.write().await.rekey_wallet(input)
+    }
+}
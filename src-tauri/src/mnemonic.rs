@@ -0,0 +1,361 @@
+use bip39::{Language, Mnemonic};
+use rand_core::{OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+use tokio::sync::Mutex;
+use zeroize::Zeroizing;
+
+/// Word counts BIP-39 defines, from 128 to 256 bits of entropy.
+pub const WORD_COUNTS: [usize; 5] = [12, 15, 18, 21, 24];
+pub const DEFAULT_WORD_COUNT: usize = 24;
+
+/// How long a generated mnemonic may wait for `create_wallet`.
+pub const DRAFT_TTL: Duration = Duration::from_secs(30 * 60);
+
+// Keeps the hash of mixed entropy apart from any other use of SHA-256.
+const USER_ENTROPY_TAG: &[u8] = b"dev-wallet/user-entropy";
+
+#[derive(Error, Debug)]
+pub enum MnemonicError {
+    #[error("Unsupported word count {0}, expected 12, 15, 18, 21 or 24")]
+    WordCount(usize),
+    #[error("Unsupported language {0}")]
+    Language(String),
+    #[error("Invalid {0}: {1}")]
+    UserEntropy(EntropySource, String),
+    #[error("Mnemonic draft not found: {0}")]
+    DraftNotFound(String),
+    #[error("Failed generating the mnemonic: {0}")]
+    Generation(String),
+}
+
+pub type MnemonicResult<T> = Result<T, MnemonicError>;
+
+/// Bytes of entropy behind a mnemonic of `word_count` words.
+pub fn entropy_length(word_count: usize) -> MnemonicResult<usize> {
+    if !WORD_COUNTS.contains(&word_count) {
+        return Err(MnemonicError::WordCount(word_count));
+    }
+
+    // Each word holds 11 bits, one bit out of 33 being checksum.
+    Ok(word_count * 11 * 32 / 33 / 8)
+}
+
+pub fn language_name(language: Language) -> &'static str {
+    match language {
+        Language::English => "english",
+        Language::SimplifiedChinese => "simplified_chinese",
+        Language::TraditionalChinese => "traditional_chinese",
+        Language::Czech => "czech",
+        Language::French => "french",
+        Language::Italian => "italian",
+        Language::Japanese => "japanese",
+        Language::Korean => "korean",
+        Language::Spanish => "spanish",
+    }
+}
+
+pub fn parse_language(name: &str) -> MnemonicResult<Language> {
+    Language::all()
+        .iter()
+        .copied()
+        .find(|language| language_name(*language) == name)
+        .ok_or(MnemonicError::Language(name.to_string()))
+}
+
+/// Names of every language a mnemonic can be generated in.
+pub fn languages() -> Vec<&'static str> {
+    Language::all().iter().copied().map(language_name).collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EntropySource {
+    /// Six sided dice, rolls written `1` to `6`.
+    Dice,
+    /// Coin flips, written `h`/`t` or `1`/`0`.
+    Coins,
+}
+
+impl std::fmt::Display for EntropySource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let output = match self {
+            EntropySource::Dice => "dice rolls",
+            EntropySource::Coins => "coin flips",
+        };
+        write!(f, "{}", output)
+    }
+}
+
+/// Outcomes of physical dice or coins, typed in by the user to be mixed with the entropy of
+/// the system.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserEntropy {
+    pub source: EntropySource,
+    /// Outcomes in the order they came, whitespace and commas being ignored.
+    pub rolls: String,
+}
+
+impl UserEntropy {
+    /// One value per outcome, `0` to `5` for dice and `0` or `1` for coins.
+    pub fn outcomes(&self) -> MnemonicResult<Vec<u8>> {
+        let mut outcomes = vec![];
+
+        for c in self.rolls.chars() {
+            if c.is_whitespace() || c == ',' {
+                continue;
+            }
+
+            let outcome = match (self.source, c.to_ascii_lowercase()) {
+                (EntropySource::Dice, '1'..='6') => c as u8 - b'1',
+                (EntropySource::Coins, '0' | 't') => 0,
+                (EntropySource::Coins, '1' | 'h') => 1,
+                _ => {
+                    return Err(MnemonicError::UserEntropy(
+                        self.source,
+                        format!("unexpected {c:?}"),
+                    ))
+                }
+            };
+            outcomes.push(outcome);
+        }
+
+        if outcomes.is_empty() {
+            return Err(MnemonicError::UserEntropy(
+                self.source,
+                "nothing given".to_string(),
+            ));
+        }
+
+        Ok(outcomes)
+    }
+
+    /// Entropy the outcomes carry, provided the dice or coins are fair.
+    pub fn bits(&self) -> MnemonicResult<f64> {
+        let per_outcome = match self.source {
+            EntropySource::Dice => 6f64.log2(),
+            EntropySource::Coins => 1.0,
+        };
+
+        Ok(self.outcomes()?.len() as f64 * per_outcome)
+    }
+}
+
+/// Hashes the entropy of the system together with the user's, so the result is as strong as
+/// the best of both: biased dice can't weaken it, and neither can a broken RNG once enough
+/// rolls are given.
+pub fn mix_entropy(system: &[u8], user: &UserEntropy) -> MnemonicResult<Zeroizing<[u8; 32]>> {
+    let outcomes = Zeroizing::new(user.outcomes()?);
+
+    let mut hasher = Sha256::new();
+    hasher.update(USER_ENTROPY_TAG);
+    hasher.update((system.len() as u32).to_be_bytes());
+    hasher.update(system);
+    hasher.update(user.source.to_string().as_bytes());
+    hasher.update(&*outcomes);
+
+    Ok(Zeroizing::new(hasher.finalize().into()))
+}
+
+/// What the mnemonic is generated with, every field being optional.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MnemonicOptions {
+    /// 12, 15, 18, 21 or 24, 24 by default.
+    pub words: Option<usize>,
+    /// See [`languages`], English by default.
+    pub language: Option<String>,
+    pub user_entropy: Option<UserEntropy>,
+}
+
+/// Generates a fresh mnemonic, touching no state.
+pub fn generate(options: &MnemonicOptions) -> MnemonicResult<Mnemonic> {
+    let length = entropy_length(options.words.unwrap_or(DEFAULT_WORD_COUNT))?;
+    let language = match &options.language {
+        Some(name) => parse_language(name)?,
+        None => Language::English,
+    };
+
+    let mut entropy = Zeroizing::new([0u8; 32]);
+    OsRng.fill_bytes(&mut *entropy);
+
+    if let Some(user) = &options.user_entropy {
+        entropy = mix_entropy(&entropy[..length], user)?;
+    }
+
+    Mnemonic::from_entropy_in(language, &entropy[..length])
+        .map_err(|err| MnemonicError::Generation(err.to_string()))
+}
+
+struct Draft {
+    mnemonic: Mnemonic,
+    created_at: Instant,
+}
+
+/// Mnemonics shown to the user but not yet turned into a wallet, behind opaque tokens.
+///
+/// Each generation gets a token of its own, so windows creating wallets at the same time
+/// don't overwrite each other's mnemonic. A draft is used once, and dropped when unused
+/// for longer than its time to live.
+pub struct MnemonicDrafts {
+    drafts: Mutex<HashMap<String, Draft>>,
+    ttl: Duration,
+}
+
+impl Default for MnemonicDrafts {
+    fn default() -> Self {
+        Self::new(DRAFT_TTL)
+    }
+}
+
+impl MnemonicDrafts {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            drafts: Mutex::new(HashMap::new()),
+            ttl,
+        }
+    }
+
+    /// Keeps the mnemonic until it is taken, returns its token.
+    pub async fn insert(&self, mnemonic: Mnemonic) -> String {
+        let token = uuid::Uuid::new_v4().to_string();
+        let draft = Draft {
+            mnemonic,
+            created_at: Instant::now(),
+        };
+        self.drafts.lock().await.insert(token.clone(), draft);
+        token
+    }
+
+    /// Removes the draft and returns its mnemonic, provided it hasn't expired.
+    pub async fn take(&self, token: &str) -> MnemonicResult<Mnemonic> {
+        let draft = self.drafts.lock().await.remove(token);
+
+        match draft {
+            Some(draft) if draft.created_at.elapsed() < self.ttl => Ok(draft.mnemonic),
+            _ => Err(MnemonicError::DraftNotFound(token.to_string())),
+        }
+    }
+
+    /// Hands back a draft taken by [`MnemonicDrafts::take`] that couldn't be used, e.g. because
+    /// the wallet name was taken, so the user doesn't have to write down another mnemonic.
+    pub async fn put_back(&self, token: &str, mnemonic: Mnemonic) {
+        let draft = Draft {
+            mnemonic,
+            created_at: Instant::now(),
+        };
+        self.drafts.lock().await.insert(token.to_string(), draft);
+    }
+
+    pub async fn purge_expired(&self) {
+        let ttl = self.ttl;
+        self.drafts
+            .lock()
+            .await
+            .retain(|_, draft| draft.created_at.elapsed() < ttl);
+    }
+}
+
+/// What the frontend gets back from a generation.
+pub fn draft_to_json(
+    token: &str,
+    mnemonic: &Mnemonic,
+    user_entropy: Option<&UserEntropy>,
+) -> Value {
+    json!({
+        "token": token,
+        "mnemonic": mnemonic.to_string(),
+        "words": mnemonic.word_count(),
+        "language": language_name(mnemonic.language()),
+        "user_entropy_bits": user_entropy.and_then(|user| user.bits().ok()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generates_every_length_and_language() {
+        for words in WORD_COUNTS {
+            for name in languages() {
+                let mnemonic = generate(&MnemonicOptions {
+                    words: Some(words),
+                    language: Some(name.to_string()),
+                    user_entropy: None,
+                })
+                .unwrap();
+
+                assert_eq!(mnemonic.word_count(), words);
+                assert_eq!(language_name(mnemonic.language()), name);
+            }
+        }
+
+        assert_eq!(languages().len(), 9);
+        assert!(matches!(
+            entropy_length(13),
+            Err(MnemonicError::WordCount(13))
+        ));
+        assert!(parse_language("klingon").is_err());
+    }
+
+    #[test]
+    fn mixes_user_entropy() {
+        let dice = UserEntropy {
+            source: EntropySource::Dice,
+            rolls: "1 6 3, 4 2".to_string(),
+        };
+        assert_eq!(dice.outcomes().unwrap(), vec![0, 5, 2, 3, 1]);
+        assert!((dice.bits().unwrap() - 5.0 * 6f64.log2()).abs() < 1e-9);
+
+        let coins = UserEntropy {
+            source: EntropySource::Coins,
+            rolls: "HTth10".to_string(),
+        };
+        assert_eq!(coins.outcomes().unwrap(), vec![1, 0, 0, 1, 1, 0]);
+
+        let system = [7u8; 32];
+        let mixed = mix_entropy(&system, &dice).unwrap();
+        assert_eq!(*mixed, *mix_entropy(&system, &dice).unwrap());
+        assert_ne!(*mixed, *mix_entropy(&[8u8; 32], &dice).unwrap());
+        assert_ne!(*mixed, *mix_entropy(&system, &coins).unwrap());
+
+        let invalid = UserEntropy {
+            source: EntropySource::Dice,
+            rolls: "1 2 7".to_string(),
+        };
+        assert!(matches!(
+            generate(&MnemonicOptions {
+                user_entropy: Some(invalid),
+                ..Default::default()
+            }),
+            Err(MnemonicError::UserEntropy(EntropySource::Dice, _))
+        ));
+    }
+
+    #[tokio::test]
+    async fn drafts_are_used_once() {
+        let drafts = MnemonicDrafts::default();
+        let mnemonic = generate(&MnemonicOptions::default()).unwrap();
+        let token = drafts.insert(mnemonic.clone()).await;
+
+        assert_eq!(drafts.take(&token).await.unwrap(), mnemonic);
+        assert!(matches!(
+            drafts.take(&token).await,
+            Err(MnemonicError::DraftNotFound(_))
+        ));
+
+        drafts.put_back(&token, mnemonic.clone()).await;
+        assert_eq!(drafts.take(&token).await.unwrap(), mnemonic);
+
+        let drafts = MnemonicDrafts::new(Duration::ZERO);
+        let token = drafts.insert(mnemonic).await;
+        assert!(drafts.take(&token).await.is_err());
+    }
+}
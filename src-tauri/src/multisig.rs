@@ -0,0 +1,600 @@
+use bitcoin::{
+    bip32::{ChildNumber, DerivationPath, Xpriv, Xpub},
+    secp256k1, Address, Psbt, Transaction,
+};
+use miniscript::{
+    descriptor::{DescriptorPublicKey, Wildcard},
+    Descriptor,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{collections::HashSet, str::FromStr};
+use thiserror::Error;
+
+use crate::{
+    account::Network,
+    path_builder::{PathAddressKind, PathBuilder},
+    psbt::{self, PsbtError},
+    utils::AESKey,
+    wallet::WalletModel,
+};
+
+/// Most keys `sortedmulti` takes inside P2WSH.
+pub const MAX_COSIGNERS: usize = 20;
+
+#[derive(Error, Debug)]
+pub enum MultisigError {
+    #[error("Threshold {0} out of range for {1} cosigners")]
+    Threshold(usize, usize),
+    #[error("Invalid cosigner {0}: {1}")]
+    Cosigner(String, String),
+    #[error("Cosigner {0} appears more than once")]
+    DuplicateCosigner(String),
+    #[error("Wallet {0} is not a cosigner of this multisig")]
+    NotCosigner(String),
+    #[error("Invalid descriptor: {0}")]
+    Descriptor(String),
+    #[error("Failed deriving the cosigner key: {0}")]
+    Derivation(String),
+    #[error("Taproot multisigs are policy wallets, e.g. thresh(2,pk(a),pk(b),pk(c))")]
+    Taproot,
+    #[error(transparent)]
+    Psbt(#[from] PsbtError),
+}
+
+pub type MultisigResult<T> = Result<T, MultisigError>;
+
+/// How the multisig script is wrapped, each with the BIP-48 script type of its paths.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MultisigScriptType {
+    /// `sh(wsh(sortedmulti(...)))`, BIP-48 script type 1'.
+    ShWsh,
+    /// `wsh(sortedmulti(...))`, BIP-48 script type 2'.
+    #[default]
+    Wsh,
+    /// `tr(...)` script trees, BIP-48 script type 3'. Only for policy wallets, tapscript has no
+    /// `sortedmulti`.
+    Tr,
+}
+
+impl MultisigScriptType {
+    pub fn from_string(text: &str) -> Option<Self> {
+        match text {
+            "sh_wsh" => Some(MultisigScriptType::ShWsh),
+            "wsh" => Some(MultisigScriptType::Wsh),
+            "tr" => Some(MultisigScriptType::Tr),
+            _ => None,
+        }
+    }
+
+    fn address_kind(&self) -> PathAddressKind {
+        match self {
+            MultisigScriptType::ShWsh => PathAddressKind::MultisigSegWit,
+            MultisigScriptType::Wsh => PathAddressKind::MultisigNativeSegWit,
+            MultisigScriptType::Tr => PathAddressKind::MultisigTaproot,
+        }
+    }
+}
+
+impl std::fmt::Display for MultisigScriptType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let output = match self {
+            MultisigScriptType::ShWsh => "sh_wsh",
+            MultisigScriptType::Wsh => "wsh",
+            MultisigScriptType::Tr => "tr",
+        };
+        write!(f, "{}", output)
+    }
+}
+
+/// BIP-48 path of the account cosigners share their extended public key at.
+pub fn account_path(
+    script_type: MultisigScriptType,
+    network: Network,
+    account: u32,
+) -> MultisigResult<DerivationPath> {
+    PathBuilder::new()
+        .address_kind(script_type.address_kind())
+        .network_kind(network.to_bitcoin_network_kind())
+        .account_index(account)
+        .account_path()
+        .map_err(|err| MultisigError::Derivation(err.to_string()))
+}
+
+/// One key of the multisig.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Cosigner {
+    /// `[fingerprint/path]xpub` of the account, the way descriptors write it.
+    pub key: String,
+    /// Set when the key belongs to a wallet of the vault, which can then sign.
+    pub wallet_id: Option<String>,
+}
+
+impl Cosigner {
+    /// Cosigner out of a wallet of the vault, sharing its key at the BIP-48 account path.
+    pub fn local(
+        wallet: &WalletModel,
+        key: &AESKey,
+        script_type: MultisigScriptType,
+        network: Network,
+        account: u32,
+    ) -> MultisigResult<Cosigner> {
+        let seed = wallet.decrypt_seed(key);
+
+        if let Err(err) = seed {
+            return Err(MultisigError::Derivation(err.to_string()));
+        }
+
+        let secp = secp256k1::Secp256k1::new();
+        let master = Xpriv::new_master(network.to_bitcoin_network_kind(), &seed.unwrap());
+
+        if let Err(err) = master {
+            return Err(MultisigError::Derivation(err.to_string()));
+        }
+
+        let master = master.unwrap();
+        let path = account_path(script_type, network, account)?;
+        let xprv = master.derive_priv(&secp, &path);
+
+        if let Err(err) = xprv {
+            return Err(MultisigError::Derivation(err.to_string()));
+        }
+
+        let xpub = Xpub::from_priv(&secp, &xprv.unwrap());
+
+        Ok(Cosigner {
+            key: format!("[{}/{}]{}", master.fingerprint(&secp), path, xpub),
+            wallet_id: Some(wallet.id.clone()),
+        })
+    }
+
+    /// Cosigner out of an imported `[fingerprint/path]xpub`, which the vault can't sign for.
+    /// The origin is required, signers find their key by it.
+    pub fn imported(key: &str) -> MultisigResult<Cosigner> {
+        let cosigner = Cosigner {
+            key: key.trim().to_string(),
+            wallet_id: None,
+        };
+        let xkey = cosigner.xkey()?;
+
+        if xkey.origin.is_none() {
+            return Err(MultisigError::Cosigner(
+                cosigner.key,
+                "the key origin [fingerprint/path] is missing".to_string(),
+            ));
+        }
+
+        Ok(cosigner)
+    }
+
+    fn xkey(&self) -> MultisigResult<miniscript::descriptor::DescriptorXKey<Xpub>> {
+        let invalid = |reason: &str| MultisigError::Cosigner(self.key.clone(), reason.to_string());
+
+        match DescriptorPublicKey::from_str(&self.key) {
+            Ok(DescriptorPublicKey::XPub(xkey)) => {
+                if !xkey.derivation_path.is_master() || xkey.wildcard != Wildcard::None {
+                    return Err(invalid("expected the key of the account, without /*"));
+                }
+                Ok(xkey)
+            }
+            Ok(_) => Err(invalid("expected an extended public key")),
+            Err(err) => Err(invalid(&err.to_string())),
+        }
+    }
+
+    /// Key of the cosigner for every address of the `change` chain.
+    fn descriptor_key(&self, change: u32) -> MultisigResult<DescriptorPublicKey> {
+        let mut xkey = self.xkey()?;
+        let change = ChildNumber::from_normal_idx(change);
+
+        if let Err(err) = change {
+            return Err(MultisigError::Descriptor(err.to_string()));
+        }
+
+        xkey.derivation_path = DerivationPath::from(vec![change.unwrap()]);
+        xkey.wildcard = Wildcard::Unhardened;
+        Ok(DescriptorPublicKey::XPub(xkey))
+    }
+
+    /// Checks the key belongs to `network`, so no address of another network gets derived.
+    pub fn check_network(&self, network: Network) -> MultisigResult<()> {
+        if self.xkey()?.xkey.network != network.to_bitcoin_network_kind() {
+            return Err(MultisigError::Cosigner(
+                self.key.clone(),
+                format!("not a {network} key"),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Cosigner as given when creating a multisig: a wallet of the vault, unlocked by one of its
+/// sessions, or an imported `[fingerprint/path]xpub`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum CosignerInput {
+    Wallet {
+        wallet_id: String,
+        session_id: String,
+    },
+    Key {
+        key: String,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct StoreMultisigInput {
+    pub name: String,
+    pub threshold: usize,
+    pub script_type: MultisigScriptType,
+    pub network: Network,
+    pub cosigners: Vec<Cosigner>,
+}
+
+impl StoreMultisigInput {
+    /// Checks the multisig can be spent from: a reachable threshold, distinct keys and all of
+    /// them on the network of the multisig.
+    pub fn validate(&self) -> MultisigResult<()> {
+        if self.script_type == MultisigScriptType::Tr {
+            return Err(MultisigError::Taproot);
+        }
+
+        let count = self.cosigners.len();
+
+        if count == 0 || count > MAX_COSIGNERS || self.threshold == 0 || self.threshold > count {
+            return Err(MultisigError::Threshold(self.threshold, count));
+        }
+
+        let mut keys = HashSet::new();
+        for cosigner in self.cosigners.iter() {
+            let xkey = cosigner.xkey()?;
+
+            if !keys.insert(xkey.xkey) {
+                return Err(MultisigError::DuplicateCosigner(cosigner.key.clone()));
+            }
+
+            cosigner.check_network(self.network)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// M-of-N wallet, its keys being held by the cosigners rather than the vault.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MultisigModel {
+    pub id: String,
+    pub name: String,
+    pub threshold: usize,
+    pub script_type: MultisigScriptType,
+    pub network: Network,
+    pub cosigners: Vec<Cosigner>,
+    pub created_at: Option<String>,
+}
+
+impl From<StoreMultisigInput> for MultisigModel {
+    fn from(value: StoreMultisigInput) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            name: value.name,
+            threshold: value.threshold,
+            script_type: value.script_type,
+            network: value.network,
+            cosigners: value.cosigners,
+            created_at: None,
+        }
+    }
+}
+
+impl MultisigModel {
+    /// Descriptor of the receive (`0`) or change (`1`) chain.
+    pub fn descriptor(&self, change: u32) -> MultisigResult<Descriptor<DescriptorPublicKey>> {
+        let mut keys = vec![];
+        for cosigner in self.cosigners.iter() {
+            keys.push(cosigner.descriptor_key(change)?);
+        }
+
+        let descriptor = match self.script_type {
+            MultisigScriptType::ShWsh => Descriptor::new_sh_wsh_sortedmulti(self.threshold, keys),
+            MultisigScriptType::Wsh => Descriptor::new_wsh_sortedmulti(self.threshold, keys),
+            MultisigScriptType::Tr => return Err(MultisigError::Taproot),
+        };
+
+        descriptor.map_err(|err| MultisigError::Descriptor(err.to_string()))
+    }
+
+    pub fn address(&self, change: u32, index: u32) -> MultisigResult<Address> {
+        let descriptor = self.descriptor(change)?.at_derivation_index(index);
+
+        if let Err(err) = descriptor {
+            return Err(MultisigError::Descriptor(err.to_string()));
+        }
+
+        descriptor
+            .unwrap()
+            .address(self.network.to_bitcoin_network())
+            .map_err(|err| MultisigError::Descriptor(err.to_string()))
+    }
+
+    /// The cosigner held by the wallet, `NotCosigner` when the wallet isn't one.
+    pub fn cosigner_of(&self, wallet_id: &str) -> MultisigResult<&Cosigner> {
+        self.cosigners
+            .iter()
+            .find(|cosigner| cosigner.wallet_id.as_deref() == Some(wallet_id))
+            .ok_or(MultisigError::NotCosigner(wallet_id.to_string()))
+    }
+
+    /// Fills in what signers need to know about the inputs spending from the multisig, returns
+    /// the indexes of those inputs.
+    pub fn update_psbt(&self, psbt: &mut Psbt) -> MultisigResult<Vec<usize>> {
+        let descriptors = [self.descriptor(0)?, self.descriptor(1)?];
+        Ok(psbt::update(psbt, &descriptors))
+    }
+
+    /// Adds the signatures of the cosigner wallet, `seed` being its decrypted seed. Returns how
+    /// many were added.
+    pub fn sign_psbt(
+        &self,
+        psbt: &mut Psbt,
+        wallet_id: &str,
+        seed: &[u8],
+    ) -> MultisigResult<usize> {
+        self.cosigner_of(wallet_id)?;
+
+        if self.update_psbt(psbt)?.is_empty() {
+            return Err(PsbtError::NothingToSign.into());
+        }
+
+        let master = Xpriv::new_master(self.network.to_bitcoin_network_kind(), seed);
+
+        if let Err(err) = master {
+            return Err(MultisigError::Derivation(err.to_string()));
+        }
+
+        Ok(psbt::sign(psbt, &master.unwrap())?)
+    }
+
+    /// Finalizes the PSBT once every input of the multisig holds enough signatures.
+    pub fn finalize_psbt(&self, psbt: &mut Psbt) -> MultisigResult<Transaction> {
+        let inputs = self.update_psbt(psbt)?;
+        psbt::check_threshold(psbt, &inputs, self.threshold)?;
+        Ok(psbt::finalize(psbt)?)
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "id": self.id,
+            "name": self.name,
+            "threshold": self.threshold,
+            "script_type": self.script_type,
+            "network": self.network,
+            "cosigners": self.cosigners,
+            "descriptor": self.descriptor(0).map(|descriptor| descriptor.to_string()).ok(),
+            "change_descriptor": self.descriptor(1).map(|descriptor| descriptor.to_string()).ok(),
+            "created_at": self.created_at,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{kdf::KdfParams, wallet::StoreWalletInput};
+    use bitcoin::{
+        absolute::LockTime, hashes::Hash, transaction::Version, Amount, OutPoint, ScriptBuf,
+        Sequence, TxIn, TxOut, Txid, Witness,
+    };
+
+    fn cosigner_wallets(count: usize) -> Vec<(WalletModel, AESKey)> {
+        (0..count)
+            .map(|i| {
+                let mut wallet = StoreWalletInput::new(&format!("cosigner-{i}"), "password");
+                wallet.kdf(KdfParams::fast());
+                let wallet = WalletModel::from(wallet.build().unwrap());
+                let key = wallet.authenticate("password").unwrap();
+                (wallet, key)
+            })
+            .collect()
+    }
+
+    fn multisig(
+        wallets: &[(WalletModel, AESKey)],
+        threshold: usize,
+        script_type: MultisigScriptType,
+    ) -> MultisigModel {
+        let cosigners = wallets
+            .iter()
+            .map(|(wallet, key)| {
+                Cosigner::local(wallet, key, script_type, Network::Regtest, 0).unwrap()
+            })
+            .collect();
+        let input = StoreMultisigInput {
+            name: "vault".to_string(),
+            threshold,
+            script_type,
+            network: Network::Regtest,
+            cosigners,
+        };
+        input.validate().unwrap();
+        MultisigModel::from(input)
+    }
+
+    /// Spends an output paying the first receive address of the multisig.
+    fn spending_psbt(multisig: &MultisigModel) -> Psbt {
+        let tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(Txid::all_zeros(), 0),
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(99_000),
+                script_pubkey: multisig.address(1, 0).unwrap().script_pubkey(),
+            }],
+        };
+
+        let mut psbt = Psbt::from_unsigned_tx(tx).unwrap();
+        psbt.inputs[0].witness_utxo = Some(TxOut {
+            value: Amount::from_sat(100_000),
+            script_pubkey: multisig.address(0, 0).unwrap().script_pubkey(),
+        });
+        psbt
+    }
+
+    fn seed(wallet: &WalletModel, key: &AESKey) -> Vec<u8> {
+        wallet.decrypt_seed(key).unwrap().to_vec()
+    }
+
+    #[test]
+    fn builds_bip48_descriptors() {
+        let wallets = cosigner_wallets(3);
+        let wsh = multisig(&wallets, 2, MultisigScriptType::Wsh);
+        let sh_wsh = multisig(&wallets, 2, MultisigScriptType::ShWsh);
+
+        assert!(wsh.cosigners[0].key.contains("/48'/1'/0'/2']tpub"));
+        assert!(sh_wsh.cosigners[0].key.contains("/48'/1'/0'/1']tpub"));
+        assert!(wsh
+            .descriptor(0)
+            .unwrap()
+            .to_string()
+            .starts_with("wsh(sortedmulti(2,"));
+        assert!(sh_wsh
+            .descriptor(1)
+            .unwrap()
+            .to_string()
+            .starts_with("sh(wsh(sortedmulti(2,"));
+        assert!(wsh.address(0, 0).unwrap().to_string().starts_with("bcrt1q"));
+        assert!(sh_wsh.address(0, 0).unwrap().to_string().starts_with('2'));
+        assert_ne!(wsh.address(0, 0).unwrap(), wsh.address(0, 1).unwrap());
+
+        // Imported keys keep working the same as local ones.
+        let imported = Cosigner::imported(&wsh.cosigners[2].key).unwrap();
+        assert_eq!(imported.wallet_id, None);
+        let mut mixed = wsh.clone();
+        mixed.cosigners[2] = imported;
+        assert_eq!(mixed.address(0, 5).unwrap(), wsh.address(0, 5).unwrap());
+    }
+
+    #[test]
+    fn rejects_invalid_multisigs() {
+        let wallets = cosigner_wallets(2);
+        let valid = multisig(&wallets, 2, MultisigScriptType::Wsh);
+        let input = |threshold, cosigners: Vec<Cosigner>| StoreMultisigInput {
+            name: "vault".to_string(),
+            threshold,
+            script_type: MultisigScriptType::Wsh,
+            network: Network::Regtest,
+            cosigners,
+        };
+
+        assert!(matches!(
+            input(3, valid.cosigners.clone()).validate(),
+            Err(MultisigError::Threshold(3, 2))
+        ));
+        assert!(matches!(
+            input(0, valid.cosigners.clone()).validate(),
+            Err(MultisigError::Threshold(0, 2))
+        ));
+        assert!(matches!(
+            input(
+                1,
+                vec![valid.cosigners[0].clone(), valid.cosigners[0].clone()]
+            )
+            .validate(),
+            Err(MultisigError::DuplicateCosigner(_))
+        ));
+
+        let mut mainnet = input(1, valid.cosigners.clone());
+        mainnet.network = Network::Mainnet;
+        assert!(matches!(
+            mainnet.validate(),
+            Err(MultisigError::Cosigner(..))
+        ));
+
+        let bare = valid.cosigners[0].key.split(']').nth(1).unwrap();
+        assert!(Cosigner::imported(bare).is_err());
+        assert!(Cosigner::imported(&format!("{}/0/*", valid.cosigners[0].key)).is_err());
+
+        let mut taproot = input(1, valid.cosigners.clone());
+        taproot.script_type = MultisigScriptType::Tr;
+        assert!(matches!(taproot.validate(), Err(MultisigError::Taproot)));
+
+        // Accounts are hardened children, which stop at 2^31.
+        let (wallet, key) = &wallets[0];
+        assert!(matches!(
+            Cosigner::local(
+                wallet,
+                key,
+                MultisigScriptType::Wsh,
+                Network::Regtest,
+                1 << 31
+            ),
+            Err(MultisigError::Derivation(_))
+        ));
+    }
+
+    #[test]
+    fn signs_combines_and_finalizes() {
+        let wallets = cosigner_wallets(3);
+
+        for script_type in [MultisigScriptType::Wsh, MultisigScriptType::ShWsh] {
+            let multisig = multisig(&wallets, 2, script_type);
+            let unsigned = spending_psbt(&multisig);
+
+            // Each cosigner signs a copy of its own.
+            let mut first = unsigned.clone();
+            let (wallet, key) = &wallets[0];
+            assert_eq!(
+                multisig
+                    .sign_psbt(&mut first, &wallet.id, &seed(wallet, key))
+                    .unwrap(),
+                1
+            );
+
+            let mut third = psbt::decode(&psbt::encode(&unsigned)).unwrap();
+            let (wallet, key) = &wallets[2];
+            multisig
+                .sign_psbt(&mut third, &wallet.id, &seed(wallet, key))
+                .unwrap();
+
+            // One signature isn't enough.
+            assert!(matches!(
+                multisig.finalize_psbt(&mut first.clone()),
+                Err(MultisigError::Psbt(PsbtError::ThresholdNotMet(0, 1, 2)))
+            ));
+
+            let mut combined = psbt::combine(vec![first, third]).unwrap();
+            assert_eq!(combined.inputs[0].partial_sigs.len(), 2);
+
+            let tx = multisig.finalize_psbt(&mut combined).unwrap();
+            assert_eq!(tx.input[0].witness.len(), 4);
+            assert_eq!(tx.output, unsigned.unsigned_tx.output);
+        }
+    }
+
+    #[test]
+    fn only_cosigners_sign() {
+        let wallets = cosigner_wallets(3);
+        let multisig = multisig(&wallets[..2], 1, MultisigScriptType::Wsh);
+        let mut psbt = spending_psbt(&multisig);
+        let (outsider, key) = &wallets[2];
+
+        assert!(matches!(
+            multisig.sign_psbt(&mut psbt, &outsider.id, &seed(outsider, key)),
+            Err(MultisigError::NotCosigner(_))
+        ));
+
+        // Inputs spending from elsewhere are left alone.
+        psbt.inputs[0].witness_utxo.as_mut().unwrap().script_pubkey = ScriptBuf::new();
+        let (wallet, key) = &wallets[0];
+        assert!(matches!(
+            multisig.sign_psbt(&mut psbt, &wallet.id, &seed(wallet, key)),
+            Err(MultisigError::Psbt(PsbtError::NothingToSign))
+        ));
+    }
+}
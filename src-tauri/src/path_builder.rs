@@ -1,4 +1,4 @@
-use bitcoin::bip32::{ChildNumber, DerivationPath};
+use bitcoin::bip32::{ChildNumber, DerivationPath, Error};
 use bitcoin::NetworkKind;
 
 pub enum AddressType {
@@ -7,9 +7,12 @@ pub enum AddressType {
 }
 
 pub enum PathAddressKind {
-    Legacy,       // P2PKH
-    SegWit,       // P2SH-P2WPKH
-    NativeSegWit, // P2WPKH
+    Legacy,               // P2PKH
+    SegWit,               // P2SH-P2WPKH
+    NativeSegWit,         // P2WPKH
+    MultisigSegWit,       // BIP-48 P2SH-P2WSH
+    MultisigNativeSegWit, // BIP-48 P2WSH
+    MultisigTaproot,      // BIP-48 P2TR
 }
 
 pub enum SupportedNetworks {
@@ -27,6 +30,7 @@ pub struct PathBuilder {
 }
 
 // m / purpose' / coin_type' / account' / change / address_index
+// m / 48' / coin_type' / account' / script_type' / change / address_index for multisig
 
 impl PathBuilder {
     pub fn new() -> PathBuilder {
@@ -50,6 +54,11 @@ impl PathBuilder {
         self
     }
 
+    pub fn address_kind(mut self, kind: PathAddressKind) -> Self {
+        self.address_kind = kind;
+        self
+    }
+
     pub fn index(mut self, index: u32) -> Self {
         self.index = index;
         self
@@ -79,17 +88,41 @@ impl PathBuilder {
             PathAddressKind::Legacy => ChildNumber::from_hardened_idx(44).unwrap(),
             PathAddressKind::SegWit => ChildNumber::from_hardened_idx(49).unwrap(),
             PathAddressKind::NativeSegWit => ChildNumber::from_hardened_idx(84).unwrap(),
+            PathAddressKind::MultisigSegWit
+            | PathAddressKind::MultisigNativeSegWit
+            | PathAddressKind::MultisigTaproot => ChildNumber::from_hardened_idx(48).unwrap(),
         }
     }
 
-    pub fn build(&self) -> DerivationPath {
+    /// Script type level BIP-48 adds after the account.
+    fn script_type(&self) -> Option<ChildNumber> {
+        match self.address_kind {
+            PathAddressKind::MultisigSegWit => Some(ChildNumber::from_hardened_idx(1).unwrap()),
+            PathAddressKind::MultisigNativeSegWit => {
+                Some(ChildNumber::from_hardened_idx(2).unwrap())
+            }
+            PathAddressKind::MultisigTaproot => Some(ChildNumber::from_hardened_idx(3).unwrap()),
+            _ => None,
+        }
+    }
+
+    /// Path of the account, the extended key cosigners share for multisig. Fails when the
+    /// account index doesn't fit a hardened child.
+    pub fn account_path(&self) -> Result<DerivationPath, Error> {
         let purpose = self.purpose();
         let coin_type = self.coin_type();
-        let account_index = ChildNumber::from_hardened_idx(self.account_index).unwrap();
+        let account_index = ChildNumber::from_hardened_idx(self.account_index)?;
+
+        let mut path = vec![purpose, coin_type, account_index];
+        path.extend(self.script_type());
+        Ok(DerivationPath::from(path))
+    }
+
+    pub fn build(&self) -> DerivationPath {
         let change_index = ChildNumber::from_normal_idx(self.change_index).unwrap();
         let index = ChildNumber::from_normal_idx(self.index).unwrap();
 
-        DerivationPath::from(vec![purpose, coin_type, account_index, change_index, index])
+        self.account_path().unwrap().extend([change_index, index])
     }
 }
 
@@ -103,4 +136,24 @@ mod tests {
 
         assert_eq!(path, "49'/0'/0'/0/0");
     }
+
+    #[test]
+    fn can_build_bip48_path() {
+        let path = PathBuilder::new()
+            .address_kind(PathAddressKind::MultisigNativeSegWit)
+            .network_kind(NetworkKind::Test)
+            .account_index(3);
+
+        assert_eq!(path.account_path().unwrap().to_string(), "48'/1'/3'/2'");
+        assert_eq!(
+            path.change_index(1).index(7).build().to_string(),
+            "48'/1'/3'/2'/1/7"
+        );
+
+        let taproot = PathBuilder::new()
+            .address_kind(PathAddressKind::MultisigTaproot)
+            .network_kind(NetworkKind::Main);
+        assert_eq!(taproot.account_path().unwrap().to_string(), "48'/0'/0'/3'");
+        assert!(taproot.account_index(1 << 31).account_path().is_err());
+    }
 }
@@ -0,0 +1,1106 @@
+use bitcoin::{
+    absolute,
+    bip32::Xpriv,
+    hashes::{hash160, ripemd160, sha256, Hash},
+    relative,
+    secp256k1::{Secp256k1, Verification},
+    Address, Psbt, PublicKey, Sequence, TapSighashType, Transaction,
+};
+use miniscript::{
+    descriptor::{DefiniteDescriptorKey, DescriptorPublicKey, DescriptorType},
+    hash256,
+    miniscript::satisfy::Placeholder,
+    plan::{Assets, Plan},
+    policy::{concrete::DescriptorCtx, Concrete},
+    Descriptor, ForEachKey, Segwitv0, TranslateErr, TranslatePk, Translator,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    collections::{HashMap, HashSet},
+    str::FromStr,
+};
+use thiserror::Error;
+
+use crate::{
+    account::Network,
+    multisig::{Cosigner, CosignerInput, MultisigError, MultisigScriptType},
+    psbt::{self, PsbtError},
+    taproot::{self, TapTree, TaprootError, NUMS_KEY},
+};
+
+/// Alias templates use for a `tr()` internal key nobody can sign with, leaving only the
+/// script paths.
+pub const UNSPENDABLE: &str = "unspendable";
+
+#[derive(Error, Debug)]
+pub enum PolicyError {
+    #[error("Invalid policy: {0}")]
+    Policy(String),
+    #[error("Invalid key alias {0}, only letters, digits and underscores are allowed")]
+    Alias(String),
+    #[error("Key {0} is given more than once")]
+    DuplicateAlias(String),
+    #[error("The policy uses key {0}, which isn't given")]
+    UnknownKey(String),
+    #[error("Key {0} isn't used by the policy")]
+    UnusedKey(String),
+    #[error("Failed compiling the policy: {0}")]
+    Compile(String),
+    #[error("Invalid descriptor: {0}")]
+    Descriptor(String),
+    #[error("Wallet {0} holds no key of this policy")]
+    NotCosigner(String),
+    #[error("No spend path can be satisfied with the given keys and timelocks")]
+    NoSpendPath,
+    #[error("Failed deriving the key: {0}")]
+    Derivation(String),
+    #[error(transparent)]
+    Key(#[from] MultisigError),
+    #[error(transparent)]
+    Psbt(#[from] PsbtError),
+    #[error(transparent)]
+    Taproot(#[from] TaprootError),
+}
+
+pub type PolicyResult<T> = Result<T, PolicyError>;
+
+/// Key of the policy, referred to by its alias in the policy text, e.g. `pk(alice)`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PolicyKey {
+    pub alias: String,
+    #[serde(flatten)]
+    pub cosigner: Cosigner,
+}
+
+impl PolicyKey {
+    /// Key of every address of the receive and change chains, `[fp/path]xpub/<0;1>/*`.
+    fn multipath_key(&self) -> PolicyResult<DescriptorPublicKey> {
+        DescriptorPublicKey::from_str(&format!("{}/<0;1>/*", self.cosigner.key))
+            .map_err(|err| PolicyError::Descriptor(err.to_string()))
+    }
+
+    /// Key of the addresses of one chain, as the plans and the assets refer to it.
+    fn chain_key(&self, change: u32) -> PolicyResult<DescriptorPublicKey> {
+        DescriptorPublicKey::from_str(&format!("{}/{change}/*", self.cosigner.key))
+            .map_err(|err| PolicyError::Descriptor(err.to_string()))
+    }
+}
+
+/// Key of the policy as given when creating the wallet, a local wallet sharing its key at the
+/// BIP-48 path of `account` or an imported key.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PolicyKeyInput {
+    pub alias: String,
+    #[serde(flatten)]
+    pub cosigner: CosignerInput,
+    #[serde(default)]
+    pub account: u32,
+}
+
+/// Replaces the aliases of the policy by the keys they stand for.
+struct AliasTranslator<'a>(&'a HashMap<String, DescriptorPublicKey>);
+
+fn parse_hash<H: FromStr>(text: &str) -> PolicyResult<H>
+where
+    H::Err: std::fmt::Display,
+{
+    H::from_str(text).map_err(|err| PolicyError::Policy(format!("invalid hash {text}: {err}")))
+}
+
+fn unspendable_key() -> DescriptorPublicKey {
+    DescriptorPublicKey::from_str(NUMS_KEY).unwrap()
+}
+
+impl Translator<String, DescriptorPublicKey, PolicyError> for AliasTranslator<'_> {
+    fn pk(&mut self, alias: &String) -> PolicyResult<DescriptorPublicKey> {
+        if alias == UNSPENDABLE {
+            return Ok(unspendable_key());
+        }
+
+        self.0
+            .get(alias)
+            .cloned()
+            .ok_or(PolicyError::UnknownKey(alias.clone()))
+    }
+
+    fn sha256(&mut self, hash: &String) -> PolicyResult<sha256::Hash> {
+        parse_hash(hash)
+    }
+
+    fn hash256(&mut self, hash: &String) -> PolicyResult<hash256::Hash> {
+        parse_hash(hash)
+    }
+
+    fn ripemd160(&mut self, hash: &String) -> PolicyResult<ripemd160::Hash> {
+        parse_hash(hash)
+    }
+
+    fn hash160(&mut self, hash: &String) -> PolicyResult<hash160::Hash> {
+        parse_hash(hash)
+    }
+}
+
+/// Keys of both chains by alias, every alias `used` having to be in `keys` and the other way
+/// around.
+fn alias_keys(
+    keys: &[PolicyKey],
+    used: &HashSet<&String>,
+) -> PolicyResult<HashMap<String, DescriptorPublicKey>> {
+    let mut aliases = HashMap::new();
+
+    for key in keys {
+        if key.alias.is_empty()
+            || key.alias == UNSPENDABLE
+            || !key
+                .alias
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_')
+        {
+            return Err(PolicyError::Alias(key.alias.clone()));
+        }
+
+        if !used.contains(&key.alias) {
+            return Err(PolicyError::UnusedKey(key.alias.clone()));
+        }
+
+        if aliases
+            .insert(key.alias.clone(), key.multipath_key()?)
+            .is_some()
+        {
+            return Err(PolicyError::DuplicateAlias(key.alias.clone()));
+        }
+    }
+
+    Ok(aliases)
+}
+
+/// Compiles `policy`, e.g. `or(thresh(2,pk(a),pk(b),pk(c)),and(pk(d),older(1000)))`, into a
+/// descriptor of both chains. Every alias of the policy must be in `keys` and the other way
+/// around. Taproot puts a key able to sign alone on the key path when there is one, the
+/// [`NUMS_KEY`] otherwise.
+pub fn compile(
+    policy: &str,
+    keys: &[PolicyKey],
+    script_type: MultisigScriptType,
+) -> PolicyResult<Descriptor<DescriptorPublicKey>> {
+    let parsed = Concrete::<String>::from_str(policy.trim());
+
+    if let Err(err) = parsed {
+        return Err(PolicyError::Policy(err.to_string()));
+    }
+
+    let parsed = parsed.unwrap();
+    let used: HashSet<&String> = parsed.keys().into_iter().collect();
+    let aliases = alias_keys(keys, &used)?;
+    let translated = parsed.translate_pk(&mut AliasTranslator(&aliases))?;
+
+    let descriptor = match script_type {
+        MultisigScriptType::ShWsh => {
+            translated.compile_to_descriptor::<Segwitv0>(DescriptorCtx::ShWsh)
+        }
+        MultisigScriptType::Wsh => translated.compile_to_descriptor::<Segwitv0>(DescriptorCtx::Wsh),
+        // A lone key leaves the compiler with an empty tree, it only needs the key path.
+        MultisigScriptType::Tr => match &translated {
+            Concrete::Key(key) => Descriptor::new_tr(key.clone(), None),
+            _ => translated.compile_tr(Some(unspendable_key())),
+        },
+    };
+
+    descriptor.map_err(|err| PolicyError::Compile(err.to_string()))
+}
+
+fn parse_template(template: &str) -> PolicyResult<Descriptor<String>> {
+    Descriptor::<String>::from_str(template.trim())
+        .map_err(|err| PolicyError::Descriptor(err.to_string()))
+}
+
+/// Script type of a descriptor template, for the local keys to be shared at its BIP-48 path.
+pub fn template_script_type(template: &str) -> PolicyResult<MultisigScriptType> {
+    match parse_template(template)?.desc_type() {
+        DescriptorType::ShWsh | DescriptorType::ShWshSortedMulti => Ok(MultisigScriptType::ShWsh),
+        DescriptorType::Wsh | DescriptorType::WshSortedMulti => Ok(MultisigScriptType::Wsh),
+        DescriptorType::Tr => Ok(MultisigScriptType::Tr),
+        _ => Err(PolicyError::Descriptor(
+            "only sh(wsh()), wsh() and tr() templates are supported".to_string(),
+        )),
+    }
+}
+
+/// Fills in the keys of a descriptor template written with aliases, e.g.
+/// `tr(unspendable,{pk(a),and_v(v:pk(b),older(144))})`, for script trees the compiler wouldn't
+/// pick. [`UNSPENDABLE`] stands for the [`NUMS_KEY`].
+pub fn from_template(
+    template: &str,
+    keys: &[PolicyKey],
+) -> PolicyResult<Descriptor<DescriptorPublicKey>> {
+    template_script_type(template)?;
+    let parsed = parse_template(template)?;
+
+    let mut used: HashSet<&String> = HashSet::new();
+    parsed.for_each_key(|alias| {
+        if alias != UNSPENDABLE {
+            used.insert(alias);
+        }
+        true
+    });
+
+    let aliases = alias_keys(keys, &used)?;
+    let descriptor = match parsed.translate_pk(&mut AliasTranslator(&aliases)) {
+        Ok(descriptor) => descriptor,
+        Err(TranslateErr::TranslatorErr(err)) => return Err(err),
+        Err(TranslateErr::OuterError(err)) => return Err(PolicyError::Descriptor(err.to_string())),
+    };
+
+    if let Err(err) = descriptor.sanity_check() {
+        return Err(PolicyError::Descriptor(err.to_string()));
+    }
+
+    Ok(descriptor)
+}
+
+/// What a spend can count on, for [`PolicyWalletModel::plan`] to pick the cheapest path.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct SpendConditions {
+    /// Aliases of the keys able to sign.
+    pub keys: Vec<String>,
+    /// Current block height, for `after` heights.
+    pub height: Option<u32>,
+    /// Current median time past, for `after` timestamps.
+    pub time: Option<u32>,
+    /// Height the spent coin confirmed at, its age in blocks counting for `older`.
+    pub confirmed_height: Option<u32>,
+    /// Median time past of the block the spent coin confirmed in.
+    pub confirmed_time: Option<u32>,
+}
+
+impl SpendConditions {
+    fn absolute_timelocks(&self) -> PolicyResult<Vec<Option<absolute::LockTime>>> {
+        let mut timelocks = vec![];
+
+        if let Some(height) = self.height {
+            match absolute::LockTime::from_height(height) {
+                Ok(timelock) => timelocks.push(Some(timelock)),
+                Err(err) => return Err(PolicyError::Policy(err.to_string())),
+            }
+        }
+
+        if let Some(time) = self.time {
+            match absolute::LockTime::from_time(time) {
+                Ok(timelock) => timelocks.push(Some(timelock)),
+                Err(err) => return Err(PolicyError::Policy(err.to_string())),
+            }
+        }
+
+        if timelocks.is_empty() {
+            timelocks.push(None);
+        }
+
+        Ok(timelocks)
+    }
+
+    fn relative_timelocks(&self) -> Vec<Option<relative::LockTime>> {
+        let mut timelocks = vec![];
+
+        if let (Some(height), Some(confirmed)) = (self.height, self.confirmed_height) {
+            let blocks = height.saturating_sub(confirmed).min(u16::MAX as u32);
+            timelocks.push(Some(relative::LockTime::from_height(blocks as u16)));
+        }
+
+        if let (Some(time), Some(confirmed)) = (self.time, self.confirmed_time) {
+            let intervals = (time.saturating_sub(confirmed) / 512).min(u16::MAX as u32);
+            timelocks.push(Some(relative::LockTime::from_512_second_intervals(
+                intervals as u16,
+            )));
+        }
+
+        if timelocks.is_empty() {
+            timelocks.push(None);
+        }
+
+        timelocks
+    }
+}
+
+/// Whether the witness element is a signature of `key`.
+fn signs_with<C: Verification>(
+    placeholder: &Placeholder<DefiniteDescriptorKey>,
+    key: &PublicKey,
+    secp: &Secp256k1<C>,
+) -> bool {
+    match placeholder {
+        Placeholder::EcdsaSigPk(pk) => pk.derive_public_key(secp).is_ok_and(|pk| pk == *key),
+        Placeholder::EcdsaSigPkHash(hash) => *hash == hash160::Hash::hash(&key.to_bytes()),
+        Placeholder::SchnorrSigPk(pk, _, _) => pk
+            .derive_public_key(secp)
+            .is_ok_and(|pk| pk.inner.x_only_public_key() == key.inner.x_only_public_key()),
+        Placeholder::SchnorrSigPkHash(hash, _, _) => {
+            *hash == hash160::Hash::hash(&key.inner.x_only_public_key().0.serialize())
+        }
+        _ => false,
+    }
+}
+
+/// Cheapest way to spend a coin of the wallet under some [`SpendConditions`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SpendPlan {
+    /// Aliases of the keys that have to sign.
+    pub keys: Vec<String>,
+    /// `nLockTime` of the spending transaction, `0` when the path has no absolute timelock.
+    pub lock_time: u32,
+    /// `nSequence` of the spending input.
+    pub sequence: u32,
+    /// Weight the satisfaction adds to the transaction, to estimate the fee.
+    pub satisfaction_weight: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct StorePolicyWalletInput {
+    pub name: String,
+    pub policy: String,
+    pub descriptor: String,
+    pub network: Network,
+    pub keys: Vec<PolicyKey>,
+}
+
+impl StorePolicyWalletInput {
+    /// Checks every key is on `network` and compiles the policy.
+    pub fn compile(
+        name: &str,
+        policy: &str,
+        script_type: MultisigScriptType,
+        network: Network,
+        keys: Vec<PolicyKey>,
+    ) -> PolicyResult<Self> {
+        for key in keys.iter() {
+            key.cosigner.check_network(network)?;
+        }
+
+        let descriptor = compile(policy, &keys, script_type)?;
+
+        Ok(StorePolicyWalletInput {
+            name: name.to_string(),
+            policy: policy.trim().to_string(),
+            descriptor: descriptor.to_string(),
+            network,
+            keys,
+        })
+    }
+
+    /// Checks every key is on `network` and fills them in the descriptor template, see
+    /// [`from_template`].
+    pub fn from_template(
+        name: &str,
+        template: &str,
+        network: Network,
+        keys: Vec<PolicyKey>,
+    ) -> PolicyResult<Self> {
+        for key in keys.iter() {
+            key.cosigner.check_network(network)?;
+        }
+
+        let descriptor = from_template(template, &keys)?;
+
+        Ok(StorePolicyWalletInput {
+            name: name.to_string(),
+            policy: template.trim().to_string(),
+            descriptor: descriptor.to_string(),
+            network,
+            keys,
+        })
+    }
+}
+
+/// Wallet spending under a miniscript policy, e.g. with recovery paths behind timelocks.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PolicyWalletModel {
+    pub id: String,
+    pub name: String,
+    /// Policy or descriptor template the descriptor was built from, with the key aliases.
+    pub policy: String,
+    /// Both chains as `<0;1>/*`. Kept rather than compiled again, another version of the
+    /// compiler may pick other scripts and so other addresses.
+    pub descriptor: String,
+    pub network: Network,
+    pub keys: Vec<PolicyKey>,
+    pub created_at: Option<String>,
+}
+
+impl From<StorePolicyWalletInput> for PolicyWalletModel {
+    fn from(value: StorePolicyWalletInput) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            name: value.name,
+            policy: value.policy,
+            descriptor: value.descriptor,
+            network: value.network,
+            keys: value.keys,
+            created_at: None,
+        }
+    }
+}
+
+impl PolicyWalletModel {
+    /// Descriptor of the receive (`0`) or change (`1`) chain.
+    pub fn descriptor(&self, change: u32) -> PolicyResult<Descriptor<DescriptorPublicKey>> {
+        let descriptors = Descriptor::<DescriptorPublicKey>::from_str(&self.descriptor)
+            .and_then(|descriptor| descriptor.into_single_descriptors());
+
+        if let Err(err) = descriptors {
+            return Err(PolicyError::Descriptor(err.to_string()));
+        }
+
+        descriptors
+            .unwrap()
+            .into_iter()
+            .nth(change as usize)
+            .ok_or(PolicyError::Descriptor(format!("no chain {change}")))
+    }
+
+    pub fn address(&self, change: u32, index: u32) -> PolicyResult<Address> {
+        let descriptor = self.descriptor(change)?.at_derivation_index(index);
+
+        if let Err(err) = descriptor {
+            return Err(PolicyError::Descriptor(err.to_string()));
+        }
+
+        descriptor
+            .unwrap()
+            .address(self.network.to_bitcoin_network())
+            .map_err(|err| PolicyError::Descriptor(err.to_string()))
+    }
+
+    /// Leaves of the `tr()` output at `index` of the chain, with their control blocks.
+    pub fn tap_tree(&self, change: u32, index: u32) -> PolicyResult<TapTree> {
+        let descriptor = self.descriptor(change)?.at_derivation_index(index);
+
+        if let Err(err) = descriptor {
+            return Err(PolicyError::Descriptor(err.to_string()));
+        }
+
+        Ok(taproot::tap_tree(&descriptor.unwrap())?)
+    }
+
+    fn key(&self, alias: &str) -> PolicyResult<&PolicyKey> {
+        self.keys
+            .iter()
+            .find(|key| key.alias == alias)
+            .ok_or(PolicyError::UnknownKey(alias.to_string()))
+    }
+
+    /// Picks the cheapest spend path `conditions` satisfy. Every path has the same shape at
+    /// any address, so the first receive address stands for all of them.
+    pub fn plan(&self, conditions: &SpendConditions) -> PolicyResult<SpendPlan> {
+        let descriptor = self.descriptor(0)?.at_derivation_index(0);
+
+        if let Err(err) = descriptor {
+            return Err(PolicyError::Descriptor(err.to_string()));
+        }
+
+        let descriptor = descriptor.unwrap();
+        let mut keys = vec![];
+        for alias in conditions.keys.iter() {
+            keys.push(self.key(alias)?.chain_key(0)?);
+        }
+
+        let mut best: Option<Plan> = None;
+        for absolute in conditions.absolute_timelocks()? {
+            for relative in conditions.relative_timelocks() {
+                let mut assets = Assets::new().add(keys.clone());
+                if let Some(absolute) = absolute {
+                    assets = assets.after(absolute);
+                }
+                if let Some(relative) = relative {
+                    assets = assets.older(relative);
+                }
+
+                if let Ok(plan) = descriptor.clone().plan(&assets) {
+                    if best
+                        .as_ref()
+                        .is_none_or(|best| plan.satisfaction_weight() < best.satisfaction_weight())
+                    {
+                        best = Some(plan);
+                    }
+                }
+            }
+        }
+
+        let plan = best.ok_or(PolicyError::NoSpendPath)?;
+        let secp = Secp256k1::verification_only();
+        let mut signers = vec![];
+
+        for key in self.keys.iter() {
+            let derived = key.chain_key(0)?.at_derivation_index(0);
+
+            if let Err(err) = derived {
+                return Err(PolicyError::Derivation(err.to_string()));
+            }
+
+            let derived = derived.unwrap().derive_public_key(&secp);
+
+            if let Err(err) = derived {
+                return Err(PolicyError::Derivation(err.to_string()));
+            }
+
+            let derived = derived.unwrap();
+            if plan
+                .witness_template()
+                .iter()
+                .any(|placeholder| signs_with(placeholder, &derived, &secp))
+            {
+                signers.push(key.alias.clone());
+            }
+        }
+
+        let sequence = match plan.relative_timelock {
+            Some(timelock) => timelock.to_sequence(),
+            None => Sequence::ENABLE_RBF_NO_LOCKTIME,
+        };
+
+        Ok(SpendPlan {
+            keys: signers,
+            lock_time: plan
+                .absolute_timelock
+                .map_or(0, |timelock| timelock.to_consensus_u32()),
+            sequence: sequence.to_consensus_u32(),
+            satisfaction_weight: plan.satisfaction_weight(),
+        })
+    }
+
+    /// Fills in what signers need to know about the inputs spending from the wallet, returns
+    /// the indexes of those inputs.
+    pub fn update_psbt(&self, psbt: &mut Psbt) -> PolicyResult<Vec<usize>> {
+        let descriptors = [self.descriptor(0)?, self.descriptor(1)?];
+        Ok(psbt::update(psbt, &descriptors))
+    }
+
+    /// `NotCosigner` unless the wallet holds one of the keys of the policy.
+    pub fn check_cosigner(&self, wallet_id: &str) -> PolicyResult<()> {
+        if !self
+            .keys
+            .iter()
+            .any(|key| key.cosigner.wallet_id.as_deref() == Some(wallet_id))
+        {
+            return Err(PolicyError::NotCosigner(wallet_id.to_string()));
+        }
+
+        Ok(())
+    }
+
+    /// Adds the signatures of every key of the policy the wallet holds, `seed` being its
+    /// decrypted seed, committing to `sighash_type` rather than the whole transaction when
+    /// given. Taproot keys sign both the key path and every leaf they appear in. Returns how
+    /// many signatures were added.
+    pub fn sign_psbt(
+        &self,
+        psbt: &mut Psbt,
+        wallet_id: &str,
+        seed: &[u8],
+        sighash_type: Option<TapSighashType>,
+    ) -> PolicyResult<usize> {
+        self.check_cosigner(wallet_id)?;
+
+        let inputs = self.update_psbt(psbt)?;
+
+        if inputs.is_empty() {
+            return Err(PsbtError::NothingToSign.into());
+        }
+
+        if let Some(sighash_type) = sighash_type {
+            psbt::set_sighash_type(psbt, &inputs, sighash_type);
+        }
+
+        let master = Xpriv::new_master(self.network.to_bitcoin_network_kind(), seed);
+
+        if let Err(err) = master {
+            return Err(PolicyError::Derivation(err.to_string()));
+        }
+
+        Ok(psbt::sign(psbt, &master.unwrap())?)
+    }
+
+    /// Finalizes the PSBT with whichever path its signatures and timelocks satisfy, the lock
+    /// time and sequences of the transaction having to match the path, see [`Self::plan`].
+    pub fn finalize_psbt(&self, psbt: &mut Psbt) -> PolicyResult<Transaction> {
+        self.update_psbt(psbt)?;
+        Ok(psbt::finalize(psbt)?)
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "id": self.id,
+            "name": self.name,
+            "policy": self.policy,
+            "descriptor": self.descriptor,
+            "network": self.network,
+            "keys": self.keys,
+            "created_at": self.created_at,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{kdf::KdfParams, utils::AESKey, wallet::StoreWalletInput, wallet::WalletModel};
+    use bitcoin::{
+        absolute::LockTime, hashes::Hash, transaction::Version, Amount, OutPoint, ScriptBuf, TxIn,
+        TxOut, Txid, Witness,
+    };
+
+    const RECOVERY: &str = "or(thresh(2,pk(a),pk(b),pk(c)),and(pk(d),older(1000)))";
+
+    fn signers(count: usize) -> Vec<(WalletModel, AESKey)> {
+        (0..count)
+            .map(|i| {
+                let mut wallet = StoreWalletInput::new(&format!("signer-{i}"), "password");
+                wallet.kdf(KdfParams::fast());
+                let wallet = WalletModel::from(wallet.build().unwrap());
+                let key = wallet.authenticate("password").unwrap();
+                (wallet, key)
+            })
+            .collect()
+    }
+
+    fn keys(wallets: &[(WalletModel, AESKey)]) -> Vec<PolicyKey> {
+        script_keys(wallets, MultisigScriptType::Wsh)
+    }
+
+    fn script_keys(
+        wallets: &[(WalletModel, AESKey)],
+        script_type: MultisigScriptType,
+    ) -> Vec<PolicyKey> {
+        let aliases = ["a", "b", "c", "d"];
+        wallets
+            .iter()
+            .zip(aliases)
+            .map(|((wallet, key), alias)| PolicyKey {
+                alias: alias.to_string(),
+                cosigner: Cosigner::local(wallet, key, script_type, Network::Regtest, 0).unwrap(),
+            })
+            .collect()
+    }
+
+    fn policy_wallet(policy: &str, keys: Vec<PolicyKey>) -> PolicyWalletModel {
+        PolicyWalletModel::from(
+            StorePolicyWalletInput::compile(
+                "recovery",
+                policy,
+                MultisigScriptType::Wsh,
+                Network::Regtest,
+                keys,
+            )
+            .unwrap(),
+        )
+    }
+
+    /// Spends an output paying the first receive address of the wallet.
+    fn spending_psbt(wallet: &PolicyWalletModel, plan: &SpendPlan) -> Psbt {
+        let tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::from_consensus(plan.lock_time),
+            input: vec![TxIn {
+                previous_output: OutPoint::new(Txid::all_zeros(), 0),
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::from_consensus(plan.sequence),
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(99_000),
+                script_pubkey: wallet.address(1, 0).unwrap().script_pubkey(),
+            }],
+        };
+
+        let mut psbt = Psbt::from_unsigned_tx(tx).unwrap();
+        psbt.inputs[0].witness_utxo = Some(TxOut {
+            value: Amount::from_sat(100_000),
+            script_pubkey: wallet.address(0, 0).unwrap().script_pubkey(),
+        });
+        psbt
+    }
+
+    fn conditions(keys: &[&str]) -> SpendConditions {
+        SpendConditions {
+            keys: keys.iter().map(|key| key.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn compiles_policies_to_descriptors() {
+        let wallets = signers(4);
+        let wallet = policy_wallet(RECOVERY, keys(&wallets));
+
+        assert!(wallet.descriptor.starts_with("wsh("));
+        assert!(wallet.descriptor.contains("/<0;1>/*"));
+        assert!(wallet
+            .descriptor(1)
+            .unwrap()
+            .to_string()
+            .contains("older(1000)"));
+        assert!(wallet
+            .address(0, 0)
+            .unwrap()
+            .to_string()
+            .starts_with("bcrt1q"));
+        assert_ne!(wallet.address(0, 0).unwrap(), wallet.address(1, 0).unwrap());
+
+        let sh_wsh = StorePolicyWalletInput::compile(
+            "nested",
+            RECOVERY,
+            MultisigScriptType::ShWsh,
+            Network::Regtest,
+            keys(&wallets),
+        )
+        .unwrap();
+        assert!(sh_wsh.descriptor.starts_with("sh(wsh("));
+
+        // The stored descriptor, not the policy, decides the addresses.
+        let mut edited = wallet.clone();
+        edited.policy = "pk(a)".to_string();
+        assert_eq!(edited.address(0, 3).unwrap(), wallet.address(0, 3).unwrap());
+    }
+
+    #[test]
+    fn parses_key_inputs() {
+        let keys: Vec<PolicyKeyInput> = serde_json::from_value(json!([
+            {"alias": "a", "wallet_id": "wallet", "session_id": "session", "account": 1},
+            {"alias": "b", "key": "[d34db33f/48'/1'/0'/2']tpub"},
+        ]))
+        .unwrap();
+
+        assert!(matches!(
+            &keys[0].cosigner,
+            CosignerInput::Wallet { wallet_id, .. } if wallet_id == "wallet"
+        ));
+        assert_eq!(keys[0].account, 1);
+        assert!(matches!(&keys[1].cosigner, CosignerInput::Key { .. }));
+        assert_eq!(keys[1].account, 0);
+    }
+
+    #[test]
+    fn rejects_invalid_policies() {
+        let wallets = signers(4);
+        let compile = |policy: &str, keys: Vec<PolicyKey>| {
+            StorePolicyWalletInput::compile(
+                "recovery",
+                policy,
+                MultisigScriptType::Wsh,
+                Network::Regtest,
+                keys,
+            )
+        };
+
+        assert!(matches!(
+            compile("or(pk(a),", keys(&wallets)),
+            Err(PolicyError::Policy(_))
+        ));
+        assert!(matches!(
+            compile(RECOVERY, keys(&wallets[..3])),
+            Err(PolicyError::UnknownKey(alias)) if alias == "d"
+        ));
+        assert!(matches!(
+            compile("or(pk(a),pk(b))", keys(&wallets[..3])),
+            Err(PolicyError::UnusedKey(alias)) if alias == "c"
+        ));
+
+        let mut duplicated = keys(&wallets[..2]);
+        duplicated[1].alias = "a".to_string();
+        assert!(matches!(
+            compile("pk(a)", duplicated),
+            Err(PolicyError::DuplicateAlias(_))
+        ));
+
+        let mut invalid = keys(&wallets[..1]);
+        invalid[0].alias = "a,b".to_string();
+        assert!(matches!(
+            compile("pk(a)", invalid),
+            Err(PolicyError::Alias(_))
+        ));
+
+        // Keys of another network.
+        assert!(matches!(
+            StorePolicyWalletInput::compile(
+                "recovery",
+                RECOVERY,
+                MultisigScriptType::Wsh,
+                Network::Mainnet,
+                keys(&wallets),
+            ),
+            Err(PolicyError::Key(MultisigError::Cosigner(..)))
+        ));
+    }
+
+    #[test]
+    fn plans_spend_paths() {
+        let wallets = signers(4);
+        let wallet = policy_wallet(RECOVERY, keys(&wallets));
+
+        let plan = wallet.plan(&conditions(&["a", "c"])).unwrap();
+        assert_eq!(plan.keys, vec!["a", "c"]);
+        assert_eq!(plan.lock_time, 0);
+        assert_eq!(plan.sequence, Sequence::ENABLE_RBF_NO_LOCKTIME.0);
+
+        // The recovery key alone waits for the coin to be 1000 blocks old.
+        assert!(matches!(
+            wallet.plan(&conditions(&["d"])),
+            Err(PolicyError::NoSpendPath)
+        ));
+        let mut recovery = conditions(&["d"]);
+        recovery.height = Some(2000);
+        recovery.confirmed_height = Some(1500);
+        assert!(matches!(
+            wallet.plan(&recovery),
+            Err(PolicyError::NoSpendPath)
+        ));
+
+        recovery.confirmed_height = Some(1000);
+        let plan = wallet.plan(&recovery).unwrap();
+        assert_eq!(plan.keys, vec!["d"]);
+        assert_eq!(plan.sequence, 1000);
+
+        // Both paths are open, a single signature is the cheapest.
+        recovery.keys = vec!["a".to_string(), "b".to_string(), "d".to_string()];
+        assert_eq!(wallet.plan(&recovery).unwrap().keys, vec!["d"]);
+
+        assert!(matches!(
+            wallet.plan(&conditions(&["e"])),
+            Err(PolicyError::UnknownKey(_))
+        ));
+
+        // Absolute timelocks need the chain to have reached them.
+        let timelocked = policy_wallet("or(pk(a),and(pk(b),after(500)))", keys(&wallets[..2]));
+        let mut late = conditions(&["b"]);
+        late.height = Some(499);
+        assert!(matches!(
+            timelocked.plan(&late),
+            Err(PolicyError::NoSpendPath)
+        ));
+        late.height = Some(600);
+        let plan = timelocked.plan(&late).unwrap();
+        assert_eq!(plan.keys, vec!["b"]);
+        assert_eq!(plan.lock_time, 500);
+    }
+
+    #[test]
+    fn signs_and_finalizes_each_path() {
+        let wallets = signers(4);
+        let wallet = policy_wallet(RECOVERY, keys(&wallets));
+        let seed = |i: usize| {
+            let (wallet, key) = &wallets[i];
+            (
+                wallet.id.clone(),
+                wallet.decrypt_seed(key).unwrap().to_vec(),
+            )
+        };
+
+        // 2 of the 3 main keys.
+        let plan = wallet.plan(&conditions(&["a", "b"])).unwrap();
+        let mut psbt = spending_psbt(&wallet, &plan);
+        for i in [0, 1] {
+            let (id, seed) = seed(i);
+            assert_eq!(wallet.sign_psbt(&mut psbt, &id, &seed, None).unwrap(), 1);
+        }
+        wallet.finalize_psbt(&mut psbt).unwrap();
+
+        // The recovery key, once the sequence carries the relative timelock.
+        let (id, recovery_seed) = seed(3);
+        let mut early = spending_psbt(&wallet, &plan);
+        wallet
+            .sign_psbt(&mut early, &id, &recovery_seed, None)
+            .unwrap();
+        assert!(matches!(
+            wallet.finalize_psbt(&mut early),
+            Err(PolicyError::Psbt(PsbtError::Finalize(_)))
+        ));
+
+        let mut recovery = conditions(&["d"]);
+        recovery.height = Some(1000);
+        recovery.confirmed_height = Some(0);
+        let plan = wallet.plan(&recovery).unwrap();
+        let mut psbt = spending_psbt(&wallet, &plan);
+        wallet
+            .sign_psbt(&mut psbt, &id, &recovery_seed, None)
+            .unwrap();
+        let tx = wallet.finalize_psbt(&mut psbt).unwrap();
+        assert_eq!(tx.input[0].sequence, Sequence::from_height(1000));
+
+        let outsider = signers(1);
+        let (outsider, key) = &outsider[0];
+        assert!(matches!(
+            wallet.sign_psbt(
+                &mut spending_psbt(&wallet, &plan),
+                &outsider.id,
+                &outsider.decrypt_seed(key).unwrap(),
+                None
+            ),
+            Err(PolicyError::NotCosigner(_))
+        ));
+    }
+
+    fn seed_of(wallets: &[(WalletModel, AESKey)], i: usize) -> (String, Vec<u8>) {
+        let (wallet, key) = &wallets[i];
+        (
+            wallet.id.clone(),
+            wallet.decrypt_seed(key).unwrap().to_vec(),
+        )
+    }
+
+    #[test]
+    fn signs_taproot_key_and_script_paths() {
+        let wallets = signers(2);
+        let wallet = PolicyWalletModel::from(
+            StorePolicyWalletInput::compile(
+                "taproot",
+                "or(99@pk(a),1@and(pk(b),older(144)))",
+                MultisigScriptType::Tr,
+                Network::Regtest,
+                script_keys(&wallets, MultisigScriptType::Tr),
+            )
+            .unwrap(),
+        );
+
+        assert!(wallet.descriptor.starts_with("tr("));
+        assert!(wallet
+            .address(0, 0)
+            .unwrap()
+            .to_string()
+            .starts_with("bcrt1p"));
+
+        // The key able to sign alone spends through the key path, a single signature.
+        let plan = wallet.plan(&conditions(&["a"])).unwrap();
+        assert_eq!(plan.keys, vec!["a"]);
+        let mut psbt = spending_psbt(&wallet, &plan);
+        let (id, seed) = seed_of(&wallets, 0);
+        wallet.sign_psbt(&mut psbt, &id, &seed, None).unwrap();
+        assert!(psbt.inputs[0].tap_key_sig.is_some());
+        let tx = wallet.finalize_psbt(&mut psbt).unwrap();
+        assert_eq!(tx.input[0].witness.len(), 1);
+
+        // The other one goes through its leaf: signature, script and control block.
+        let mut recovery = conditions(&["b"]);
+        recovery.height = Some(200);
+        recovery.confirmed_height = Some(0);
+        let plan = wallet.plan(&recovery).unwrap();
+        assert_eq!(plan.keys, vec!["b"]);
+        assert_eq!(plan.sequence, 144);
+        let mut psbt = spending_psbt(&wallet, &plan);
+        let (id, seed) = seed_of(&wallets, 1);
+        wallet.sign_psbt(&mut psbt, &id, &seed, None).unwrap();
+        assert_eq!(psbt.inputs[0].tap_script_sigs.len(), 1);
+        let tx = wallet.finalize_psbt(&mut psbt).unwrap();
+        assert_eq!(tx.input[0].witness.len(), 3);
+    }
+
+    #[test]
+    fn builds_descriptor_templates() {
+        let wallets = signers(2);
+        let template = "tr(unspendable,{pk(a),and_v(v:pk(b),older(144))})";
+        assert_eq!(
+            template_script_type(template).unwrap(),
+            MultisigScriptType::Tr
+        );
+
+        let wallet = PolicyWalletModel::from(
+            StorePolicyWalletInput::from_template(
+                "vault",
+                template,
+                Network::Regtest,
+                script_keys(&wallets, MultisigScriptType::Tr),
+            )
+            .unwrap(),
+        );
+        assert_eq!(wallet.policy, template);
+        assert!(wallet.descriptor.starts_with(&format!("tr({NUMS_KEY},{{")));
+
+        // No key path, even the key signing alone needs its leaf.
+        let plan = wallet.plan(&conditions(&["a"])).unwrap();
+        let mut psbt = spending_psbt(&wallet, &plan);
+        let (id, seed) = seed_of(&wallets, 0);
+        wallet.sign_psbt(&mut psbt, &id, &seed, None).unwrap();
+        assert!(psbt.inputs[0].tap_key_sig.is_none());
+        let tx = wallet.finalize_psbt(&mut psbt).unwrap();
+        assert_eq!(tx.input[0].witness.len(), 3);
+
+        let wsh = StorePolicyWalletInput::from_template(
+            "vault",
+            "wsh(or_d(pk(a),and_v(v:pk(b),older(144))))",
+            Network::Regtest,
+            keys(&wallets),
+        )
+        .unwrap();
+        assert!(wsh.descriptor.starts_with("wsh(or_d("));
+
+        let template = |template: &str, keys: Vec<PolicyKey>| {
+            StorePolicyWalletInput::from_template("vault", template, Network::Regtest, keys)
+        };
+        let tr_keys = || script_keys(&wallets, MultisigScriptType::Tr);
+
+        assert!(matches!(
+            template("pkh(a)", tr_keys()),
+            Err(PolicyError::Descriptor(_))
+        ));
+        assert!(matches!(
+            template("tr(unspendable,pk(a))", tr_keys()),
+            Err(PolicyError::UnusedKey(alias)) if alias == "b"
+        ));
+        assert!(matches!(
+            template(
+                "tr(unspendable,{pk(a),pk(c)})",
+                script_keys(&wallets[..1], MultisigScriptType::Tr)
+            ),
+            Err(PolicyError::UnknownKey(alias)) if alias == "c"
+        ));
+
+        let mut reserved = tr_keys();
+        reserved[0].alias = UNSPENDABLE.to_string();
+        assert!(matches!(
+            template("tr(unspendable,pk(b))", reserved),
+            Err(PolicyError::Alias(_))
+        ));
+    }
+
+    #[test]
+    fn signs_with_other_sighash_types() {
+        let wallets = signers(1);
+        let wallet = PolicyWalletModel::from(
+            StorePolicyWalletInput::compile(
+                "single",
+                "pk(a)",
+                MultisigScriptType::Tr,
+                Network::Regtest,
+                script_keys(&wallets, MultisigScriptType::Tr),
+            )
+            .unwrap(),
+        );
+
+        let plan = wallet.plan(&conditions(&["a"])).unwrap();
+        let (id, seed) = seed_of(&wallets, 0);
+        let secp = Secp256k1::verification_only();
+        let output_key = wallet.address(0, 0).unwrap().script_pubkey().as_bytes()[2..].to_vec();
+        let output_key = bitcoin::XOnlyPublicKey::from_slice(&output_key).unwrap();
+
+        for sighash_type in [
+            TapSighashType::All,
+            TapSighashType::None,
+            TapSighashType::SinglePlusAnyoneCanPay,
+        ] {
+            let mut psbt = spending_psbt(&wallet, &plan);
+            wallet
+                .sign_psbt(&mut psbt, &id, &seed, Some(sighash_type))
+                .unwrap();
+
+            let signature = psbt.inputs[0].tap_key_sig.unwrap();
+            assert_eq!(signature.sighash_type, sighash_type);
+
+            let sighash = taproot::psbt_sighash(&psbt, 0, None, sighash_type).unwrap();
+            let message = bitcoin::secp256k1::Message::from_digest(sighash.to_byte_array());
+            secp.verify_schnorr(&signature.signature, &message, &output_key)
+                .unwrap();
+
+            // 65 bytes, the sighash type being appended.
+            let tx = wallet.finalize_psbt(&mut psbt).unwrap();
+            assert_eq!(tx.input[0].witness.nth(0).unwrap().len(), 65);
+        }
+    }
+}
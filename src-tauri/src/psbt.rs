@@ -0,0 +1,188 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use bitcoin::{
+    bip32::Xpriv,
+    psbt::{SigningErrors, SigningKeys},
+    secp256k1, Psbt, TapSighashType, Transaction,
+};
+use miniscript::{
+    descriptor::DescriptorPublicKey,
+    psbt::{PsbtExt, PsbtInputExt},
+    Descriptor,
+};
+use thiserror::Error;
+
+/// How many addresses of each descriptor are searched for the output an input spends, past
+/// the last one the wallet could have handed out.
+pub const SCAN_LIMIT: u32 = 1000;
+
+#[derive(Error, Debug)]
+pub enum PsbtError {
+    #[error("Invalid PSBT: {0}")]
+    Invalid(String),
+    #[error("Failed combining PSBTs: {0}")]
+    Combine(String),
+    #[error("No input of the PSBT can be signed by this wallet")]
+    NothingToSign,
+    #[error("Failed signing: {0}")]
+    Signing(String),
+    #[error("Input {0} has {1} signatures out of the {2} it needs")]
+    ThresholdNotMet(usize, usize, usize),
+    #[error("Failed finalizing: {0}")]
+    Finalize(String),
+}
+
+pub type PsbtResult<T> = Result<T, PsbtError>;
+
+/// PSBTs travel as base64, the way Bitcoin Core and most wallets exchange them.
+pub fn decode(text: &str) -> PsbtResult<Psbt> {
+    let bytes = STANDARD.decode(text.trim());
+
+    if let Err(err) = bytes {
+        return Err(PsbtError::Invalid(err.to_string()));
+    }
+
+    Psbt::deserialize(&bytes.unwrap()).map_err(|err| PsbtError::Invalid(err.to_string()))
+}
+
+pub fn encode(psbt: &Psbt) -> String {
+    STANDARD.encode(psbt.serialize())
+}
+
+/// Fills in the scripts and key origins of every input spending an address of `descriptors`,
+/// the index being found by comparing the spent output against the first [`SCAN_LIMIT`]
+/// addresses. Returns the indexes of the inputs that were matched.
+pub fn update(psbt: &mut Psbt, descriptors: &[Descriptor<DescriptorPublicKey>]) -> Vec<usize> {
+    let mut matched = vec![];
+
+    for i in 0..psbt.inputs.len() {
+        let spent = match (
+            &psbt.inputs[i].witness_utxo,
+            &psbt.inputs[i].non_witness_utxo,
+        ) {
+            (Some(utxo), _) => Some(utxo.script_pubkey.clone()),
+            (None, Some(tx)) => {
+                let vout = psbt.unsigned_tx.input[i].previous_output.vout as usize;
+                tx.output.get(vout).map(|out| out.script_pubkey.clone())
+            }
+            _ => None,
+        };
+
+        let Some(spent) = spent else { continue };
+
+        let found = descriptors.iter().find_map(|descriptor| {
+            (0..SCAN_LIMIT).find_map(|index| {
+                let definite = descriptor.at_derivation_index(index).ok()?;
+                (definite.script_pubkey() == spent).then_some(definite)
+            })
+        });
+
+        if let Some(definite) = found {
+            if psbt.inputs[i]
+                .update_with_descriptor_unchecked(&definite)
+                .is_ok()
+            {
+                matched.push(i);
+            }
+        }
+    }
+
+    matched
+}
+
+/// Sets the sighash type the signatures of `inputs` commit to. `SIGHASH_DEFAULT` only exists
+/// for taproot and is left implicit, as BIP-371 advises.
+pub fn set_sighash_type(psbt: &mut Psbt, inputs: &[usize], sighash_type: TapSighashType) {
+    for &i in inputs {
+        psbt.inputs[i].sighash_type = match sighash_type {
+            TapSighashType::Default => None,
+            sighash_type => Some(sighash_type.into()),
+        };
+    }
+}
+
+/// Signs every input `master` holds a key of, per the key origins of the inputs. Returns how
+/// many signatures were added.
+pub fn sign(psbt: &mut Psbt, master: &Xpriv) -> PsbtResult<usize> {
+    let secp = secp256k1::Secp256k1::new();
+
+    let (keys, errors) = match psbt.sign(master, &secp) {
+        Ok(keys) => (keys, SigningErrors::new()),
+        Err((keys, errors)) => (keys, errors),
+    };
+
+    let count: usize = keys
+        .values()
+        .map(|keys| match keys {
+            SigningKeys::Ecdsa(keys) => keys.len(),
+            SigningKeys::Schnorr(keys) => keys.len(),
+        })
+        .sum();
+
+    // Inputs of other wallets can't be signed, which is fine as long as ours were.
+    if count > 0 {
+        return Ok(count);
+    }
+
+    if errors.is_empty() {
+        return Err(PsbtError::NothingToSign);
+    }
+
+    let errors: Vec<String> = errors
+        .iter()
+        .map(|(input, err)| format!("input {input}: {err}"))
+        .collect();
+    Err(PsbtError::Signing(errors.join(", ")))
+}
+
+/// Merges the signatures and data of every PSBT, which must all spend the same transaction.
+pub fn combine(psbts: Vec<Psbt>) -> PsbtResult<Psbt> {
+    let mut psbts = psbts.into_iter();
+    let combined = psbts.next();
+
+    if combined.is_none() {
+        return Err(PsbtError::Combine("nothing to combine".to_string()));
+    }
+
+    let mut combined = combined.unwrap();
+    for psbt in psbts {
+        if let Err(err) = combined.combine(psbt) {
+            return Err(PsbtError::Combine(err.to_string()));
+        }
+    }
+
+    Ok(combined)
+}
+
+/// Checks each input in `inputs` carries at least `threshold` signatures.
+pub fn check_threshold(psbt: &Psbt, inputs: &[usize], threshold: usize) -> PsbtResult<()> {
+    for &i in inputs {
+        let input = &psbt.inputs[i];
+        // Inputs finalized earlier no longer list their signatures.
+        if input.final_script_witness.is_some() {
+            continue;
+        }
+
+        if input.partial_sigs.len() < threshold {
+            return Err(PsbtError::ThresholdNotMet(
+                i,
+                input.partial_sigs.len(),
+                threshold,
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Finalizes every input and extracts the transaction, ready to be broadcast. The fee rate
+/// isn't checked, regtest fixtures often pay absurd fees on purpose.
+pub fn finalize(psbt: &mut Psbt) -> PsbtResult<Transaction> {
+    let secp = secp256k1::Secp256k1::verification_only();
+
+    if let Err(errors) = psbt.finalize_mut(&secp) {
+        let errors: Vec<String> = errors.iter().map(|err| err.to_string()).collect();
+        return Err(PsbtError::Finalize(errors.join(", ")));
+    }
+
+    Ok(psbt.clone().extract_tx_unchecked_fee_rate())
+}
@@ -0,0 +1,304 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{cmp::Ordering, mem::discriminant};
+
+use crate::{
+    account::AccountModel,
+    vault_interface::{VaultError, VaultResult},
+};
+
+pub const DEFAULT_PAGE_SIZE: u32 = 50;
+pub const MAX_PAGE_SIZE: u32 = 500;
+
+/// What accounts are ordered by, ties are always broken by id.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AccountSort {
+    /// Position picked by the user, then creation.
+    #[default]
+    Position,
+    CreatedAt,
+    Address,
+    Label,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortDirection {
+    #[default]
+    Asc,
+    Desc,
+}
+
+/// One value of the key accounts are sorted by.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum SortValue {
+    Int(i64),
+    Text(String),
+}
+
+impl AccountSort {
+    /// Columns making the sort key, in order, the id always being the last one.
+    pub fn columns(&self) -> &'static [&'static str] {
+        match self {
+            AccountSort::Position => &["position", "created_at", "id"],
+            AccountSort::CreatedAt => &["created_at", "id"],
+            AccountSort::Address => &["address", "id"],
+            AccountSort::Label => &["label", "id"],
+        }
+    }
+
+    /// Sort key of the account, matching [`AccountSort::columns`].
+    pub fn key(&self, account: &AccountModel) -> Vec<SortValue> {
+        self.columns()
+            .iter()
+            .map(|column| match *column {
+                "position" => SortValue::Int(account.position),
+                "created_at" => SortValue::Text(account.created_at.clone().unwrap_or_default()),
+                "address" => SortValue::Text(account.address.clone()),
+                "label" => SortValue::Text(account.label.clone()),
+                _ => SortValue::Text(account.id.clone()),
+            })
+            .collect()
+    }
+}
+
+/// Filters, order and page of an account listing. Every filter is optional, `cursor` is the
+/// `next_cursor` of the previous page.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AccountQuery {
+    pub blockchain: Option<String>,
+    pub network: Option<String>,
+    /// `p2pkh`, `p2sh`, `p2wpkh`, `p2wsh` or `p2tr`.
+    pub script_type: Option<String>,
+    /// Case insensitive part of the label.
+    pub label: Option<String>,
+    pub used: Option<bool>,
+    pub sort: AccountSort,
+    pub direction: SortDirection,
+    pub limit: Option<u32>,
+    pub cursor: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AccountPage {
+    pub accounts: Vec<AccountModel>,
+    /// `None` on the last page.
+    pub next_cursor: Option<String>,
+}
+
+impl AccountPage {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "accounts": self.accounts.iter().map(|account| account.to_json()).collect::<Vec<Value>>(),
+            "next_cursor": self.next_cursor,
+        })
+    }
+}
+
+impl AccountQuery {
+    pub fn limit(&self) -> usize {
+        self.limit
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE) as usize
+    }
+
+    /// Sort key of the last account of the previous page, checked against the current sort
+    /// so a cursor can't be replayed with another one.
+    pub fn decode_cursor(&self) -> VaultResult<Option<Vec<SortValue>>> {
+        let cursor = match &self.cursor {
+            Some(cursor) => cursor,
+            None => return Ok(None),
+        };
+
+        let invalid = || VaultError::Parser(format!("Invalid cursor {cursor}"));
+
+        let data = hex::decode(cursor).map_err(|_| invalid())?;
+        let key = serde_json::from_slice::<Vec<SortValue>>(&data).map_err(|_| invalid())?;
+
+        let expected = self.sort.key(&AccountModel::default());
+        if key.len() != expected.len()
+            || key
+                .iter()
+                .zip(expected.iter())
+                .any(|(value, expected)| discriminant(value) != discriminant(expected))
+        {
+            return Err(invalid());
+        }
+
+        Ok(Some(key))
+    }
+
+    pub fn encode_cursor(key: &[SortValue]) -> String {
+        hex::encode(serde_json::to_vec(key).unwrap_or_default())
+    }
+
+    /// `label` turned into a `LIKE` pattern, `\` being the escape character.
+    pub fn label_pattern(&self) -> Option<String> {
+        self.label.as_ref().map(|label| {
+            let escaped = label
+                .replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_");
+            format!("%{escaped}%")
+        })
+    }
+
+    pub fn matches(&self, account: &AccountModel) -> bool {
+        let matches = |filter: &Option<String>, value: &str| {
+            filter.as_ref().is_none_or(|filter| filter == value)
+        };
+
+        matches(&self.blockchain, &account.blockchain)
+            && matches(&self.network, &account.network)
+            && matches(&self.script_type, &account.script_type)
+            && self
+                .label
+                .as_ref()
+                .is_none_or(|label| account.label.to_lowercase().contains(&label.to_lowercase()))
+            && self.used.is_none_or(|used| used == account.used)
+    }
+
+    fn compare(&self, a: &[SortValue], b: &[SortValue]) -> Ordering {
+        match self.direction {
+            SortDirection::Asc => a.cmp(b),
+            SortDirection::Desc => b.cmp(a),
+        }
+    }
+
+    /// Builds the page out of rows already filtered, ordered and positioned after the cursor,
+    /// of which one more than the limit was fetched to know whether another page follows.
+    pub fn paginate(&self, mut accounts: Vec<AccountModel>) -> AccountPage {
+        let limit = self.limit();
+        let mut next_cursor = None;
+
+        if accounts.len() > limit {
+            accounts.truncate(limit);
+            next_cursor = accounts
+                .last()
+                .map(|account| AccountQuery::encode_cursor(&self.sort.key(account)));
+        }
+
+        AccountPage {
+            accounts,
+            next_cursor,
+        }
+    }
+
+    /// Runs the whole query over accounts held in memory.
+    pub fn apply(&self, accounts: Vec<AccountModel>) -> VaultResult<AccountPage> {
+        let cursor = self.decode_cursor()?;
+
+        let mut accounts: Vec<(Vec<SortValue>, AccountModel)> = accounts
+            .into_iter()
+            .filter(|account| self.matches(account))
+            .map(|account| (self.sort.key(&account), account))
+            .filter(|(key, _)| {
+                cursor
+                    .as_ref()
+                    .is_none_or(|cursor| self.compare(key, cursor) == Ordering::Greater)
+            })
+            .collect();
+
+        accounts.sort_by(|(a, _), (b, _)| self.compare(a, b));
+
+        Ok(self.paginate(
+            accounts
+                .into_iter()
+                .take(self.limit() + 1)
+                .map(|(_, account)| account)
+                .collect(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn account(id: &str, position: i64, label: &str) -> AccountModel {
+        AccountModel {
+            id: id.to_string(),
+            address: format!("address-{id}"),
+            label: label.to_string(),
+            position,
+            created_at: Some("2026-10-19 10:00:00".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn pages_through_every_account_once() {
+        let accounts: Vec<AccountModel> = (0..7)
+            .map(|i| account(&format!("{i}"), i % 3, ""))
+            .collect();
+
+        let mut query = AccountQuery {
+            limit: Some(3),
+            direction: SortDirection::Desc,
+            ..Default::default()
+        };
+
+        let mut seen = vec![];
+        loop {
+            let page = query.apply(accounts.clone()).unwrap();
+            seen.extend(page.accounts.into_iter().map(|account| account.id));
+
+            match page.next_cursor {
+                Some(cursor) => query.cursor = Some(cursor),
+                None => break,
+            }
+        }
+
+        assert_eq!(seen, vec!["5", "2", "4", "1", "6", "3", "0"]);
+    }
+
+    #[test]
+    fn filters_by_label() {
+        let accounts = vec![
+            account("a", 0, "Savings"),
+            account("b", 0, "spending"),
+            account("c", 0, "50%"),
+        ];
+
+        let query = AccountQuery {
+            label: Some("SAV".to_string()),
+            ..Default::default()
+        };
+        let page = query.apply(accounts).unwrap();
+        assert_eq!(page.accounts.len(), 1);
+        assert_eq!(page.accounts[0].id, "a");
+
+        let query = AccountQuery {
+            label: Some("0%".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(query.label_pattern().unwrap(), "%0\\%%");
+    }
+
+    #[test]
+    fn rejects_cursors_of_other_sorts() {
+        let cursor = AccountQuery::encode_cursor(&AccountSort::Position.key(&account("a", 1, "")));
+
+        let query = AccountQuery {
+            sort: AccountSort::Label,
+            cursor: Some(cursor.clone()),
+            ..Default::default()
+        };
+        assert!(matches!(query.decode_cursor(), Err(VaultError::Parser(_))));
+
+        let query = AccountQuery {
+            cursor: Some("zz".to_string()),
+            ..Default::default()
+        };
+        assert!(query.decode_cursor().is_err());
+
+        let query = AccountQuery {
+            cursor: Some(cursor),
+            ..Default::default()
+        };
+        assert!(query.decode_cursor().unwrap().is_some());
+    }
+}
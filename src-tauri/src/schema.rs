@@ -0,0 +1,66 @@
+use sqlx::migrate::Migrator;
+
+use crate::vault_interface::{VaultError, VaultResult};
+
+/// Latest migration this version of the app ships.
+pub fn latest_version(migrator: &Migrator) -> i64 {
+    migrator
+        .iter()
+        .map(|migration| migration.version)
+        .max()
+        .unwrap_or_default()
+}
+
+/// Latest migration applied to the database, 0 for a blank one.
+pub fn current_version(applied: &[i64]) -> i64 {
+    applied.iter().copied().max().unwrap_or_default()
+}
+
+/// Whether migrating would change anything.
+pub fn has_pending(applied: &[i64], migrator: &Migrator) -> bool {
+    migrator
+        .iter()
+        .any(|migration| !applied.contains(&migration.version))
+}
+
+/// Refuses a database migrated by a newer version of the app: its schema may hold anything,
+/// and migrations only ever go forward.
+pub fn check_version(applied: &[i64], migrator: &Migrator) -> VaultResult<()> {
+    let current = current_version(applied);
+    let latest = latest_version(migrator);
+
+    if current > latest {
+        return Err(VaultError::SchemaTooNew(current, latest));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static MIGRATOR: Migrator = sqlx::migrate!();
+
+    #[test]
+    fn refuses_newer_schemas() {
+        let latest = latest_version(&MIGRATOR);
+        assert!(latest > 0);
+
+        assert!(check_version(&[], &MIGRATOR).is_ok());
+        assert!(check_version(&[latest], &MIGRATOR).is_ok());
+        assert!(matches!(
+            check_version(&[latest, latest + 1], &MIGRATOR),
+            Err(VaultError::SchemaTooNew(found, supported)) if found == latest + 1 && supported == latest
+        ));
+    }
+
+    #[test]
+    fn finds_pending_migrations() {
+        let applied: Vec<i64> = MIGRATOR.iter().map(|migration| migration.version).collect();
+
+        assert!(has_pending(&[], &MIGRATOR));
+        assert!(has_pending(&applied[..1], &MIGRATOR));
+        assert!(!has_pending(&applied, &MIGRATOR));
+    }
+}
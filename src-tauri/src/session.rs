@@ -0,0 +1,214 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use thiserror::Error;
+use tokio::sync::Mutex;
+use zeroize::Zeroizing;
+
+use crate::utils::AESKey;
+
+/// How long a session may stay unused before the wallet re-locks.
+pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Error, Debug)]
+pub enum SessionError {
+    #[error("Session not found: {0}")]
+    NotFound(String),
+    #[error("Session expired: {0}")]
+    Expired(String),
+    #[error("Session {0} does not belong to wallet {1}")]
+    WalletMismatch(String, String),
+}
+
+pub type SessionResult<T> = Result<T, SessionError>;
+
+struct Session {
+    wallet_id: String,
+    // Boxed so the key is never moved around in memory, and zeroed when dropped.
+    key: Box<Zeroizing<AESKey>>,
+    last_used: Instant,
+}
+
+impl Session {
+    fn is_expired(&self, idle_timeout: Duration) -> bool {
+        self.last_used.elapsed() >= idle_timeout
+    }
+}
+
+/// Keeps the unwrapped wallet keys of authenticated wallets behind opaque session ids.
+///
+/// A session is dropped (and its key zeroed) when it is locked explicitly, when its
+/// wallet is removed, or once it has been idle for longer than the configured timeout.
+pub struct SessionManager {
+    sessions: Mutex<HashMap<String, Session>>,
+    idle_timeout: Duration,
+}
+
+impl Default for SessionManager {
+    fn default() -> Self {
+        Self::new(DEFAULT_IDLE_TIMEOUT)
+    }
+}
+
+impl SessionManager {
+    pub fn new(idle_timeout: Duration) -> Self {
+        Self {
+            sessions: Mutex::new(HashMap::new()),
+            idle_timeout,
+        }
+    }
+
+    pub fn idle_timeout(&self) -> Duration {
+        self.idle_timeout
+    }
+
+    /// Stores the key for the given wallet and returns the id of the new session.
+    pub async fn open(&self, wallet_id: &str, key: AESKey) -> String {
+        let id = uuid::Uuid::new_v4().to_string();
+        let session = Session {
+            wallet_id: wallet_id.to_string(),
+            key: Box::new(Zeroizing::new(key)),
+            last_used: Instant::now(),
+        };
+        self.sessions.lock().await.insert(id.clone(), session);
+        id
+    }
+
+    /// Returns the key held by the session, provided it is still alive and belongs to
+    /// `wallet_id`. Every successful lookup resets the idle timer. The copy handed out is zeroed
+    /// when dropped as well.
+    pub async fn key(&self, session_id: &str, wallet_id: &str) -> SessionResult<Zeroizing<AESKey>> {
+        let mut sessions = self.sessions.lock().await;
+        let session = sessions.get_mut(session_id);
+
+        if session.is_none() {
+            return Err(SessionError::NotFound(session_id.to_string()));
+        }
+
+        let session = session.unwrap();
+
+        if session.is_expired(self.idle_timeout) {
+            sessions.remove(session_id);
+            return Err(SessionError::Expired(session_id.to_string()));
+        }
+
+        if session.wallet_id != wallet_id {
+            return Err(SessionError::WalletMismatch(
+                session_id.to_string(),
+                wallet_id.to_string(),
+            ));
+        }
+
+        session.last_used = Instant::now();
+        Ok(Zeroizing::new(**session.key))
+    }
+
+    /// Returns the wallet id the session was opened for.
+    pub async fn wallet_id(&self, session_id: &str) -> SessionResult<String> {
+        let sessions = self.sessions.lock().await;
+        let session = sessions.get(session_id);
+
+        if session.is_none() {
+            return Err(SessionError::NotFound(session_id.to_string()));
+        }
+
+        let session = session.unwrap();
+
+        if session.is_expired(self.idle_timeout) {
+            return Err(SessionError::Expired(session_id.to_string()));
+        }
+
+        Ok(session.wallet_id.clone())
+    }
+
+    /// Drops the session, returns `false` if there was nothing to lock.
+    pub async fn lock(&self, session_id: &str) -> bool {
+        self.sessions.lock().await.remove(session_id).is_some()
+    }
+
+    /// Drops every session opened for the given wallet.
+    pub async fn lock_wallet(&self, wallet_id: &str) {
+        self.sessions
+            .lock()
+            .await
+            .retain(|_, session| session.wallet_id != wallet_id);
+    }
+
+    /// Drops every session, e.g. when switching to a vault holding other wallets.
+    pub async fn lock_all(&self) {
+        self.sessions.lock().await.clear();
+    }
+
+    /// Drops all the sessions which have been idle for too long, returns how many were dropped.
+    pub async fn purge_expired(&self) -> usize {
+        let mut sessions = self.sessions.lock().await;
+        let before = sessions.len();
+        let idle_timeout = self.idle_timeout;
+        sessions.retain(|_, session| !session.is_expired(idle_timeout));
+        before - sessions.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn can_open_and_lock_session() {
+        let sessions = SessionManager::default();
+        let key = [7u8; 32];
+        let id = sessions.open("wallet", key).await;
+
+        assert_eq!(*sessions.key(&id, "wallet").await.unwrap(), key);
+        assert_eq!(sessions.wallet_id(&id).await.unwrap(), "wallet");
+
+        assert!(sessions.lock(&id).await);
+        assert!(matches!(
+            sessions.key(&id, "wallet").await,
+            Err(SessionError::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn rejects_session_of_another_wallet() {
+        let sessions = SessionManager::default();
+        let id = sessions.open("wallet", [1u8; 32]).await;
+
+        assert!(matches!(
+            sessions.key(&id, "other").await,
+            Err(SessionError::WalletMismatch(_, _))
+        ));
+    }
+
+    #[tokio::test]
+    async fn expires_idle_sessions() {
+        let sessions = SessionManager::new(Duration::from_millis(10));
+        let id = sessions.open("wallet", [1u8; 32]).await;
+        let other = sessions.open("wallet", [1u8; 32]).await;
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        assert!(matches!(
+            sessions.key(&id, "wallet").await,
+            Err(SessionError::Expired(_))
+        ));
+        assert_eq!(sessions.purge_expired().await, 1);
+        assert!(!sessions.lock(&other).await);
+    }
+
+    #[tokio::test]
+    async fn can_lock_all_sessions_of_wallet() {
+        let sessions = SessionManager::default();
+        let first = sessions.open("wallet", [1u8; 32]).await;
+        let second = sessions.open("wallet", [1u8; 32]).await;
+        let other = sessions.open("other", [1u8; 32]).await;
+
+        sessions.lock_wallet("wallet").await;
+
+        assert!(sessions.key(&first, "wallet").await.is_err());
+        assert!(sessions.key(&second, "wallet").await.is_err());
+        assert!(sessions.key(&other, "other").await.is_ok());
+    }
+}
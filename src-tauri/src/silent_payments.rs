@@ -0,0 +1,1227 @@
+use bitcoin::{
+    bech32::{
+        primitives::{
+            decode::CheckedHrpstring,
+            iter::{ByteIterExt, Fe32IterExt},
+        },
+        Bech32m, Fe32, Hrp,
+    },
+    bip32::{ChildNumber, DerivationPath, KeySource, Xpriv},
+    consensus::{deserialize, serialize},
+    hashes::{hash160, sha256, Hash, HashEngine},
+    hex::{DisplayHex, FromHex},
+    key::{Keypair, Parity, Secp256k1, TapTweak, TweakedPublicKey, XOnlyPublicKey},
+    script::Instruction,
+    secp256k1::{PublicKey, Scalar, SecretKey, Signing, Verification},
+    Amount, NetworkKind, OutPoint, Psbt, ScriptBuf, Transaction, TxIn, TxOut,
+};
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+use thiserror::Error;
+
+use crate::{account::Network, taproot::NUMS_KEY};
+
+/// Purpose of the BIP-352 paths, `m/352'/coin'/account'/1'/0` for the scan key and
+/// `m/352'/coin'/account'/0'/0` for the spend key.
+pub const PURPOSE: u32 = 352;
+
+/// Label reserved for change, never handed out but always scanned for.
+pub const CHANGE_LABEL: u32 = 0;
+
+const MAINNET_HRP: &str = "sp";
+const TESTNET_HRP: &str = "tsp";
+
+#[derive(Error, Debug)]
+pub enum SilentPaymentError {
+    #[error("Invalid silent payment address: {0}")]
+    Address(String),
+    #[error("Silent payment address of another network, expected {0}")]
+    WrongNetwork(Network),
+    #[error("Label 0 is reserved for change")]
+    ChangeLabel,
+    #[error("Failed deriving the silent payment keys: {0}")]
+    Derivation(String),
+    #[error("Invalid transaction: {0}")]
+    Transaction(String),
+    #[error("Input {0} spends an output of a future segwit version")]
+    FutureSegwit(usize),
+    #[error("Input {0} can't be signed by this wallet, every eligible input has to be")]
+    MissingInputKey(usize),
+    #[error("No input spends a P2TR, P2WPKH, P2SH-P2WPKH or P2PKH output")]
+    NoEligibleInputs,
+    #[error("Failed tweaking the keys: {0}")]
+    Tweak(String),
+}
+
+pub type SilentPaymentResult<T> = Result<T, SilentPaymentError>;
+
+/// SHA256 of `data` prefixed twice with the hash of `tag`, as BIP-340 defines.
+fn tagged_hash(tag: &str, data: &[&[u8]]) -> [u8; 32] {
+    let tag = sha256::Hash::hash(tag.as_bytes());
+    let mut engine = sha256::Hash::engine();
+    engine.input(tag.as_ref());
+    engine.input(tag.as_ref());
+
+    for bytes in data {
+        engine.input(bytes);
+    }
+
+    sha256::Hash::from_engine(engine).to_byte_array()
+}
+
+fn scalar(hash: [u8; 32]) -> SilentPaymentResult<Scalar> {
+    Scalar::from_be_bytes(hash).map_err(|err| SilentPaymentError::Tweak(err.to_string()))
+}
+
+fn tweak_err(err: bitcoin::secp256k1::Error) -> SilentPaymentError {
+    SilentPaymentError::Tweak(err.to_string())
+}
+
+/// Commits to the transaction inputs, so the same keys paying twice give other outputs.
+fn input_hash(outpoints: &[OutPoint], sum: &PublicKey) -> SilentPaymentResult<Scalar> {
+    let smallest = outpoints
+        .iter()
+        .map(serialize)
+        .min()
+        .ok_or(SilentPaymentError::NoEligibleInputs)?;
+
+    scalar(tagged_hash(
+        "BIP0352/Inputs",
+        &[&smallest, &sum.serialize()],
+    ))
+}
+
+/// Tweak of the `k`th output paying the same scan key.
+fn shared_secret_tweak(secret: &PublicKey, k: u32) -> SilentPaymentResult<Scalar> {
+    scalar(tagged_hash(
+        "BIP0352/SharedSecret",
+        &[&secret.serialize(), &k.to_be_bytes()],
+    ))
+}
+
+fn label_tweak(scan: &SecretKey, label: u32) -> SilentPaymentResult<Scalar> {
+    scalar(tagged_hash(
+        "BIP0352/Label",
+        &[&scan.secret_bytes(), &label.to_be_bytes()],
+    ))
+}
+
+/// `sp1…` address, the scan key finding the payments and the spend key, maybe labeled,
+/// spending them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SilentPaymentAddress {
+    pub scan: PublicKey,
+    pub spend: PublicKey,
+    pub network: NetworkKind,
+}
+
+impl SilentPaymentAddress {
+    pub fn check_network(&self, network: Network) -> SilentPaymentResult<()> {
+        if self.network != network.to_bitcoin_network_kind() {
+            return Err(SilentPaymentError::WrongNetwork(network));
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for SilentPaymentAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let hrp = match self.network {
+            NetworkKind::Main => Hrp::parse_unchecked(MAINNET_HRP),
+            NetworkKind::Test => Hrp::parse_unchecked(TESTNET_HRP),
+        };
+        let mut data = self.scan.serialize().to_vec();
+        data.extend(self.spend.serialize());
+
+        let chars = data
+            .into_iter()
+            .bytes_to_fes()
+            .with_checksum::<Bech32m>(&hrp)
+            .with_witness_version(Fe32::Q)
+            .chars();
+
+        for c in chars {
+            write!(f, "{c}")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for SilentPaymentAddress {
+    type Err = SilentPaymentError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parsed = CheckedHrpstring::new::<Bech32m>(s);
+
+        if let Err(err) = parsed {
+            return Err(SilentPaymentError::Address(err.to_string()));
+        }
+
+        let mut parsed = parsed.unwrap();
+        let network = match parsed.hrp().to_lowercase().as_str() {
+            MAINNET_HRP => NetworkKind::Main,
+            TESTNET_HRP => NetworkKind::Test,
+            hrp => return Err(SilentPaymentError::Address(format!("unknown prefix {hrp}"))),
+        };
+
+        let version = match parsed.remove_witness_version() {
+            Some(version) => version.to_u8(),
+            None => return Err(SilentPaymentError::Address("no version".to_string())),
+        };
+        let data: Vec<u8> = parsed.byte_iter().collect();
+
+        // Later versions may append data, their first 66 bytes still being the keys.
+        let keys = match version {
+            0 if data.len() == 66 => &data[..],
+            1..=30 if data.len() >= 66 => &data[..66],
+            31 => return Err(SilentPaymentError::Address("version 31".to_string())),
+            _ => return Err(SilentPaymentError::Address("invalid length".to_string())),
+        };
+
+        let scan = PublicKey::from_slice(&keys[..33]);
+        let spend = PublicKey::from_slice(&keys[33..]);
+
+        match (scan, spend) {
+            (Ok(scan), Ok(spend)) => Ok(SilentPaymentAddress {
+                scan,
+                spend,
+                network,
+            }),
+            _ => Err(SilentPaymentError::Address("invalid key".to_string())),
+        }
+    }
+}
+
+/// Output of a candidate transaction paying the wallet. Its key is the spend key plus
+/// `tweak`, to be stored along with the outpoint to spend it later.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ReceivedOutput {
+    pub vout: u32,
+    pub value: u64,
+    pub output_key: String,
+    pub tweak: String,
+    pub label: Option<u32>,
+}
+
+/// Scan and spend keys of an account of the wallet.
+#[derive(Debug, Clone, Copy)]
+pub struct SilentPaymentKeys {
+    pub scan: SecretKey,
+    pub spend: SecretKey,
+    pub network: Network,
+}
+
+impl SilentPaymentKeys {
+    pub fn derive(seed: &[u8], network: Network, account: u32) -> SilentPaymentResult<Self> {
+        let secp = Secp256k1::new();
+        let master = Xpriv::new_master(network.to_bitcoin_network_kind(), seed);
+
+        if let Err(err) = master {
+            return Err(SilentPaymentError::Derivation(err.to_string()));
+        }
+
+        let master = master.unwrap();
+        let coin = match network {
+            Network::Mainnet => 0,
+            _ => 1,
+        };
+
+        let key = |chain: u32| {
+            let path: Result<Vec<ChildNumber>, _> = [
+                ChildNumber::from_hardened_idx(PURPOSE),
+                ChildNumber::from_hardened_idx(coin),
+                ChildNumber::from_hardened_idx(account),
+                ChildNumber::from_hardened_idx(chain),
+                ChildNumber::from_normal_idx(0),
+            ]
+            .into_iter()
+            .collect();
+
+            path.and_then(|path| master.derive_priv(&secp, &DerivationPath::from(path)))
+                .map(|key| key.private_key)
+                .map_err(|err| SilentPaymentError::Derivation(err.to_string()))
+        };
+
+        Ok(SilentPaymentKeys {
+            scan: key(1)?,
+            spend: key(0)?,
+            network,
+        })
+    }
+
+    /// Address to hand out, a labeled one telling apart who paid without another scan key.
+    pub fn address(&self, label: Option<u32>) -> SilentPaymentResult<SilentPaymentAddress> {
+        let secp = Secp256k1::new();
+        let mut spend = self.spend.public_key(&secp);
+
+        match label {
+            Some(CHANGE_LABEL) => return Err(SilentPaymentError::ChangeLabel),
+            Some(label) => {
+                spend = spend
+                    .add_exp_tweak(&secp, &label_tweak(&self.scan, label)?)
+                    .map_err(tweak_err)?;
+            }
+            None => {}
+        }
+
+        Ok(SilentPaymentAddress {
+            scan: self.scan.public_key(&secp),
+            spend,
+            network: self.network.to_bitcoin_network_kind(),
+        })
+    }
+
+    /// Taproot outputs of `tx` paying the wallet, `prevouts` being the outputs its inputs
+    /// spend. Payments to `labels` are found too, along with change.
+    pub fn scan(
+        &self,
+        tx: &Transaction,
+        prevouts: &[TxOut],
+        labels: &[u32],
+    ) -> SilentPaymentResult<Vec<ReceivedOutput>> {
+        if prevouts.len() != tx.input.len() {
+            return Err(SilentPaymentError::Transaction(format!(
+                "{} inputs but {} prevouts",
+                tx.input.len(),
+                prevouts.len()
+            )));
+        }
+
+        let secp = Secp256k1::new();
+        let mut keys = vec![];
+
+        for (txin, prevout) in tx.input.iter().zip(prevouts) {
+            if future_segwit(&prevout.script_pubkey) {
+                return Ok(vec![]);
+            }
+
+            if let Some(key) = input_public_key(txin, prevout) {
+                keys.push(key);
+            }
+        }
+
+        if keys.is_empty() {
+            return Ok(vec![]);
+        }
+
+        // Keys adding up to nothing can't have been used to pay anyone.
+        let Ok(sum) = PublicKey::combine_keys(&keys.iter().collect::<Vec<_>>()) else {
+            return Ok(vec![]);
+        };
+
+        let outpoints: Vec<OutPoint> = tx.input.iter().map(|txin| txin.previous_output).collect();
+        let secret = sum
+            .mul_tweak(&secp, &input_hash(&outpoints, &sum)?)
+            .and_then(|sum| sum.mul_tweak(&secp, &Scalar::from(self.scan)))
+            .map_err(tweak_err)?;
+
+        let mut labeled = vec![];
+        for label in std::iter::once(CHANGE_LABEL).chain(labels.iter().copied()) {
+            let tweak = label_tweak(&self.scan, label)?;
+            let key = SecretKey::from_slice(&tweak.to_be_bytes()).map_err(tweak_err)?;
+            labeled.push((label, tweak, key.public_key(&secp)));
+        }
+
+        let mut outputs: Vec<(u32, &TxOut, XOnlyPublicKey)> = tx
+            .output
+            .iter()
+            .enumerate()
+            .filter(|(_, output)| output.script_pubkey.is_p2tr())
+            .filter_map(|(vout, output)| {
+                let key = XOnlyPublicKey::from_slice(&output.script_pubkey.as_bytes()[2..]);
+                key.ok().map(|key| (vout as u32, output, key))
+            })
+            .collect();
+
+        let spend = self.spend.public_key(&secp);
+        let mut found = vec![];
+
+        for k in 0.. {
+            let tweak = shared_secret_tweak(&secret, k)?;
+            let expected = spend.add_exp_tweak(&secp, &tweak).map_err(tweak_err)?;
+            let negated = expected.negate(&secp);
+
+            let matched = outputs.iter().enumerate().find_map(|(i, (_, _, key))| {
+                if *key == expected.x_only_public_key().0 {
+                    return Some((i, None));
+                }
+
+                // The output is the expected key plus a label, the x-only key hiding which of
+                // the two points it was.
+                [Parity::Even, Parity::Odd].iter().find_map(|parity| {
+                    let difference = key.public_key(*parity).combine(&negated).ok()?;
+                    labeled
+                        .iter()
+                        .find(|(_, _, key)| *key == difference)
+                        .map(|(label, tweak, _)| (i, Some((*label, *tweak))))
+                })
+            });
+
+            let Some((i, label)) = matched else { break };
+            let (vout, output, key) = outputs.remove(i);
+
+            let mut total = SecretKey::from_slice(&tweak.to_be_bytes()).map_err(tweak_err)?;
+            if let Some((_, label_tweak)) = label {
+                total = total.add_tweak(&label_tweak).map_err(tweak_err)?;
+            }
+
+            found.push(ReceivedOutput {
+                vout,
+                value: output.value.to_sat(),
+                output_key: key.to_string(),
+                tweak: total.secret_bytes().to_lower_hex_string(),
+                label: label.map(|(label, _)| label),
+            });
+        }
+
+        Ok(found)
+    }
+}
+
+fn future_segwit(script: &ScriptBuf) -> bool {
+    script
+        .witness_version()
+        .is_some_and(|version| version.to_num() > 1)
+}
+
+/// Key of an input counting towards the shared secret, BIP-352 only using those it can read
+/// from P2TR, P2WPKH, P2SH-P2WPKH and P2PKH spends.
+fn input_public_key(txin: &TxIn, prevout: &TxOut) -> Option<PublicKey> {
+    let script = &prevout.script_pubkey;
+
+    if script.is_p2tr() {
+        let mut witness: Vec<&[u8]> = txin.witness.iter().collect();
+
+        if witness.len() > 1
+            && witness
+                .last()
+                .is_some_and(|last| last.first() == Some(&0x50))
+        {
+            witness.pop();
+        }
+
+        // Script paths of trees with no key path don't reveal a key of the sender.
+        if witness.len() > 1 {
+            let control_block = witness.last()?;
+            let nums = Vec::from_hex(NUMS_KEY).ok()?;
+            if control_block.get(1..33) == Some(&nums[..]) {
+                return None;
+            }
+        }
+
+        return XOnlyPublicKey::from_slice(&script.as_bytes()[2..])
+            .ok()
+            .map(|key| key.public_key(Parity::Even));
+    }
+
+    if script.is_p2wpkh() {
+        return compressed_key(txin.witness.last()?);
+    }
+
+    if script.is_p2sh() {
+        let mut pushes = txin.script_sig.instructions();
+        let redeem_script = match (pushes.next(), pushes.next()) {
+            (Some(Ok(Instruction::PushBytes(bytes))), None) => {
+                ScriptBuf::from(bytes.as_bytes().to_vec())
+            }
+            _ => return None,
+        };
+
+        if redeem_script.is_p2wpkh() {
+            return compressed_key(txin.witness.last()?);
+        }
+
+        return None;
+    }
+
+    if script.is_p2pkh() {
+        // The key is the last 33 bytes hashing to the address, whatever pushes the signature
+        // script is made of.
+        let hash = &script.as_bytes()[3..23];
+        let bytes = txin.script_sig.as_bytes();
+
+        return (33..=bytes.len())
+            .rev()
+            .map(|end| &bytes[end - 33..end])
+            .find(|key| hash160::Hash::hash(key).as_byte_array() == hash)
+            .and_then(compressed_key);
+    }
+
+    None
+}
+
+fn compressed_key(bytes: &[u8]) -> Option<PublicKey> {
+    match bytes.len() {
+        33 => PublicKey::from_slice(bytes).ok(),
+        _ => None,
+    }
+}
+
+/// Keys of the outputs paying `recipients` in order, `keys` being the private keys of the
+/// eligible inputs, those spending taproot outputs already negated to their even key, and
+/// `outpoints` what every input spends.
+pub fn output_keys(
+    keys: &[SecretKey],
+    outpoints: &[OutPoint],
+    recipients: &[SilentPaymentAddress],
+) -> SilentPaymentResult<Vec<XOnlyPublicKey>> {
+    let secp = Secp256k1::new();
+    let mut keys = keys.iter();
+    let mut sum = *keys.next().ok_or(SilentPaymentError::NoEligibleInputs)?;
+
+    for key in keys {
+        sum = sum.add_tweak(&Scalar::from(*key)).map_err(tweak_err)?;
+    }
+
+    let input_hash = input_hash(outpoints, &sum.public_key(&secp))?;
+    let sum = sum.mul_tweak(&input_hash).map_err(tweak_err)?;
+    let mut outputs = vec![];
+
+    for (i, recipient) in recipients.iter().enumerate() {
+        // Outputs to the same scan key share a secret, each taking the next index.
+        let k = recipients[..i]
+            .iter()
+            .filter(|other| other.scan == recipient.scan)
+            .count();
+        let secret = recipient
+            .scan
+            .mul_tweak(&secp, &Scalar::from(sum))
+            .map_err(tweak_err)?;
+        let key = recipient
+            .spend
+            .add_exp_tweak(&secp, &shared_secret_tweak(&secret, k as u32)?)
+            .map_err(tweak_err)?;
+
+        outputs.push(key.x_only_public_key().0);
+    }
+
+    Ok(outputs)
+}
+
+fn derive_key<C: Signing>(
+    master: &Xpriv,
+    source: &KeySource,
+    secp: &Secp256k1<C>,
+) -> Option<SecretKey> {
+    if source.0 != master.fingerprint(secp) {
+        return None;
+    }
+
+    master
+        .derive_priv(secp, &source.1)
+        .ok()
+        .map(|key| key.private_key)
+}
+
+/// Private key of a PSBT input as it counts towards the shared secret, `None` when the input
+/// doesn't count.
+fn input_secret_key<C: Signing + Verification>(
+    psbt: &Psbt,
+    index: usize,
+    prevout: &TxOut,
+    master: &Xpriv,
+    secp: &Secp256k1<C>,
+) -> SilentPaymentResult<Option<SecretKey>> {
+    let input = &psbt.inputs[index];
+    let script = &prevout.script_pubkey;
+
+    if script.is_p2tr() {
+        let Some(internal_key) = input.tap_internal_key else {
+            return Err(SilentPaymentError::MissingInputKey(index));
+        };
+
+        // Trees with no key path are spent through a script, which doesn't reveal a key.
+        if XOnlyPublicKey::from_str(NUMS_KEY).is_ok_and(|nums| nums == internal_key) {
+            return Ok(None);
+        }
+
+        let secret = input
+            .tap_key_origins
+            .get(&internal_key)
+            .filter(|(leaves, _)| leaves.is_empty())
+            .and_then(|(_, source)| derive_key(master, source, secp))
+            .ok_or(SilentPaymentError::MissingInputKey(index))?;
+
+        let keypair = Keypair::from_secret_key(secp, &secret)
+            .tap_tweak(secp, input.tap_merkle_root)
+            .to_keypair();
+
+        // The receiver only sees the x-only output key, the even point.
+        return Ok(Some(match keypair.x_only_public_key().1 {
+            Parity::Even => keypair.secret_key(),
+            Parity::Odd => keypair.secret_key().negate(),
+        }));
+    }
+
+    let eligible = script.is_p2wpkh()
+        || script.is_p2pkh()
+        || (script.is_p2sh()
+            && match &input.redeem_script {
+                Some(redeem_script) => redeem_script.is_p2wpkh(),
+                None => {
+                    return Err(SilentPaymentError::Transaction(format!(
+                        "input {index} lacks its redeem script"
+                    )))
+                }
+            });
+
+    if !eligible {
+        return Ok(None);
+    }
+
+    let secret = input
+        .bip32_derivation
+        .iter()
+        .find_map(|(key, source)| {
+            derive_key(master, source, secp).filter(|secret| secret.public_key(secp) == *key)
+        })
+        .ok_or(SilentPaymentError::MissingInputKey(index))?;
+
+    // Uncompressed P2PKH keys don't count, the compressed one not hashing to the address.
+    if script.is_p2pkh()
+        && *script
+            != ScriptBuf::new_p2pkh(&bitcoin::PublicKey::new(secret.public_key(secp)).pubkey_hash())
+    {
+        return Ok(None);
+    }
+
+    Ok(Some(secret))
+}
+
+/// Silent payment output added to a PSBT.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SentOutput {
+    pub address: String,
+    pub vout: usize,
+    pub output_key: String,
+    pub amount: u64,
+}
+
+/// Recipient of a silent payment, as given when building the transaction.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SilentPaymentRecipient {
+    pub address: String,
+    pub amount: u64,
+}
+
+/// Appends the outputs paying `recipients` to the PSBT. The keys of the outputs depend on
+/// every input, which can't change afterwards, and on the keys of those the sender can read
+/// a key from, which `master` has to hold per their key origins.
+pub fn add_outputs(
+    psbt: &mut Psbt,
+    master: &Xpriv,
+    network: Network,
+    recipients: &[SilentPaymentRecipient],
+) -> SilentPaymentResult<Vec<SentOutput>> {
+    let mut addresses = vec![];
+
+    for recipient in recipients {
+        let address = SilentPaymentAddress::from_str(&recipient.address)?;
+        address.check_network(network)?;
+        addresses.push(address);
+    }
+
+    let secp = Secp256k1::new();
+    let mut keys = vec![];
+    let mut outpoints = vec![];
+
+    for i in 0..psbt.inputs.len() {
+        let outpoint = psbt.unsigned_tx.input[i].previous_output;
+        let prevout = match (
+            &psbt.inputs[i].witness_utxo,
+            &psbt.inputs[i].non_witness_utxo,
+        ) {
+            (Some(utxo), _) => Some(utxo.clone()),
+            (None, Some(tx)) => tx.output.get(outpoint.vout as usize).cloned(),
+            _ => None,
+        };
+
+        let Some(prevout) = prevout else {
+            return Err(SilentPaymentError::Transaction(format!(
+                "input {i} lacks the output it spends"
+            )));
+        };
+
+        if future_segwit(&prevout.script_pubkey) {
+            return Err(SilentPaymentError::FutureSegwit(i));
+        }
+
+        if let Some(key) = input_secret_key(psbt, i, &prevout, master, &secp)? {
+            keys.push(key);
+        }
+        outpoints.push(outpoint);
+    }
+
+    let output_keys = output_keys(&keys, &outpoints, &addresses)?;
+    let mut sent = vec![];
+
+    for ((recipient, address), key) in recipients.iter().zip(addresses).zip(output_keys) {
+        psbt.unsigned_tx.output.push(TxOut {
+            value: Amount::from_sat(recipient.amount),
+            script_pubkey: ScriptBuf::new_p2tr_tweaked(TweakedPublicKey::dangerous_assume_tweaked(
+                key,
+            )),
+        });
+        psbt.outputs.push(Default::default());
+
+        sent.push(SentOutput {
+            address: address.to_string(),
+            vout: psbt.unsigned_tx.output.len() - 1,
+            output_key: key.to_string(),
+            amount: recipient.amount,
+        });
+    }
+
+    Ok(sent)
+}
+
+/// Spent output of a candidate transaction, as the chain backend knows it.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Prevout {
+    pub value: u64,
+    pub script_pubkey: String,
+}
+
+/// Transaction a chain backend flagged as possibly paying silent payments, with the outputs
+/// its inputs spend, which scanning needs the scripts of.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CandidateTransaction {
+    pub tx: String,
+    pub prevouts: Vec<Prevout>,
+}
+
+impl CandidateTransaction {
+    pub fn parse(&self) -> SilentPaymentResult<(Transaction, Vec<TxOut>)> {
+        let tx = Vec::from_hex(&self.tx)
+            .map_err(|err| err.to_string())
+            .and_then(|bytes| deserialize(&bytes).map_err(|err| err.to_string()));
+
+        if let Err(err) = tx {
+            return Err(SilentPaymentError::Transaction(err));
+        }
+
+        let mut prevouts = vec![];
+        for prevout in self.prevouts.iter() {
+            let script = ScriptBuf::from_hex(&prevout.script_pubkey);
+
+            if let Err(err) = script {
+                return Err(SilentPaymentError::Transaction(err.to_string()));
+            }
+
+            prevouts.push(TxOut {
+                value: Amount::from_sat(prevout.value),
+                script_pubkey: script.unwrap(),
+            });
+        }
+
+        Ok((tx.unwrap(), prevouts))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::{
+        absolute::LockTime, hashes::Hash, script::PushBytesBuf, transaction::Version, Sequence,
+        Txid, Witness, WitnessProgram, WitnessVersion,
+    };
+
+    const ADDRESS: &str = "sp1qqgste7k9hx0qftg6qmwlkqtwuy6cycyavzmzj85c6qdfhjdpdjtdgqjuexzk6murw56suy3e0rd2cgqvycxttddwsvgxe2usfpxumr70xc9pkqwv";
+
+    fn secret(hex: &str) -> SecretKey {
+        SecretKey::from_str(hex).unwrap()
+    }
+
+    /// Keys and address of the first BIP-352 test vector.
+    #[test]
+    fn encodes_bip352_addresses() {
+        let keys = SilentPaymentKeys {
+            scan: secret("0f694e068028a717f8af6b9411f9a133dd3565258714cc226594b34db90c1f2c"),
+            spend: secret("9d6ad855ce3417ef84e836892e5a56392bfba05fa5d97ccea30e266f540e08b3"),
+            network: Network::Mainnet,
+        };
+        let address = keys.address(None).unwrap();
+        assert_eq!(address.to_string(), ADDRESS);
+        assert_eq!(SilentPaymentAddress::from_str(ADDRESS).unwrap(), address);
+        assert_eq!(
+            SilentPaymentAddress::from_str(&ADDRESS.to_uppercase()).unwrap(),
+            address
+        );
+        assert!(address.check_network(Network::Mainnet).is_ok());
+        assert!(matches!(
+            address.check_network(Network::Regtest),
+            Err(SilentPaymentError::WrongNetwork(_))
+        ));
+
+        let testnet = SilentPaymentKeys {
+            network: Network::Signet,
+            ..keys
+        };
+        assert!(testnet
+            .address(None)
+            .unwrap()
+            .to_string()
+            .starts_with("tsp1q"));
+
+        // Labels change the spend key only.
+        let labeled = keys.address(Some(1)).unwrap();
+        assert_eq!(labeled.scan, address.scan);
+        assert_ne!(labeled.spend, address.spend);
+        assert!(matches!(
+            keys.address(Some(CHANGE_LABEL)),
+            Err(SilentPaymentError::ChangeLabel)
+        ));
+
+        let mut corrupted = ADDRESS.to_string();
+        corrupted.replace_range(10..11, "z");
+        assert!(SilentPaymentAddress::from_str(&corrupted).is_err());
+        // Version 31 is `l`, reserved for a backward incompatible change.
+        let hrp = Hrp::parse_unchecked(MAINNET_HRP);
+        let data = [address.scan.serialize(), address.spend.serialize()].concat();
+        for (version, valid) in [(Fe32::L, false), (Fe32::P, true), (Fe32::Q, true)] {
+            let encoded: String = data
+                .iter()
+                .copied()
+                .bytes_to_fes()
+                .with_checksum::<Bech32m>(&hrp)
+                .with_witness_version(version)
+                .chars()
+                .collect();
+            assert_eq!(SilentPaymentAddress::from_str(&encoded).is_ok(), valid);
+        }
+        assert!(SilentPaymentAddress::from_str(
+            "bc1p2wsldez5mud2yam29q22wgfh9439spgduvct83k3pm50fcxa5dps59h4z5"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn derives_keys_at_bip352_paths() {
+        let secp = Secp256k1::new();
+        let seed = [7u8; 64];
+        let keys = SilentPaymentKeys::derive(&seed, Network::Regtest, 2).unwrap();
+        let master = Xpriv::new_master(NetworkKind::Test, &seed).unwrap();
+        let derive = |path: &str| {
+            master
+                .derive_priv(&secp, &DerivationPath::from_str(path).unwrap())
+                .unwrap()
+                .private_key
+        };
+
+        assert_eq!(keys.scan, derive("m/352'/1'/2'/1'/0"));
+        assert_eq!(keys.spend, derive("m/352'/1'/2'/0'/0"));
+
+        let mainnet = SilentPaymentKeys::derive(&seed, Network::Mainnet, 2).unwrap();
+        assert_eq!(mainnet.scan, derive("m/352'/0'/2'/1'/0"));
+    }
+
+    /// Inputs of every eligible kind, plus a P2WSH one which doesn't count, with the key
+    /// origins and witnesses the sender and the receiver read the keys from.
+    struct Spend {
+        psbt: Psbt,
+        witnesses: Vec<(ScriptBuf, Witness)>,
+    }
+
+    fn spend(master: &Xpriv) -> Spend {
+        let secp = Secp256k1::new();
+        let fingerprint = master.fingerprint(&secp);
+        let mut inputs = vec![];
+        let mut prevouts = vec![];
+        let mut witnesses = vec![];
+        let signature = vec![0x30; 71];
+
+        for (i, kind) in ["wpkh", "tr", "sh-wpkh", "pkh", "wsh"].iter().enumerate() {
+            let path = DerivationPath::from_str(&format!("m/84'/1'/0'/0/{i}")).unwrap();
+            let secret = master.derive_priv(&secp, &path).unwrap().private_key;
+            let key = secret.public_key(&secp);
+            let compressed = bitcoin::CompressedPublicKey(key);
+            let mut input = bitcoin::psbt::Input::default();
+
+            let (script_pubkey, script_sig, witness) = match *kind {
+                "wpkh" => (
+                    ScriptBuf::new_p2wpkh(&compressed.wpubkey_hash()),
+                    ScriptBuf::new(),
+                    Witness::from_slice(&[signature.clone(), key.serialize().to_vec()]),
+                ),
+                "tr" => {
+                    let internal_key = key.x_only_public_key().0;
+                    input.tap_internal_key = Some(internal_key);
+                    input
+                        .tap_key_origins
+                        .insert(internal_key, (vec![], (fingerprint, path.clone())));
+                    (
+                        ScriptBuf::new_p2tr(&secp, internal_key, None),
+                        ScriptBuf::new(),
+                        Witness::from_slice(&[vec![0x01; 64]]),
+                    )
+                }
+                "sh-wpkh" => {
+                    let redeem_script = ScriptBuf::new_p2wpkh(&compressed.wpubkey_hash());
+                    input.redeem_script = Some(redeem_script.clone());
+                    let push = PushBytesBuf::try_from(redeem_script.to_bytes()).unwrap();
+                    (
+                        ScriptBuf::new_p2sh(&redeem_script.script_hash()),
+                        bitcoin::script::Builder::new()
+                            .push_slice(push)
+                            .into_script(),
+                        Witness::from_slice(&[signature.clone(), key.serialize().to_vec()]),
+                    )
+                }
+                "pkh" => (
+                    ScriptBuf::new_p2pkh(&bitcoin::PublicKey::new(key).pubkey_hash()),
+                    bitcoin::script::Builder::new()
+                        .push_slice(PushBytesBuf::try_from(signature.clone()).unwrap())
+                        .push_slice(key.serialize())
+                        .into_script(),
+                    Witness::new(),
+                ),
+                _ => {
+                    let script = ScriptBuf::new_p2pk(&bitcoin::PublicKey::new(key));
+                    (
+                        ScriptBuf::new_p2wsh(&script.wscript_hash()),
+                        ScriptBuf::new(),
+                        Witness::from_slice(&[signature.clone(), script.to_bytes()]),
+                    )
+                }
+            };
+
+            if *kind != "tr" {
+                input.bip32_derivation.insert(key, (fingerprint, path));
+            }
+
+            let prevout = TxOut {
+                value: Amount::from_sat(50_000),
+                script_pubkey,
+            };
+            input.witness_utxo = Some(prevout.clone());
+            inputs.push(input);
+            prevouts.push(prevout);
+            witnesses.push((script_sig, witness));
+        }
+
+        let tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: (0..inputs.len())
+                .map(|i| TxIn {
+                    previous_output: OutPoint::new(Txid::hash(&[i as u8]), i as u32),
+                    script_sig: ScriptBuf::new(),
+                    sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                    witness: Witness::new(),
+                })
+                .collect(),
+            output: vec![],
+        };
+
+        let mut psbt = Psbt::from_unsigned_tx(tx).unwrap();
+        psbt.inputs = inputs;
+        Spend { psbt, witnesses }
+    }
+
+    /// What the chain ends up with once the sender signed.
+    fn broadcast(spend: &Spend) -> (Transaction, Vec<TxOut>) {
+        let mut tx = spend.psbt.unsigned_tx.clone();
+        for (txin, (script_sig, witness)) in tx.input.iter_mut().zip(spend.witnesses.iter()) {
+            txin.script_sig = script_sig.clone();
+            txin.witness = witness.clone();
+        }
+
+        let prevouts = spend
+            .psbt
+            .inputs
+            .iter()
+            .map(|input| input.witness_utxo.clone().unwrap())
+            .collect();
+        (tx, prevouts)
+    }
+
+    fn recipient(address: &SilentPaymentAddress, amount: u64) -> SilentPaymentRecipient {
+        SilentPaymentRecipient {
+            address: address.to_string(),
+            amount,
+        }
+    }
+
+    #[test]
+    fn pays_and_finds_silent_payments() {
+        let secp = Secp256k1::new();
+        let sender = Xpriv::new_master(NetworkKind::Test, &[1u8; 64]).unwrap();
+        let receiver = SilentPaymentKeys::derive(&[2u8; 64], Network::Regtest, 0).unwrap();
+        let address = receiver.address(None).unwrap();
+        let labeled = receiver.address(Some(3)).unwrap();
+
+        let mut spend = spend(&sender);
+        let recipients = [
+            recipient(&address, 10_000),
+            recipient(&labeled, 20_000),
+            recipient(&address, 30_000),
+        ];
+        let sent = add_outputs(&mut spend.psbt, &sender, Network::Regtest, &recipients).unwrap();
+        assert_eq!(sent.len(), 3);
+        assert_eq!(spend.psbt.outputs.len(), 3);
+
+        // Paying the same address twice still gives distinct outputs. The keys were worked
+        // out apart from this module, with the arithmetic giving the outputs of the BIP-352
+        // vectors in `scans_bip352_vectors`.
+        let keys: Vec<&str> = sent.iter().map(|sent| sent.output_key.as_str()).collect();
+        assert_eq!(
+            keys,
+            [
+                "3406086cc7024aa0c7dc83d9de39d00735f5a5b2073437c48dd3a5127818c8c9",
+                "c040c19d03c9c2645109a4490778f9fe6b8146e0c7ed284bb4af25d56e045c07",
+                "12534f0e28bd23a8e8b4a50be2138c1d375a1874ffe12d576c4b6504ce528dfd",
+            ]
+        );
+
+        let (tx, prevouts) = broadcast(&spend);
+        let found = receiver.scan(&tx, &prevouts, &[3]).unwrap();
+        assert_eq!(found.len(), 3);
+
+        let spend_key = receiver.spend;
+        for output in found.iter() {
+            let sent = sent.iter().find(|sent| sent.vout == output.vout as usize);
+            assert_eq!(output.output_key, sent.unwrap().output_key);
+            assert_eq!(output.label, (output.vout == 1).then_some(3));
+
+            // The spend key plus the tweak is the key of the output.
+            let tweak = SecretKey::from_str(&output.tweak).unwrap();
+            let key = spend_key.add_tweak(&Scalar::from(tweak)).unwrap();
+            assert_eq!(
+                key.x_only_public_key(&secp).0.to_string(),
+                output.output_key
+            );
+        }
+
+        // Labels not scanned for go unnoticed along with the outputs after them, and so do
+        // other wallets.
+        assert_eq!(receiver.scan(&tx, &prevouts, &[]).unwrap().len(), 1);
+        let other = SilentPaymentKeys::derive(&[3u8; 64], Network::Regtest, 0).unwrap();
+        assert!(other.scan(&tx, &prevouts, &[3]).unwrap().is_empty());
+
+        // Other inputs give other outputs.
+        let (mut tx, prevouts) = broadcast(&spend);
+        tx.input[0].previous_output.vout = 9;
+        assert!(receiver.scan(&tx, &prevouts, &[3]).unwrap().is_empty());
+    }
+
+    /// Transaction of the first BIP-352 vectors, its inputs being P2WPKH spends of the vector
+    /// keys and its outputs paying `outputs`.
+    fn vector_transaction(outpoints: [&str; 2], outputs: &[&str]) -> (Transaction, Vec<TxOut>) {
+        let keys = [
+            "025a1e61f898173040e20616d43e9f496fba90338a39faa1ed98fcbaeee4dd9be5",
+            "03bd85685d03d111699b15d046319febe77f8de5286e9e512703cdee1bf3be3792",
+        ]
+        .map(|key| PublicKey::from_str(key).unwrap());
+
+        let tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: outpoints
+                .iter()
+                .zip(keys.iter())
+                .map(|(outpoint, key)| TxIn {
+                    previous_output: OutPoint::from_str(outpoint).unwrap(),
+                    script_sig: ScriptBuf::new(),
+                    sequence: Sequence::MAX,
+                    witness: Witness::from_slice(&[vec![0x30; 71], key.serialize().to_vec()]),
+                })
+                .collect(),
+            output: outputs
+                .iter()
+                .map(|key| TxOut {
+                    value: Amount::from_sat(1_000),
+                    script_pubkey: ScriptBuf::new_p2tr_tweaked(
+                        TweakedPublicKey::dangerous_assume_tweaked(
+                            XOnlyPublicKey::from_str(key).unwrap(),
+                        ),
+                    ),
+                })
+                .collect(),
+        };
+
+        let prevouts = keys
+            .iter()
+            .map(|key| TxOut {
+                value: Amount::from_sat(50_000),
+                script_pubkey: ScriptBuf::new_p2wpkh(
+                    &bitcoin::CompressedPublicKey(*key).wpubkey_hash(),
+                ),
+            })
+            .collect();
+        (tx, prevouts)
+    }
+
+    /// Outputs of the BIP-352 send_and_receive vectors paying the address of
+    /// `encodes_bip352_addresses`. The simple send ones are copied from the vectors, those
+    /// paying the address twice and a label were worked out from the same keys.
+    #[test]
+    fn scans_bip352_vectors() {
+        let keys = SilentPaymentKeys {
+            scan: secret("0f694e068028a717f8af6b9411f9a133dd3565258714cc226594b34db90c1f2c"),
+            spend: secret("9d6ad855ce3417ef84e836892e5a56392bfba05fa5d97ccea30e266f540e08b3"),
+            network: Network::Mainnet,
+        };
+        let found = |outpoints, outputs: &[&str], labels: &[u32]| {
+            let (tx, prevouts) = vector_transaction(outpoints, outputs);
+            keys.scan(&tx, &prevouts, labels)
+                .unwrap()
+                .into_iter()
+                .map(|output| (output.output_key, output.label))
+                .collect::<Vec<_>>()
+        };
+        let txid = "f4184fc596403b9d638783cf57adfe4c75c605f6356fbc91338530e9831e9e16";
+        let first = format!("{txid}:0");
+        let second = "a1075db55d416d3ca199f55b6084e2115b9345e16c5cf302fc80e9d5fbf5d48d:0";
+        let simple = "3e9fce73d4e77a4809908e3c3a2e54ee147b9312dc5044a193d1fc85de46e3c1";
+
+        // Simple send: two inputs, in either order.
+        for outpoints in [[first.as_str(), second], [second, first.as_str()]] {
+            assert_eq!(
+                found(outpoints, &[simple], &[]),
+                [(simple.to_string(), None)]
+            );
+        }
+
+        // Simple send: two inputs from the same transaction.
+        let same = "79e71baa2ba3fc66396de3a04f168c7bf24d6870ec88ca877754790c1db357b6";
+        let (third, seventh) = (format!("{txid}:3"), format!("{txid}:7"));
+        assert_eq!(
+            found([&third, &seventh], &[same], &[]),
+            [(same.to_string(), None)]
+        );
+
+        // Multiple outputs to the same scan key, in any order, the second one maybe labeled.
+        let outpoints = [first.as_str(), second];
+        let twice = "0ffe0b3d72d66b785e1a7ad416edcc22b951293b1507aa04850e890b002c60f1";
+        let labeled = "14d7bd6dd4aaee9823ce7dada3b92e81fafe826be8f3c85134b4ea09730540ab";
+        assert_eq!(
+            found(outpoints, &[twice, simple], &[]),
+            [(simple.to_string(), None), (twice.to_string(), None)]
+        );
+        assert_eq!(
+            found(outpoints, &[simple, labeled], &[1]),
+            [(simple.to_string(), None), (labeled.to_string(), Some(1))]
+        );
+        assert_eq!(
+            found(outpoints, &[simple, labeled], &[]),
+            [(simple.to_string(), None)]
+        );
+
+        // Other outpoints give other outputs.
+        let other = format!("{txid}:1");
+        assert!(found([&other, second], &[simple], &[]).is_empty());
+    }
+
+    #[test]
+    fn changes_through_the_change_label() {
+        let sender = Xpriv::new_master(NetworkKind::Test, &[1u8; 64]).unwrap();
+        let keys = SilentPaymentKeys::derive(&[1u8; 64], Network::Regtest, 0).unwrap();
+        let secp = Secp256k1::new();
+        let change = SilentPaymentAddress {
+            spend: keys
+                .spend
+                .public_key(&secp)
+                .add_exp_tweak(&secp, &label_tweak(&keys.scan, CHANGE_LABEL).unwrap())
+                .unwrap(),
+            ..keys.address(None).unwrap()
+        };
+
+        let mut spend = spend(&sender);
+        add_outputs(
+            &mut spend.psbt,
+            &sender,
+            Network::Regtest,
+            &[recipient(&change, 1_000)],
+        )
+        .unwrap();
+
+        let (tx, prevouts) = broadcast(&spend);
+        let found = keys.scan(&tx, &prevouts, &[]).unwrap();
+        assert_eq!(found[0].label, Some(CHANGE_LABEL));
+    }
+
+    #[test]
+    fn skips_ineligible_transactions() {
+        let sender = Xpriv::new_master(NetworkKind::Test, &[1u8; 64]).unwrap();
+        let receiver = SilentPaymentKeys::derive(&[2u8; 64], Network::Regtest, 0).unwrap();
+        let address = receiver.address(None).unwrap();
+
+        let mut spend = spend(&sender);
+        add_outputs(
+            &mut spend.psbt,
+            &sender,
+            Network::Regtest,
+            &[recipient(&address, 1_000)],
+        )
+        .unwrap();
+        let (tx, prevouts) = broadcast(&spend);
+
+        // A script path of a tree with no key path hides the key of the taproot input.
+        let nums = Vec::from_hex(NUMS_KEY).unwrap();
+        let mut script_path = tx.clone();
+        script_path.input[1].witness =
+            Witness::from_slice(&[vec![0x01; 64], vec![0x51], [vec![0xc0], nums].concat()]);
+        assert!(receiver
+            .scan(&script_path, &prevouts, &[])
+            .unwrap()
+            .is_empty());
+
+        // Spending a future segwit version disables silent payments for the transaction.
+        let mut future = prevouts.clone();
+        let program = WitnessProgram::new(WitnessVersion::V2, &[0u8; 32]).unwrap();
+        future[4].script_pubkey = ScriptBuf::new_witness_program(&program);
+        assert!(receiver.scan(&tx, &future, &[]).unwrap().is_empty());
+        spend.psbt.inputs[4].witness_utxo = Some(future[4].clone());
+        assert!(matches!(
+            add_outputs(
+                &mut spend.psbt,
+                &sender,
+                Network::Regtest,
+                &[recipient(&address, 1_000)]
+            ),
+            Err(SilentPaymentError::FutureSegwit(4))
+        ));
+
+        assert!(matches!(
+            receiver.scan(&tx, &prevouts[1..], &[]),
+            Err(SilentPaymentError::Transaction(_))
+        ));
+    }
+
+    #[test]
+    fn needs_every_eligible_input_key() {
+        let sender = Xpriv::new_master(NetworkKind::Test, &[1u8; 64]).unwrap();
+        let stranger = Xpriv::new_master(NetworkKind::Test, &[9u8; 64]).unwrap();
+        let address = SilentPaymentKeys::derive(&[2u8; 64], Network::Regtest, 0)
+            .unwrap()
+            .address(None)
+            .unwrap();
+
+        let mut spend = spend(&sender);
+        assert!(matches!(
+            add_outputs(
+                &mut spend.psbt,
+                &stranger,
+                Network::Regtest,
+                &[recipient(&address, 1_000)]
+            ),
+            Err(SilentPaymentError::MissingInputKey(0))
+        ));
+
+        let mainnet = SilentPaymentAddress {
+            network: NetworkKind::Main,
+            ..address
+        };
+        assert!(matches!(
+            add_outputs(
+                &mut spend.psbt,
+                &sender,
+                Network::Regtest,
+                &[recipient(&mainnet, 1_000)]
+            ),
+            Err(SilentPaymentError::WrongNetwork(_))
+        ));
+        assert!(spend.psbt.unsigned_tx.output.is_empty());
+    }
+}
@@ -1,21 +1,67 @@
 use async_trait::async_trait;
 use sqlx::{
-    migrate::MigrateDatabase,
-    sqlite::{SqlitePoolOptions, SqliteRow},
-    Pool, Row, Sqlite,
+    migrate::{MigrateDatabase, Migrator},
+    query::Query,
+    sqlite::{SqliteArguments, SqliteConnectOptions, SqlitePoolOptions, SqliteRow},
+    ConnectOptions, Connection, Executor, Pool, QueryBuilder, Row, Sqlite, Transaction,
+};
+use std::{
+    fs,
+    io::Read,
+    ops::Deref,
+    path::{Path, PathBuf},
+    str::FromStr,
 };
-use std::ops::Deref;
 
 use super::{
-    account::{AccountModel, Blockchain, Network, StoreAccountInput},
-    vault_interface::{VaultError, VaultInterface, VaultResult},
-    wallet::{StoreWalletInput, WalletModel},
+    account::{AccountModel, Blockchain, Network, StoreAccountInput, UpdateAccountInput},
+    audit::{AuditAction, AuditEvent, StoreAuditEventInput},
+    labels::{Label, LabelType},
+    multisig::{MultisigModel, MultisigScriptType, StoreMultisigInput},
+    policy::{PolicyWalletModel, StorePolicyWalletInput},
+    query::{AccountPage, AccountQuery, SortDirection, SortValue},
+    schema,
+    throttle::{unix_now, AuthAttempts},
+    vault_interface::{VaultError, VaultInterface, VaultResult, VaultTransaction},
+    wallet::{RekeyWalletInput, StoreWalletInput, UpdateWalletInput, WalletModel},
 };
 
 pub type DatabasePool = Pool<Sqlite>;
 
+/// Every plaintext SQLite database starts with this header, SQLCipher ones look like noise.
+const SQLITE_HEADER: &[u8; 16] = b"SQLite format 3\0";
+
+static MIGRATOR: Migrator = sqlx::migrate!();
+
 pub struct SqliteVault(DatabasePool);
 
+/// Row of an `INSERT` or `UPDATE ... RETURNING`, the statement being run to completion.
+/// `fetch_one` and `fetch_optional` stop after the first row, which leaves the write
+/// uncommitted, and out of sight of the other connections of the pool, until the connection
+/// runs its next statement.
+async fn fetch_returning<'q: 'c, 'c, E>(
+    query: Query<'q, Sqlite, SqliteArguments<'q>>,
+    executor: E,
+) -> Result<Option<SqliteRow>, sqlx::Error>
+where
+    E: Executor<'c, Database = Sqlite>,
+{
+    Ok(query.fetch_all(executor).await?.into_iter().next())
+}
+
+/// Same as [`fetch_returning`], in a transaction of its own. sqlx steps a failed statement
+/// again until its caller gives up on the result, and so may re-run an insert that failed on a
+/// unique constraint after the conflicting row is gone. The rollback comes after and undoes it.
+async fn write_returning<'q>(
+    pool: &DatabasePool,
+    query: Query<'q, Sqlite, SqliteArguments<'q>>,
+) -> Result<Option<SqliteRow>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let row = fetch_returning(query, &mut *tx).await?;
+    tx.commit().await?;
+    Ok(row)
+}
+
 impl Deref for SqliteVault {
     type Target = DatabasePool;
     fn deref(&self) -> &Self::Target {
@@ -23,25 +69,278 @@ impl Deref for SqliteVault {
     }
 }
 
+/// Writes of a [`SqliteVault`] running inside a database transaction.
+pub struct SqliteTransaction(Transaction<'static, Sqlite>);
+
+#[async_trait]
+impl VaultTransaction for SqliteTransaction {
+    async fn insert_wallet(&mut self, input: StoreWalletInput) -> VaultResult<WalletModel> {
+        let id = uuid::Uuid::new_v4().to_string();
+        let result = sqlx::query("INSERT into wallets (id, name, seed, password) values (?,?,?,?)")
+            .bind(&id)
+            .bind(&input.name)
+            .bind(&input.encrypted_seed)
+            .bind(&input.encrypted_pass)
+            .execute(&mut *self.0)
+            .await;
+
+        if let Err(err) = result {
+            return Err(VaultError::Inserting(err.to_string()));
+        }
+
+        Ok(WalletModel {
+            id,
+            name: input.name,
+            password: input.encrypted_pass,
+            seed: input.encrypted_seed,
+        })
+    }
+
+    async fn insert_account(&mut self, input: StoreAccountInput) -> VaultResult<AccountModel> {
+        let AccountModel {
+            id,
+            address,
+            blockchain,
+            wallet_id,
+            network,
+            created_at: _,
+            path,
+            script_type,
+            ..
+        } = AccountModel::from(input.clone());
+
+        let res = sqlx::query("INSERT into accounts (id, wallet_id, address, path, blockchain, network, script_type) values (?,?,?,?,?,?,?)")
+            .bind(&id)
+            .bind(&wallet_id)
+            .bind(&address)
+            .bind(&path)
+            .bind(&blockchain)
+            .bind(&network)
+            .bind(&script_type)
+            .execute(&mut *self.0)
+            .await;
+
+        if let Err(err) = res {
+            return Err(VaultError::Inserting(err.to_string()));
+        }
+
+        Ok(AccountModel {
+            id,
+            address,
+            blockchain,
+            wallet_id,
+            network,
+            created_at: None,
+            path,
+            script_type,
+            ..Default::default()
+        })
+    }
+
+    async fn update_account(&mut self, input: UpdateAccountInput) -> VaultResult<AccountModel> {
+        let query = sqlx::query(
+            "UPDATE accounts SET label = COALESCE(?1, label), notes = COALESCE(?2, notes),
+            colour = COALESCE(?3, colour), archived = COALESCE(?4, archived), position = COALESCE(?5, position),
+            used = COALESCE(?6, used)
+            WHERE id = ?7 RETURNING *;",
+        )
+        .bind(&input.label)
+        .bind(&input.notes)
+        .bind(&input.colour)
+        .bind(input.archived)
+        .bind(input.position)
+        .bind(input.used)
+        .bind(&input.id);
+        let res = fetch_returning(query, &mut *self.0).await;
+
+        match res {
+            Err(err) => Err(VaultError::Updating(err.to_string())),
+            Ok(None) => Err(VaultError::NotFound(input.id)),
+            Ok(Some(row)) => SqliteVault::parse_account(&row),
+        }
+    }
+
+    async fn remove_account_by_id(&mut self, id: &str) -> VaultResult<()> {
+        let result = sqlx::query("DELETE FROM accounts WHERE id = ?;")
+            .bind(id)
+            .execute(&mut *self.0)
+            .await;
+
+        if let Err(err) = result {
+            return Err(VaultError::Removing(err.to_string()));
+        }
+
+        Ok(())
+    }
+
+    async fn remove_wallet_by_id(&mut self, id: &str) -> VaultResult<()> {
+        // Accounts, attempts and labels reference the wallet, so they have to go first.
+        for query in [
+            "DELETE FROM accounts WHERE wallet_id = ?1;",
+            "DELETE FROM auth_attempts WHERE wallet_id = ?1;",
+            "DELETE FROM labels WHERE wallet_id = ?1;",
+            "DELETE FROM wallets WHERE id = ?1;",
+        ] {
+            let result = sqlx::query(query).bind(id).execute(&mut *self.0).await;
+
+            if let Err(err) = result {
+                return Err(VaultError::Removing(err.to_string()));
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn set_labels(&mut self, wallet_id: &str, labels: Vec<Label>) -> VaultResult<()> {
+        for label in labels.iter() {
+            let res = sqlx::query(
+                "INSERT INTO labels (wallet_id, type, ref, label, origin, spendable) VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+                ON CONFLICT (wallet_id, type, ref) DO UPDATE SET label = ?4, origin = ?5, spendable = ?6;",
+            )
+            .bind(wallet_id)
+            .bind(label.kind.to_string())
+            .bind(&label.reference)
+            .bind(&label.label)
+            .bind(&label.origin)
+            .bind(label.spendable)
+            .execute(&mut *self.0)
+            .await;
+
+            if let Err(err) = res {
+                return Err(VaultError::Inserting(err.to_string()));
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn remove_multisig_by_id(&mut self, id: &str) -> VaultResult<()> {
+        let res = sqlx::query("DELETE FROM multisig_wallets WHERE id = ?1;")
+            .bind(id)
+            .execute(&mut *self.0)
+            .await;
+
+        if let Err(err) = res {
+            return Err(VaultError::Removing(err.to_string()));
+        }
+
+        Ok(())
+    }
+
+    async fn remove_policy_wallet_by_id(&mut self, id: &str) -> VaultResult<()> {
+        let res = sqlx::query("DELETE FROM policy_wallets WHERE id = ?1;")
+            .bind(id)
+            .execute(&mut *self.0)
+            .await;
+
+        if let Err(err) = res {
+            return Err(VaultError::Removing(err.to_string()));
+        }
+
+        Ok(())
+    }
+
+    async fn append_audit_event(&mut self, input: StoreAuditEventInput) -> VaultResult<AuditEvent> {
+        let last = sqlx::query(
+            "SELECT * FROM audit_events WHERE wallet_id = ?1 ORDER BY sequence DESC LIMIT 1;",
+        )
+        .bind(&input.wallet_id)
+        .fetch_optional(&mut *self.0)
+        .await;
+
+        if let Err(err) = last {
+            return Err(VaultError::Listing(err.to_string()));
+        }
+
+        let last = match last.unwrap() {
+            Some(row) => Some(SqliteVault::parse_audit_event(&row)?),
+            None => None,
+        };
+
+        let event = AuditEvent::chain(last.as_ref(), input, unix_now());
+
+        // Two writers chaining after the same event collide on the primary key.
+        let res = sqlx::query(
+            "INSERT INTO audit_events (wallet_id, sequence, action, details, created_at, prev_hash, hash)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7);",
+        )
+        .bind(&event.wallet_id)
+        .bind(event.sequence)
+        .bind(event.action.to_string())
+        .bind(&event.details)
+        .bind(event.created_at)
+        .bind(&event.prev_hash)
+        .bind(&event.hash)
+        .execute(&mut *self.0)
+        .await;
+
+        if let Err(err) = res {
+            return Err(VaultError::Inserting(err.to_string()));
+        }
+
+        Ok(event)
+    }
+
+    async fn commit(self: Box<Self>) -> VaultResult<()> {
+        if let Err(err) = self.0.commit().await {
+            return Err(VaultError::Transaction(err.to_string()));
+        }
+
+        Ok(())
+    }
+
+    async fn rollback(self: Box<Self>) -> VaultResult<()> {
+        if let Err(err) = self.0.rollback().await {
+            return Err(VaultError::Transaction(err.to_string()));
+        }
+
+        Ok(())
+    }
+}
+
 #[async_trait]
 impl VaultInterface for SqliteVault {
+    async fn begin(&self) -> VaultResult<Box<dyn VaultTransaction + '_>> {
+        let tx = self.0.begin().await;
+
+        if let Err(err) = tx {
+            return Err(VaultError::Transaction(err.to_string()));
+        }
+
+        let mut tx = tx.unwrap();
+
+        // Takes the write lock right away like `BEGIN IMMEDIATE` would, waiting for other
+        // writers. A transaction reading before writing would otherwise fail with
+        // `SQLITE_BUSY` as soon as another one wrote in between.
+        let res = sqlx::query("UPDATE wallets SET id = id WHERE 0;")
+            .execute(&mut *tx)
+            .await;
+
+        if let Err(err) = res {
+            return Err(VaultError::Transaction(err.to_string()));
+        }
+
+        Ok(Box::new(SqliteTransaction(tx)))
+    }
+
     async fn get_account_by_id(&self, id: &str) -> VaultResult<AccountModel> {
         let res = sqlx::query("SELECT * FROM accounts WHERE id = ?;")
             .bind(id)
             .fetch_one(&self.0)
             .await;
-        if let Err(_) = res {
-            return Err(VaultError::NotFound(id.to_string()));
+        if let Err(err) = res {
+            return Err(VaultError::lookup(id, err));
         }
 
         SqliteVault::parse_account(&res.unwrap())
     }
 
     async fn get_all_accounts(&self, wallet_id: &str) -> VaultResult<Vec<AccountModel>> {
-        let res = sqlx::query("SELECT * from accounts WHERE wallet_id = ?;")
-            .bind(wallet_id)
-            .fetch_all(&self.0)
-            .await;
+        let res =
+            sqlx::query("SELECT * from accounts WHERE wallet_id = ? ORDER BY position, rowid;")
+                .bind(wallet_id)
+                .fetch_all(&self.0)
+                .await;
 
         if let Err(err) = res {
             return Err(VaultError::Listing(err.to_string()));
@@ -61,13 +360,83 @@ impl VaultInterface for SqliteVault {
         Ok(accounts)
     }
 
+    async fn query_accounts(
+        &self,
+        wallet_id: &str,
+        query: &AccountQuery,
+    ) -> VaultResult<AccountPage> {
+        let cursor = query.decode_cursor()?;
+        let mut builder = QueryBuilder::<Sqlite>::new("SELECT * FROM accounts WHERE wallet_id = ");
+        builder.push_bind(wallet_id);
+
+        for (column, value) in [
+            ("blockchain", &query.blockchain),
+            ("network", &query.network),
+            ("script_type", &query.script_type),
+        ] {
+            if let Some(value) = value {
+                builder.push(format!(" AND {column} = ")).push_bind(value);
+            }
+        }
+
+        if let Some(pattern) = query.label_pattern() {
+            builder
+                .push(" AND label LIKE ")
+                .push_bind(pattern)
+                .push(" ESCAPE '\\'");
+        }
+
+        if let Some(used) = query.used {
+            builder.push(" AND used = ").push_bind(used);
+        }
+
+        let columns = query.sort.columns();
+        let (direction, comparison) = match query.direction {
+            SortDirection::Asc => ("ASC", ">"),
+            SortDirection::Desc => ("DESC", "<"),
+        };
+
+        if let Some(cursor) = cursor {
+            builder.push(format!(" AND ({}) {comparison} (", columns.join(", ")));
+            let mut values = builder.separated(", ");
+            for value in cursor {
+                match value {
+                    SortValue::Int(value) => values.push_bind(value),
+                    SortValue::Text(value) => values.push_bind(value),
+                };
+            }
+            builder.push(")");
+        }
+
+        let order: Vec<String> = columns
+            .iter()
+            .map(|column| format!("{column} {direction}"))
+            .collect();
+        builder
+            .push(format!(" ORDER BY {} LIMIT ", order.join(", ")))
+            .push_bind(query.limit() as i64 + 1);
+
+        let res = builder.build().fetch_all(&self.0).await;
+
+        if let Err(err) = res {
+            return Err(VaultError::Listing(err.to_string()));
+        }
+
+        let mut accounts = vec![];
+        for acc in res.unwrap().iter() {
+            accounts.push(SqliteVault::parse_account(acc)?);
+        }
+
+        Ok(query.paginate(accounts))
+    }
+
     async fn get_all_wallets(&self) -> VaultResult<Vec<WalletModel>> {
         let res = sqlx::query("SELECT * from wallets;")
             .fetch_all(&self.0)
             .await;
 
         if let Err(err) = res {
-            return Err(VaultError::NotFound(err.to_string()));
+            return Err(VaultError::Listing(err.to_string()));
         }
 
         let results = res.unwrap();
@@ -88,8 +457,8 @@ impl VaultInterface for SqliteVault {
             .bind(id)
             .fetch_one(&self.0)
             .await;
-        if let Err(_) = res {
-            return Err(VaultError::NotFound(id.to_string()));
+        if let Err(err) = res {
+            return Err(VaultError::lookup(id, err));
         }
 
         let result = res.unwrap();
@@ -101,8 +470,8 @@ impl VaultInterface for SqliteVault {
             .bind(name)
             .fetch_one(&self.0)
             .await;
-        if let Err(_) = res {
-            return Err(VaultError::NotFound(name.to_string()));
+        if let Err(err) = res {
+            return Err(VaultError::lookup(name, err));
         }
 
         let result = res.unwrap();
@@ -110,120 +479,502 @@ impl VaultInterface for SqliteVault {
     }
 
     async fn remove_account_by_id(&self, id: &str) -> VaultResult<()> {
-        let result = sqlx::query("DELETE FROM accounts WHERE id = ?;")
-            .bind(id)
-            .execute(&self.0)
-            .await;
-
-        if let Err(err) = result {
-            return Err(VaultError::Removing(err.to_string()));
-        }
-
-        Ok(())
+        let mut tx = self.begin().await?;
+        let res = tx.remove_account_by_id(id).await?;
+        tx.commit().await?;
+        Ok(res)
     }
 
     async fn remove_wallet_by_id(&self, id: &str) -> VaultResult<()> {
-        let result = sqlx::query(
-            "
-            BEGIN TRANSACTION;
-    -- Delete associated accounts first
-    DELETE FROM accounts
-    WHERE wallet_id = ?;
-
-    -- Delete the wallet after associated accounts are deleted
-    DELETE FROM wallets
-    WHERE id = ?;
-
-    COMMIT;
-        ",
-        )
-        .bind(id)
-        .execute(&self.0)
-        .await;
-        if let Err(err) = result {
-            return Err(VaultError::Removing(err.to_string()));
-        }
-        Ok(())
+        let mut tx = self.begin().await?;
+        let res = tx.remove_wallet_by_id(id).await?;
+        tx.commit().await?;
+        Ok(res)
     }
 
     async fn insert_wallet(&self, input: StoreWalletInput) -> VaultResult<WalletModel> {
-        let id = uuid::Uuid::new_v4().to_string();
-        let result = sqlx::query("INSERT into wallets (id, name, seed, password) values (?,?,?,?)")
-            .bind(&id)
-            .bind(&input.name)
-            .bind(&input.encrypted_seed)
-            .bind(&input.encrypted_pass)
-            .execute(&self.0)
-            .await;
+        let mut tx = self.begin().await?;
+        let res = tx.insert_wallet(input).await?;
+        tx.commit().await?;
+        Ok(res)
+    }
 
-        if let Err(err) = result {
-            return Err(VaultError::Inserting(err.to_string()));
-        }
+    async fn insert_account(&self, input: StoreAccountInput) -> VaultResult<AccountModel> {
+        let mut tx = self.begin().await?;
+        let res = tx.insert_account(input).await?;
+        tx.commit().await?;
+        Ok(res)
+    }
 
-        Ok(WalletModel {
-            id,
-            name: input.name,
-            password: input.encrypted_pass,
-            seed: input.encrypted_seed,
-        })
+    async fn update_wallet(&self, input: UpdateWalletInput) -> VaultResult<WalletModel> {
+        let query =
+            sqlx::query("UPDATE wallets SET name = COALESCE(?1, name) WHERE id = ?2 RETURNING *;")
+                .bind(&input.name)
+                .bind(&input.id);
+        let res = write_returning(&self.0, query).await;
+
+        match res {
+            Err(err) => Err(VaultError::Updating(err.to_string())),
+            Ok(None) => Err(VaultError::NotFound(input.id)),
+            Ok(Some(row)) => SqliteVault::parse_wallet(&row),
+        }
     }
 
-    async fn insert_account(&self, input: StoreAccountInput) -> VaultResult<AccountModel> {
-        let AccountModel {
-            id,
-            address,
-            blockchain,
-            wallet_id,
-            network,
-            created_at: _,
-            path,
-        } = AccountModel::from(input.clone());
+    async fn update_account(&self, input: UpdateAccountInput) -> VaultResult<AccountModel> {
+        let mut tx = self.begin().await?;
+        let res = tx.update_account(input).await?;
+        tx.commit().await?;
+        Ok(res)
+    }
 
-        let res = sqlx::query("INSERT into accounts (id, wallet_id, address, path, blockchain, network) values (?,?,?,?,?,?)")
-            .bind(&id)
-            .bind(&wallet_id)
-            .bind(&address)
-            .bind(&path)
-            .bind(&blockchain)
-            .bind(&network)
-            .execute(&self.0)
+    async fn get_labels(&self, wallet_id: &str) -> VaultResult<Vec<Label>> {
+        let res = sqlx::query("SELECT * FROM labels WHERE wallet_id = ?1 ORDER BY type, ref;")
+            .bind(wallet_id)
+            .fetch_all(&self.0)
             .await;
 
         if let Err(err) = res {
-            return Err(VaultError::Inserting(err.to_string()));
+            return Err(VaultError::Listing(err.to_string()));
         }
 
-        Ok(AccountModel {
-            id,
-            address,
-            blockchain,
-            wallet_id,
-            network,
-            created_at: None,
-            path,
-        })
+        let mut labels = vec![];
+        for row in res.unwrap().iter() {
+            labels.push(SqliteVault::parse_label(row)?);
+        }
+
+        Ok(labels)
     }
-}
 
-impl SqliteVault {
+    async fn set_labels(&self, wallet_id: &str, labels: Vec<Label>) -> VaultResult<()> {
+        let mut tx = self.begin().await?;
+        tx.set_labels(wallet_id, labels).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn insert_multisig(&self, input: StoreMultisigInput) -> VaultResult<MultisigModel> {
+        let cosigners = serde_json::to_string(&input.cosigners);
+
+        if let Err(err) = cosigners {
+            return Err(VaultError::Inserting(err.to_string()));
+        }
+
+        let query = sqlx::query(
+            "INSERT INTO multisig_wallets (id, name, threshold, script_type, network, cosigners)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6) RETURNING *;",
+        )
+        .bind(uuid::Uuid::new_v4().to_string())
+        .bind(&input.name)
+        .bind(input.threshold as i64)
+        .bind(input.script_type.to_string())
+        .bind(input.network.to_string())
+        .bind(cosigners.unwrap());
+        let res = write_returning(&self.0, query).await;
+
+        match res {
+            Err(err) => Err(VaultError::Inserting(err.to_string())),
+            Ok(None) => Err(VaultError::Inserting(input.name)),
+            Ok(Some(row)) => SqliteVault::parse_multisig(&row),
+        }
+    }
+
+    async fn get_multisig_by_id(&self, id: &str) -> VaultResult<MultisigModel> {
+        let res = sqlx::query("SELECT * FROM multisig_wallets WHERE id = ?1;")
+            .bind(id)
+            .fetch_one(&self.0)
+            .await;
+
+        if let Err(err) = res {
+            return Err(VaultError::lookup(id, err));
+        }
+
+        SqliteVault::parse_multisig(&res.unwrap())
+    }
+
+    async fn get_all_multisigs(&self) -> VaultResult<Vec<MultisigModel>> {
+        let res = sqlx::query("SELECT * FROM multisig_wallets ORDER BY created_at, rowid;")
+            .fetch_all(&self.0)
+            .await;
+
+        if let Err(err) = res {
+            return Err(VaultError::Listing(err.to_string()));
+        }
+
+        let mut multisigs = vec![];
+        for row in res.unwrap().iter() {
+            multisigs.push(SqliteVault::parse_multisig(row)?);
+        }
+
+        Ok(multisigs)
+    }
+
+    async fn remove_multisig_by_id(&self, id: &str) -> VaultResult<()> {
+        let mut tx = self.begin().await?;
+        let res = tx.remove_multisig_by_id(id).await?;
+        tx.commit().await?;
+        Ok(res)
+    }
+
+    async fn insert_policy_wallet(
+        &self,
+        input: StorePolicyWalletInput,
+    ) -> VaultResult<PolicyWalletModel> {
+        let keys = serde_json::to_string(&input.keys);
+
+        if let Err(err) = keys {
+            return Err(VaultError::Inserting(err.to_string()));
+        }
+
+        let query = sqlx::query(
+            "INSERT INTO policy_wallets (id, name, policy, descriptor, network, keys)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6) RETURNING *;",
+        )
+        .bind(uuid::Uuid::new_v4().to_string())
+        .bind(&input.name)
+        .bind(&input.policy)
+        .bind(&input.descriptor)
+        .bind(input.network.to_string())
+        .bind(keys.unwrap());
+        let res = write_returning(&self.0, query).await;
+
+        match res {
+            Err(err) => Err(VaultError::Inserting(err.to_string())),
+            Ok(None) => Err(VaultError::Inserting(input.name)),
+            Ok(Some(row)) => SqliteVault::parse_policy_wallet(&row),
+        }
+    }
+
+    async fn get_policy_wallet_by_id(&self, id: &str) -> VaultResult<PolicyWalletModel> {
+        let res = sqlx::query("SELECT * FROM policy_wallets WHERE id = ?1;")
+            .bind(id)
+            .fetch_one(&self.0)
+            .await;
+
+        if let Err(err) = res {
+            return Err(VaultError::lookup(id, err));
+        }
+
+        SqliteVault::parse_policy_wallet(&res.unwrap())
+    }
+
+    async fn get_all_policy_wallets(&self) -> VaultResult<Vec<PolicyWalletModel>> {
+        let res = sqlx::query("SELECT * FROM policy_wallets ORDER BY created_at, rowid;")
+            .fetch_all(&self.0)
+            .await;
+
+        if let Err(err) = res {
+            return Err(VaultError::Listing(err.to_string()));
+        }
+
+        let mut wallets = vec![];
+        for row in res.unwrap().iter() {
+            wallets.push(SqliteVault::parse_policy_wallet(row)?);
+        }
+
+        Ok(wallets)
+    }
+
+    async fn remove_policy_wallet_by_id(&self, id: &str) -> VaultResult<()> {
+        let mut tx = self.begin().await?;
+        let res = tx.remove_policy_wallet_by_id(id).await?;
+        tx.commit().await?;
+        Ok(res)
+    }
+
+    async fn get_audit_events(&self, wallet_id: &str) -> VaultResult<Vec<AuditEvent>> {
+        let res = sqlx::query("SELECT * FROM audit_events WHERE wallet_id = ?1 ORDER BY sequence;")
+            .bind(wallet_id)
+            .fetch_all(&self.0)
+            .await;
+
+        if let Err(err) = res {
+            return Err(VaultError::Listing(err.to_string()));
+        }
+
+        let mut events = vec![];
+        for row in res.unwrap().iter() {
+            events.push(SqliteVault::parse_audit_event(row)?);
+        }
+
+        Ok(events)
+    }
+
+    async fn get_auth_attempts(&self, wallet_id: &str) -> VaultResult<AuthAttempts> {
+        let res = sqlx::query("SELECT * FROM auth_attempts WHERE wallet_id = ?;")
+            .bind(wallet_id)
+            .fetch_optional(&self.0)
+            .await;
+
+        if let Err(err) = res {
+            return Err(VaultError::Listing(err.to_string()));
+        }
+
+        match res.unwrap() {
+            Some(row) => SqliteVault::parse_auth_attempts(&row),
+            None => Ok(AuthAttempts {
+                wallet_id: wallet_id.to_string(),
+                ..Default::default()
+            }),
+        }
+    }
+
+    async fn record_failed_auth(&self, wallet_id: &str) -> VaultResult<AuthAttempts> {
+        let query = sqlx::query(
+            "INSERT INTO auth_attempts (wallet_id, failed_attempts, last_failed_at) VALUES (?1, 1, ?2)
+            ON CONFLICT(wallet_id) DO UPDATE SET failed_attempts = failed_attempts + 1, last_failed_at = ?2
+            RETURNING *;",
+        )
+        .bind(wallet_id)
+        .bind(unix_now());
+        let res = fetch_returning(query, &self.0).await;
+
+        match res {
+            Err(err) => Err(VaultError::Updating(err.to_string())),
+            Ok(None) => Err(VaultError::NotFound(wallet_id.to_string())),
+            Ok(Some(row)) => SqliteVault::parse_auth_attempts(&row),
+        }
+    }
+
+    async fn reset_auth_attempts(&self, wallet_id: &str) -> VaultResult<()> {
+        let res = sqlx::query("DELETE FROM auth_attempts WHERE wallet_id = ?;")
+            .bind(wallet_id)
+            .execute(&self.0)
+            .await;
+
+        if let Err(err) = res {
+            return Err(VaultError::Removing(err.to_string()));
+        }
+
+        Ok(())
+    }
+
+    async fn rekey_wallet(&self, input: RekeyWalletInput) -> VaultResult<()> {
+        let tx = self.0.begin().await;
+
+        if let Err(err) = tx {
+            return Err(VaultError::Updating(err.to_string()));
+        }
+
+        let mut tx = tx.unwrap();
+
+        let res = sqlx::query("UPDATE wallets SET password = ?, seed = ? WHERE id = ?;")
+            .bind(&input.encrypted_pass)
+            .bind(&input.encrypted_seed)
+            .bind(&input.wallet_id)
+            .execute(&mut *tx)
+            .await;
+
+        match res {
+            Err(err) => return Err(VaultError::Updating(err.to_string())),
+            Ok(res) if res.rows_affected() == 0 => {
+                return Err(VaultError::NotFound(input.wallet_id))
+            }
+            Ok(_) => {}
+        }
+
+        // The update holds the write lock, no account can be inserted until the commit.
+        let ids = sqlx::query_scalar::<_, String>("SELECT id FROM accounts WHERE wallet_id = ?;")
+            .bind(&input.wallet_id)
+            .fetch_all(&mut *tx)
+            .await;
+
+        if let Err(err) = ids {
+            return Err(VaultError::Listing(err.to_string()));
+        }
+
+        input.check_accounts(ids.unwrap().iter().map(String::as_str))?;
+
+        for (id, path) in input.account_paths.iter() {
+            let res = sqlx::query("UPDATE accounts SET path = ? WHERE id = ? AND wallet_id = ?;")
+                .bind(path)
+                .bind(id)
+                .bind(&input.wallet_id)
+                .execute(&mut *tx)
+                .await;
+
+            if let Err(err) = res {
+                return Err(VaultError::Updating(err.to_string()));
+            }
+        }
+
+        if let Err(err) = tx.commit().await {
+            return Err(VaultError::Updating(err.to_string()));
+        }
+
+        Ok(())
+    }
+}
+
+impl SqliteVault {
     pub async fn new(url: Option<&str>) -> Self {
-        let connection_url = url.unwrap_or("sqlite://database.db");
-        let db_exists = Sqlite::database_exists(&connection_url)
+        SqliteVault::connect(url)
             .await
-            .expect("Database exist checking failed");
-        if !db_exists {
-            Sqlite::create_database(&connection_url)
-                .await
-                .expect("Creating database failure!");
+            .expect("Failed opening the database")
+    }
+
+    /// Same as [`SqliteVault::new`], without panicking.
+    pub async fn connect(url: Option<&str>) -> VaultResult<Self> {
+        let connection_url = url.unwrap_or("sqlite://database.db");
+        let db_exists = Sqlite::database_exists(connection_url).await;
+
+        if let Err(err) = db_exists {
+            return Err(VaultError::Listing(err.to_string()));
+        }
+
+        if !db_exists.unwrap() {
+            if let Err(err) = Sqlite::create_database(connection_url).await {
+                return Err(VaultError::Inserting(err.to_string()));
+            }
             println!("The datbase does not exist, therefore it was just created")
         }
 
         let connection = SqlitePoolOptions::new()
             .max_connections(5)
-            .connect(&connection_url)
-            .await
-            .unwrap();
-        Self(connection)
+            .connect(connection_url)
+            .await;
+
+        if let Err(err) = connection {
+            return Err(VaultError::Listing(err.to_string()));
+        }
+
+        Ok(Self(connection.unwrap()))
+    }
+
+    /// Opens a vault encrypted as a whole with SQLCipher, keyed from `password`.
+    ///
+    /// An existing plaintext database is encrypted in place first. Requires the `sqlcipher`
+    /// feature, plain SQLite would silently ignore the key and leave everything in clear.
+    pub async fn new_encrypted(url: Option<&str>, password: &str) -> VaultResult<Self> {
+        if !cfg!(feature = "sqlcipher") {
+            return Err(VaultError::Encryption(
+                "dev-wallet was built without the sqlcipher feature".to_string(),
+            ));
+        }
+
+        let connection_url = url.unwrap_or("sqlite://database.db");
+        let options = SqliteConnectOptions::from_str(connection_url);
+
+        if let Err(err) = options {
+            return Err(VaultError::Encryption(err.to_string()));
+        }
+
+        let options = options.unwrap();
+        let path = options.clone().get_filename().to_path_buf();
+
+        if SqliteVault::is_plaintext(&path) {
+            SqliteVault::encrypt_existing(&path, password).await?;
+        }
+
+        let options = options
+            .create_if_missing(true)
+            .pragma("key", SqliteVault::quote(password));
+
+        let connection = SqlitePoolOptions::new()
+            .max_connections(5)
+            .connect_with(options)
+            .await;
+
+        if let Err(err) = connection {
+            return Err(VaultError::Encryption(err.to_string()));
+        }
+
+        let vault = Self(connection.unwrap());
+
+        // Reading the schema fails with "file is not a database" when the key is wrong.
+        let res = sqlx::query("SELECT count(*) FROM sqlite_master;")
+            .fetch_one(&vault.0)
+            .await;
+
+        if let Err(err) = res {
+            return Err(VaultError::Encryption(err.to_string()));
+        }
+
+        Ok(vault)
+    }
+
+    /// Whether the file holds a non empty, unencrypted SQLite database.
+    pub fn is_plaintext(path: &Path) -> bool {
+        let mut header = [0u8; 16];
+        let file = fs::File::open(path);
+
+        if file.is_err() {
+            return false;
+        }
+
+        match file.unwrap().read_exact(&mut header) {
+            Ok(_) => &header == SQLITE_HEADER,
+            Err(_) => false,
+        }
+    }
+
+    /// Rewrites a plaintext database into a SQLCipher one keyed with `password`, then swaps
+    /// it in place of the original file.
+    pub async fn encrypt_existing(path: &Path, password: &str) -> VaultResult<()> {
+        let mut encrypted_path = PathBuf::from(path);
+        encrypted_path.as_mut_os_string().push(".encrypting");
+
+        if encrypted_path.exists() {
+            if let Err(err) = fs::remove_file(&encrypted_path) {
+                return Err(VaultError::Encryption(err.to_string()));
+            }
+        }
+
+        // Attaching creates the encrypted file, which needs the connection to be allowed to.
+        let connection = SqliteConnectOptions::new()
+            .filename(path)
+            .create_if_missing(true)
+            .connect()
+            .await;
+
+        if let Err(err) = connection {
+            return Err(VaultError::Encryption(err.to_string()));
+        }
+
+        let mut connection = connection.unwrap();
+
+        let user_version = sqlx::query("PRAGMA user_version;")
+            .fetch_one(&mut connection)
+            .await;
+
+        if let Err(err) = user_version {
+            return Err(VaultError::Encryption(err.to_string()));
+        }
+
+        let user_version: i64 = user_version.unwrap().get(0);
+
+        let statements = [
+            format!(
+                "ATTACH DATABASE {} AS encrypted KEY {};",
+                SqliteVault::quote(&encrypted_path.to_string_lossy()),
+                SqliteVault::quote(password)
+            ),
+            "SELECT sqlcipher_export('encrypted');".to_string(),
+            format!("PRAGMA encrypted.user_version = {};", user_version),
+            "DETACH DATABASE encrypted;".to_string(),
+        ];
+
+        for statement in statements.iter() {
+            let res = sqlx::query(statement).execute(&mut connection).await;
+
+            if let Err(err) = res {
+                let _ = fs::remove_file(&encrypted_path);
+                return Err(VaultError::Encryption(err.to_string()));
+            }
+        }
+
+        if let Err(err) = connection.close().await {
+            return Err(VaultError::Encryption(err.to_string()));
+        }
+
+        if let Err(err) = fs::rename(&encrypted_path, path) {
+            return Err(VaultError::Encryption(err.to_string()));
+        }
+
+        Ok(())
+    }
+
+    fn quote(text: &str) -> String {
+        format!("'{}'", text.replace('\'', "''"))
     }
 
     pub fn parse_wallet(entry: &SqliteRow) -> VaultResult<WalletModel> {
@@ -249,6 +1000,13 @@ impl SqliteVault {
         let network: String = entry.get("network");
         let wallet_id: String = entry.get("wallet_id");
         let created_at: String = entry.get("created_at");
+        let label: String = entry.get("label");
+        let notes: String = entry.get("notes");
+        let colour: String = entry.get("colour");
+        let archived: bool = entry.get("archived");
+        let position: i64 = entry.get("position");
+        let script_type: String = entry.get("script_type");
+        let used: bool = entry.get("used");
 
         let blockchain = Blockchain::from_string(&blockchain);
 
@@ -269,16 +1027,235 @@ impl SqliteVault {
             wallet_id,
             path,
             created_at: Some(created_at),
+            label,
+            notes,
+            colour,
+            archived,
+            position,
+            script_type,
+            used,
+        })
+    }
+
+    pub fn parse_auth_attempts(entry: &SqliteRow) -> VaultResult<AuthAttempts> {
+        let wallet_id: String = entry.get("wallet_id");
+        let failed_attempts: i64 = entry.get("failed_attempts");
+        let last_failed_at: Option<i64> = entry.get("last_failed_at");
+
+        Ok(AuthAttempts {
+            wallet_id,
+            failed_attempts: failed_attempts as u32,
+            last_failed_at,
         })
     }
 
-    pub async fn migrate(&self) -> Result<(), VaultError> {
-        let status = sqlx::migrate!().run(&self.0).await;
+    pub fn parse_label(entry: &SqliteRow) -> VaultResult<Label> {
+        let kind: String = entry.get("type");
+        let reference: String = entry.get("ref");
+        let label: Option<String> = entry.get("label");
+        let origin: Option<String> = entry.get("origin");
+        let spendable: Option<bool> = entry.get("spendable");
+
+        let kind = LabelType::from_string(&kind);
+
+        if kind.is_none() {
+            return Err(VaultError::Parser(format!(
+                "Unknown label type for {reference}"
+            )));
+        }
+
+        Ok(Label {
+            kind: kind.unwrap(),
+            reference,
+            label,
+            origin,
+            spendable,
+        })
+    }
+
+    pub fn parse_multisig(entry: &SqliteRow) -> VaultResult<MultisigModel> {
+        let id: String = entry.get("id");
+        let name: String = entry.get("name");
+        let threshold: i64 = entry.get("threshold");
+        let script_type: String = entry.get("script_type");
+        let network: String = entry.get("network");
+        let cosigners: String = entry.get("cosigners");
+        let created_at: Option<String> = entry.get("created_at");
+
+        let script_type = MultisigScriptType::from_string(&script_type);
+
+        if script_type.is_none() {
+            return Err(VaultError::Parser(format!(
+                "Unknown script type for multisig {name}"
+            )));
+        }
+
+        let network = Network::from_string(&network);
+
+        if let Err(err) = network {
+            return Err(VaultError::Parser(err.to_string()));
+        }
+
+        let cosigners = serde_json::from_str(&cosigners);
+
+        if let Err(err) = cosigners {
+            return Err(VaultError::Parser(err.to_string()));
+        }
+
+        Ok(MultisigModel {
+            id,
+            name,
+            threshold: threshold as usize,
+            script_type: script_type.unwrap(),
+            network: network.unwrap(),
+            cosigners: cosigners.unwrap(),
+            created_at,
+        })
+    }
+
+    pub fn parse_policy_wallet(entry: &SqliteRow) -> VaultResult<PolicyWalletModel> {
+        let id: String = entry.get("id");
+        let network: String = entry.get("network");
+        let keys: String = entry.get("keys");
+        let created_at: Option<String> = entry.get("created_at");
+
+        let network = Network::from_string(&network);
+
+        if let Err(err) = network {
+            return Err(VaultError::Parser(err.to_string()));
+        }
+
+        let keys = serde_json::from_str(&keys);
+
+        if let Err(err) = keys {
+            return Err(VaultError::Parser(err.to_string()));
+        }
+
+        Ok(PolicyWalletModel {
+            id,
+            name: entry.get("name"),
+            policy: entry.get("policy"),
+            descriptor: entry.get("descriptor"),
+            network: network.unwrap(),
+            keys: keys.unwrap(),
+            created_at,
+        })
+    }
+
+    pub fn parse_audit_event(entry: &SqliteRow) -> VaultResult<AuditEvent> {
+        let wallet_id: String = entry.get("wallet_id");
+        let sequence: i64 = entry.get("sequence");
+        let action: String = entry.get("action");
+        let details: String = entry.get("details");
+        let created_at: i64 = entry.get("created_at");
+        let prev_hash: String = entry.get("prev_hash");
+        let hash: String = entry.get("hash");
+
+        let action = AuditAction::from_string(&action);
+
+        if action.is_none() {
+            return Err(VaultError::Parser(format!(
+                "Unknown audit action for event {sequence}"
+            )));
+        }
+
+        Ok(AuditEvent {
+            wallet_id,
+            sequence,
+            action: action.unwrap(),
+            details,
+            created_at,
+            prev_hash,
+            hash,
+        })
+    }
+
+    /// Versions of the migrations applied to the database.
+    pub async fn applied_migrations(&self) -> VaultResult<Vec<i64>> {
+        let res = sqlx::query(
+            "SELECT count(*) FROM sqlite_master WHERE type = 'table' AND name = '_sqlx_migrations';",
+        )
+        .fetch_one(&self.0)
+        .await;
+
+        if let Err(err) = res {
+            return Err(VaultError::Migrating(err.to_string()));
+        }
+
+        let tables: i64 = res.unwrap().get(0);
+        if tables == 0 {
+            return Ok(vec![]);
+        }
+
+        let res =
+            sqlx::query("SELECT version FROM _sqlx_migrations WHERE success ORDER BY version;")
+                .fetch_all(&self.0)
+                .await;
+
+        if let Err(err) = res {
+            return Err(VaultError::Migrating(err.to_string()));
+        }
+
+        Ok(res.unwrap().iter().map(|row| row.get(0)).collect())
+    }
+
+    /// Copies the database file next to itself, `None` when it only lives in memory.
+    pub async fn backup(&self, version: i64) -> VaultResult<Option<PathBuf>> {
+        let path = self
+            .0
+            .connect_options()
+            .as_ref()
+            .clone()
+            .get_filename()
+            .to_path_buf();
+
+        if !path.is_file() {
+            return Ok(None);
+        }
+
+        // Moves whatever sits in the write-ahead log into the file before copying it.
+        let res = sqlx::query("PRAGMA wal_checkpoint(TRUNCATE);")
+            .execute(&self.0)
+            .await;
+
+        if let Err(err) = res {
+            return Err(VaultError::Migrating(err.to_string()));
+        }
+
+        let mut backup = path.clone();
+        backup
+            .as_mut_os_string()
+            .push(format!(".v{}.{}.bak", version, unix_now()));
+
+        if let Err(err) = fs::copy(&path, &backup) {
+            return Err(VaultError::Migrating(format!(
+                "Failed backing up {}: {}",
+                path.display(),
+                err
+            )));
+        }
+
+        Ok(Some(backup))
+    }
+
+    /// Brings the schema up to date, backing the database up first when it already holds
+    /// data, the path of the backup being returned. A database migrated by a newer version of
+    /// the app is left untouched.
+    pub async fn migrate(&self) -> Result<Option<PathBuf>, VaultError> {
+        let applied = self.applied_migrations().await?;
+        schema::check_version(&applied, &MIGRATOR)?;
+        let mut backup = None;
+
+        if !applied.is_empty() && schema::has_pending(&applied, &MIGRATOR) {
+            backup = self.backup(schema::current_version(&applied)).await?;
+        }
+
+        let status = MIGRATOR.run(&self.0).await;
 
         if let Err(err) = status {
             return Err(VaultError::Migrating(err.to_string()));
         }
 
-        Ok(())
+        Ok(backup)
     }
 }
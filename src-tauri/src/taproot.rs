@@ -0,0 +1,407 @@
+use bitcoin::{
+    hex::DisplayHex,
+    sighash::{Annex, Prevouts, SighashCache},
+    taproot::{LeafVersion, TapLeafHash},
+    Psbt, TapSighash, TapSighashType, Transaction, TxOut,
+};
+use miniscript::{DefiniteDescriptorKey, Descriptor, ToPublicKey};
+use serde::Serialize;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// BIP-341 point nobody knows the private key of, the internal key of trees with no key path.
+pub const NUMS_KEY: &str = "50929b74c1a04954b78b4b6035e97a5e078a5a0f28ec96d547bfee9ace803ac0";
+
+/// Code separator position BIP-342 sighashes commit to when the script has none.
+const NO_CODESEPARATOR: u32 = 0xFFFFFFFF;
+
+#[derive(Error, Debug)]
+pub enum TaprootError {
+    #[error("Unknown sighash type {0}")]
+    SighashType(String),
+    #[error("Input {0} doesn't exist or lacks the output it spends")]
+    MissingUtxo(usize),
+    #[error("Invalid annex: {0}")]
+    Annex(String),
+    #[error("Failed computing the sighash: {0}")]
+    Sighash(String),
+    #[error("Not a taproot descriptor")]
+    NotTaproot,
+}
+
+pub type TaprootResult<T> = Result<T, TaprootError>;
+
+/// Parses sighash types the way Bitcoin Core names them, e.g. `SIGHASH_ALL` or
+/// `SINGLE|ANYONECANPAY`, the `SIGHASH_` prefix and the case being optional.
+pub fn parse_sighash_type(text: &str) -> TaprootResult<TapSighashType> {
+    let name: Vec<String> = text
+        .trim()
+        .to_uppercase()
+        .split('|')
+        .map(|part| {
+            let part = part.trim();
+            match part.starts_with("SIGHASH_") {
+                true => part.to_string(),
+                false => format!("SIGHASH_{part}"),
+            }
+        })
+        .collect();
+
+    TapSighashType::from_str(&name.join("|"))
+        .map_err(|_| TaprootError::SighashType(text.to_string()))
+}
+
+/// Whether only the input being signed is committed to, the others being free to change.
+fn anyone_can_pay(sighash_type: TapSighashType) -> bool {
+    sighash_type as u8 & 0x80 != 0
+}
+
+/// BIP-341 signature hash of input `index` of `tx`, for the key path or the script of `leaf`.
+/// `prevouts` are the outputs every input spends, only the one of `index` being needed with
+/// `ANYONECANPAY`.
+pub fn sighash(
+    tx: &Transaction,
+    prevouts: &[TxOut],
+    index: usize,
+    leaf: Option<TapLeafHash>,
+    annex: Option<&[u8]>,
+    sighash_type: TapSighashType,
+) -> TaprootResult<TapSighash> {
+    let annex = match annex {
+        Some(bytes) => match Annex::new(bytes) {
+            Ok(annex) => Some(annex),
+            Err(err) => return Err(TaprootError::Annex(err.to_string())),
+        },
+        None => None,
+    };
+
+    let prevouts = if anyone_can_pay(sighash_type) {
+        match prevouts.get(index) {
+            Some(prevout) => Prevouts::One(index, prevout.clone()),
+            None => return Err(TaprootError::MissingUtxo(index)),
+        }
+    } else {
+        Prevouts::All(prevouts)
+    };
+
+    SighashCache::new(tx)
+        .taproot_signature_hash(
+            index,
+            &prevouts,
+            annex,
+            leaf.map(|leaf| (leaf, NO_CODESEPARATOR)),
+            sighash_type,
+        )
+        .map_err(|err| TaprootError::Sighash(err.to_string()))
+}
+
+/// [`sighash`] of an input of the PSBT, each input having to carry the output it spends.
+pub fn psbt_sighash(
+    psbt: &Psbt,
+    index: usize,
+    leaf: Option<TapLeafHash>,
+    sighash_type: TapSighashType,
+) -> TaprootResult<TapSighash> {
+    let mut prevouts = vec![];
+
+    for (i, input) in psbt.inputs.iter().enumerate() {
+        let vout = psbt.unsigned_tx.input[i].previous_output.vout as usize;
+        let prevout = match (&input.witness_utxo, &input.non_witness_utxo) {
+            (Some(utxo), _) => Some(utxo.clone()),
+            (None, Some(tx)) => tx.output.get(vout).cloned(),
+            _ => None,
+        };
+
+        match prevout {
+            Some(prevout) => prevouts.push(prevout),
+            // Other inputs don't count with ANYONECANPAY, they are left out of the hash.
+            None if i != index && anyone_can_pay(sighash_type) => prevouts.push(TxOut::NULL),
+            None => return Err(TaprootError::MissingUtxo(i)),
+        }
+    }
+
+    if index >= prevouts.len() {
+        return Err(TaprootError::MissingUtxo(index));
+    }
+
+    sighash(
+        &psbt.unsigned_tx,
+        &prevouts,
+        index,
+        leaf,
+        None,
+        sighash_type,
+    )
+}
+
+/// Script of a tap leaf along with what spending through it takes.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TapLeaf {
+    pub script: String,
+    pub asm: String,
+    pub leaf_version: u8,
+    pub leaf_hash: String,
+    pub depth: u8,
+    /// Proves the leaf belongs to the tree of the output key, goes last in the witness.
+    pub control_block: String,
+}
+
+/// Keys and leaves of a `tr()` output.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TapTree {
+    pub internal_key: String,
+    pub output_key: String,
+    pub merkle_root: Option<String>,
+    pub leaves: Vec<TapLeaf>,
+}
+
+pub fn tap_tree(descriptor: &Descriptor<DefiniteDescriptorKey>) -> TaprootResult<TapTree> {
+    let Descriptor::Tr(tr) = descriptor else {
+        return Err(TaprootError::NotTaproot);
+    };
+
+    let spend_info = tr.spend_info();
+    let mut leaves = vec![];
+
+    for (depth, script) in tr.iter_scripts() {
+        let script = script.encode();
+        let control_block = spend_info.control_block(&(script.clone(), LeafVersion::TapScript));
+
+        leaves.push(TapLeaf {
+            asm: script.to_asm_string(),
+            script: script.to_hex_string(),
+            leaf_version: LeafVersion::TapScript.to_consensus(),
+            leaf_hash: TapLeafHash::from_script(&script, LeafVersion::TapScript).to_string(),
+            depth,
+            control_block: control_block
+                .map(|block| block.serialize().to_lower_hex_string())
+                .unwrap_or_default(),
+        });
+    }
+
+    Ok(TapTree {
+        internal_key: tr.internal_key().to_x_only_pubkey().to_string(),
+        output_key: spend_info.output_key().to_string(),
+        merkle_root: spend_info.merkle_root().map(|root| root.to_string()),
+        leaves,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::{consensus::deserialize, hex::FromHex, Network, ScriptBuf};
+    use miniscript::DescriptorPublicKey;
+
+    /// Taproot sighash vectors of Bitcoin Core, one per sighash type, then with an annex, through
+    /// a script path and with both: transaction, spent outputs, input, expected hash.
+    #[allow(clippy::type_complexity)]
+    const SIGHASHES: [(&str, &str, usize, &str, TapSighashType, Option<&str>, Option<&str>); 10] = [
+        (
+            "020000000164eb050a5e3da0c2a65e4786f26d753b7bc69691fabccafb11f7acef36641f1846010000003101b2b404392a22000000000017a9147f2bde86fe78bf68a0544a4f290e12f0b7e0a08c87580200000000000017a91425d11723074ecfb96a0a83c3956bfaf362ae0c908758020000000000001600147e20f938993641de67bb0cdd71682aa34c4d29ad5802000000000000160014c64984dc8761acfa99418bd6bedc79b9287d652d72000000",
+            "01365724000000000023542156b39dab4f8f3508e0432cfb41fab110170acaa2d4c42539cb90a4dc7c093bc500",
+            0,
+            "33ca0ebfb4a945eeee9569fc0f5040221275f88690b7f8592ada88ce3bdf6703",
+            TapSighashType::Default,
+            None,
+            None,
+        ),
+        (
+            "0200000002fff49be59befe7566050737910f6ccdc5e749c7f8860ddc140386463d88c5ad0f3000000002cf68eb4a3d67f9d4c079249f7e4f27b8854815cb1ed13842d4fbf395f9e217fd605ee24090100000065235d9203f458520000000000160014b6d48333bb13b4c644e57c43a9a26df3a44b785e58020000000000001976a914eea9461a9e1e3f765d3af3e726162e0229fe3eb688ac58020000000000001976a9143a8869c9f2b5ea1d4ff3aeeb6a8fb2fffb1ad5fe88ac0ad7125c",
+            "02591f220000000000225120f25ad35583ea31998d968871d7de1abd2a52f6fe4178b54ea158274806ff4ece48fb310000000000225120f25ad35583ea31998d968871d7de1abd2a52f6fe4178b54ea158274806ff4ece",
+            1,
+            "626ab955d58c9a8a600a0c580549d06dc7da4e802eb2a531f62a588e430967a8",
+            TapSighashType::All,
+            None,
+            None,
+        ),
+        (
+            "0200000001350005f65aa830ced2079df348e2d8c2bdb4f10e2dde6a161d8a07b40d1ad87dae000000001611d0d603d9dc0e000000000017a914459b6d7d6bbb4d8837b4bf7e9a4556f952da2f5c8758020000000000001976a9141dd70e1299ffc2d5b51f6f87de9dfe9398c33cbb88ac58020000000000001976a9141dd70e1299ffc2d5b51f6f87de9dfe9398c33cbb88aca71c1f4f",
+            "01c4811000000000002251201bf9297d0a2968ae6693aadd0fa514717afefd218087a239afb7418e2d22e65c",
+            0,
+            "dfa9437f9c9a1d1f9af271f79f2f5482f287cdb0d2e03fa92c8a9b216cc6061c",
+            TapSighashType::AllPlusAnyoneCanPay,
+            None,
+            None,
+        ),
+        (
+            "020000000185bed1a6da2bffbd60ec681a1bfb71c5111d6395b99b3f8b2bf90167111bcb18f5010000007c83ace802ded24a00000000001600142c4698f9f7a773866879755aa78c516fb332af8e5802000000000000160014d38639dfbac4259323b98a472405db0c461b31fa61073747",
+            "0144c84d0000000000225120e3f2107989c88e67296ab2faca930efa2e3a5bd3ff0904835a11c9e807458621",
+            0,
+            "3129de36a5d05fff97ffca31eb75fcccbbbc27b3147a7a36a9e4b45d8b625067",
+            TapSighashType::None,
+            None,
+            None,
+        ),
+        (
+            "eb93dbb901028c8515589dac980b6e7f8e4088b77ed866ca0d6d210a7218b6fd0f6b22dd6d7300000000eb4740a9047efc0e0000000000160014913da2128d8fcf292b3691db0e187414aa1783825802000000000000160014913da2128d8fcf292b3691db0e187414aa178382580200000000000017a9143dd27f01c6f7ef9bb9159937b17f17065ed01a0c875802000000000000160014d7630e19df70ada9905ede1722b800c0005f246641000000",
+            "013fed110000000000225120eb536ae8c33580290630fc495046e998086a64f8f33b93b07967d9029b265c55",
+            0,
+            "2441e8b0e063a2083ee790f14f2045022f07258ddde5ee01de543c9e789d80ae",
+            TapSighashType::NonePlusAnyoneCanPay,
+            None,
+            None,
+        ),
+        (
+            "02000000017836b409a5fed32211407e44b971591f2032053f14701fb5b3a30c0ff382f2cc9c0100000061ac55f60288fb5600000000001976a9144ea02f6f182b082fb6ce47e36bbde390b6a41b5088ac58020000000000001976a9144ea02f6f182b082fb6ce47e36bbde390b6a41b5088ace4000000",
+            "01efa558000000000022512007071ea3dc7e331b0687d0193d1e6d6ed10e645ef36f10ef8831d5e522ac9e80",
+            0,
+            "30239345177cadd0e3ea413d49803580abb6cb27971b481b7788a78d35117a88",
+            TapSighashType::Single,
+            None,
+            None,
+        ),
+        (
+            "0100000001aa6deae89d5e0aaca58714fc76ef6f3c8284224888089232d4e663843ed3ab3eae010000008b6657a60450cb4c0000000000160014a3d42b5413ef0c0701c4702f3cd7d4df222c147058020000000000001976a91430b4ed8723a4ee8992aa2c8814cfe5c3ad0ab9d988ac5802000000000000160014365b1166a6ed0a5e8e9dff17a6d00bbb43454bc758020000000000001976a914bc98c51a84fe7fad5dc380eb8b39586eff47241688ac4f313247",
+            "0107af4e00000000002251202c36d243dfc06cb56a248e62df27ecba7417307511a81ae61aa41c597a929c69",
+            0,
+            "bf9c83f26c6dd16449e4921f813f551c4218e86f2ec906ca8611175b41b566df",
+            TapSighashType::SinglePlusAnyoneCanPay,
+            None,
+            None,
+        ),
+        (
+            "0200000001df8123752e8f37d132c4e9f1ff7e4f9b986ade9211267e9ebd5fd22a5e718dec6d01000000ce4023b903cb7b23000000000017a914a18b36ea7a094db2f4940fc09edf154e86de7bd787580200000000000017a914afd0d512a2c5c2b40e25669e9cc460303c325b8b87580200000000000017a914a18b36ea7a094db2f4940fc09edf154e86de7bd787f6020000",
+            "01ea49260000000000225120ab5e9800806bf18cb246edcf5fe63441208fe955a4b5a35bbff65f5db622a010",
+            0,
+            "3b003000add359a364a156e73e02846782a59d0d95ca8c4638aaad99f2ef915c",
+            TapSighashType::SinglePlusAnyoneCanPay,
+            Some("507b979802e62d397acb29f56743a791894b99372872fc5af06a4f6e8d242d0615cda53062bb20e6ec79756fe39183f0c128adfe85559a8fa042b042c018aa8010143799e44f0893c40e1e"),
+            None,
+        ),
+        (
+            "020000000189fc651483f9296b906455dd939813bf086b1bbe7c77635e157c8e14ae29062195010000004445b5c7044561320000000000160014331414dbdada7fb578f700f38fb69995fc9b5ab958020000000000001976a914268db0a8104cc6d8afd91233cc8b3d1ace8ac3ef88ac580200000000000017a914ec00dcb368d6a693e11986d265f659d2f59e8be2875802000000000000160014c715799a49a0bae3956df9c17cb4440a673ac0df6f010000",
+            "011bec34000000000022512028055142ea437db73382e991861446040b61dd2185c4891d7daf6893d79f7182",
+            0,
+            "d66de5274a60400c7b08c86ba6b7f198f40660079edf53aca89d2a9501317f2e",
+            TapSighashType::All,
+            None,
+            Some("20cc4e1107aea1d170c5ff5b6817e1303010049724fb3caa7941792ea9d29b3e2bacab"),
+        ),
+        (
+            "020000000132fb72cb8fba496755f027a9743e2d698c831fdb8304e4d1a346ac92cbf51acba50100000026bdc7df044aad34000000000017a9144fa2554ed6174586854fa3bc01de58dcf33567d0875802000000000000160014950367e1e62cdf240b35b883fc2f5e39f0eb9ab95802000000000000160014950367e1e62cdf240b35b883fc2f5e39f0eb9ab958020000000000001600141b31217d48ccc8760dcc0710fade5866d628e733a02d5122",
+            "011458360000000000225120a7baec3fb9f84614e3899fcc010c638f80f13539344120e1f4d8b68a9a011a13",
+            0,
+            "a0042aa434f9a75904b64043f2a283f8b4c143c7f4f7f49a6cbe5b9f745f4c15",
+            TapSighashType::All,
+            Some("50a6272b470e1460e3332ade7bb14b81671c564fb6245761bd5bd531394b28860e0b3808ab229fb51791fb6ae6fa82d915b2efb8f6df83ae1f5ab3db13e30928875e2a22b749d89358de481f19286cd4caa792ce27f9559082d227a731c5486882cc707f83da361c51b7aadd9a0cf68fe7480c410fa137b454482d9a1ebf0f96d760b4d61426fc109c6e8e99a508372c45caa7b000a41f8251305da3f206c1849985ba03f3d9592832b4053afbd23ab25d0465df0bc25a36c223aacf8e04ec736a418c72dc319e4da3e972e349713ca600965e7c665f2090d5a70e241ac164115a1f5639f28b1773327715ca307ace64a2de7f0e3df70a2ffee3857689f909c0dad46d8a20fa373a4cc6eed6d4c9806bf146f0d76baae1"),
+            Some("7520ab9160dd8299dc1367659be3e8f66781fe440d52940c7f8d314a89b9f2698d406ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6eadac"),
+        ),
+    ];
+
+    #[test]
+    fn computes_sighashes_of_every_type() {
+        for (tx, prevouts, index, expected, sighash_type, annex, script) in SIGHASHES {
+            let tx: Transaction = deserialize(&Vec::from_hex(tx).unwrap()).unwrap();
+            let prevouts: Vec<TxOut> = deserialize(&Vec::from_hex(prevouts).unwrap()).unwrap();
+            let annex = annex.map(|annex| Vec::from_hex(annex).unwrap());
+            let leaf = script.map(|script| {
+                TapLeafHash::from_script(
+                    &ScriptBuf::from_hex(script).unwrap(),
+                    LeafVersion::TapScript,
+                )
+            });
+
+            let hash = sighash(&tx, &prevouts, index, leaf, annex.as_deref(), sighash_type);
+            assert_eq!(hash.unwrap().to_string(), expected, "{sighash_type}");
+        }
+    }
+
+    #[test]
+    fn parses_sighash_types() {
+        assert_eq!(
+            parse_sighash_type("SIGHASH_DEFAULT").unwrap(),
+            TapSighashType::Default
+        );
+        assert_eq!(parse_sighash_type("all").unwrap(), TapSighashType::All);
+        assert_eq!(
+            parse_sighash_type("single|anyonecanpay").unwrap(),
+            TapSighashType::SinglePlusAnyoneCanPay
+        );
+        assert_eq!(
+            parse_sighash_type("SIGHASH_NONE|SIGHASH_ANYONECANPAY").unwrap(),
+            TapSighashType::NonePlusAnyoneCanPay
+        );
+        assert!(parse_sighash_type("ANYONECANPAY").is_err());
+        assert!(parse_sighash_type("0x01").is_err());
+    }
+
+    fn definite(descriptor: &str) -> Descriptor<DefiniteDescriptorKey> {
+        Descriptor::<DescriptorPublicKey>::from_str(descriptor)
+            .unwrap()
+            .at_derivation_index(0)
+            .unwrap()
+    }
+
+    /// `scriptPubKey` vectors of BIP-341. Its `bip341_tests.json` isn't vendored, the key path
+    /// and single leaf cases are enough to pin the tweak and the control blocks.
+    #[test]
+    fn builds_bip341_outputs() {
+        let key_only =
+            definite("tr(d6889cb081036e0faefa3a35157ad71086b123b2b144b649798b494c300a961d)");
+        assert_eq!(
+            key_only.address(Network::Bitcoin).unwrap().to_string(),
+            "bc1p2wsldez5mud2yam29q22wgfh9439spgduvct83k3pm50fcxa5dps59h4z5"
+        );
+        let tree = tap_tree(&key_only).unwrap();
+        assert_eq!(
+            tree.output_key,
+            "53a1f6e454df1aa2776a2814a721372d6258050de330b3c6d10ee8f4e0dda343"
+        );
+        assert_eq!(tree.merkle_root, None);
+        assert!(tree.leaves.is_empty());
+
+        let one_leaf = definite(
+            "tr(187791b6f712a8ea41c8ecdd0ee77fab3e85263b37e1ec18a3651926b3a6cf27,pk(d85a959b0290bf19bb89ed43c916be835475d013da4b362117393e25a48229b8))",
+        );
+        assert_eq!(
+            one_leaf.address(Network::Bitcoin).unwrap().to_string(),
+            "bc1pz37fc4cn9ah8anwm4xqqhvxygjf9rjf2resrw8h8w4tmvcs0863sa2e586"
+        );
+        let tree = tap_tree(&one_leaf).unwrap();
+        assert_eq!(
+            tree.output_key,
+            "147c9c57132f6e7ecddba9800bb0c4449251c92a1e60371ee77557b6620f3ea3"
+        );
+        assert_eq!(
+            tree.leaves[0].script,
+            "20d85a959b0290bf19bb89ed43c916be835475d013da4b362117393e25a48229b8ac"
+        );
+        assert_eq!(
+            tree.leaves[0].control_block,
+            "c1187791b6f712a8ea41c8ecdd0ee77fab3e85263b37e1ec18a3651926b3a6cf27"
+        );
+    }
+
+    #[test]
+    fn lists_leaves_with_control_blocks() {
+        let descriptor = definite(&format!(
+            "tr({NUMS_KEY},{{pk(d85a959b0290bf19bb89ed43c916be835475d013da4b362117393e25a48229b8),{{pk(b617298552a72ade070667e86ca63b8f5789a9fe8731ef91202a91c9f3459007),and_v(v:pk(d6889cb081036e0faefa3a35157ad71086b123b2b144b649798b494c300a961d),older(144))}}}})"
+        ));
+        let tree = tap_tree(&descriptor).unwrap();
+
+        assert_eq!(tree.internal_key, NUMS_KEY);
+        assert!(tree.merkle_root.is_some());
+        assert_eq!(
+            tree.leaves
+                .iter()
+                .map(|leaf| leaf.depth)
+                .collect::<Vec<u8>>(),
+            vec![1, 2, 2]
+        );
+
+        // Control blocks hold the internal key, then one 32 bytes hash per level.
+        for leaf in tree.leaves.iter() {
+            assert_eq!(leaf.leaf_version, 0xc0);
+            assert_eq!(
+                leaf.control_block.len(),
+                2 * (33 + 32 * leaf.depth as usize)
+            );
+            assert_eq!(&leaf.control_block[2..66], NUMS_KEY);
+        }
+
+        let wsh =
+            definite("wsh(pk(02d85a959b0290bf19bb89ed43c916be835475d013da4b362117393e25a48229b8))");
+        assert!(matches!(tap_tree(&wsh), Err(TaprootError::NotTaproot)));
+    }
+}
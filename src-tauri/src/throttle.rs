@@ -0,0 +1,210 @@
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{
+    sync::LazyLock,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use zeroize::Zeroizing;
+
+use crate::{
+    audit::{self, AuditAction},
+    utils::{blocking, KeyedLocks},
+    vault_interface::VaultInterface,
+    wallet::{AuthError, AuthResult, WalletModel},
+};
+
+// Held from reading the failed attempts of a wallet until the outcome is recorded, so
+// concurrent guesses can't all be checked against the same count.
+static ATTEMPT_LOCKS: LazyLock<KeyedLocks> = LazyLock::new(KeyedLocks::default);
+
+/// Failed authentication attempts recorded for a wallet.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuthAttempts {
+    pub wallet_id: String,
+    pub failed_attempts: u32,
+    /// Unix timestamp (seconds) of the last failed attempt.
+    pub last_failed_at: Option<i64>,
+}
+
+/// Decides how long a wallet has to wait before the password may be tried again.
+///
+/// The first `free_attempts` failures are not delayed, every failure after that doubles
+/// the delay starting from `base_delay` up to `max_delay`. Once `lockout_threshold`
+/// failures are reached the wallet is locked out for `lockout_duration`.
+#[derive(Debug, Clone)]
+pub struct ThrottlePolicy {
+    pub free_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    pub lockout_threshold: Option<u32>,
+    pub lockout_duration: Duration,
+}
+
+impl Default for ThrottlePolicy {
+    fn default() -> Self {
+        Self {
+            free_attempts: 3,
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(5 * 60),
+            lockout_threshold: None,
+            lockout_duration: Duration::from_secs(60 * 60),
+        }
+    }
+}
+
+pub fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+impl ThrottlePolicy {
+    /// How long the wallet has to wait after its last failed attempt.
+    pub fn delay(&self, failed_attempts: u32) -> Duration {
+        if let Some(threshold) = self.lockout_threshold {
+            if failed_attempts >= threshold {
+                return self.lockout_duration;
+            }
+        }
+
+        if failed_attempts < self.free_attempts {
+            return Duration::ZERO;
+        }
+
+        let exponent = (failed_attempts - self.free_attempts).min(31);
+        self.base_delay
+            .saturating_mul(1u32 << exponent)
+            .min(self.max_delay)
+    }
+
+    /// Returns an error with the remaining wait when the wallet may not be authenticated at `now`.
+    pub fn check_at(&self, attempts: &AuthAttempts, now: i64) -> Result<(), AuthError> {
+        let last_failed_at = match attempts.last_failed_at {
+            Some(at) => at,
+            None => return Ok(()),
+        };
+
+        let delay = self.delay(attempts.failed_attempts).as_secs() as i64;
+        let remaining = last_failed_at + delay - now;
+
+        if remaining <= 0 {
+            return Ok(());
+        }
+
+        match self.lockout_threshold {
+            Some(threshold) if attempts.failed_attempts >= threshold => Err(AuthError::LockedOut(
+                attempts.failed_attempts,
+                remaining as u64,
+            )),
+            _ => Err(AuthError::Throttled(remaining as u64)),
+        }
+    }
+
+    pub fn check(&self, attempts: &AuthAttempts) -> Result<(), AuthError> {
+        self.check_at(attempts, unix_now())
+    }
+}
+
+/// Authenticates the wallet while enforcing the policy: refuses to even verify the password
+/// while the wallet is throttled, records failures and clears them on success. Both outcomes
+/// go to the audit log. Attempts on the same wallet are checked one after the other.
+pub async fn authenticate<V: VaultInterface + ?Sized>(
+    vault: &V,
+    policy: &ThrottlePolicy,
+    wallet: &WalletModel,
+    password: &str,
+) -> AuthResult {
+    let _guard = ATTEMPT_LOCKS.lock(&wallet.id).await;
+    let attempts = vault.get_auth_attempts(&wallet.id).await;
+
+    if let Err(err) = attempts {
+        return Err(AuthError::Attempts(err.to_string()));
+    }
+
+    policy.check(&attempts.unwrap())?;
+
+    let key = {
+        let wallet = wallet.clone();
+        let password = Zeroizing::new(password.to_string());
+        blocking(move || wallet.authenticate(&password)).await
+    };
+
+    let tracked = match key {
+        Ok(_) => vault.reset_auth_attempts(&wallet.id).await,
+        Err(AuthError::Failed(_)) => vault.record_failed_auth(&wallet.id).await.map(|_| ()),
+        Err(_) => Ok(()),
+    };
+
+    if let Err(err) = tracked {
+        return Err(AuthError::Attempts(err.to_string()));
+    }
+
+    let action = match key {
+        Ok(_) => Some(AuditAction::AuthSucceeded),
+        Err(AuthError::Failed(_)) => Some(AuditAction::AuthFailed),
+        Err(_) => None,
+    };
+
+    if let Some(action) = action {
+        let recorded =
+            audit::record(vault, &wallet.id, action, json!({ "method": "password" })).await;
+
+        if let Err(err) = recorded {
+            return Err(AuthError::Audit(err.to_string()));
+        }
+    }
+
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attempts(failed_attempts: u32, last_failed_at: i64) -> AuthAttempts {
+        AuthAttempts {
+            wallet_id: "wallet".to_string(),
+            failed_attempts,
+            last_failed_at: Some(last_failed_at),
+        }
+    }
+
+    #[test]
+    fn delay_grows_exponentially_up_to_max() {
+        let policy = ThrottlePolicy::default();
+
+        assert_eq!(policy.delay(2), Duration::ZERO);
+        assert_eq!(policy.delay(3), Duration::from_secs(1));
+        assert_eq!(policy.delay(4), Duration::from_secs(2));
+        assert_eq!(policy.delay(6), Duration::from_secs(8));
+        assert_eq!(policy.delay(100), policy.max_delay);
+    }
+
+    #[test]
+    fn reports_remaining_wait() {
+        let policy = ThrottlePolicy::default();
+
+        assert!(policy.check_at(&AuthAttempts::default(), 0).is_ok());
+        assert!(policy.check_at(&attempts(2, 100), 100).is_ok());
+        assert!(matches!(
+            policy.check_at(&attempts(6, 100), 103),
+            Err(AuthError::Throttled(5))
+        ));
+        assert!(policy.check_at(&attempts(6, 100), 108).is_ok());
+    }
+
+    #[test]
+    fn locks_out_after_threshold() {
+        let policy = ThrottlePolicy {
+            lockout_threshold: Some(5),
+            ..Default::default()
+        };
+
+        assert!(matches!(
+            policy.check_at(&attempts(5, 0), 60),
+            Err(AuthError::LockedOut(5, 3540))
+        ));
+        assert!(policy.check_at(&attempts(5, 0), 3600).is_ok());
+    }
+}
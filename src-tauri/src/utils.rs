@@ -3,7 +3,13 @@ use aes_gcm::aead::{Aead, KeyInit};
 use aes_gcm::{AeadCore, Aes256Gcm, Nonce};
 use bitcoin::hex::{Case, DisplayHex};
 use rand_core::{self, OsRng};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
 use thiserror::Error;
+use tokio::sync::{Mutex as AsyncMutex, OwnedMutexGuard};
+
 #[derive(Error, Debug)]
 pub enum AESError {
     #[error("Failed to encrypt: {0}")]
@@ -66,6 +72,37 @@ pub fn decrypt(key: &AESKey, data: &[u8]) -> AESResult {
     Ok(decrypted.unwrap())
 }
 
+/// Runs CPU heavy work, such as the KDF or key derivation, on the blocking thread pool so it
+/// doesn't stall every other task of the runtime. Panics of the work are carried over.
+pub async fn blocking<F, T>(work: F) -> T
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    match tokio::task::spawn_blocking(work).await {
+        Ok(output) => output,
+        Err(err) => std::panic::resume_unwind(err.into_panic()),
+    }
+}
+
+/// Async locks handed out per key, such as a wallet id, for work on the same key which
+/// mustn't interleave while work on other keys goes on.
+#[derive(Default)]
+pub struct KeyedLocks(Mutex<HashMap<String, Arc<AsyncMutex<()>>>>);
+
+impl KeyedLocks {
+    pub async fn lock(&self, key: &str) -> OwnedMutexGuard<()> {
+        let lock = {
+            let mut locks = self.0.lock().unwrap_or_else(|err| err.into_inner());
+            // Nobody holds or waits for the ones only the map references.
+            locks.retain(|_, lock| Arc::strong_count(lock) > 1);
+            locks.entry(key.to_string()).or_default().clone()
+        };
+
+        lock.lock_owned().await
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
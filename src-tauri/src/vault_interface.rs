@@ -2,8 +2,14 @@ use async_trait::async_trait;
 use thiserror::Error;
 
 use super::{
-    account::{AccountModel, StoreAccountInput},
-    wallet::{StoreWalletInput, WalletModel},
+    account::{AccountModel, StoreAccountInput, UpdateAccountInput},
+    audit::{AuditEvent, StoreAuditEventInput},
+    labels::Label,
+    multisig::{MultisigModel, StoreMultisigInput},
+    policy::{PolicyWalletModel, StorePolicyWalletInput},
+    query::{AccountPage, AccountQuery},
+    throttle::AuthAttempts,
+    wallet::{RekeyWalletInput, StoreWalletInput, UpdateWalletInput, WalletModel},
 };
 
 #[derive(Error, Debug)]
@@ -19,21 +25,83 @@ pub enum VaultError {
     #[error("Failed removing: {0}")]
     Removing(String),
     #[error("Failed migrating, cause: {0}")]
-    Migrating(String)
+    Migrating(String),
+    #[error("Failed updating: {0}")]
+    Updating(String),
+    #[error("Encryption failure: {0}")]
+    Encryption(String),
+    #[error("Transaction failure: {0}")]
+    Transaction(String),
+    #[error("Database schema version {0} is newer than {1}, the latest this version supports")]
+    SchemaTooNew(i64, i64),
+    #[error("Database failure: {0}")]
+    Database(String),
+}
+
+impl VaultError {
+    /// Error of a lookup by `key`, a missing row being [`VaultError::NotFound`] and anything
+    /// else a [`VaultError::Database`] failure.
+    pub fn lookup(key: &str, err: sqlx::Error) -> VaultError {
+        match err {
+            sqlx::Error::RowNotFound => VaultError::NotFound(key.to_string()),
+            err => VaultError::Database(err.to_string()),
+        }
+    }
 }
 
 pub type VaultResult<T> = Result<T, VaultError>;
 
+/// Writes applied all at once on [`VaultTransaction::commit`], or not at all.
+///
+/// Dropping the transaction without committing rolls it back. Reads through the vault don't
+/// see the pending writes, and other writers wait for the transaction to end.
 #[async_trait]
-pub trait VaultInterface {
+pub trait VaultTransaction: Send {
+    async fn insert_wallet(&mut self, input: StoreWalletInput) -> VaultResult<WalletModel>;
+
+    async fn insert_account(&mut self, input: StoreAccountInput) -> VaultResult<AccountModel>;
+
+    async fn update_account(&mut self, input: UpdateAccountInput) -> VaultResult<AccountModel>;
+
+    async fn remove_account_by_id(&mut self, id: &str) -> VaultResult<()>;
+
+    async fn remove_wallet_by_id(&mut self, id: &str) -> VaultResult<()>;
+
+    /// Stores the labels, replacing the ones with the same type and reference.
+    async fn set_labels(&mut self, wallet_id: &str, labels: Vec<Label>) -> VaultResult<()>;
+
+    async fn remove_policy_wallet_by_id(&mut self, id: &str) -> VaultResult<()>;
+
+    async fn remove_multisig_by_id(&mut self, id: &str) -> VaultResult<()>;
+
+    /// Chains a new event after the last one of the wallet.
+    async fn append_audit_event(&mut self, input: StoreAuditEventInput) -> VaultResult<AuditEvent>;
+
+    async fn commit(self: Box<Self>) -> VaultResult<()>;
+
+    async fn rollback(self: Box<Self>) -> VaultResult<()>;
+}
+
+#[async_trait]
+pub trait VaultInterface: Send + Sync {
+    async fn begin(&self) -> VaultResult<Box<dyn VaultTransaction + '_>>;
+
     async fn get_wallet_by_id(&self, id: &str) -> VaultResult<WalletModel>;
 
     async fn get_wallet_by_name(&self, name: &str) -> VaultResult<WalletModel>;
 
     async fn get_account_by_id(&self, id: &str) -> VaultResult<AccountModel>;
 
+    /// Accounts of the wallet, ordered by position then creation.
     async fn get_all_accounts(&self, id: &str) -> VaultResult<Vec<AccountModel>>;
 
+    /// One page of the accounts of the wallet matching the query.
+    async fn query_accounts(
+        &self,
+        wallet_id: &str,
+        query: &AccountQuery,
+    ) -> VaultResult<AccountPage>;
+
     async fn get_all_wallets(&self) -> VaultResult<Vec<WalletModel>>;
 
     async fn remove_account_by_id(&self, id: &str) -> VaultResult<()>;
@@ -43,4 +111,79 @@ pub trait VaultInterface {
     async fn insert_wallet(&self, input: StoreWalletInput) -> VaultResult<WalletModel>;
 
     async fn insert_account(&self, input: StoreAccountInput) -> VaultResult<AccountModel>;
+
+    /// Inserts every account or, when one of them fails, none.
+    async fn insert_accounts(
+        &self,
+        inputs: Vec<StoreAccountInput>,
+    ) -> VaultResult<Vec<AccountModel>> {
+        let mut tx = self.begin().await?;
+        let mut accounts = vec![];
+
+        for input in inputs {
+            accounts.push(tx.insert_account(input).await?);
+        }
+
+        tx.commit().await?;
+        Ok(accounts)
+    }
+
+    /// Renames the wallet, fails with `Updating` when the name is taken by another one.
+    async fn update_wallet(&self, input: UpdateWalletInput) -> VaultResult<WalletModel>;
+
+    async fn update_account(&self, input: UpdateAccountInput) -> VaultResult<AccountModel>;
+
+    /// BIP-329 labels stored for the wallet, ordered by type then reference.
+    async fn get_labels(&self, wallet_id: &str) -> VaultResult<Vec<Label>>;
+
+    /// Stores the labels all at once, replacing the ones with the same type and reference.
+    async fn set_labels(&self, wallet_id: &str, labels: Vec<Label>) -> VaultResult<()>;
+
+    /// Stores the multisig, fails with `Inserting` when the name is taken by another one.
+    async fn insert_multisig(&self, input: StoreMultisigInput) -> VaultResult<MultisigModel>;
+
+    async fn get_multisig_by_id(&self, id: &str) -> VaultResult<MultisigModel>;
+
+    /// Multisigs ordered by creation.
+    async fn get_all_multisigs(&self) -> VaultResult<Vec<MultisigModel>>;
+
+    /// Removes the multisig alone, its cosigner wallets are left as they are.
+    async fn remove_multisig_by_id(&self, id: &str) -> VaultResult<()>;
+
+    /// Stores the policy wallet, fails with `Inserting` when the name is taken by another one.
+    async fn insert_policy_wallet(
+        &self,
+        input: StorePolicyWalletInput,
+    ) -> VaultResult<PolicyWalletModel>;
+
+    async fn get_policy_wallet_by_id(&self, id: &str) -> VaultResult<PolicyWalletModel>;
+
+    /// Policy wallets ordered by creation.
+    async fn get_all_policy_wallets(&self) -> VaultResult<Vec<PolicyWalletModel>>;
+
+    /// Removes the policy wallet alone, the wallets holding its keys are left as they are.
+    async fn remove_policy_wallet_by_id(&self, id: &str) -> VaultResult<()>;
+
+    /// Appends an event to the audit log, which outlives the wallet it refers to.
+    async fn append_audit_event(&self, input: StoreAuditEventInput) -> VaultResult<AuditEvent> {
+        let mut tx = self.begin().await?;
+        let event = tx.append_audit_event(input).await?;
+        tx.commit().await?;
+        Ok(event)
+    }
+
+    /// Audit events of the wallet, ordered by sequence.
+    async fn get_audit_events(&self, wallet_id: &str) -> VaultResult<Vec<AuditEvent>>;
+
+    /// Returns the failed authentication attempts of the wallet, zero when none were recorded.
+    async fn get_auth_attempts(&self, wallet_id: &str) -> VaultResult<AuthAttempts>;
+
+    /// Increments the failed attempts counter of the wallet and stamps the time of the failure.
+    async fn record_failed_auth(&self, wallet_id: &str) -> VaultResult<AuthAttempts>;
+
+    async fn reset_auth_attempts(&self, wallet_id: &str) -> VaultResult<()>;
+
+    /// Atomically replaces the password hash and encrypted seed of a wallet together with
+    /// the encrypted paths of its accounts.
+    async fn rekey_wallet(&self, input: RekeyWalletInput) -> VaultResult<()>;
 }
@@ -3,16 +3,19 @@ use bitcoin::hex::{Case, DisplayHex};
 use rand::RngCore;
 use rand_core::{self, OsRng};
 
-use argon2::{
-    password_hash::{PasswordHash, PasswordHasher, SaltString},
-    Argon2, PasswordVerifier,
-};
+use argon2::{password_hash::PasswordHash, PasswordVerifier};
+use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use thiserror::Error;
+use zeroize::Zeroizing;
 
-use crate::utils::{encrypt, AESError, AESKey};
+use crate::{
+    kdf::KdfParams,
+    utils::{decrypt, encrypt, AESError, AESKey},
+    vault_interface::{VaultError, VaultResult},
+};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WalletModel {
     pub id: String,
     pub name: String,
@@ -20,19 +23,33 @@ pub struct WalletModel {
     pub seed: String,
 }
 
+/// Changes to a wallet, `None` fields are left as they are.
+#[derive(Debug, Default, Clone)]
+pub struct UpdateWalletInput {
+    pub id: String,
+    pub name: Option<String>,
+}
+
 #[derive(Error, Debug)]
 pub enum AuthError {
     #[error("Authentication failed: {0}")]
     Failed(String),
     #[error("Parser failed {0}")]
     Parser(String),
+    #[error("Too many failed attempts, retry in {0} seconds")]
+    Throttled(u64),
+    #[error("Wallet locked out after {0} failed attempts, retry in {1} seconds")]
+    LockedOut(u32, u64),
+    #[error("Failed tracking attempts: {0}")]
+    Attempts(String),
+    #[error("Failed recording audit event: {0}")]
+    Audit(String),
 }
 
 pub type AuthResult = Result<AESKey, AuthError>;
 
 impl WalletModel {
     pub fn authenticate(&self, password: &str) -> AuthResult {
-        let argon2 = Argon2::default();
         let parsed_password = PasswordHash::new(&self.password);
 
         if let Err(err) = parsed_password {
@@ -41,6 +58,14 @@ impl WalletModel {
 
         let parsed_password = parsed_password.unwrap();
 
+        let argon2 = KdfParams::from_hash(&parsed_password).and_then(|params| params.hasher());
+
+        if let Err(err) = argon2 {
+            return Err(AuthError::Parser(err.to_string()));
+        }
+
+        let argon2 = argon2.unwrap();
+
         let auth_result = argon2.verify_password(password.as_bytes(), &parsed_password);
         if let Err(err) = auth_result {
             return Err(AuthError::Failed(err.to_string()));
@@ -53,6 +78,57 @@ impl WalletModel {
         Ok(key)
     }
 
+    /// Checks a key remembered outside of the vault, by making sure it still decrypts the seed.
+    pub fn authenticate_with_key(&self, key: AESKey) -> AuthResult {
+        let seed = hex::decode(&self.seed);
+
+        if let Err(err) = seed {
+            return Err(AuthError::Parser(err.to_string()));
+        }
+
+        if let Err(err) = decrypt(&key, &seed.unwrap()) {
+            return Err(AuthError::Failed(err.to_string()));
+        }
+
+        Ok(key)
+    }
+
+    /// The BIP-39 seed of the wallet, e.g. to derive BIP-85 children from.
+    pub fn decrypt_seed(&self, key: &AESKey) -> Result<Zeroizing<Vec<u8>>, AESError> {
+        let seed = hex::decode(&self.seed);
+
+        if let Err(err) = seed {
+            return Err(AESError::Decrypt(err.to_string()));
+        }
+
+        Ok(Zeroizing::new(decrypt(key, &seed.unwrap())?))
+    }
+
+    /// The KDF parameters recorded in the wallet password hash.
+    pub fn kdf_params(&self) -> Result<KdfParams, AuthError> {
+        let parsed_password = PasswordHash::new(&self.password);
+
+        if let Err(err) = parsed_password {
+            return Err(AuthError::Parser(err.to_string()));
+        }
+
+        let params = KdfParams::from_hash(&parsed_password.unwrap());
+
+        if let Err(err) = params {
+            return Err(AuthError::Parser(err.to_string()));
+        }
+
+        Ok(params.unwrap())
+    }
+
+    /// Whether the wallet was created with weaker KDF parameters than `target`.
+    pub fn needs_rehash(&self, target: &KdfParams) -> bool {
+        match self.kdf_params() {
+            Ok(params) => params.is_weaker_than(target),
+            Err(_) => false,
+        }
+    }
+
     pub fn to_json(&self) -> Value {
         json!({
             "id": self.id,
@@ -79,11 +155,45 @@ pub struct StoreWalletInput {
     pub encrypted_seed: String,
 }
 
+/// New secrets of a wallet whose password was re-hashed, every account path re-encrypted
+/// with the new key is listed as `(account id, encrypted path)`.
+#[derive(Default, Debug)]
+pub struct RekeyWalletInput {
+    pub wallet_id: String,
+    pub encrypted_pass: String,
+    pub encrypted_seed: String,
+    pub account_paths: Vec<(String, String)>,
+}
+
+impl RekeyWalletInput {
+    /// Fails unless every one of `account_ids`, the accounts the wallet has when the vault
+    /// rekeys it, got its path re-encrypted. One inserted in the meantime would be left
+    /// under the old key, which nothing can derive anymore.
+    pub fn check_accounts<'a>(
+        &self,
+        account_ids: impl IntoIterator<Item = &'a str>,
+    ) -> VaultResult<()> {
+        for id in account_ids {
+            if !self.account_paths.iter().any(|(rekeyed, _)| rekeyed == id) {
+                return Err(VaultError::Updating(format!(
+                    "Account {id} of wallet {} has no re-encrypted path",
+                    self.wallet_id
+                )));
+            }
+        }
+
+        Ok(())
+    }
+}
+
 #[derive(Debug)]
 pub struct WalletInputBuilder {
     name: String,
     password: String,
     mnemonic: Mnemonic,
+    kdf: KdfParams,
+    /// BIP-39 passphrase the seed is derived with, the password when unset.
+    passphrase: Option<String>,
 }
 
 impl From<Mnemonic> for WalletInputBuilder {
@@ -92,6 +202,8 @@ impl From<Mnemonic> for WalletInputBuilder {
             name: String::new(),
             password: String::new(),
             mnemonic: value,
+            kdf: KdfParams::default(),
+            passphrase: None,
         }
     }
 }
@@ -106,6 +218,8 @@ impl WalletInputBuilder {
             mnemonic,
             name: "".to_string(),
             password: "".to_string(),
+            kdf: KdfParams::default(),
+            passphrase: None,
         }
     }
 
@@ -119,6 +233,18 @@ impl WalletInputBuilder {
         self
     }
 
+    pub fn kdf(&mut self, params: KdfParams) -> &mut Self {
+        self.kdf = params;
+        self
+    }
+
+    /// Derives the seed with `passphrase` rather than the password, e.g. an empty one so the
+    /// addresses match those of any other BIP-39 wallet.
+    pub fn passphrase(&mut self, passphrase: &str) -> &mut Self {
+        self.passphrase = Some(passphrase.to_string());
+        self
+    }
+
     pub fn regenerate_mnemonic(&mut self) -> &mut Self {
         let mut entropy = [0u8; 32];
         let mut rng = OsRng;
@@ -133,21 +259,17 @@ impl WalletInputBuilder {
     }
 
     pub fn build(&self) -> Result<StoreWalletInput, AESError> {
-        let salt = SaltString::generate(&mut OsRng);
-        let hasher = Argon2::default();
-        let password = hasher
-            .hash_password(&self.password.as_bytes(), &salt)
+        let (password, key) = self
+            .kdf
+            .hash_password(&self.password)
             .expect("Failed hashing the password");
 
-        let aes_key = &password.clone().hash.unwrap();
-        let mut key: [u8; 32] = [0u8; 32];
-        key.copy_from_slice(&aes_key.as_bytes()[..32]);
-
-        let seed = self.mnemonic.to_seed(&self.password);
+        let passphrase = self.passphrase.as_ref().unwrap_or(&self.password);
+        let seed = self.mnemonic.to_seed(passphrase);
 
         let encrypted_seed = encrypt(&key, &seed)?;
         Ok(StoreWalletInput {
-            encrypted_pass: password.to_string(),
+            encrypted_pass: password,
             encrypted_seed: encrypted_seed.to_hex_string(Case::Lower),
             name: self.name.to_string(),
         })
@@ -165,6 +287,8 @@ impl StoreWalletInput {
             name: name.to_string(),
             password: password.to_string(),
             mnemonic,
+            kdf: KdfParams::default(),
+            passphrase: None,
         }
     }
 }
@@ -174,7 +298,8 @@ mod tests {
     use super::*;
     #[test]
     fn can_create_wallet_input_from_name_and_password() {
-        let res = StoreWalletInput::new("name", "password");
+        let mut res = StoreWalletInput::new("name", "password");
+        res.kdf(KdfParams::fast());
         assert_eq!(res.name, "name");
         assert_eq!(res.password, "password"); // not encrypted
         assert!(res.mnemonic.to_string().len() > 0);
@@ -198,7 +323,8 @@ mod tests {
         rng.fill_bytes(&mut entropy);
         let mnemonic = Mnemonic::from_entropy(&entropy).expect("Mnemonic generation fail");
 
-        let res = WalletInputBuilder::from(mnemonic);
+        let mut res = WalletInputBuilder::from(mnemonic);
+        res.kdf(KdfParams::fast());
         assert!(res.mnemonic.to_string().len() > 0);
 
         let wallet_input = res.build().unwrap();
@@ -212,7 +338,8 @@ mod tests {
 
     #[test]
     fn can_create_wallet_input_from_new() {
-        let res = WalletInputBuilder::new();
+        let mut res = WalletInputBuilder::new();
+        res.kdf(KdfParams::fast());
         assert!(res.mnemonic.to_string().len() > 0);
 
         let wallet_input = res.build().unwrap();
@@ -223,4 +350,33 @@ mod tests {
 
         assert!(model.seed.len() > 0);
     }
+
+    #[test]
+    fn can_authenticate_with_custom_kdf_params() {
+        let params = KdfParams::new(64, 2, 1).unwrap();
+        let mut res = StoreWalletInput::new("name", "password");
+        res.kdf(params);
+
+        let model = WalletModel::from(res.build().unwrap());
+
+        assert_eq!(model.kdf_params().unwrap(), params);
+        assert!(model.authenticate("password").is_ok());
+        assert!(matches!(
+            model.authenticate("wrong"),
+            Err(AuthError::Failed(_))
+        ));
+        assert!(model.needs_rehash(&KdfParams::default()));
+    }
+
+    #[test]
+    fn can_authenticate_with_remembered_key() {
+        let mut res = StoreWalletInput::new("name", "password");
+        res.kdf(KdfParams::fast());
+
+        let model = WalletModel::from(res.build().unwrap());
+        let key = model.authenticate("password").unwrap();
+
+        assert_eq!(model.authenticate_with_key(key).unwrap(), key);
+        assert!(model.authenticate_with_key([0u8; 32]).is_err());
+    }
 }
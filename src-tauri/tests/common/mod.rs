@@ -0,0 +1,976 @@
+//! Behaviour every `VaultInterface` implementation has to share, run against each backend
+//! through `vault_conformance!`.
+#![allow(dead_code)]
+
+use account::{Blockchain, Network, StoreAccountInput, UpdateAccountInput};
+use audit::{AuditAction, GENESIS_HASH};
+use dev_wallet::*;
+use kdf::KdfParams;
+use labels::{Label, LabelType};
+use multisig::{Cosigner, MultisigScriptType, StoreMultisigInput};
+use policy::{PolicyKey, StorePolicyWalletInput};
+use query::{AccountQuery, AccountSort, SortDirection};
+use serde_json::json;
+use vault_interface::{VaultError, VaultInterface};
+use wallet::{
+    RekeyWalletInput, StoreWalletInput, UpdateWalletInput, WalletInputBuilder, WalletModel,
+};
+
+pub fn wallet_input(name: &str) -> StoreWalletInput {
+    let mut wallet = WalletInputBuilder::new();
+    wallet.name(name);
+    wallet.password("password");
+    wallet.kdf(KdfParams::fast());
+    wallet.build().unwrap()
+}
+
+pub fn account_input(wallet: &WalletModel, address: &str) -> StoreAccountInput {
+    StoreAccountInput {
+        wallet_id: wallet.id.clone(),
+        address: address.to_string(),
+        encrypted_path: format!("path-{address}"),
+        blockchain: Blockchain::Bitcoin,
+        network: Network::Mainnet,
+    }
+}
+
+pub async fn can_insert_and_get_wallet(vault: &dyn VaultInterface) {
+    let input = wallet_input("main");
+    let seed = input.encrypted_seed.clone();
+    let wallet = vault.insert_wallet(input).await.unwrap();
+
+    let by_id = vault.get_wallet_by_id(&wallet.id).await.unwrap();
+    assert_eq!(by_id.name, "main");
+    assert_eq!(by_id.seed, seed);
+    assert_eq!(by_id.password, wallet.password);
+
+    let by_name = vault.get_wallet_by_name("main").await.unwrap();
+    assert_eq!(by_name.id, wallet.id);
+}
+
+pub async fn rejects_duplicate_wallet_name(vault: &dyn VaultInterface) {
+    vault.insert_wallet(wallet_input("main")).await.unwrap();
+
+    let res = vault.insert_wallet(wallet_input("main")).await;
+    assert!(matches!(res, Err(VaultError::Inserting(_))));
+    assert_eq!(vault.get_all_wallets().await.unwrap().len(), 1);
+}
+
+pub async fn reports_missing_wallet(vault: &dyn VaultInterface) {
+    assert!(matches!(
+        vault.get_wallet_by_id("missing").await,
+        Err(VaultError::NotFound(_))
+    ));
+    assert!(matches!(
+        vault.get_wallet_by_name("missing").await,
+        Err(VaultError::NotFound(_))
+    ));
+}
+
+pub async fn can_list_wallets(vault: &dyn VaultInterface) {
+    assert!(vault.get_all_wallets().await.unwrap().is_empty());
+
+    vault.insert_wallet(wallet_input("main")).await.unwrap();
+    vault.insert_wallet(wallet_input("second")).await.unwrap();
+
+    let names: Vec<String> = vault
+        .get_all_wallets()
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|wallet| wallet.name)
+        .collect();
+    assert_eq!(names, vec!["main", "second"]);
+}
+
+pub async fn can_insert_and_get_account(vault: &dyn VaultInterface) {
+    let wallet = vault.insert_wallet(wallet_input("main")).await.unwrap();
+    let account = vault
+        .insert_account(account_input(&wallet, "address"))
+        .await
+        .unwrap();
+
+    let found = vault.get_account_by_id(&account.id).await.unwrap();
+    assert_eq!(found.wallet_id, wallet.id);
+    assert_eq!(found.address, "address");
+    assert_eq!(found.path, "path-address");
+    assert_eq!(found.network, Network::Mainnet.to_string());
+    assert_eq!(found.blockchain, Blockchain::Bitcoin.to_string());
+    assert!(found.created_at.is_some());
+}
+
+pub async fn rejects_duplicate_address(vault: &dyn VaultInterface) {
+    let wallet = vault.insert_wallet(wallet_input("main")).await.unwrap();
+    vault
+        .insert_account(account_input(&wallet, "address"))
+        .await
+        .unwrap();
+
+    let mut duplicate = account_input(&wallet, "address");
+    duplicate.encrypted_path = "other-path".to_string();
+    let res = vault.insert_account(duplicate).await;
+    assert!(matches!(res, Err(VaultError::Inserting(_))));
+}
+
+pub async fn rejects_account_of_missing_wallet(vault: &dyn VaultInterface) {
+    let wallet = WalletModel::from(wallet_input("ghost"));
+    let res = vault
+        .insert_account(account_input(&wallet, "address"))
+        .await;
+    assert!(matches!(res, Err(VaultError::Inserting(_))));
+}
+
+pub async fn reports_missing_account(vault: &dyn VaultInterface) {
+    assert!(matches!(
+        vault.get_account_by_id("missing").await,
+        Err(VaultError::NotFound(_))
+    ));
+}
+
+pub async fn lists_accounts_per_wallet(vault: &dyn VaultInterface) {
+    let main = vault.insert_wallet(wallet_input("main")).await.unwrap();
+    let second = vault.insert_wallet(wallet_input("second")).await.unwrap();
+
+    for address in ["a", "b"] {
+        vault
+            .insert_account(account_input(&main, address))
+            .await
+            .unwrap();
+    }
+    vault
+        .insert_account(account_input(&second, "c"))
+        .await
+        .unwrap();
+
+    assert_eq!(vault.get_all_accounts(&main.id).await.unwrap().len(), 2);
+    assert_eq!(vault.get_all_accounts(&second.id).await.unwrap().len(), 1);
+    assert!(vault.get_all_accounts("missing").await.unwrap().is_empty());
+}
+
+pub async fn can_remove_account(vault: &dyn VaultInterface) {
+    let wallet = vault.insert_wallet(wallet_input("main")).await.unwrap();
+    let account = vault
+        .insert_account(account_input(&wallet, "address"))
+        .await
+        .unwrap();
+
+    vault.remove_account_by_id(&account.id).await.unwrap();
+    assert!(matches!(
+        vault.get_account_by_id(&account.id).await,
+        Err(VaultError::NotFound(_))
+    ));
+
+    // Removing something which is not there is not an error.
+    vault.remove_account_by_id(&account.id).await.unwrap();
+}
+
+pub async fn removing_wallet_cascades(vault: &dyn VaultInterface) {
+    let wallet = vault.insert_wallet(wallet_input("main")).await.unwrap();
+    let other = vault.insert_wallet(wallet_input("other")).await.unwrap();
+    let account = vault
+        .insert_account(account_input(&wallet, "address"))
+        .await
+        .unwrap();
+    let other_account = vault
+        .insert_account(account_input(&other, "other-address"))
+        .await
+        .unwrap();
+    vault.record_failed_auth(&wallet.id).await.unwrap();
+
+    vault.remove_wallet_by_id(&wallet.id).await.unwrap();
+
+    assert!(matches!(
+        vault.get_wallet_by_id(&wallet.id).await,
+        Err(VaultError::NotFound(_))
+    ));
+    assert!(vault.get_account_by_id(&account.id).await.is_err());
+    let attempts = vault.get_auth_attempts(&wallet.id).await.unwrap();
+    assert_eq!(attempts.failed_attempts, 0);
+
+    assert!(vault.get_wallet_by_id(&other.id).await.is_ok());
+    assert!(vault.get_account_by_id(&other_account.id).await.is_ok());
+
+    // The name is free again.
+    vault.insert_wallet(wallet_input("main")).await.unwrap();
+}
+
+pub async fn tracks_auth_attempts(vault: &dyn VaultInterface) {
+    let wallet = vault.insert_wallet(wallet_input("main")).await.unwrap();
+
+    let attempts = vault.get_auth_attempts(&wallet.id).await.unwrap();
+    assert_eq!(attempts.failed_attempts, 0);
+    assert!(attempts.last_failed_at.is_none());
+
+    vault.record_failed_auth(&wallet.id).await.unwrap();
+    let attempts = vault.record_failed_auth(&wallet.id).await.unwrap();
+    assert_eq!(attempts.failed_attempts, 2);
+    assert!(attempts.last_failed_at.is_some());
+    assert_eq!(vault.get_auth_attempts(&wallet.id).await.unwrap(), attempts);
+
+    vault.reset_auth_attempts(&wallet.id).await.unwrap();
+    let attempts = vault.get_auth_attempts(&wallet.id).await.unwrap();
+    assert_eq!(attempts.failed_attempts, 0);
+
+    assert!(vault.record_failed_auth("missing").await.is_err());
+}
+
+pub async fn can_rekey_wallet(vault: &dyn VaultInterface) {
+    let wallet = vault.insert_wallet(wallet_input("main")).await.unwrap();
+    let account = vault
+        .insert_account(account_input(&wallet, "address"))
+        .await
+        .unwrap();
+
+    vault
+        .rekey_wallet(RekeyWalletInput {
+            wallet_id: wallet.id.clone(),
+            encrypted_pass: "new-pass".to_string(),
+            encrypted_seed: "new-seed".to_string(),
+            account_paths: vec![(account.id.clone(), "new-path".to_string())],
+        })
+        .await
+        .unwrap();
+
+    let wallet = vault.get_wallet_by_id(&wallet.id).await.unwrap();
+    assert_eq!(wallet.password, "new-pass");
+    assert_eq!(wallet.seed, "new-seed");
+    let account = vault.get_account_by_id(&account.id).await.unwrap();
+    assert_eq!(account.path, "new-path");
+
+    // An account missing from the input would be left under the old key.
+    let other = vault
+        .insert_account(account_input(&wallet, "other"))
+        .await
+        .unwrap();
+    let res = vault
+        .rekey_wallet(RekeyWalletInput {
+            wallet_id: wallet.id.clone(),
+            encrypted_pass: "newer-pass".to_string(),
+            encrypted_seed: "newer-seed".to_string(),
+            account_paths: vec![(account.id.clone(), "newer-path".to_string())],
+        })
+        .await;
+    assert!(matches!(res, Err(VaultError::Updating(_))));
+
+    let wallet = vault.get_wallet_by_id(&wallet.id).await.unwrap();
+    assert_eq!(wallet.password, "new-pass");
+    assert_eq!(wallet.seed, "new-seed");
+    let account = vault.get_account_by_id(&account.id).await.unwrap();
+    assert_eq!(account.path, "new-path");
+    assert_eq!(
+        vault.get_account_by_id(&other.id).await.unwrap().path,
+        other.path
+    );
+
+    let res = vault
+        .rekey_wallet(RekeyWalletInput {
+            wallet_id: "missing".to_string(),
+            ..Default::default()
+        })
+        .await;
+    assert!(matches!(res, Err(VaultError::NotFound(_))));
+}
+
+pub async fn can_rename_wallet(vault: &dyn VaultInterface) {
+    let wallet = vault.insert_wallet(wallet_input("main")).await.unwrap();
+    vault.insert_wallet(wallet_input("other")).await.unwrap();
+
+    let renamed = vault
+        .update_wallet(UpdateWalletInput {
+            id: wallet.id.clone(),
+            name: Some("renamed".to_string()),
+        })
+        .await
+        .unwrap();
+    assert_eq!(renamed.name, "renamed");
+    assert_eq!(renamed.seed, wallet.seed);
+    assert_eq!(
+        vault.get_wallet_by_name("renamed").await.unwrap().id,
+        wallet.id
+    );
+
+    // Names stay unique.
+    let res = vault
+        .update_wallet(UpdateWalletInput {
+            id: wallet.id.clone(),
+            name: Some("other".to_string()),
+        })
+        .await;
+    assert!(matches!(res, Err(VaultError::Updating(_))));
+    assert_eq!(
+        vault.get_wallet_by_id(&wallet.id).await.unwrap().name,
+        "renamed"
+    );
+
+    let res = vault
+        .update_wallet(UpdateWalletInput {
+            id: "missing".to_string(),
+            name: Some("missing".to_string()),
+        })
+        .await;
+    assert!(matches!(res, Err(VaultError::NotFound(_))));
+}
+
+pub async fn can_update_account_metadata(vault: &dyn VaultInterface) {
+    let wallet = vault.insert_wallet(wallet_input("main")).await.unwrap();
+    let account = vault
+        .insert_account(account_input(&wallet, "address"))
+        .await
+        .unwrap();
+    assert_eq!(account.label, "");
+    assert!(!account.archived);
+
+    let updated = vault
+        .update_account(UpdateAccountInput {
+            id: account.id.clone(),
+            label: Some("savings".to_string()),
+            notes: Some("cold storage".to_string()),
+            colour: Some("#ff8800".to_string()),
+            archived: Some(true),
+            position: Some(3),
+            used: Some(true),
+        })
+        .await
+        .unwrap();
+    assert_eq!(updated.label, "savings");
+    assert_eq!(updated.address, "address");
+
+    // Fields left to `None` are not touched.
+    vault
+        .update_account(UpdateAccountInput {
+            id: account.id.clone(),
+            label: Some("spending".to_string()),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    let found = vault.get_account_by_id(&account.id).await.unwrap();
+    assert_eq!(found.label, "spending");
+    assert_eq!(found.notes, "cold storage");
+    assert_eq!(found.colour, "#ff8800");
+    assert!(found.archived);
+    assert_eq!(found.position, 3);
+    assert!(found.used);
+    assert_eq!(found.path, "path-address");
+
+    let res = vault
+        .update_account(UpdateAccountInput {
+            id: "missing".to_string(),
+            label: Some("label".to_string()),
+            ..Default::default()
+        })
+        .await;
+    assert!(matches!(res, Err(VaultError::NotFound(_))));
+}
+
+pub async fn orders_accounts_by_position(vault: &dyn VaultInterface) {
+    let wallet = vault.insert_wallet(wallet_input("main")).await.unwrap();
+
+    let mut ids = vec![];
+    for address in ["a", "b", "c"] {
+        let account = vault
+            .insert_account(account_input(&wallet, address))
+            .await
+            .unwrap();
+        ids.push(account.id);
+    }
+
+    vault
+        .update_account(UpdateAccountInput {
+            id: ids[0].clone(),
+            position: Some(1),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    let addresses: Vec<String> = vault
+        .get_all_accounts(&wallet.id)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|account| account.address)
+        .collect();
+    assert_eq!(addresses, vec!["b", "c", "a"]);
+}
+
+pub async fn can_query_accounts(vault: &dyn VaultInterface) {
+    let wallet = vault.insert_wallet(wallet_input("main")).await.unwrap();
+    let other = vault.insert_wallet(wallet_input("other")).await.unwrap();
+    vault
+        .insert_account(account_input(&other, "other-address"))
+        .await
+        .unwrap();
+
+    let addresses = [
+        "1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2",
+        "3J98t1WpEZ73CNmQviecrnyiWrnqRhWNLy",
+        "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq",
+        "bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr",
+        "not-an-address",
+    ];
+    let mut ids = vec![];
+    for (index, address) in addresses.iter().enumerate() {
+        let mut input = account_input(&wallet, address);
+        if index == 0 {
+            input.network = Network::Testnet;
+        }
+        ids.push(vault.insert_account(input).await.unwrap().id);
+    }
+
+    vault
+        .update_account(UpdateAccountInput {
+            id: ids[1].clone(),
+            label: Some("Savings 100%".to_string()),
+            used: Some(true),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    let count = |query: AccountQuery| {
+        let wallet_id = wallet.id.clone();
+        async move {
+            vault
+                .query_accounts(&wallet_id, &query)
+                .await
+                .unwrap()
+                .accounts
+                .len()
+        }
+    };
+
+    assert_eq!(count(AccountQuery::default()).await, 5);
+    assert_eq!(
+        count(AccountQuery {
+            script_type: Some("p2wpkh".to_string()),
+            ..Default::default()
+        })
+        .await,
+        1
+    );
+    assert_eq!(
+        count(AccountQuery {
+            network: Some(Network::Testnet.to_string()),
+            ..Default::default()
+        })
+        .await,
+        1
+    );
+    assert_eq!(
+        count(AccountQuery {
+            label: Some("sAVINGS 1".to_string()),
+            ..Default::default()
+        })
+        .await,
+        1
+    );
+    // `%` is matched literally.
+    assert_eq!(
+        count(AccountQuery {
+            label: Some("0%".to_string()),
+            ..Default::default()
+        })
+        .await,
+        1
+    );
+    assert_eq!(
+        count(AccountQuery {
+            label: Some("%s".to_string()),
+            ..Default::default()
+        })
+        .await,
+        0
+    );
+    assert_eq!(
+        count(AccountQuery {
+            used: Some(false),
+            ..Default::default()
+        })
+        .await,
+        4
+    );
+
+    let found = vault
+        .query_accounts(
+            &wallet.id,
+            &AccountQuery {
+                script_type: Some("p2tr".to_string()),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(found.accounts[0].address, addresses[3]);
+    assert!(found.next_cursor.is_none());
+
+    // Walking the pages gives every account once, in order.
+    let mut query = AccountQuery {
+        sort: AccountSort::Address,
+        direction: SortDirection::Desc,
+        limit: Some(2),
+        ..Default::default()
+    };
+    let mut seen = vec![];
+    loop {
+        let page = vault.query_accounts(&wallet.id, &query).await.unwrap();
+        assert!(page.accounts.len() <= 2);
+        seen.extend(page.accounts.into_iter().map(|account| account.address));
+
+        match page.next_cursor {
+            Some(cursor) => query.cursor = Some(cursor),
+            None => break,
+        }
+    }
+    let mut expected: Vec<String> = addresses.iter().map(|a| a.to_string()).collect();
+    expected.sort();
+    expected.reverse();
+    assert_eq!(seen, expected);
+
+    for sort in [
+        AccountSort::Position,
+        AccountSort::CreatedAt,
+        AccountSort::Label,
+    ] {
+        let mut query = AccountQuery {
+            sort,
+            limit: Some(2),
+            ..Default::default()
+        };
+        let mut seen = vec![];
+        loop {
+            let page = vault.query_accounts(&wallet.id, &query).await.unwrap();
+            seen.extend(page.accounts.into_iter().map(|account| account.id));
+
+            match page.next_cursor {
+                Some(cursor) => query.cursor = Some(cursor),
+                None => break,
+            }
+        }
+        seen.sort();
+        let mut expected = ids.clone();
+        expected.sort();
+        assert_eq!(seen, expected, "{sort:?}");
+    }
+
+    let res = vault
+        .query_accounts(
+            &wallet.id,
+            &AccountQuery {
+                cursor: Some("garbage".to_string()),
+                ..Default::default()
+            },
+        )
+        .await;
+    assert!(matches!(res, Err(VaultError::Parser(_))));
+}
+
+pub async fn insert_accounts_is_atomic(vault: &dyn VaultInterface) {
+    let wallet = vault.insert_wallet(wallet_input("main")).await.unwrap();
+
+    let accounts = vault
+        .insert_accounts(vec![
+            account_input(&wallet, "a"),
+            account_input(&wallet, "b"),
+        ])
+        .await
+        .unwrap();
+    assert_eq!(accounts.len(), 2);
+
+    // "b" is already taken, "c" and "d" must not be inserted either.
+    let res = vault
+        .insert_accounts(vec![
+            account_input(&wallet, "c"),
+            account_input(&wallet, "b"),
+            account_input(&wallet, "d"),
+        ])
+        .await;
+    assert!(matches!(res, Err(VaultError::Inserting(_))));
+
+    // Same within the batch itself.
+    let res = vault
+        .insert_accounts(vec![
+            account_input(&wallet, "e"),
+            account_input(&wallet, "e"),
+        ])
+        .await;
+    assert!(res.is_err());
+
+    let addresses: Vec<String> = vault
+        .get_all_accounts(&wallet.id)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|account| account.address)
+        .collect();
+    assert_eq!(addresses, vec!["a", "b"]);
+}
+
+pub async fn transactions_commit_or_roll_back(vault: &dyn VaultInterface) {
+    let mut tx = vault.begin().await.unwrap();
+    let wallet = tx.insert_wallet(wallet_input("main")).await.unwrap();
+    tx.insert_account(account_input(&wallet, "a"))
+        .await
+        .unwrap();
+    tx.rollback().await.unwrap();
+    assert!(vault.get_all_wallets().await.unwrap().is_empty());
+
+    // Dropping is the same as rolling back.
+    {
+        let mut tx = vault.begin().await.unwrap();
+        tx.insert_wallet(wallet_input("main")).await.unwrap();
+    }
+    assert!(vault.get_all_wallets().await.unwrap().is_empty());
+
+    let mut tx = vault.begin().await.unwrap();
+    let wallet = tx.insert_wallet(wallet_input("main")).await.unwrap();
+    let account = tx
+        .insert_account(account_input(&wallet, "a"))
+        .await
+        .unwrap();
+    tx.update_account(UpdateAccountInput {
+        id: account.id.clone(),
+        label: Some("label".to_string()),
+        ..Default::default()
+    })
+    .await
+    .unwrap();
+    tx.commit().await.unwrap();
+
+    let found = vault.get_account_by_id(&account.id).await.unwrap();
+    assert_eq!(found.label, "label");
+
+    let mut tx = vault.begin().await.unwrap();
+    tx.remove_wallet_by_id(&wallet.id).await.unwrap();
+    tx.rollback().await.unwrap();
+    assert!(vault.get_wallet_by_id(&wallet.id).await.is_ok());
+    assert!(vault.get_account_by_id(&account.id).await.is_ok());
+}
+
+pub async fn records_audit_events(vault: &dyn VaultInterface) {
+    let wallet = vault.insert_wallet(wallet_input("main")).await.unwrap();
+    let other = vault.insert_wallet(wallet_input("other")).await.unwrap();
+
+    let accounts = audit::insert_accounts(
+        vault,
+        vec![account_input(&wallet, "a"), account_input(&wallet, "b")],
+    )
+    .await
+    .unwrap();
+
+    // Failed operations leave no trace.
+    let res = audit::insert_accounts(
+        vault,
+        vec![account_input(&wallet, "c"), account_input(&wallet, "a")],
+    )
+    .await;
+    assert!(res.is_err());
+
+    audit::remove_account(vault, &accounts[0]).await.unwrap();
+    audit::record(
+        vault,
+        &other.id,
+        AuditAction::SecretExported,
+        json!({ "kind": "xprv" }),
+    )
+    .await
+    .unwrap();
+    audit::remove_wallet(vault, &wallet).await.unwrap();
+
+    // The log outlives the wallet.
+    let events = vault.get_audit_events(&wallet.id).await.unwrap();
+    let actions: Vec<AuditAction> = events.iter().map(|event| event.action).collect();
+    assert_eq!(
+        actions,
+        vec![
+            AuditAction::AccountCreated,
+            AuditAction::AccountCreated,
+            AuditAction::AccountRemoved,
+            AuditAction::WalletRemoved,
+        ]
+    );
+    assert_eq!(events[0].prev_hash, GENESIS_HASH);
+    assert_eq!(events[3].sequence, 4);
+    assert!(events[2].details.contains(&accounts[0].address));
+    assert_eq!(audit::verify(&events), Ok(()));
+
+    let events = vault.get_audit_events(&other.id).await.unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].prev_hash, GENESIS_HASH);
+    assert_eq!(audit::verify(&events), Ok(()));
+}
+
+pub fn label(kind: LabelType, reference: &str, text: &str) -> Label {
+    Label {
+        kind,
+        reference: reference.to_string(),
+        label: Some(text.to_string()),
+        origin: None,
+        spendable: None,
+    }
+}
+
+pub async fn can_store_labels(vault: &dyn VaultInterface) {
+    let wallet = vault.insert_wallet(wallet_input("main")).await.unwrap();
+    let other = vault.insert_wallet(wallet_input("other")).await.unwrap();
+    assert!(vault.get_labels(&wallet.id).await.unwrap().is_empty());
+
+    let mut output = label(LabelType::Output, "txid:1", "change");
+    output.spendable = Some(false);
+    output.origin = Some("wpkh([d34db33f/84'/0'/0'])".to_string());
+
+    vault
+        .set_labels(
+            &wallet.id,
+            vec![output.clone(), label(LabelType::Tx, "txid", "rent")],
+        )
+        .await
+        .unwrap();
+    vault
+        .set_labels(&other.id, vec![label(LabelType::Tx, "txid", "other")])
+        .await
+        .unwrap();
+
+    // Same type and reference replaces the label.
+    vault
+        .set_labels(&wallet.id, vec![label(LabelType::Tx, "txid", "groceries")])
+        .await
+        .unwrap();
+
+    let labels = vault.get_labels(&wallet.id).await.unwrap();
+    assert_eq!(
+        labels,
+        vec![output, label(LabelType::Tx, "txid", "groceries")]
+    );
+    assert_eq!(vault.get_labels(&other.id).await.unwrap().len(), 1);
+
+    // Labels set in a transaction only land with it.
+    let mut tx = vault.begin().await.unwrap();
+    tx.set_labels(&other.id, vec![label(LabelType::Tx, "txid", "rolled back")])
+        .await
+        .unwrap();
+    tx.rollback().await.unwrap();
+    assert_eq!(
+        vault.get_labels(&other.id).await.unwrap(),
+        vec![label(LabelType::Tx, "txid", "other")]
+    );
+
+    assert!(vault
+        .set_labels("missing", vec![label(LabelType::Tx, "txid", "rent")])
+        .await
+        .is_err());
+
+    vault.remove_wallet_by_id(&wallet.id).await.unwrap();
+    assert!(vault.get_labels(&wallet.id).await.unwrap().is_empty());
+}
+
+pub async fn can_store_multisigs(vault: &dyn VaultInterface) {
+    let wallet = vault.insert_wallet(wallet_input("main")).await.unwrap();
+    let input = |name: &str| StoreMultisigInput {
+        name: name.to_string(),
+        threshold: 2,
+        script_type: MultisigScriptType::ShWsh,
+        network: Network::Regtest,
+        cosigners: vec![
+            Cosigner {
+                key: "[d34db33f/48'/1'/0'/1']tpub-local".to_string(),
+                wallet_id: Some(wallet.id.clone()),
+            },
+            Cosigner {
+                key: "[f00dbabe/48'/1'/0'/1']tpub-imported".to_string(),
+                wallet_id: None,
+            },
+        ],
+    };
+
+    let first = vault.insert_multisig(input("vault")).await.unwrap();
+    let second = vault.insert_multisig(input("savings")).await.unwrap();
+    assert!(first.created_at.is_some());
+    assert_eq!(first.threshold, 2);
+    assert_eq!(first.script_type, MultisigScriptType::ShWsh);
+    assert_eq!(first.network, Network::Regtest);
+    assert_eq!(first.cosigners, input("vault").cosigners);
+
+    assert!(matches!(
+        vault.insert_multisig(input("vault")).await,
+        Err(VaultError::Inserting(_))
+    ));
+
+    assert_eq!(vault.get_multisig_by_id(&first.id).await.unwrap(), first);
+    let ids: Vec<String> = vault
+        .get_all_multisigs()
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|multisig| multisig.id)
+        .collect();
+    assert_eq!(ids, vec![first.id.clone(), second.id.clone()]);
+
+    let mut tx = vault.begin().await.unwrap();
+    tx.remove_multisig_by_id(&first.id).await.unwrap();
+    tx.rollback().await.unwrap();
+    assert!(vault.get_multisig_by_id(&first.id).await.is_ok());
+
+    audit::remove_multisig(vault, &first, &wallet.id)
+        .await
+        .unwrap();
+    assert!(matches!(
+        vault.get_multisig_by_id(&first.id).await,
+        Err(VaultError::NotFound(_))
+    ));
+    assert_eq!(vault.get_all_multisigs().await.unwrap(), vec![second]);
+    assert!(vault.get_wallet_by_id(&wallet.id).await.is_ok());
+
+    let events = vault.get_audit_events(&wallet.id).await.unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].action, AuditAction::MultisigRemoved);
+    assert!(events[0].details.contains(&first.id));
+}
+
+pub async fn can_store_policy_wallets(vault: &dyn VaultInterface) {
+    let wallet = vault.insert_wallet(wallet_input("main")).await.unwrap();
+    let input = |name: &str| StorePolicyWalletInput {
+        name: name.to_string(),
+        policy: "or(pk(main),and(pk(backup),older(1000)))".to_string(),
+        descriptor: "wsh(or_d(pk(main-key),and_v(v:pk(backup-key),older(1000))))".to_string(),
+        network: Network::Regtest,
+        keys: vec![
+            PolicyKey {
+                alias: "main".to_string(),
+                cosigner: Cosigner {
+                    key: "[d34db33f/48'/1'/0'/2']tpub-local".to_string(),
+                    wallet_id: Some(wallet.id.clone()),
+                },
+            },
+            PolicyKey {
+                alias: "backup".to_string(),
+                cosigner: Cosigner {
+                    key: "[f00dbabe/48'/1'/0'/2']tpub-imported".to_string(),
+                    wallet_id: None,
+                },
+            },
+        ],
+    };
+
+    let first = vault.insert_policy_wallet(input("recovery")).await.unwrap();
+    let second = vault
+        .insert_policy_wallet(input("inheritance"))
+        .await
+        .unwrap();
+    assert!(first.created_at.is_some());
+    assert_eq!(first.policy, input("recovery").policy);
+    assert_eq!(first.descriptor, input("recovery").descriptor);
+    assert_eq!(first.network, Network::Regtest);
+    assert_eq!(first.keys, input("recovery").keys);
+
+    assert!(matches!(
+        vault.insert_policy_wallet(input("recovery")).await,
+        Err(VaultError::Inserting(_))
+    ));
+
+    assert_eq!(
+        vault.get_policy_wallet_by_id(&first.id).await.unwrap(),
+        first
+    );
+    let ids: Vec<String> = vault
+        .get_all_policy_wallets()
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|wallet| wallet.id)
+        .collect();
+    assert_eq!(ids, vec![first.id.clone(), second.id.clone()]);
+
+    let mut tx = vault.begin().await.unwrap();
+    tx.remove_policy_wallet_by_id(&first.id).await.unwrap();
+    tx.rollback().await.unwrap();
+    assert!(vault.get_policy_wallet_by_id(&first.id).await.is_ok());
+
+    audit::remove_policy_wallet(vault, &first, &wallet.id)
+        .await
+        .unwrap();
+    assert!(matches!(
+        vault.get_policy_wallet_by_id(&first.id).await,
+        Err(VaultError::NotFound(_))
+    ));
+    assert_eq!(vault.get_all_policy_wallets().await.unwrap(), vec![second]);
+    assert!(vault.get_wallet_by_id(&wallet.id).await.is_ok());
+
+    let events = vault.get_audit_events(&wallet.id).await.unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].action, AuditAction::PolicyWalletRemoved);
+    assert!(events[0].details.contains(&first.id));
+}
+
+pub async fn chains_concurrent_audit_events(vault: &dyn VaultInterface) {
+    let wallet = vault.insert_wallet(wallet_input("main")).await.unwrap();
+    let record = |attempt: u32| {
+        audit::record(
+            vault,
+            &wallet.id,
+            AuditAction::AuthFailed,
+            json!({ "attempt": attempt }),
+        )
+    };
+
+    let (a, b, c, d) = tokio::join!(record(1), record(2), record(3), record(4));
+    for res in [a, b, c, d] {
+        res.unwrap();
+    }
+
+    let events = vault.get_audit_events(&wallet.id).await.unwrap();
+    assert_eq!(events.len(), 4);
+    assert_eq!(audit::verify(&events), Ok(()));
+}
+
+/// Generates a test module running every conformance check against the vault built by
+/// `$vault`, a fresh one being built for each check. An attribute given before `$vault`, like
+/// `#[ignore]`, is put on every check.
+#[macro_export]
+macro_rules! vault_conformance {
+    ($backend:ident, #[$attr:meta], $vault:expr) => {
+        mod $backend {
+            use super::*;
+
+            macro_rules! check {
+                ($name:ident) => {
+                    #[tokio::test]
+                    #[$attr]
+                    async fn $name() {
+                        let vault = $vault;
+                        common::$name(&vault).await;
+                    }
+                };
+            }
+
+            check!(can_insert_and_get_wallet);
+            check!(rejects_duplicate_wallet_name);
+            check!(reports_missing_wallet);
+            check!(can_list_wallets);
+            check!(can_insert_and_get_account);
+            check!(rejects_duplicate_address);
+            check!(rejects_account_of_missing_wallet);
+            check!(reports_missing_account);
+            check!(lists_accounts_per_wallet);
+            check!(can_remove_account);
+            check!(removing_wallet_cascades);
+            check!(tracks_auth_attempts);
+            check!(can_rekey_wallet);
+            check!(can_rename_wallet);
+            check!(can_update_account_metadata);
+            check!(orders_accounts_by_position);
+            check!(can_store_labels);
+            check!(can_store_multisigs);
+            check!(can_store_policy_wallets);
+            check!(can_query_accounts);
+            check!(insert_accounts_is_atomic);
+            check!(transactions_commit_or_roll_back);
+            check!(records_audit_events);
+            check!(chains_concurrent_audit_events);
+        }
+    };
+    ($backend:ident, $vault:expr) => {
+        vault_conformance!($backend, #[cfg(all())], $vault);
+    };
+}
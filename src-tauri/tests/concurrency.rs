@@ -0,0 +1,160 @@
+use bitcoin::bip32::DerivationPath;
+use dev_wallet::*;
+use kdf::KdfParams;
+use std::{
+    env, fs,
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use throttle::ThrottlePolicy;
+use {
+    account::AccountInputBuilder,
+    query::AccountQuery,
+    sqlite::SqliteVault,
+    utils::blocking,
+    vault_interface::VaultInterface,
+    wallet::{AuthError, WalletInputBuilder, WalletModel},
+};
+
+async fn create_wallet(vault: &dyn VaultInterface, name: &str, kdf: KdfParams) -> WalletModel {
+    let mut wallet = WalletInputBuilder::new();
+
+    wallet.name(name);
+    wallet.password("password");
+    wallet.kdf(kdf);
+    let wallet = wallet.build().unwrap();
+
+    vault.insert_wallet(wallet).await.unwrap()
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn kdf_does_not_stall_the_runtime() {
+    let vault = SqliteVault::new(Some("sqlite::memory:")).await;
+    vault.migrate().await.unwrap();
+    let vault: Arc<dyn VaultInterface> = Arc::new(vault);
+
+    let wallet = create_wallet(&*vault, "main", KdfParams::default()).await;
+
+    let start = Instant::now();
+    wallet.authenticate("password").unwrap();
+    let kdf_time = start.elapsed();
+
+    let authentication = {
+        let vault = vault.clone();
+        let wallet = wallet.clone();
+        tokio::spawn(async move {
+            throttle::authenticate(&*vault, &ThrottlePolicy::default(), &wallet, "password").await
+        })
+    };
+
+    // Other tasks keep running while the password is checked, on this single thread.
+    let mut longest_stall = Duration::ZERO;
+    let mut tick = Instant::now();
+    while !authentication.is_finished() {
+        tokio::time::sleep(Duration::from_millis(1)).await;
+        longest_stall = longest_stall.max(tick.elapsed());
+        tick = Instant::now();
+    }
+
+    assert!(authentication.await.unwrap().is_ok());
+    assert!(
+        longest_stall < kdf_time / 2,
+        "stalled {longest_stall:?}, the KDF takes {kdf_time:?}"
+    );
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn commands_run_concurrently() {
+    let dir = env::temp_dir().join(format!("dev-wallet-{}", uuid::Uuid::new_v4()));
+    fs::create_dir_all(&dir).unwrap();
+    let url = format!("sqlite://{}", dir.join("database.db").display());
+
+    let vault = SqliteVault::new(Some(&url)).await;
+    vault.migrate().await.unwrap();
+    let vault: Arc<dyn VaultInterface> = Arc::new(vault);
+
+    let mut wallets = vec![];
+    for i in 0..4 {
+        wallets.push(create_wallet(&*vault, &format!("wallet-{i}"), KdfParams::fast()).await);
+    }
+
+    // Every wallet gets authenticated, derives accounts and lists them from several tasks.
+    let mut tasks = vec![];
+    for (i, wallet) in wallets.iter().cycle().take(16).enumerate() {
+        // Each task of a wallet derives its own range of addresses.
+        let path = format!("m/84'/0'/0'/0/{}", i / wallets.len() * 5);
+        let vault = vault.clone();
+        let wallet = wallet.clone();
+
+        tasks.push(tokio::spawn(async move {
+            let key =
+                throttle::authenticate(&*vault, &ThrottlePolicy::default(), &wallet, "password")
+                    .await
+                    .unwrap();
+
+            let mut builder = AccountInputBuilder::from(wallet.clone());
+            builder.path(DerivationPath::from_str(&path).unwrap());
+            let accounts = blocking(move || builder.build_many(key, 5)).await.unwrap();
+            audit::insert_accounts(&*vault, accounts).await.unwrap();
+
+            vault
+                .query_accounts(&wallet.id, &AccountQuery::default())
+                .await
+                .unwrap();
+            vault.get_all_wallets().await.unwrap();
+        }));
+    }
+
+    for task in tasks {
+        task.await.unwrap();
+    }
+
+    for wallet in wallets.iter() {
+        let accounts = vault.get_all_accounts(&wallet.id).await.unwrap();
+        assert_eq!(accounts.len(), 20);
+
+        let events = vault.get_audit_events(&wallet.id).await.unwrap();
+        assert_eq!(events.len(), 4 + 20);
+        assert_eq!(audit::verify(&events), Ok(()));
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn concurrent_guesses_are_throttled() {
+    let vault = SqliteVault::new(Some("sqlite::memory:")).await;
+    vault.migrate().await.unwrap();
+    let vault: Arc<dyn VaultInterface> = Arc::new(vault);
+
+    let wallet = create_wallet(&*vault, "main", KdfParams::fast()).await;
+    let policy = Arc::new(ThrottlePolicy {
+        lockout_threshold: Some(3),
+        ..Default::default()
+    });
+
+    let mut tasks = vec![];
+    for _ in 0..32 {
+        let vault = vault.clone();
+        let wallet = wallet.clone();
+        let policy = policy.clone();
+
+        tasks.push(tokio::spawn(async move {
+            throttle::authenticate(&*vault, &policy, &wallet, "wrong").await
+        }));
+    }
+
+    let mut checked = 0;
+    for task in tasks {
+        match task.await.unwrap() {
+            Err(AuthError::Failed(_)) => checked += 1,
+            Err(AuthError::Throttled(_) | AuthError::LockedOut(..)) => {}
+            res => panic!("unexpected outcome {res:?}"),
+        }
+    }
+
+    assert_eq!(checked, policy.free_attempts);
+    let attempts = vault.get_auth_attempts(&wallet.id).await.unwrap();
+    assert_eq!(attempts.failed_attempts, policy.free_attempts);
+}
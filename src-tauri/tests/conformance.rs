@@ -0,0 +1,59 @@
+mod common;
+
+use dev_wallet::{memory::MemoryVault, postgres::PostgresVault, sqlite::SqliteVault};
+use sqlx::postgres::PgConnectOptions;
+use std::str::FromStr;
+
+// A file, the way the app opens it. The connections of `sqlite::memory:` share one cache, which
+// hides writes another connection hasn't committed yet.
+vault_conformance!(sqlite_vault, {
+    let path = std::env::temp_dir().join(format!("dev-wallet-{}.db", uuid::Uuid::new_v4()));
+    let vault = SqliteVault::new(Some(&format!("sqlite://{}", path.display()))).await;
+    vault.migrate().await.unwrap();
+    vault
+});
+
+vault_conformance!(memory_vault, MemoryVault::new());
+
+fn json_vault_path() -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("dev-wallet-{}.json", uuid::Uuid::new_v4()))
+}
+
+vault_conformance!(json_file_vault, {
+    dev_wallet::json_vault::JsonFileVault::open_with_kdf(
+        json_vault_path(),
+        "password",
+        dev_wallet::kdf::KdfParams::fast(),
+    )
+    .await
+    .unwrap()
+});
+
+/// Connects to the Postgres behind `TEST_DATABASE_URL` inside a schema of its own, so checks
+/// don't see each other's rows.
+async fn postgres_vault() -> PostgresVault {
+    let url = std::env::var("TEST_DATABASE_URL")
+        .expect("TEST_DATABASE_URL has to point at a Postgres database to run these checks");
+    let schema = format!("conformance_{}", uuid::Uuid::new_v4().simple());
+
+    let vault = PostgresVault::new(&url).await.unwrap();
+    sqlx::query(&format!("CREATE SCHEMA {schema};"))
+        .execute(&*vault)
+        .await
+        .unwrap();
+
+    let options = PgConnectOptions::from_str(&url)
+        .unwrap()
+        .options([("search_path", schema.as_str())]);
+    let vault = PostgresVault::connect_with(options).await.unwrap();
+    vault.migrate().await.unwrap();
+    vault
+}
+
+// Ignored unless asked for, so a missing database shows up as skipped rather than passing:
+// TEST_DATABASE_URL=postgres://postgres@localhost/devwallet cargo test --test conformance -- --ignored
+vault_conformance!(
+    postgres_vault,
+    #[ignore = "needs a Postgres database in TEST_DATABASE_URL"],
+    postgres_vault().await
+);
@@ -0,0 +1,67 @@
+#![cfg(feature = "sqlcipher")]
+
+use dev_wallet::*;
+use kdf::KdfParams;
+use std::{env, fs, path::PathBuf};
+use {sqlite::SqliteVault, vault_interface::VaultInterface, wallet::WalletInputBuilder};
+
+fn database_path() -> PathBuf {
+    env::temp_dir().join(format!("dev-wallet-{}.db", uuid::Uuid::new_v4()))
+}
+
+#[tokio::test]
+async fn can_encrypt_existing_database() {
+    let path = database_path();
+    let url = format!("sqlite://{}", path.display());
+
+    let vault = SqliteVault::new(Some(&url)).await;
+    vault.migrate().await.unwrap();
+
+    let mut wallet = WalletInputBuilder::new();
+    wallet.name("main");
+    wallet.password("password");
+    wallet.kdf(KdfParams::fast());
+    vault.insert_wallet(wallet.build().unwrap()).await.unwrap();
+    vault.close().await;
+
+    assert!(SqliteVault::is_plaintext(&path));
+
+    let vault = SqliteVault::new_encrypted(Some(&url), "master")
+        .await
+        .unwrap();
+    vault.migrate().await.unwrap();
+    assert!(vault.get_wallet_by_name("main").await.is_ok());
+    vault.close().await;
+
+    assert!(!SqliteVault::is_plaintext(&path));
+    let raw = fs::read(&path).unwrap();
+    assert!(!raw.windows(4).any(|window| window == b"main"));
+
+    assert!(SqliteVault::new_encrypted(Some(&url), "wrong")
+        .await
+        .is_err());
+
+    fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn can_create_encrypted_database() {
+    let path = database_path();
+    let url = format!("sqlite://{}", path.display());
+
+    let vault = SqliteVault::new_encrypted(Some(&url), "master")
+        .await
+        .unwrap();
+    vault.migrate().await.unwrap();
+    vault.close().await;
+
+    assert!(!SqliteVault::is_plaintext(&path));
+
+    let vault = SqliteVault::new_encrypted(Some(&url), "master")
+        .await
+        .unwrap();
+    assert!(vault.get_all_wallets().await.unwrap().is_empty());
+    vault.close().await;
+
+    fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,110 @@
+mod common;
+
+use dev_wallet::*;
+use fs4::fs_std::FileExt;
+use json_vault::JsonFileVault;
+use kdf::KdfParams;
+use std::{
+    env, fs,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+use vault_interface::VaultInterface;
+
+fn vault_path() -> PathBuf {
+    env::temp_dir().join(format!("dev-wallet-{}.json", uuid::Uuid::new_v4()))
+}
+
+#[tokio::test]
+async fn persists_wallets_across_reopening() {
+    let path = vault_path();
+
+    let vault = JsonFileVault::open_with_kdf(&path, "master", KdfParams::fast())
+        .await
+        .unwrap();
+    vault
+        .insert_wallet(common::wallet_input("main"))
+        .await
+        .unwrap();
+    drop(vault);
+
+    let vault = JsonFileVault::open(&path, "master").await.unwrap();
+    assert!(vault.get_wallet_by_name("main").await.is_ok());
+
+    let raw = fs::read_to_string(&path).unwrap();
+    assert!(!raw.contains("main"));
+
+    assert!(JsonFileVault::open(&path, "wrong").await.is_err());
+
+    fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn sees_writes_of_other_instances() {
+    let path = vault_path();
+
+    let first = JsonFileVault::open_with_kdf(&path, "master", KdfParams::fast())
+        .await
+        .unwrap();
+    let second = JsonFileVault::open(&path, "master").await.unwrap();
+
+    first
+        .insert_wallet(common::wallet_input("main"))
+        .await
+        .unwrap();
+    assert!(second.get_wallet_by_name("main").await.is_ok());
+
+    // The unique name is enforced against the latest document, not a stale copy.
+    assert!(second
+        .insert_wallet(common::wallet_input("main"))
+        .await
+        .is_err());
+
+    fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn refuses_documents_from_newer_versions() {
+    let path = vault_path();
+
+    JsonFileVault::open_with_kdf(&path, "master", KdfParams::fast())
+        .await
+        .unwrap();
+
+    let mut document: serde_json::Value =
+        serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+    document["version"] = serde_json::json!(json_vault::SCHEMA_VERSION + 1);
+    fs::write(&path, serde_json::to_vec(&document).unwrap()).unwrap();
+
+    assert!(JsonFileVault::open(&path, "master").await.is_err());
+
+    fs::remove_file(path).unwrap();
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn waiting_for_other_processes_does_not_stall_the_runtime() {
+    let path = vault_path();
+    let vault = JsonFileVault::open_with_kdf(&path, "master", KdfParams::fast())
+        .await
+        .unwrap();
+
+    // Stands for another process in the middle of a write.
+    let mut lock_path = path.clone().into_os_string();
+    lock_path.push(".lock");
+    let lock = fs::File::open(&lock_path).unwrap();
+    FileExt::lock_exclusive(&lock).unwrap();
+
+    let read = tokio::spawn(async move { vault.get_all_wallets().await });
+
+    // Other tasks keep running on this single thread while the read waits for the lock.
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_millis(100) {
+        tokio::time::sleep(Duration::from_millis(1)).await;
+    }
+    assert!(!read.is_finished());
+
+    FileExt::unlock(&lock).unwrap();
+    assert!(read.await.unwrap().unwrap().is_empty());
+
+    fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,105 @@
+mod common;
+
+use common::{account_input, wallet_input};
+use dev_wallet::{
+    labels::{self, LabelError},
+    memory::MemoryVault,
+    vault_interface::VaultInterface,
+};
+
+const TXID: &str = "f91d0a8a78462bc59398f2c5d7a84fcff491c26ba54c4833478b202796c8aafd";
+
+#[tokio::test]
+async fn labels_round_trip_between_wallets() {
+    let vault = MemoryVault::new();
+    let source = vault.insert_wallet(wallet_input("source")).await.unwrap();
+    let target = vault.insert_wallet(wallet_input("target")).await.unwrap();
+
+    for wallet in [&source, &target] {
+        vault
+            .insert_account(account_input(wallet, &format!("{}-address", wallet.name)))
+            .await
+            .unwrap();
+    }
+
+    let content = format!(
+        r#"{{"type":"addr","ref":"source-address","label":"Savings"}}
+{{"type":"tx","ref":"{TXID}","label":"Rent","origin":"wpkh([d34db33f/84'/0'/0'])"}}
+{{"type":"output","ref":"{TXID}:1","label":"Change","spendable":false}}
+{{"type":"input","ref":"{TXID}:0","label":"Input"}}
+{{"type":"xpub","ref":"xpub661MyMwAqRbcFtXgS5sYJABqqG9YLmC4Q1Rdap9gSE8NqtwybGhePY2gZ29ESFjqJoCu1Rupje8YtGqsefD265TMg7usUDFdp6W1EGMcet8","label":"Cold"}}
+{{"type":"addr","ref":"bc1q34aq5drpuwy3wgl9lhup9892qp6svr8ldzyy7c","label":"Elsewhere"}}
+{{"type":"nostr","ref":"npub","label":"From the future"}}
+"#
+    );
+
+    let report = labels::import(&vault, &source.id, &content).await.unwrap();
+    assert_eq!(report.accounts, 1);
+    assert_eq!(report.labels, 5);
+    assert_eq!(report.unknown, 1);
+
+    let account = &vault.get_all_accounts(&source.id).await.unwrap()[0];
+    assert_eq!(account.label, "Savings");
+
+    let exported = labels::export(&vault, &source.id).await.unwrap();
+    let (mut expected, _) = labels::parse(&content).unwrap();
+    let (mut actual, _) = labels::parse(&exported).unwrap();
+    let key = |label: &labels::Label| (label.kind.to_string(), label.reference.clone());
+    expected.sort_by_key(key);
+    actual.sort_by_key(key);
+    assert_eq!(actual, expected);
+
+    // Moving them to another wallet keeps everything but the addresses it doesn't own.
+    let report = labels::import(&vault, &target.id, &exported).await.unwrap();
+    assert_eq!(report.accounts, 0);
+    assert_eq!(report.labels, 6);
+    // The account address of the source is just another address for the target.
+    let mut expected = vault.get_labels(&source.id).await.unwrap();
+    expected.extend(
+        labels::parse(&exported)
+            .unwrap()
+            .0
+            .into_iter()
+            .filter(|label| label.reference == "source-address"),
+    );
+    expected.sort_by_key(key);
+    assert_eq!(vault.get_labels(&target.id).await.unwrap(), expected);
+}
+
+#[tokio::test]
+async fn keeps_account_labels_missing_from_records() {
+    let vault = MemoryVault::new();
+    let wallet = vault.insert_wallet(wallet_input("main")).await.unwrap();
+    let account = vault
+        .insert_account(account_input(&wallet, "address"))
+        .await
+        .unwrap();
+    let content = r#"{"type":"addr","ref":"address","label":"Savings"}"#;
+    labels::import(&vault, &wallet.id, content).await.unwrap();
+
+    let content = r#"{"type":"addr","ref":"address"}"#;
+    let report = labels::import(&vault, &wallet.id, content).await.unwrap();
+    assert_eq!(report.accounts, 0);
+    assert_eq!(report.labels, 0);
+
+    let account = vault.get_account_by_id(&account.id).await.unwrap();
+    assert_eq!(account.label, "Savings");
+}
+
+#[tokio::test]
+async fn refuses_corrupt_documents() {
+    let vault = MemoryVault::new();
+    let wallet = vault.insert_wallet(wallet_input("main")).await.unwrap();
+
+    let content = format!(
+        "{{\"type\":\"tx\",\"ref\":\"{TXID}\",\"label\":\"Rent\"}}\n{{\"type\":\"tx\",\"ref\":"
+    );
+    let res = labels::import(&vault, &wallet.id, &content).await;
+    assert!(matches!(res, Err(LabelError::Record(2, _))));
+
+    // Nothing from the valid first line was kept.
+    assert!(vault.get_labels(&wallet.id).await.unwrap().is_empty());
+
+    let res = labels::import(&vault, "missing", "").await;
+    assert!(matches!(res, Err(LabelError::Vault(_))));
+}
@@ -1,9 +1,16 @@
 use dev_wallet::*;
+use kdf::KdfParams;
 use path_builder::PathBuilder;
+use std::{env, fs, time::Duration};
+use throttle::ThrottlePolicy;
 use tokio;
 use {
-    account::AccountInputBuilder, sqlite::SqliteVault, vault_interface::VaultInterface,
-    wallet::WalletInputBuilder,
+    account::AccountInputBuilder,
+    audit::AuditAction,
+    config::{ConfigFile, DEFAULT_PROFILE},
+    sqlite::SqliteVault,
+    vault_interface::{VaultError, VaultInterface},
+    wallet::{AuthError, WalletInputBuilder},
 };
 
 #[tokio::test]
@@ -12,6 +19,7 @@ async fn can_create_wallet() {
 
     wallet.name("name");
     wallet.password("password");
+    wallet.kdf(KdfParams::fast());
 
     let wallet = wallet.build().unwrap();
     let name = wallet.name.clone();
@@ -31,6 +39,7 @@ async fn can_create_account() {
 
     wallet.name(&name);
     wallet.password(&password);
+    wallet.kdf(KdfParams::fast());
 
     let wallet = wallet.build().unwrap();
     let name = wallet.name.clone();
@@ -63,6 +72,7 @@ async fn can_find_wallet() {
 
     wallet.name(&name);
     wallet.password(&password);
+    wallet.kdf(KdfParams::fast());
     let wallet = wallet.build().unwrap();
     let wallet = vault.insert_wallet(wallet).await.unwrap();
 
@@ -73,6 +83,22 @@ async fn can_find_wallet() {
     assert_eq!(wallet.name, name);
 }
 
+#[tokio::test]
+async fn lookups_tell_missing_rows_from_failures() {
+    let vault = SqliteVault::new(Some("sqlite::memory:")).await;
+    vault.migrate().await.unwrap();
+
+    let res = vault.get_wallet_by_name("missing").await;
+    assert!(matches!(res, Err(VaultError::NotFound(name)) if name == "missing"));
+
+    vault.close().await;
+
+    let res = vault.get_wallet_by_name("missing").await;
+    assert!(matches!(res, Err(VaultError::Database(_))));
+    let res = vault.get_account_by_id("missing").await;
+    assert!(matches!(res, Err(VaultError::Database(_))));
+}
+
 #[tokio::test]
 
 async fn can_list_all_wallets() {
@@ -97,6 +123,7 @@ async fn can_list_all_wallets() {
         let mut wallet = WalletInputBuilder::new();
         wallet.name(&input.name);
         wallet.password(&input.password);
+        wallet.kdf(KdfParams::fast());
         let wallet = wallet.build().unwrap();
         vault.insert_wallet(wallet).await.unwrap();
     }
@@ -115,6 +142,7 @@ async fn can_list_all_accounts_for_wallet() {
 
     wallet.name("main");
     wallet.password("password");
+    wallet.kdf(KdfParams::fast());
     let wallet = wallet.build().unwrap();
 
     let wallet = vault.insert_wallet(wallet).await.unwrap();
@@ -136,6 +164,42 @@ async fn can_list_all_accounts_for_wallet() {
     assert_eq!(res.len(), paths.len());
 }
 
+#[tokio::test]
+async fn can_insert_derived_accounts_at_once() {
+    let vault = SqliteVault::new(Some("sqlite::memory:")).await;
+    vault.migrate().await.unwrap();
+
+    let mut wallet = WalletInputBuilder::new();
+
+    wallet.name("main");
+    wallet.password("password");
+    wallet.kdf(KdfParams::fast());
+    let wallet = wallet.build().unwrap();
+
+    let wallet = vault.insert_wallet(wallet).await.unwrap();
+
+    let key = wallet.authenticate("password").unwrap();
+
+    let mut builder = AccountInputBuilder::from(wallet.clone());
+    builder.path(PathBuilder::new().index(5).build());
+    let accounts = builder.build_many(key, 3).unwrap();
+
+    let single = AccountInputBuilder::from(wallet.clone())
+        .path(PathBuilder::new().index(7).build())
+        .build(key)
+        .unwrap();
+    assert_eq!(accounts[2].address, single.address);
+
+    vault.insert_accounts(accounts.clone()).await.unwrap();
+    assert_eq!(vault.get_all_accounts(&wallet.id).await.unwrap().len(), 3);
+
+    // Deriving again from index 7 collides on its address, so none of them gets in.
+    builder.path(PathBuilder::new().index(7).build());
+    let overlapping = builder.build_many(key, 3).unwrap();
+    assert!(vault.insert_accounts(overlapping).await.is_err());
+    assert_eq!(vault.get_all_accounts(&wallet.id).await.unwrap().len(), 3);
+}
+
 #[tokio::test]
 async fn can_remove_wallet() {
     let vault = SqliteVault::new(Some("sqlite::memory:")).await;
@@ -145,6 +209,7 @@ async fn can_remove_wallet() {
 
     wallet.name("main");
     wallet.password("password");
+    wallet.kdf(KdfParams::fast());
     let wallet = wallet.build().unwrap();
 
     let wallet = vault.insert_wallet(wallet).await.unwrap();
@@ -169,6 +234,7 @@ async fn can_remove_accounts() {
 
     wallet.name("main");
     wallet.password("password");
+    wallet.kdf(KdfParams::fast());
     let wallet = wallet.build().unwrap();
 
     let wallet = vault.insert_wallet(wallet).await.unwrap();
@@ -194,3 +260,349 @@ async fn can_remove_accounts() {
     let res = vault.get_all_accounts(&wallet.id).await.unwrap();
     assert_eq!(res.len(), 0);
 }
+
+#[tokio::test]
+async fn can_remove_wallet_with_failed_attempts() {
+    let vault = SqliteVault::new(Some("sqlite::memory:")).await;
+    vault.migrate().await.unwrap();
+
+    let mut wallet = WalletInputBuilder::new();
+
+    wallet.name("main");
+    wallet.password("password");
+    wallet.kdf(KdfParams::fast());
+    let wallet = wallet.build().unwrap();
+
+    let wallet = vault.insert_wallet(wallet).await.unwrap();
+    vault.record_failed_auth(&wallet.id).await.unwrap();
+
+    vault.remove_wallet_by_id(&wallet.id).await.unwrap();
+
+    assert!(vault.get_wallet_by_id(&wallet.id).await.is_err());
+    let attempts = vault.get_auth_attempts(&wallet.id).await.unwrap();
+    assert_eq!(attempts.failed_attempts, 0);
+}
+
+#[tokio::test]
+async fn throttles_repeated_failed_authentication() {
+    let vault = SqliteVault::new(Some("sqlite::memory:")).await;
+    vault.migrate().await.unwrap();
+
+    let mut wallet = WalletInputBuilder::new();
+
+    wallet.name("main");
+    wallet.password("password");
+    wallet.kdf(KdfParams::fast());
+    let wallet = wallet.build().unwrap();
+
+    let wallet = vault.insert_wallet(wallet).await.unwrap();
+    let policy = ThrottlePolicy {
+        free_attempts: 2,
+        base_delay: Duration::from_secs(60),
+        ..Default::default()
+    };
+
+    let res = throttle::authenticate(&vault, &policy, &wallet, "wrong").await;
+    assert!(matches!(res, Err(AuthError::Failed(_))));
+
+    let res = throttle::authenticate(&vault, &policy, &wallet, "wrong").await;
+    assert!(matches!(res, Err(AuthError::Failed(_))));
+
+    // Even the right password is refused while the wallet is throttled.
+    let res = throttle::authenticate(&vault, &policy, &wallet, "password").await;
+    assert!(matches!(res, Err(AuthError::Throttled(_))));
+
+    let attempts = vault.get_auth_attempts(&wallet.id).await.unwrap();
+    assert_eq!(attempts.failed_attempts, 2);
+
+    vault.reset_auth_attempts(&wallet.id).await.unwrap();
+    let res = throttle::authenticate(&vault, &policy, &wallet, "password").await;
+    assert!(res.is_ok());
+
+    // Throttled attempts never reach the password check, so they aren't logged.
+    let actions: Vec<AuditAction> = vault
+        .get_audit_events(&wallet.id)
+        .await
+        .unwrap()
+        .iter()
+        .map(|event| event.action)
+        .collect();
+    assert_eq!(
+        actions,
+        vec![
+            AuditAction::AuthFailed,
+            AuditAction::AuthFailed,
+            AuditAction::AuthSucceeded
+        ]
+    );
+}
+
+#[tokio::test]
+async fn audit_events_are_append_only() {
+    let vault = SqliteVault::new(Some("sqlite::memory:")).await;
+    vault.migrate().await.unwrap();
+
+    let mut wallet = WalletInputBuilder::new();
+
+    wallet.name("main");
+    wallet.password("password");
+    wallet.kdf(KdfParams::fast());
+    let wallet = wallet.build().unwrap();
+
+    let wallet = vault.insert_wallet(wallet).await.unwrap();
+    throttle::authenticate(&vault, &ThrottlePolicy::default(), &wallet, "password")
+        .await
+        .unwrap();
+
+    let res = sqlx::query("UPDATE audit_events SET action = 'auth_failed';")
+        .execute(&*vault)
+        .await;
+    assert!(res.is_err());
+
+    let res = sqlx::query("DELETE FROM audit_events;")
+        .execute(&*vault)
+        .await;
+    assert!(res.is_err());
+
+    let events = vault.get_audit_events(&wallet.id).await.unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(audit::verify(&events), Ok(()));
+}
+
+#[tokio::test]
+async fn can_upgrade_wallet_kdf_params() {
+    let vault = SqliteVault::new(Some("sqlite::memory:")).await;
+    vault.migrate().await.unwrap();
+
+    let mut wallet = WalletInputBuilder::new();
+
+    wallet.name("main");
+    wallet.password("password");
+    wallet.kdf(KdfParams::fast());
+    let wallet = wallet.build().unwrap();
+
+    let wallet = vault.insert_wallet(wallet).await.unwrap();
+    let key = wallet.authenticate("password").unwrap();
+
+    let account = AccountInputBuilder::from(wallet.clone())
+        .path(PathBuilder::new().build())
+        .build(key)
+        .unwrap();
+    let account = vault.insert_account(account).await.unwrap();
+
+    let target = KdfParams::new(64, 2, 1).unwrap();
+    assert!(wallet.needs_rehash(&target));
+
+    let new_key = kdf::upgrade_wallet(&vault, &wallet, "password", key, &target)
+        .await
+        .unwrap();
+
+    let wallet = vault.get_wallet_by_id(&wallet.id).await.unwrap();
+    assert!(!wallet.needs_rehash(&target));
+    assert_eq!(wallet.kdf_params().unwrap(), target);
+    assert_eq!(wallet.authenticate("password").unwrap(), new_key);
+
+    // The account path is still readable with the new key.
+    let account = vault.get_account_by_id(&account.id).await.unwrap();
+    let path = utils::decrypt(&new_key, &hex::decode(account.path).unwrap()).unwrap();
+    assert_eq!(
+        String::from_utf8(path).unwrap(),
+        PathBuilder::new().build().to_string()
+    );
+
+    // The same account can be derived again from the re-encrypted seed.
+    let derived = AccountInputBuilder::from(wallet)
+        .path(PathBuilder::new().build())
+        .build(new_key)
+        .unwrap();
+    assert_eq!(derived.address, account.address);
+}
+
+#[tokio::test]
+async fn backs_up_before_migrating_and_refuses_newer_schemas() {
+    let dir = env::temp_dir().join(format!("dev-wallet-{}", uuid::Uuid::new_v4()));
+    fs::create_dir_all(&dir).unwrap();
+    let url = format!("sqlite://{}", dir.join("database.db").display());
+    let backups = || {
+        fs::read_dir(&dir)
+            .unwrap()
+            .filter(|entry| {
+                entry
+                    .as_ref()
+                    .unwrap()
+                    .file_name()
+                    .to_string_lossy()
+                    .ends_with(".bak")
+            })
+            .count()
+    };
+
+    // A blank database has nothing worth backing up.
+    let vault = SqliteVault::connect(Some(&url)).await.unwrap();
+    assert_eq!(vault.migrate().await.unwrap(), None);
+    assert_eq!(backups(), 0);
+
+    // Up to date, nothing to do either.
+    assert_eq!(vault.migrate().await.unwrap(), None);
+    assert_eq!(backups(), 0);
+
+    // As if the app had just been upgraded with a new migration.
+    sqlx::query(
+        "DELETE FROM _sqlx_migrations WHERE version = (SELECT max(version) FROM _sqlx_migrations);",
+    )
+    .execute(&*vault)
+    .await
+    .unwrap();
+    let backup = vault.migrate().await.unwrap();
+    assert_eq!(backups(), 1);
+    assert!(backup.unwrap().starts_with(&dir));
+
+    // As if a newer version of the app had opened the database.
+    sqlx::query(
+        "INSERT INTO _sqlx_migrations (version, description, success, checksum, execution_time)
+        VALUES (99991231, 'from the future', TRUE, x'00', 0);",
+    )
+    .execute(&*vault)
+    .await
+    .unwrap();
+    let res = vault.migrate().await;
+    assert!(matches!(res, Err(VaultError::SchemaTooNew(99991231, _))));
+    assert_eq!(backups(), 1);
+
+    vault.close().await;
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn default_profile_adopts_the_legacy_database() {
+    let dir = env::temp_dir().join(format!("dev-wallet-{}", uuid::Uuid::new_v4()));
+    fs::create_dir_all(&dir).unwrap();
+    let legacy = dir.join("database.db");
+
+    // What a version of the app without profiles left in the working directory.
+    let vault = SqliteVault::connect(Some(&format!("sqlite://{}", legacy.display())))
+        .await
+        .unwrap();
+    vault.migrate().await.unwrap();
+    let mut wallet = WalletInputBuilder::new();
+    wallet.name("legacy");
+    wallet.password("password");
+    wallet.kdf(KdfParams::fast());
+    vault.insert_wallet(wallet.build().unwrap()).await.unwrap();
+    vault.close().await;
+
+    // Only the default profile takes it, and only while it has no database.
+    let data_dir = dir.join("data");
+    let file = ConfigFile::parse("[profiles.regtest]\n").unwrap();
+    let regtest = file.profile("regtest", &data_dir).unwrap();
+    assert!(!regtest.adopt_legacy_database(&legacy).unwrap());
+
+    let profile = file.profile(DEFAULT_PROFILE, &data_dir).unwrap();
+    fs::create_dir_all(&profile.dir).unwrap();
+    assert!(profile.adopt_legacy_database(&legacy).unwrap());
+    assert!(!legacy.exists());
+
+    let vault = profile.open(None).await.unwrap().vault;
+    let wallet = vault.get_wallet_by_name("legacy").await.unwrap();
+    assert_eq!(wallet.name, "legacy");
+
+    fs::write(&legacy, b"newer").unwrap();
+    assert!(!profile.adopt_legacy_database(&legacy).unwrap());
+    assert!(legacy.exists());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn dev_wallets_are_reproducible() {
+    let vault = SqliteVault::new(Some("sqlite::memory:")).await;
+    vault.migrate().await.unwrap();
+
+    let spec = fixture::FixtureSpec {
+        networks: vec![account::Network::Regtest, account::Network::Testnet],
+        count: 3,
+        ..Default::default()
+    };
+    let expected = spec
+        .derive(&fixture::dev_mnemonic(None).unwrap().to_seed(""))
+        .unwrap();
+
+    // Whatever the password, the wallet derives the addresses of the bare mnemonic.
+    let wallet = fixture::dev_wallet_input("dev", "password", None, KdfParams::fast())
+        .unwrap()
+        .build()
+        .unwrap();
+    let wallet = vault.insert_wallet(wallet).await.unwrap();
+    let key = wallet.authenticate("password").unwrap();
+
+    let (entries, accounts) = spec
+        .build(&AccountInputBuilder::from(wallet.clone()), key)
+        .unwrap();
+    assert_eq!(entries, expected);
+
+    audit::insert_accounts(&vault, accounts).await.unwrap();
+    let stored = vault.get_all_accounts(&wallet.id).await.unwrap();
+
+    // Legacy and nested segwit addresses are the same on both networks.
+    assert_eq!(stored.len(), 2 * 4 * 3 - 2 * 3);
+    assert!(stored
+        .iter()
+        .all(|account| ["Regtest", "Testnet"].contains(&account.network.as_str())));
+}
+
+#[tokio::test]
+async fn spawns_bip85_child_wallets() {
+    let vault = SqliteVault::new(Some("sqlite::memory:")).await;
+    vault.migrate().await.unwrap();
+
+    let mut wallet = WalletInputBuilder::new();
+    wallet.name("master");
+    wallet.password("password");
+    wallet.kdf(KdfParams::fast());
+    let master = vault.insert_wallet(wallet.build().unwrap()).await.unwrap();
+    let seed = master
+        .decrypt_seed(&master.authenticate("password").unwrap())
+        .unwrap();
+
+    // Children are the same every time, and one per index.
+    let child = bip85::derive_mnemonic(&seed, 12, "english", 0).unwrap();
+    assert_eq!(
+        child,
+        bip85::derive_mnemonic(&seed, 12, "english", 0).unwrap()
+    );
+    assert_ne!(
+        child,
+        bip85::derive_mnemonic(&seed, 12, "english", 1).unwrap()
+    );
+    assert_eq!(child.word_count(), 12);
+
+    let mut wallet = WalletInputBuilder::from(child);
+    wallet.name("child");
+    wallet.password("password");
+    wallet.kdf(KdfParams::fast());
+    let child = audit::insert_child_wallet(
+        &vault,
+        wallet.build().unwrap(),
+        &master.id,
+        "m/83696968'/39'/0'/12'/0'",
+    )
+    .await
+    .unwrap();
+    assert_ne!(child.seed, master.seed);
+
+    let events = vault.get_audit_events(&master.id).await.unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].action, AuditAction::SecretExported);
+    assert!(events[0].details.contains(&child.id));
+
+    // A child that can't be stored isn't recorded as exported.
+    let mut wallet = WalletInputBuilder::new();
+    wallet.name("child");
+    wallet.password("password");
+    wallet.kdf(KdfParams::fast());
+    let res = audit::insert_child_wallet(&vault, wallet.build().unwrap(), &master.id, "m/0'").await;
+    assert!(matches!(res, Err(VaultError::Inserting(_))));
+    assert_eq!(vault.get_audit_events(&master.id).await.unwrap().len(), 1);
+
+    assert!(master.decrypt_seed(&[0u8; 32]).is_err());
+}
@@ -8,9 +8,22 @@ import Loading from "./components/loading.tsx";
 import { QueryErrorResetBoundary } from "react-query";
 import { ErrorBoundary } from "react-error-boundary";
 import fallbackError from "./components/fallback-error.tsx";
+import Error from "./components/error.tsx";
+import useStartupStatus from "./hooks/use-startup-status.ts";
 
 function App() {
   const appState = useAppState();
+  const startup = useStartupStatus();
+
+  if (startup.isLoading) {
+    return <Loading />;
+  }
+
+  // The vault couldn't be opened, e.g. its database was written by a newer version.
+  if (startup.data?.error) {
+    return <Error error={startup.data.error.message} />;
+  }
+
   return (
     <BrowserRouter>
       <Routes>
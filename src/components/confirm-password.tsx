@@ -11,6 +11,7 @@ import {
   Input,
 } from "@chakra-ui/react";
 import { FormEvent, PropsWithChildren, useEffect, useState } from "react";
+import { errorMessage } from "../utils/utils.ts";
 
 interface ConfirmPasswordProps extends PropsWithChildren {
   isDrawerOpen: boolean;
@@ -35,7 +36,7 @@ export default function ConfirmPassword(props: ConfirmPasswordProps) {
       await props.onConfirmation(password);
     } catch (err) {
       setIsInvalid(true);
-      setErrorMessage(err.toString);
+      setErrorMessage(errorMessage(err));
     }
   };
 
@@ -1,13 +1,22 @@
-import { Flex, Heading, IconButton, useColorMode } from "@chakra-ui/react";
+import {
+  Flex,
+  Heading,
+  IconButton,
+  Select,
+  useColorMode,
+} from "@chakra-ui/react";
 import { useCallback } from "react";
 import Logo from "./logo.tsx";
 import { Moon01, Sun } from "@untitled-ui/icons-react";
+import { useProfiles, useSwitchProfile } from "../hooks/use-profiles.ts";
 
 export default function Navbar({ text = "" }) {
   const { colorMode, setColorMode } = useColorMode();
   const handleColorChange = useCallback(() => {
     setColorMode(colorMode === "light" ? "dark" : "light");
   }, [colorMode]);
+  const { data: profiles } = useProfiles();
+  const switchProfile = useSwitchProfile();
   return (
     <Flex
       h="10"
@@ -23,6 +32,27 @@ export default function Navbar({ text = "" }) {
         {text}
       </Heading>
       <Flex grow={1} alignItems="center" gap={2}>
+        {profiles && profiles.profiles.length > 1 && (
+          <Select
+            size="sm"
+            aria-label="Profile"
+            value={profiles.active}
+            isDisabled={switchProfile.isLoading}
+            onChange={(event) => switchProfile.mutate(event.target.value)}
+          >
+            {profiles.profiles.map((profile) => (
+              <option
+                key={profile.name}
+                value={profile.name}
+                disabled={!!profile.error}
+              >
+                {profile.network
+                  ? `${profile.name} (${profile.network})`
+                  : profile.name}
+              </option>
+            ))}
+          </Select>
+        )}
         <IconButton aria-label="Theme" onClick={handleColorChange}>
           {colorMode === "dark" ? <Sun /> : <Moon01 />}
         </IconButton>
@@ -3,6 +3,7 @@ import { invoke } from "@tauri-apps/api";
 import { useAppState } from "../state.ts";
 import { useNavigate } from "react-router-dom";
 import { useToast } from "@chakra-ui/react";
+import { CommandError, errorMessage } from "../utils/utils.ts";
 
 export type AuthenticateInput = {
   name: string;
@@ -17,7 +18,7 @@ export type AuthenticateData = {
 export default function useAuthenticate() {
   const navigate = useNavigate();
   const toast = useToast();
-  const mutation = useMutation<AuthenticateData, string, AuthenticateInput>(
+  const mutation = useMutation<AuthenticateData, CommandError, AuthenticateInput>(
     "authenticate",
     {
       mutationFn: async ({ password, name }) => {
@@ -32,7 +33,7 @@ export default function useAuthenticate() {
           title: "Authentication failed",
           status: "error",
           isClosable: true,
-          description: `Details: ${error}`,
+          description: `Details: ${errorMessage(error)}`,
         });
       },
     },
@@ -2,6 +2,7 @@ import { useMutation } from "react-query";
 import { invoke } from "@tauri-apps/api";
 import { useParams } from "react-router-dom";
 import useListAccounts, { Account } from "./use-list-accounts.ts";
+import { CommandError } from "../utils/utils.ts";
 
 type CreateAccountInput = {
   path: string;
@@ -11,7 +12,7 @@ type CreateAccountInput = {
 export default function useCreateAccount() {
   const { wallet_id } = useParams();
   const listQuery = useListAccounts(false);
-  return useMutation<Account, string, CreateAccountInput>(
+  return useMutation<Account, CommandError, CreateAccountInput>(
     "create-account",
     async ({ path, password }) => {
       return await invoke("create_account", {
@@ -1,10 +1,12 @@
 import { useMutation } from "react-query";
 import { invoke } from "@tauri-apps/api";
 import { useToast } from "@chakra-ui/react";
+import { CommandError, errorMessage } from "../utils/utils.ts";
 
 export type VariablesType = {
   password: string;
   name: string;
+  draft: string;
 };
 
 export type CreateWalletDataType = {
@@ -13,13 +15,14 @@ export type CreateWalletDataType = {
 
 export default function useCreateWallet() {
   const toast = useToast();
-  const mutation = useMutation<CreateWalletDataType, string, VariablesType>(
+  const mutation = useMutation<CreateWalletDataType, CommandError, VariablesType>(
     "create-wallet",
     {
       mutationFn: (variables) => {
         return invoke("create_wallet", {
           password: variables.password,
           name: variables.name,
+          draft: variables.draft,
         });
       },
       onSuccess: () => {
@@ -33,7 +36,7 @@ export default function useCreateWallet() {
       onError: (err) => {
         toast({
           title: "Failed to create wallet",
-          description: `There was some issue preventing the wallet from creation. Details: "${err}"`,
+          description: `There was some issue preventing the wallet from creation. Details: "${errorMessage(err)}"`,
           isClosable: true,
           status: "error",
         });
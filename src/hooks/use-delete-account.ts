@@ -1,6 +1,7 @@
 import { useMutation } from "react-query";
 import useListAccounts from "./use-list-accounts.ts";
 import { invoke } from "@tauri-apps/api";
+import { CommandError } from "../utils/utils.ts";
 
 type DeleteAccountInput = {
   walletID: string;
@@ -14,7 +15,7 @@ type DeleteAccountResponse = {
 
 export default function useDeleteAccount() {
   const listQuery = useListAccounts(false);
-  return useMutation<DeleteAccountResponse, string, DeleteAccountInput>(
+  return useMutation<DeleteAccountResponse, CommandError, DeleteAccountInput>(
     "delete-wallet",
     async (input) => {
       return await invoke("remove_account", {
@@ -1,5 +1,6 @@
 import { useMutation, useQueryClient } from "react-query";
 import { invoke } from "@tauri-apps/api";
+import { CommandError } from "../utils/utils.ts";
 
 type DeleteAccountInput = {
   walletID: string;
@@ -12,7 +13,7 @@ type DeleteAccountResponse = {
 
 export default function useDeleteWallet() {
   const queryClient = useQueryClient();
-  return useMutation<DeleteAccountResponse, string, DeleteAccountInput>(
+  return useMutation<DeleteAccountResponse, CommandError, DeleteAccountInput>(
     "delete-wallet",
     async (input) => {
       return await invoke("remove_wallet", {
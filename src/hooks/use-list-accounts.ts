@@ -18,6 +18,11 @@ export interface Account {
   blockchain: AccountBlockchain;
 }
 
+type AccountPage = {
+  accounts: Account[];
+  next_cursor: string | null;
+};
+
 type QueryFNData = {
   wallet_id: string;
 };
@@ -32,7 +37,10 @@ export default function useListAccounts(enabled = true) {
           "The wallet ID is not present in the URL, aborting ...",
         );
       }
-      return await invoke("list_accounts", { walletId: wallet_id });
+      const page: AccountPage = await invoke("list_accounts", {
+        walletId: wallet_id,
+      });
+      return page.accounts;
     },
     { enabled, suspense: true, useErrorBoundary: true, retry: false },
   );
@@ -1,22 +1,37 @@
 import { useQuery } from "react-query";
 import { invoke } from "@tauri-apps/api";
+import { CommandError } from "../utils/utils.ts";
 
-export default function useMnemonics() {
-  const query = useQuery<unknown, string, string>(
-    "mnemonics-key",
+export type MnemonicOptions = {
+  words?: 12 | 15 | 18 | 21 | 24;
+  language?: string;
+  user_entropy?: {
+    source: "dice" | "coins";
+    rolls: string;
+  };
+};
+
+export type MnemonicDraft = {
+  // Handed to `create_wallet`, each draft is used once.
+  token: string;
+  mnemonic: string;
+  words: number;
+  language: string;
+  user_entropy_bits: number | null;
+};
+
+export default function useMnemonics(options: MnemonicOptions = {}) {
+  const query = useQuery<unknown, CommandError, MnemonicDraft>(
+    ["mnemonics-key", options],
     async () => {
-      const res: string[] = await invoke("generate_mnemonic");
+      const res: MnemonicDraft = await invoke("generate_mnemonic", { options });
       return res;
     },
     {
       refetchOnMount: true,
       cacheTime: 0,
       structuralSharing: false,
-      onSuccess: (data) => {
-        // return data.split(" ");
-        console.log(data.split(" ").length);
-      },
     },
   );
   return query;
-}
\ No newline at end of file
+}
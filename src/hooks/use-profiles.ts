@@ -0,0 +1,43 @@
+import { useMutation, useQuery, useQueryClient } from "react-query";
+import { invoke } from "@tauri-apps/api";
+import { useAppState } from "../state.ts";
+import { CommandError } from "../utils/utils.ts";
+
+export type Profile = {
+  name: string;
+  vault?: string;
+  network?: string;
+  chain?: string;
+  chain_url?: string | null;
+  error?: string;
+};
+
+type ProfilesResponse = {
+  active: string;
+  profiles: Profile[];
+};
+
+export function useProfiles() {
+  return useQuery<unknown, CommandError, ProfilesResponse>("profiles", async () => {
+    const response: ProfilesResponse = await invoke("list_profiles");
+    return response;
+  });
+}
+
+export function useSwitchProfile() {
+  const queryClient = useQueryClient();
+  const logout = useAppState((state) => state.logout);
+  return useMutation<Profile, CommandError, string>(
+    "switch-profile",
+    async (name) => {
+      return await invoke("switch_profile", { name });
+    },
+    {
+      async onSuccess() {
+        // Sessions of the previous profile are locked by the backend.
+        logout();
+        await queryClient.invalidateQueries();
+      },
+    },
+  );
+}
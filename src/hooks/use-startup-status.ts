@@ -0,0 +1,17 @@
+import { useQuery } from "react-query";
+import { invoke } from "@tauri-apps/api";
+import { CommandError } from "../utils/utils.ts";
+
+type StartupStatus = {
+  ok: boolean;
+  error: CommandError | null;
+  // Copy of the database taken before migrating it.
+  backup: string | null;
+};
+
+export default function useStartupStatus() {
+  return useQuery<unknown, CommandError, StartupStatus>("startup-status", async () => {
+    const status: StartupStatus = await invoke("startup_status");
+    return status;
+  });
+}
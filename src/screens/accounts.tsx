@@ -31,7 +31,11 @@ import useListAccounts, {
 import Navbar from "../components/navbar.tsx";
 import { Copy01, Trash01 } from "@untitled-ui/icons-react";
 import { FormEvent, PropsWithChildren, useEffect, useState } from "react";
-import { isValidDerivationPath, removeRef } from "../utils/utils.ts";
+import {
+  errorMessage,
+  isValidDerivationPath,
+  removeRef,
+} from "../utils/utils.ts";
 import useCreateAccount from "../hooks/use-create-account.ts";
 
 const INITIAL_ACCOUNT_DATA = {
@@ -99,7 +103,7 @@ function CreateAccount({ isDrawerOpen, onClose }: CreateAccountProps) {
       toast({
         status: "error",
         title: "Account creation error",
-        description: `wasn't able to create account, details:${error.toString()}`,
+        description: `wasn't able to create account, details:${errorMessage(error)}`,
       });
     }
   };
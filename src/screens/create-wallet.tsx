@@ -23,6 +23,7 @@ import { Check, Copy01, RefreshCw01 } from "@untitled-ui/icons-react";
 import useCreateWallet from "../hooks/use-create-wallet.ts";
 import InternalLink from "../components/internal-link.tsx";
 import Navbar from "../components/navbar.tsx";
+import { errorMessage } from "../utils/utils.ts";
 
 const INITIAL_STATE = {
   name: "",
@@ -80,6 +81,7 @@ export default function MnemonicScreen() {
       await createWalletMut.mutateAsync({
         password: state.password,
         name: state.name,
+        draft: mnemonics.data.token,
       });
 
       setState(JSON.parse(JSON.stringify(INITIAL_STATE)));
@@ -97,7 +99,7 @@ export default function MnemonicScreen() {
   );
 
   const handleMnemonicsCopy = () => {
-    setValue(mnemonics.toString);
+    setValue(mnemonics.data?.mnemonic ?? "");
     onCopy();
     setIsCopyTooltipOpen(true);
     setTimeout(() => {
@@ -117,7 +119,7 @@ export default function MnemonicScreen() {
     return <Loading />;
   }
   if (mnemonics.error) {
-    return <Error error={mnemonics.error} />;
+    return <Error error={errorMessage(mnemonics.error)} />;
   }
   return (
     <Screen>
@@ -182,7 +184,7 @@ export default function MnemonicScreen() {
                 </Flex>
               </Flex>
               <Code position="relative" p={2} _light={{ bg: "gray.200" }}>
-                {mnemonics.data?.split(" ").join(", ")}
+                {mnemonics.data?.mnemonic.split(" ").join(", ")}
               </Code>
               <Spacer mt={2} />
               <FormControl
@@ -3,6 +3,7 @@ import { create } from "zustand";
 export interface AppState {
   wallet_authenticated: string | null;
   setAuthenticated: (name: string) => void;
+  logout: () => void;
 }
 
 export const useAppState = create<AppState>((setState, getState, store) => ({
@@ -7,3 +7,17 @@ export function isValidDerivationPath(path: string): boolean {
 export function removeRef(data: object) {
   return JSON.parse(JSON.stringify(data));
 }
+
+/** Error rejected by every backend command, `code` being stable enough to branch on. */
+export type CommandError = {
+  code: string;
+  message: string;
+  details: unknown;
+};
+
+export function errorMessage(error: unknown): string {
+  if (error && typeof error === "object" && "message" in error) {
+    return String(error.message);
+  }
+  return String(error);
+}